@@ -0,0 +1,8 @@
+DROP TABLE "book_enrichments";
+DROP TYPE "enrichment_status";
+
+ALTER TABLE "books"
+    DROP COLUMN "publisher",
+    DROP COLUMN "published_year",
+    DROP COLUMN "page_count",
+    DROP COLUMN "cover_url";
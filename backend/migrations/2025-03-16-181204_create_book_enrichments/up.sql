@@ -0,0 +1,25 @@
+ALTER TABLE "books"
+    ADD COLUMN "publisher" text,
+    ADD COLUMN "published_year" INTEGER,
+    ADD COLUMN "page_count" INTEGER,
+    ADD COLUMN "cover_url" text;
+
+CREATE TYPE "enrichment_status" AS ENUM ('pending', 'matched', 'unmatched', 'failed');
+
+CREATE TABLE "book_enrichments" (
+    "book" uuid PRIMARY KEY NOT NULL REFERENCES "books" ("id") ON DELETE CASCADE,
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    "status" "enrichment_status" NOT NULL DEFAULT 'pending',
+    "provider" text,
+    "matched_by" text,
+    "confidence" REAL,
+    "attempts" INTEGER NOT NULL DEFAULT 0,
+    "last_error" text,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+SELECT diesel_manage_updated_at('book_enrichments');
+
+INSERT INTO "book_enrichments" ("book", "user")
+SELECT "id", "user" FROM "books" WHERE "google_books_id" IS NULL;
@@ -0,0 +1,9 @@
+CREATE TABLE "book_covers" (
+    "book" uuid PRIMARY KEY NOT NULL REFERENCES "books" ("id") ON DELETE CASCADE,
+    "id" uuid NOT NULL,
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    "content_type" text NOT NULL,
+    "width" INTEGER NOT NULL,
+    "height" INTEGER NOT NULL,
+    "uploaded_at" TIMESTAMPTZ NOT NULL DEFAULT now()
+);
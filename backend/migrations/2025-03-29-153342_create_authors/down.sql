@@ -0,0 +1,3 @@
+DROP TABLE "book_contributors";
+DROP TABLE "authors";
+DROP TYPE "contributor_role";
@@ -0,0 +1,76 @@
+CREATE TYPE "contributor_role" AS ENUM ('author', 'translator', 'editor', 'illustrator', 'narrator');
+
+CREATE TABLE "authors" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    "name" text NOT NULL CHECK (name <> ''),
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE ("user", "name")
+);
+
+CREATE TABLE "book_contributors" (
+    "book" uuid NOT NULL REFERENCES "books" ("id") ON DELETE CASCADE,
+    "author" uuid NOT NULL REFERENCES "authors" ("id") ON DELETE CASCADE,
+    "role" "contributor_role" NOT NULL DEFAULT 'author',
+    "position" INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY ("book", "author", "role")
+);
+
+CREATE INDEX "book_contributors_author_idx" ON "book_contributors" ("author");
+
+-- Split the existing free-text authors like "Author A, Author B & Author C (Translator)" into
+-- contributors the same way as `contributors::parse_contributors`: roles in parentheses are
+-- recognized and suffixes like "Jr." stay with the name before them
+CREATE TEMPORARY TABLE "split_authors" ON COMMIT DROP AS
+WITH "parts" AS (
+    SELECT b."id" AS "book", b."user", n."position", trim(n."part") AS "part",
+        regexp_match(trim(n."part"), '^(.*)\(([^(]*)\)$') AS "parens"
+    FROM "books" b
+    CROSS JOIN LATERAL regexp_split_to_table(b."author", '\s*(,|;|&|\sand\s)\s*')
+        WITH ORDINALITY AS n("part", "position")
+    WHERE b."author" IS NOT NULL
+), "roles" AS (
+    SELECT *,
+        CASE lower(trim("parens"[2]))
+            WHEN 'author' THEN 'author'
+            WHEN 'goodreads author' THEN 'author'
+            WHEN 'translator' THEN 'translator'
+            WHEN 'translated by' THEN 'translator'
+            WHEN 'translation' THEN 'translator'
+            WHEN 'editor' THEN 'editor'
+            WHEN 'edited by' THEN 'editor'
+            WHEN 'illustrator' THEN 'illustrator'
+            WHEN 'illustrated by' THEN 'illustrator'
+            WHEN 'illustrations' THEN 'illustrator'
+            WHEN 'narrator' THEN 'narrator'
+            WHEN 'narrated by' THEN 'narrator'
+            WHEN 'reader' THEN 'narrator'
+        END AS "role"
+    FROM "parts"
+), "names" AS (
+    SELECT "book", "user", "position", "role",
+        CASE WHEN "role" IS NULL THEN "part" ELSE trim("parens"[1]) END AS "name"
+    FROM "roles"
+), "grouped" AS (
+    SELECT *,
+        count(*) FILTER (WHERE lower(rtrim("name", '.')) NOT IN ('jr', 'sr', 'ii', 'iii', 'iv'))
+            OVER (PARTITION BY "book" ORDER BY "position") AS "contributor"
+    FROM "names"
+    WHERE "name" <> ''
+)
+SELECT "book", "user", row_number() OVER (PARTITION BY "book" ORDER BY "contributor") AS "position",
+    string_agg("name", ', ' ORDER BY "position") AS "name",
+    coalesce((array_agg("role" ORDER BY "position" DESC) FILTER (WHERE "role" IS NOT NULL))[1], 'author')
+        AS "role"
+FROM "grouped"
+GROUP BY "book", "user", "contributor";
+
+INSERT INTO "authors" ("id", "user", "name")
+SELECT gen_random_uuid(), "user", "name"
+FROM (SELECT DISTINCT "user", "name" FROM "split_authors") s;
+
+INSERT INTO "book_contributors" ("book", "author", "role", "position")
+SELECT s."book", a."id", s."role"::"contributor_role", min(s."position") - 1
+FROM "split_authors" s
+JOIN "authors" a ON a."user" = s."user" AND a."name" = s."name"
+GROUP BY s."book", a."id", s."role";
@@ -0,0 +1,18 @@
+CREATE TABLE "series" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    "name" text NOT NULL CHECK (name <> ''),
+    "description" text,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE ("user", "name")
+);
+
+SELECT diesel_manage_updated_at('series');
+
+CREATE TABLE "series_books" (
+    "series" uuid NOT NULL REFERENCES "series" ("id") ON DELETE CASCADE,
+    "book" uuid NOT NULL REFERENCES "books" ("id") ON DELETE CASCADE,
+    "position" DOUBLE PRECISION,
+    PRIMARY KEY ("series", "book")
+);
@@ -0,0 +1,15 @@
+CREATE TABLE "tags" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    "name" text NOT NULL CHECK (name <> ''),
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE ("user", "name")
+);
+
+CREATE TABLE "book_tags" (
+    "book" uuid NOT NULL REFERENCES "books" ("id") ON DELETE CASCADE,
+    "tag" uuid NOT NULL REFERENCES "tags" ("id") ON DELETE CASCADE,
+    PRIMARY KEY ("book", "tag")
+);
+
+CREATE INDEX "book_tags_tag_idx" ON "book_tags" ("tag");
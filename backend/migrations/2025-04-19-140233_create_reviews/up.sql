@@ -0,0 +1,19 @@
+CREATE TABLE "reviews" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    "book" uuid NOT NULL REFERENCES "books" ("id") ON DELETE CASCADE,
+    "reading" uuid REFERENCES "readings" ("id") ON DELETE CASCADE,
+    -- rating in half stars, i.e. 1 is half a star and 10 are five stars
+    "rating" SMALLINT CHECK (rating BETWEEN 1 AND 10),
+    -- Markdown, spoilers are marked up as >!spoiler!<
+    "body" text,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CHECK (rating IS NOT NULL OR body IS NOT NULL)
+);
+
+-- one review per reading, plus one for the book as a whole
+CREATE UNIQUE INDEX "reviews_reading_idx" ON "reviews" ("reading") WHERE "reading" IS NOT NULL;
+CREATE UNIQUE INDEX "reviews_book_idx" ON "reviews" ("book") WHERE "reading" IS NULL;
+
+SELECT diesel_manage_updated_at('reviews');
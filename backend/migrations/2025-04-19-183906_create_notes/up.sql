@@ -0,0 +1,14 @@
+CREATE TABLE "notes" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    "book" uuid NOT NULL REFERENCES "books" ("id") ON DELETE CASCADE,
+    "reading" uuid REFERENCES "readings" ("id") ON DELETE CASCADE,
+    "page" INT CHECK (page >= 0),
+    "body" text NOT NULL CHECK (body <> ''),
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX "notes_user_book_idx" ON "notes" ("user", "book");
+
+SELECT diesel_manage_updated_at('notes');
@@ -0,0 +1,2 @@
+DROP TABLE "highlight_tags";
+DROP TABLE "highlights";
@@ -0,0 +1,25 @@
+CREATE TABLE "highlights" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    "book" uuid NOT NULL REFERENCES "books" ("id") ON DELETE CASCADE,
+    "reading" uuid REFERENCES "readings" ("id") ON DELETE SET NULL,
+    "quote" text NOT NULL CHECK (quote <> ''),
+    "page" INT CHECK (page >= 0),
+    -- free-form position for formats without pages, like Kindle locations
+    "location" text,
+    "note" text,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX "highlights_user_book_idx" ON "highlights" ("user", "book");
+
+SELECT diesel_manage_updated_at('highlights');
+
+CREATE TABLE "highlight_tags" (
+    "highlight" uuid NOT NULL REFERENCES "highlights" ("id") ON DELETE CASCADE,
+    "tag" uuid NOT NULL REFERENCES "tags" ("id") ON DELETE CASCADE,
+    PRIMARY KEY ("highlight", "tag")
+);
+
+CREATE INDEX "highlight_tags_tag_idx" ON "highlight_tags" ("tag");
@@ -0,0 +1,2 @@
+ALTER TABLE "notes" DROP COLUMN "source_hash";
+ALTER TABLE "highlights" DROP COLUMN "source_hash";
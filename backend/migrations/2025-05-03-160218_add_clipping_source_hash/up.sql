@@ -0,0 +1,6 @@
+-- identifies highlights and notes imported from an external source, to skip them on re-import
+ALTER TABLE "highlights" ADD COLUMN "source_hash" text;
+ALTER TABLE "notes" ADD COLUMN "source_hash" text;
+
+CREATE UNIQUE INDEX "highlights_source_hash_idx" ON "highlights" ("user", "source_hash");
+CREATE UNIQUE INDEX "notes_source_hash_idx" ON "notes" ("user", "source_hash");
@@ -0,0 +1,3 @@
+DROP TABLE "import_job_errors";
+DROP TABLE "import_jobs";
+DROP TYPE "import_status";
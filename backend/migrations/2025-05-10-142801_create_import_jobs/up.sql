@@ -0,0 +1,37 @@
+CREATE TYPE "import_status" AS ENUM ('queued', 'running', 'completed', 'failed');
+
+CREATE TABLE "import_jobs" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    "source" text NOT NULL,
+    "status" import_status NOT NULL DEFAULT 'queued',
+    "options" JSONB NOT NULL DEFAULT '{}',
+    -- the uploaded file, cleared once the job is done
+    "payload" BYTEA,
+    "total_rows" INT NOT NULL DEFAULT 0,
+    -- rows are processed in batches, each committed along with this counter so that an
+    -- interrupted job resumes after the last committed batch
+    "processed_rows" INT NOT NULL DEFAULT 0,
+    "added" INT NOT NULL DEFAULT 0,
+    "skipped" INT NOT NULL DEFAULT 0,
+    "failed" INT NOT NULL DEFAULT 0,
+    "error" text,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    "started_at" TIMESTAMPTZ,
+    "finished_at" TIMESTAMPTZ,
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX "import_jobs_status_idx" ON "import_jobs" ("status", "created_at");
+
+SELECT diesel_manage_updated_at('import_jobs');
+
+CREATE TABLE "import_job_errors" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "job" uuid NOT NULL REFERENCES "import_jobs" ("id") ON DELETE CASCADE,
+    "row" INT NOT NULL,
+    "message" text NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX "import_job_errors_job_idx" ON "import_job_errors" ("job", "row");
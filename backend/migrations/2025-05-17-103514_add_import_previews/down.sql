@@ -0,0 +1,10 @@
+DELETE FROM "import_jobs" WHERE "status" = 'preview';
+
+ALTER TABLE "import_jobs" DROP COLUMN "merged";
+
+ALTER TYPE "import_status" RENAME TO "import_status_old";
+CREATE TYPE "import_status" AS ENUM ('queued', 'running', 'completed', 'failed');
+ALTER TABLE "import_jobs" ALTER COLUMN "status" DROP DEFAULT;
+ALTER TABLE "import_jobs" ALTER COLUMN "status" TYPE "import_status" USING "status"::text::"import_status";
+ALTER TABLE "import_jobs" ALTER COLUMN "status" SET DEFAULT 'queued';
+DROP TYPE "import_status_old";
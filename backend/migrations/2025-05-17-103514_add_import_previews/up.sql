@@ -0,0 +1,4 @@
+-- a previewed import waits with its uploaded file until the user confirms it
+ALTER TYPE "import_status" ADD VALUE 'preview' BEFORE 'queued';
+
+ALTER TABLE "import_jobs" ADD COLUMN "merged" INT NOT NULL DEFAULT 0;
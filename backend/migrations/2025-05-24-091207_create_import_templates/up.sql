@@ -0,0 +1,12 @@
+CREATE TABLE "import_templates" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    "name" text NOT NULL,
+    -- maps the fields of a book to the columns of a CSV file, see `ColumnMapping`
+    "mapping" JSONB NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE ("user", "name")
+);
+
+SELECT diesel_manage_updated_at('import_templates');
@@ -0,0 +1,2 @@
+ALTER TABLE "readings" DROP COLUMN "due_at";
+ALTER TABLE "readings" DROP COLUMN "target_finish_at";
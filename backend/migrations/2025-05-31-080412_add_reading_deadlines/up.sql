@@ -0,0 +1,3 @@
+-- the date a reader wants to finish a book by, and when a borrowed copy has to be returned
+ALTER TABLE "readings" ADD COLUMN "target_finish_at" DATE;
+ALTER TABLE "readings" ADD COLUMN "due_at" DATE;
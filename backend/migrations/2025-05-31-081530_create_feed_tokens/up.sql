@@ -0,0 +1,11 @@
+-- secret tokens in the URLs of feeds, which calendar apps and readers can't authenticate to with a JWT
+CREATE TABLE "feed_tokens" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    "token" text NOT NULL UNIQUE,
+    "name" text,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+SELECT diesel_manage_updated_at('feed_tokens');
@@ -0,0 +1,13 @@
+-- users who opted in to publishing their reading log as an Atom feed
+CREATE TABLE "public_feeds" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "user" uuid NOT NULL UNIQUE REFERENCES "users" ("id"),
+    "enabled" boolean NOT NULL DEFAULT false,
+    "title" text,
+    -- books added to these shelves are published, finished readings and reviews always are
+    "shelves" uuid[] NOT NULL DEFAULT '{}',
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+SELECT diesel_manage_updated_at('public_feeds');
@@ -0,0 +1,2 @@
+DROP TABLE "koreader_documents";
+DROP TABLE "koreader_accounts";
@@ -0,0 +1,31 @@
+-- accounts KOReader devices log in to, username and password are set in the app
+CREATE TABLE "koreader_accounts" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "user" uuid NOT NULL UNIQUE REFERENCES "users" ("id"),
+    "username" text NOT NULL UNIQUE,
+    -- argon2 hash of the key KOReader sends, which is the md5 hash of the password, accounts
+    -- without one can't log in
+    "key_hash" text,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+SELECT diesel_manage_updated_at('koreader_accounts');
+
+-- progress KOReader synced for a document, documents are identified by a hash of their file
+CREATE TABLE "koreader_documents" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    "document" text NOT NULL,
+    -- the book the document is an edition of, progress is only tracked once it's linked
+    "book" uuid REFERENCES "books" ("id") ON DELETE SET NULL,
+    "progress" text NOT NULL,
+    "percentage" double precision NOT NULL,
+    "device" text,
+    "device_id" text,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE ("user", "document")
+);
+
+SELECT diesel_manage_updated_at('koreader_documents');
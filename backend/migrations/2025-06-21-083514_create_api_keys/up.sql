@@ -0,0 +1,14 @@
+-- long-lived keys scripts and devices authenticate with, only the sha256 hash of a key is stored
+CREATE TABLE "api_keys" (
+    "id" uuid PRIMARY KEY NOT NULL,
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    "name" text,
+    "key_hash" text NOT NULL UNIQUE,
+    -- what the key may be used for, e.g. 'progress:write'
+    "scopes" text[] NOT NULL DEFAULT '{}',
+    "last_used_at" TIMESTAMPTZ,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+SELECT diesel_manage_updated_at('api_keys');
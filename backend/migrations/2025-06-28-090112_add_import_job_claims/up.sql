@@ -0,0 +1,4 @@
+-- the worker running a job keeps its claim alive, jobs are only resumed by another worker once
+-- their heartbeat is stale
+ALTER TABLE "import_jobs" ADD COLUMN "worker" uuid;
+ALTER TABLE "import_jobs" ADD COLUMN "heartbeat_at" TIMESTAMPTZ;
@@ -0,0 +1,4 @@
+ALTER TABLE "import_jobs" DROP COLUMN "undated_reads";
+UPDATE "readings" SET "started_at" = COALESCE("finished_at", "cancelled_at", "created_at"::date) WHERE "started_at" IS NULL;
+ALTER TABLE "readings" ALTER COLUMN "started_at" SET DEFAULT now();
+ALTER TABLE "readings" ALTER COLUMN "started_at" SET NOT NULL;
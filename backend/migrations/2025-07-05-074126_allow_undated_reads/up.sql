@@ -0,0 +1,5 @@
+-- imported reads don't always say when they started, and reads without any date are counted with
+-- their import instead of being recorded
+ALTER TABLE "readings" ALTER COLUMN "started_at" DROP NOT NULL;
+ALTER TABLE "readings" ALTER COLUMN "started_at" DROP DEFAULT;
+ALTER TABLE "import_jobs" ADD COLUMN "undated_reads" integer NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- the tokens can't be recovered from their hashes, so they're removed
+DELETE FROM "feed_tokens";
+ALTER TABLE "feed_tokens" RENAME CONSTRAINT "feed_tokens_key_hash_key" TO "feed_tokens_token_key";
+ALTER TABLE "feed_tokens" RENAME COLUMN "key_hash" TO "token";
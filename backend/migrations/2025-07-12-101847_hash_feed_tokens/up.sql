@@ -0,0 +1,4 @@
+-- only the sha256 hash of feed tokens is stored, like for API keys, the tokens keep working
+ALTER TABLE "feed_tokens" RENAME COLUMN "token" TO "key_hash";
+ALTER TABLE "feed_tokens" RENAME CONSTRAINT "feed_tokens_token_key" TO "feed_tokens_key_hash_key";
+UPDATE "feed_tokens" SET "key_hash" = encode(sha256(convert_to("key_hash", 'UTF8')), 'hex');
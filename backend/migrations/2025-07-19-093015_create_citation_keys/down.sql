@@ -0,0 +1 @@
+DROP TABLE "citation_keys";
@@ -0,0 +1,10 @@
+-- citation keys are kept once assigned, so that they don't change as other books are added or removed
+CREATE TABLE "citation_keys" (
+    "user" uuid NOT NULL REFERENCES "users" ("id"),
+    -- the ISBN or title and author the key was assigned to, see `books::work_key`
+    "work" text NOT NULL,
+    "key" text NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY ("user", "work"),
+    UNIQUE ("user", "key")
+);
@@ -0,0 +1,232 @@
+use crate::auth::AuthUser;
+use crate::db::connect;
+use crate::feeds::generate_token;
+use crate::models::ApiKey;
+use crate::schema::api_keys::dsl::api_keys;
+use crate::{schema, ErrorResponse};
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::routing::post;
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Scopes API keys can be granted.
+pub const SCOPES: &[&str] = &[PROGRESS_WRITE];
+
+/// Allows reporting reading progress, e.g. through `/api/ingest/progress`.
+pub const PROGRESS_WRITE: &str = "progress:write";
+
+/// Prefix of API keys, telling them apart from JWTs and feed tokens.
+const KEY_PREFIX: &str = "bk_";
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/api-keys", post(list_api_keys))
+        .route("/api/api-keys/create", post(create_api_key))
+        .route("/api/api-keys/remove", post(remove_api_key))
+}
+
+/// Hashes an API key or feed token for storage, they're random enough for a plain sha256 hash to
+/// suffice.
+pub(crate) fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// A user authenticated with an API key, passed as `Authorization: Bearer <key>` or in the
+/// `X-Api-Key` header.
+pub struct ApiKeyUser {
+    pub user: Uuid,
+    pub scopes: Vec<String>,
+}
+
+impl ApiKeyUser {
+    /// Checks the key was granted a scope, responding with an error if it wasn't.
+    pub fn require(&self, scope: &str) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+        if self.scopes.iter().any(|s| s == scope) {
+            return Ok(());
+        }
+        Err((StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: format!("The API key lacks the {} scope.", scope) }))))
+    }
+}
+
+impl<S> FromRequestParts<S> for ApiKeyUser
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, Json<serde_json::Value>);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let key = parts
+            .headers
+            .get("x-api-key")
+            .or_else(|| parts.headers.get(axum::http::header::AUTHORIZATION))
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.strip_prefix("Bearer ").unwrap_or(v))
+            .filter(|v| v.starts_with(KEY_PREFIX))
+            .ok_or_else(|| (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Missing API key" }))))?;
+
+        let connection = &mut connect();
+
+        let now = chrono::Utc::now().naive_utc();
+        let api_key: ApiKey = diesel::update(api_keys.filter(schema::api_keys::dsl::key_hash.eq(hash_key(key))))
+            .set(schema::api_keys::dsl::last_used_at.eq(now))
+            .get_result(connection)
+            .map_err(|_| (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Invalid API key" }))))?;
+
+        Ok(ApiKeyUser {
+            user: api_key.user,
+            scopes: api_key.scopes.into_iter().flatten().collect(),
+        })
+    }
+}
+
+/// Lists the API keys of the user, the keys themselves are only shown when they're created.
+pub(crate) async fn list_api_keys(auth: AuthUser) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let results = match api_keys
+        .filter(schema::api_keys::dsl::user.eq(auth.0))
+        .order(schema::api_keys::dsl::created_at.asc())
+        .load::<ApiKey>(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading API keys: {}", e) }))),
+    };
+
+    let keys: Vec<_> = results
+        .into_iter()
+        .map(|key| {
+            json!({
+                "id": key.id.to_string(),
+                "name": key.name,
+                "scopes": key.scopes.into_iter().flatten().collect::<Vec<_>>(),
+                "last_used_at": key.last_used_at.map(|t| t.to_string()),
+                "created_at": key.created_at.to_string(),
+            })
+        })
+        .collect();
+
+    (StatusCode::OK, Json(json!({ "api_keys": keys })))
+}
+
+/// Request type for creating an API key.
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: Option<String>,
+    pub scopes: Vec<String>,
+}
+
+/// Creates a long-lived API key for scripts and devices, which unlike a login doesn't expire.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `name`: Optional, a name telling the keys apart, e.g. "E-reader script".
+/// - `scopes`: What the key may be used for, currently only `progress:write`.
+///
+/// The key is only part of this response, as only its hash is stored.
+pub(crate) async fn create_api_key(
+    auth: AuthUser,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    if payload.scopes.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "At least one scope is required.".to_string() })));
+    }
+    if let Some(scope) = payload.scopes.iter().find(|scope| !SCOPES.contains(&scope.as_str())) {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: format!("Unknown scope {}.", scope) })));
+    }
+    let mut scopes = payload.scopes;
+    scopes.sort();
+    scopes.dedup();
+
+    let key = format!("{}{}", KEY_PREFIX, generate_token());
+    let now = chrono::Utc::now().naive_utc();
+    let api_key = ApiKey {
+        id: Uuid::new_v4(),
+        user: auth.0,
+        name: payload.name.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()),
+        key_hash: hash_key(&key),
+        scopes: scopes.into_iter().map(Some).collect(),
+        last_used_at: None,
+        created_at: now,
+        updated_at: now,
+    };
+
+    match diesel::insert_into(api_keys).values(&api_key).execute(connection) {
+        Ok(_) => (
+            StatusCode::CREATED,
+            Json(json!({ "message": "API key created successfully.", "id": api_key.id.to_string(), "key": key })),
+        ),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while creating the API key: {}", e) }))),
+    }
+}
+
+/// Request type for removing an API key.
+#[derive(Debug, Deserialize)]
+pub struct RemoveApiKeyRequest {
+    pub key_id: String,
+}
+
+/// Removes an API key, it can no longer be used.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `key_id`: The UUID of the key to remove.
+pub(crate) async fn remove_api_key(
+    auth: AuthUser,
+    Json(payload): Json<RemoveApiKeyRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let key_id = match Uuid::parse_str(&payload.key_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid API key ID.".to_string() }))),
+    };
+
+    match diesel::delete(
+        api_keys
+            .filter(schema::api_keys::dsl::id.eq(key_id))
+            .filter(schema::api_keys::dsl::user.eq(auth.0)),
+    )
+    .execute(connection)
+    {
+        Ok(0) => (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "API key not found.".to_string() }))),
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "API key removed successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while removing the API key: {}", e) }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_create_api_key_requires_auth() {
+        let app = Router::new().route("/api/api-keys/create", post(create_api_key));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/api-keys/create").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_remove_api_key_requires_auth() {
+        let app = Router::new().route("/api/api-keys/remove", post(remove_api_key));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/api-keys/remove").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_require_scope() {
+        let user = ApiKeyUser { user: Uuid::nil(), scopes: vec![PROGRESS_WRITE.to_string()] };
+        assert!(user.require(PROGRESS_WRITE).is_ok());
+        assert_eq!(user.require("books:write").unwrap_err().0, StatusCode::FORBIDDEN);
+    }
+}
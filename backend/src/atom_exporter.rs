@@ -0,0 +1,118 @@
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+/// An entry of an Atom feed.
+pub struct AtomEntry {
+    /// Id of the record the entry is about, entries keep their id when they're updated.
+    pub id: Uuid,
+    pub title: String,
+    pub updated: NaiveDateTime,
+    pub published: Option<NaiveDateTime>,
+    /// HTML content of the entry.
+    pub content: Option<String>,
+    /// Link to the page the entry is about, Atom requires one for entries without content.
+    pub link: Option<String>,
+}
+
+/// Escapes text for XML content and attribute values.
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters other than whitespace aren't allowed in XML
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats a UTC timestamp as RFC 3339, like Atom expects.
+pub fn format_timestamp(timestamp: NaiveDateTime) -> String {
+    timestamp.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// Writes an Atom feed, entries are written in the given order.
+///
+/// The feed is updated when its latest entry was, or at `updated` if it has no entries.
+pub fn write_atom(
+    feed_id: Uuid,
+    title: &str,
+    author: &str,
+    self_link: &str,
+    updated: NaiveDateTime,
+    entries: &[AtomEntry],
+) -> String {
+    let updated = entries.iter().map(|entry| entry.updated).max().unwrap_or(updated);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <id>urn:uuid:{}</id>\n", feed_id));
+    xml.push_str(&format!("  <title>{}</title>\n", escape_xml(title)));
+    xml.push_str(&format!("  <updated>{}</updated>\n", format_timestamp(updated)));
+    xml.push_str(&format!("  <author><name>{}</name></author>\n", escape_xml(author)));
+    xml.push_str(&format!("  <link rel=\"self\" type=\"application/atom+xml\" href=\"{}\"/>\n", escape_xml(self_link)));
+    for entry in entries {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>urn:uuid:{}</id>\n", entry.id));
+        xml.push_str(&format!("    <title>{}</title>\n", escape_xml(&entry.title)));
+        xml.push_str(&format!("    <updated>{}</updated>\n", format_timestamp(entry.updated)));
+        if let Some(published) = entry.published {
+            xml.push_str(&format!("    <published>{}</published>\n", format_timestamp(published)));
+        }
+        if let Some(link) = &entry.link {
+            xml.push_str(&format!("    <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n", escape_xml(link)));
+        }
+        if let Some(content) = &entry.content {
+            xml.push_str(&format!("    <content type=\"html\">{}</content>\n", escape_xml(content)));
+        }
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_write_atom() {
+        let timestamp = |d, h| NaiveDate::from_ymd_opt(2025, 6, d).unwrap().and_hms_opt(h, 0, 0).unwrap();
+        let entries = vec![
+            AtomEntry {
+                id: Uuid::nil(),
+                title: "Finished Good Omens & more".to_string(),
+                updated: timestamp(7, 10),
+                published: Some(timestamp(6, 0)),
+                content: Some("<p>Loved it</p>".to_string()),
+                link: None,
+            },
+            AtomEntry {
+                id: Uuid::max(),
+                title: "Added Mort to favorites".to_string(),
+                updated: timestamp(5, 8),
+                published: None,
+                content: None,
+                link: Some("/book/1?a&b".to_string()),
+            },
+        ];
+        let xml = write_atom(Uuid::nil(), "Alice's reading", "alice", "/api/public/alice/feed.atom", timestamp(1, 0), &entries);
+        assert!(xml.contains("<title>Alice&apos;s reading</title>"));
+        assert!(xml.contains("<updated>2025-06-07T10:00:00Z</updated>\n  <author>"));
+        assert!(xml.contains(
+            "<entry>\n    <id>urn:uuid:00000000-0000-0000-0000-000000000000</id>\n    <title>Finished Good Omens &amp; more</title>\n    <updated>2025-06-07T10:00:00Z</updated>\n    <published>2025-06-06T00:00:00Z</published>\n    <content type=\"html\">&lt;p&gt;Loved it&lt;/p&gt;</content>\n  </entry>"
+        ));
+        assert!(xml.contains("<updated>2025-06-05T08:00:00Z</updated>\n    <link rel=\"alternate\" type=\"text/html\" href=\"/book/1?a&amp;b\"/>\n  </entry>"));
+        assert_eq!(xml.matches("<entry>").count(), 2);
+
+        let empty = write_atom(Uuid::nil(), "Reading", "alice", "/feed.atom", timestamp(1, 0), &[]);
+        assert!(empty.contains("<updated>2025-06-01T00:00:00Z</updated>"));
+    }
+}
@@ -1,3 +1,7 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
 use chrono::Duration;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
@@ -55,6 +59,27 @@ pub fn validate_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error
     Ok(token_data.claims)
 }
 
+/// Hashes a password with Argon2 on the blocking thread pool, as hashing is slow on purpose and
+/// would hold up other requests on the async workers.
+pub async fn hash_password(password: String) -> Option<String> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default().hash_password(password.as_bytes(), &salt).ok().map(|hash| hash.to_string())
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Checks a password against its Argon2 hash on the blocking thread pool.
+pub async fn verify_password(password: String, hash: String) -> bool {
+    tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&hash).is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+    })
+    .await
+    .unwrap_or(false)
+}
+
 pub struct AuthUser(pub Uuid);
 
 impl<S> FromRequestParts<S> for AuthUser
@@ -127,4 +152,12 @@ mod tests {
         let result = validate_token(&tampered);
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_hash_and_verify_password() {
+        let hash = hash_password("secret".to_string()).await.expect("should hash password");
+        assert!(verify_password("secret".to_string(), hash.clone()).await);
+        assert!(!verify_password("wrong".to_string(), hash).await);
+        assert!(!verify_password("secret".to_string(), "not a hash".to_string()).await);
+    }
 }
@@ -0,0 +1,916 @@
+use crate::auth::AuthUser;
+use crate::covers::{cover_key, store_cover, CoverSize, MAX_COVER_BYTES};
+use crate::db::connect;
+use crate::exports::download;
+use crate::models::{
+    Author, Book, BookContributor, BookCover, BookTag, Highlight, HighlightTag, ImportTemplate, Note, Reading,
+    ReadingEntry, Review, Series, SeriesBook, Shelf, Tag,
+};
+use crate::storage::{cover_storage, Storage};
+use crate::{schema, ErrorResponse};
+use axum::extract::{DefaultBodyLimit, Multipart};
+use axum::response::Response;
+use axum::routing::post;
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use chrono::NaiveDateTime;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read, Write};
+use tracing::error;
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::result::ZipError;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Identifies backup archives, so that other JSON files aren't mistaken for one.
+const BACKUP_FORMAT: &str = "books-backup";
+
+/// Version of the archive structure, raised whenever it changes in a way older versions of the
+/// restore can't read.
+const BACKUP_VERSION: u32 = 1;
+
+/// Name of the JSON document within zip archives.
+const BACKUP_FILE: &str = "backup.json";
+
+const MAX_BACKUP_BYTES: usize = 500 * 1024 * 1024; // 500 MB
+
+/// Largest uncompressed size of the JSON document within a zip archive.
+const MAX_DOCUMENT_BYTES: u64 = 256 * 1024 * 1024; // 256 MB
+
+/// Largest uncompressed size of everything read from a zip archive, so that a small archive can't
+/// unpack into more than the server can hold.
+const MAX_ARCHIVE_BYTES: u64 = 1024 * 1024 * 1024; // 1 GB
+
+/// Rows inserted at once, keeping below the limit of bind parameters per statement.
+const INSERT_CHUNK: usize = 1000;
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/backups/export", post(export_backup))
+        .route("/api/backups/export-zip", post(export_backup_zip))
+        .route(
+            "/api/backups/restore",
+            post(restore_backup).layer(DefaultBodyLimit::max(MAX_BACKUP_BYTES)),
+        )
+}
+
+/// Everything a user owns, with the ids it had on the server it was exported from.
+///
+/// All lists default to empty, so that archives of earlier versions without some of them can be
+/// restored.
+#[derive(Serialize, Deserialize)]
+pub struct Backup {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: NaiveDateTime,
+    /// Name of the user the archive was exported for.
+    pub user: String,
+    #[serde(default)]
+    pub shelves: Vec<Shelf>,
+    #[serde(default)]
+    pub books: Vec<Book>,
+    #[serde(default)]
+    pub authors: Vec<Author>,
+    #[serde(default)]
+    pub book_contributors: Vec<BookContributor>,
+    #[serde(default)]
+    pub series: Vec<Series>,
+    #[serde(default)]
+    pub series_books: Vec<SeriesBook>,
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    #[serde(default)]
+    pub book_tags: Vec<BookTag>,
+    #[serde(default)]
+    pub readings: Vec<Reading>,
+    #[serde(default)]
+    pub reading_entries: Vec<ReadingEntry>,
+    #[serde(default)]
+    pub reviews: Vec<Review>,
+    #[serde(default)]
+    pub notes: Vec<Note>,
+    #[serde(default)]
+    pub highlights: Vec<Highlight>,
+    #[serde(default)]
+    pub highlight_tags: Vec<HighlightTag>,
+    #[serde(default)]
+    pub import_templates: Vec<ImportTemplate>,
+    /// Covers of the books, only zip archives contain them.
+    #[serde(default)]
+    pub covers: Vec<BackupCover>,
+}
+
+/// A cover within a zip archive.
+#[derive(Serialize, Deserialize)]
+pub struct BackupCover {
+    pub book: Uuid,
+    pub content_type: String,
+    /// Path of the image within the archive.
+    pub file: String,
+}
+
+/// Loads everything a user owns into a backup.
+pub fn load_backup(connection: &mut PgConnection, user_id: Uuid) -> QueryResult<Backup> {
+    let user: String = schema::users::table
+        .find(user_id)
+        .select(schema::users::dsl::name)
+        .first(connection)?;
+
+    let books: Vec<Book> = schema::books::table
+        .filter(schema::books::dsl::user.eq(user_id))
+        .order((schema::books::dsl::added_at.asc(), schema::books::dsl::id.asc()))
+        .load(connection)?;
+    let book_ids: Vec<Uuid> = books.iter().map(|book| book.id).collect();
+
+    let highlights: Vec<Highlight> = schema::highlights::table
+        .filter(schema::highlights::dsl::user.eq(user_id))
+        .order((schema::highlights::dsl::created_at.asc(), schema::highlights::dsl::id.asc()))
+        .load(connection)?;
+    let highlight_ids: Vec<Uuid> = highlights.iter().map(|highlight| highlight.id).collect();
+
+    Ok(Backup {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        exported_at: chrono::Utc::now().naive_utc(),
+        user,
+        shelves: schema::shelves::table
+            .filter(schema::shelves::dsl::user.eq(user_id))
+            .order((schema::shelves::dsl::created_at.asc(), schema::shelves::dsl::id.asc()))
+            .load(connection)?,
+        authors: schema::authors::table
+            .filter(schema::authors::dsl::user.eq(user_id))
+            .order(schema::authors::dsl::name.asc())
+            .load(connection)?,
+        book_contributors: schema::book_contributors::table
+            .filter(schema::book_contributors::dsl::book.eq_any(&book_ids))
+            .order((schema::book_contributors::dsl::book.asc(), schema::book_contributors::dsl::position.asc()))
+            .load(connection)?,
+        series: schema::series::table
+            .filter(schema::series::dsl::user.eq(user_id))
+            .order(schema::series::dsl::name.asc())
+            .load(connection)?,
+        series_books: schema::series_books::table
+            .filter(schema::series_books::dsl::book.eq_any(&book_ids))
+            .order((schema::series_books::dsl::series.asc(), schema::series_books::dsl::book.asc()))
+            .load(connection)?,
+        tags: schema::tags::table
+            .filter(schema::tags::dsl::user.eq(user_id))
+            .order(schema::tags::dsl::name.asc())
+            .load(connection)?,
+        book_tags: schema::book_tags::table
+            .filter(schema::book_tags::dsl::book.eq_any(&book_ids))
+            .order((schema::book_tags::dsl::book.asc(), schema::book_tags::dsl::tag.asc()))
+            .load(connection)?,
+        readings: schema::readings::table
+            .filter(schema::readings::dsl::user.eq(user_id))
+            .order((schema::readings::dsl::started_at.asc(), schema::readings::dsl::id.asc()))
+            .load(connection)?,
+        reading_entries: schema::reading_entries::table
+            .filter(schema::reading_entries::dsl::user.eq(user_id))
+            .order((schema::reading_entries::dsl::read_at.asc(), schema::reading_entries::dsl::id.asc()))
+            .load(connection)?,
+        reviews: schema::reviews::table
+            .filter(schema::reviews::dsl::user.eq(user_id))
+            .order((schema::reviews::dsl::created_at.asc(), schema::reviews::dsl::id.asc()))
+            .load(connection)?,
+        notes: schema::notes::table
+            .filter(schema::notes::dsl::user.eq(user_id))
+            .order((schema::notes::dsl::created_at.asc(), schema::notes::dsl::id.asc()))
+            .load(connection)?,
+        highlight_tags: schema::highlight_tags::table
+            .filter(schema::highlight_tags::dsl::highlight.eq_any(&highlight_ids))
+            .order((schema::highlight_tags::dsl::highlight.asc(), schema::highlight_tags::dsl::tag.asc()))
+            .load(connection)?,
+        import_templates: schema::import_templates::table
+            .filter(schema::import_templates::dsl::user.eq(user_id))
+            .order(schema::import_templates::dsl::name.asc())
+            .load(connection)?,
+        covers: Vec::new(),
+        books,
+        highlights,
+    })
+}
+
+/// Why a backup couldn't be restored.
+#[derive(Debug)]
+pub enum RestoreError {
+    /// The archive is broken or refers to records it doesn't contain.
+    Invalid(String),
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for RestoreError {
+    fn from(e: diesel::result::Error) -> Self {
+        RestoreError::Database(e)
+    }
+}
+
+/// Maps the ids of an archive onto the ids of the restored records.
+#[derive(Default)]
+struct IdMap {
+    ids: HashMap<Uuid, Uuid>,
+}
+
+impl IdMap {
+    fn insert(&mut self, old: Uuid, new: Uuid) -> Uuid {
+        self.ids.insert(old, new);
+        new
+    }
+
+    /// Assigns a new id to a record of the archive.
+    fn assign(&mut self, old: Uuid) -> Uuid {
+        self.insert(old, Uuid::new_v4())
+    }
+
+    /// Returns the new id of a record the archive refers to.
+    fn get(&self, kind: &str, old: Uuid) -> Result<Uuid, RestoreError> {
+        self.ids
+            .get(&old)
+            .copied()
+            .ok_or_else(|| RestoreError::Invalid(format!("The backup refers to a missing {} {}.", kind, old)))
+    }
+
+    fn get_optional(&self, kind: &str, old: Option<Uuid>) -> Result<Option<Uuid>, RestoreError> {
+        old.map(|old| self.get(kind, old)).transpose()
+    }
+}
+
+/// Inserts rows in chunks, optionally ignoring rows which already exist.
+macro_rules! insert_chunked {
+    ($connection:expr, $table:expr, $rows:expr) => {
+        for chunk in $rows.chunks(INSERT_CHUNK) {
+            diesel::insert_into($table).values(chunk).execute($connection)?;
+        }
+    };
+    ($connection:expr, $table:expr, $rows:expr, on_conflict_do_nothing) => {
+        for chunk in $rows.chunks(INSERT_CHUNK) {
+            diesel::insert_into($table).values(chunk).on_conflict_do_nothing().execute($connection)?;
+        }
+    };
+}
+
+/// Loads the ids of the records of a user by name, restored records with these names are merged
+/// into them.
+fn ids_by_name(rows: Vec<(Uuid, String)>) -> HashMap<String, Uuid> {
+    rows.into_iter().map(|(id, name)| (name, id)).collect()
+}
+
+/// Counts of the records restored from a backup.
+#[derive(Debug, Default, Serialize)]
+pub struct RestoreSummary {
+    pub shelves: usize,
+    pub books: usize,
+    /// Books the user already had, which are kept along with their readings, reviews and notes.
+    pub skipped_books: usize,
+    pub readings: usize,
+    pub reviews: usize,
+    pub notes: usize,
+    pub highlights: usize,
+    pub covers: usize,
+}
+
+/// Recreates the records of a backup for a user, which has to be done within a transaction.
+///
+/// Every record gets a new id, so that an archive can be restored on the server it came from.
+/// Shelves, authors, series, tags and import templates the user already has a record of the same
+/// name of are merged into it. Books already on their shelf are skipped along with their readings,
+/// reviews, notes and highlights, while their contributors, series and tags are merged. Everything
+/// else is added. Returns the new ids of the added books.
+pub fn restore(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    backup: Backup,
+    summary: &mut RestoreSummary,
+) -> Result<IdMapping, RestoreError> {
+    let mut shelves = IdMap::default();
+    let existing = ids_by_name(
+        schema::shelves::table
+            .filter(schema::shelves::dsl::user.eq(user_id))
+            .order(schema::shelves::dsl::created_at.asc())
+            .select((schema::shelves::dsl::id, schema::shelves::dsl::name))
+            .load(connection)?,
+    );
+    let mut new_shelves = Vec::new();
+    for shelf in backup.shelves {
+        match existing.get(&shelf.name) {
+            Some(&id) => {
+                shelves.insert(shelf.id, id);
+            }
+            None => {
+                let id = shelves.assign(shelf.id);
+                new_shelves.push(Shelf { id, user: user_id, ..shelf });
+            }
+        }
+    }
+    summary.shelves = new_shelves.len();
+    insert_chunked!(connection, schema::shelves::table, new_shelves);
+
+    // Books the user already has on the same shelf are kept as they are, like imports do, so that
+    // restoring into an account twice doesn't duplicate the library
+    let existing: HashMap<(Uuid, String), Uuid> = schema::books::table
+        .filter(schema::books::dsl::user.eq(user_id))
+        .load::<Book>(connection)?
+        .into_iter()
+        .filter_map(|book| book_key(&book).map(|key| (key, book.id)))
+        .collect();
+    let mut books = IdMap::default();
+    let mut skipped_books = HashSet::new();
+    let mut new_books = Vec::new();
+    for book in backup.books {
+        let book = Book { user: user_id, shelf: shelves.get("shelf", book.shelf)?, ..book };
+        match book_key(&book).and_then(|key| existing.get(&key)) {
+            Some(&id) => {
+                books.insert(book.id, id);
+                skipped_books.insert(book.id);
+            }
+            None => new_books.push(Book { id: books.assign(book.id), ..book }),
+        }
+    }
+    summary.books = new_books.len();
+    summary.skipped_books = skipped_books.len();
+    insert_chunked!(connection, schema::books::table, new_books);
+
+    let mut authors = IdMap::default();
+    let existing = ids_by_name(
+        schema::authors::table
+            .filter(schema::authors::dsl::user.eq(user_id))
+            .select((schema::authors::dsl::id, schema::authors::dsl::name))
+            .load(connection)?,
+    );
+    let mut new_authors = Vec::new();
+    for author in backup.authors {
+        match existing.get(&author.name) {
+            Some(&id) => {
+                authors.insert(author.id, id);
+            }
+            None => new_authors.push(Author { id: authors.assign(author.id), user: user_id, ..author }),
+        }
+    }
+    insert_chunked!(connection, schema::authors::table, new_authors);
+
+    let contributors = backup
+        .book_contributors
+        .into_iter()
+        .map(|contributor| {
+            Ok(BookContributor {
+                book: books.get("book", contributor.book)?,
+                author: authors.get("author", contributor.author)?,
+                ..contributor
+            })
+        })
+        .collect::<Result<Vec<_>, RestoreError>>()?;
+    insert_chunked!(connection, schema::book_contributors::table, contributors, on_conflict_do_nothing);
+
+    let mut series = IdMap::default();
+    let existing = ids_by_name(
+        schema::series::table
+            .filter(schema::series::dsl::user.eq(user_id))
+            .select((schema::series::dsl::id, schema::series::dsl::name))
+            .load(connection)?,
+    );
+    let mut new_series = Vec::new();
+    for entry in backup.series {
+        match existing.get(&entry.name) {
+            Some(&id) => {
+                series.insert(entry.id, id);
+            }
+            None => new_series.push(Series { id: series.assign(entry.id), user: user_id, ..entry }),
+        }
+    }
+    insert_chunked!(connection, schema::series::table, new_series);
+
+    let series_books = backup
+        .series_books
+        .into_iter()
+        .map(|entry| {
+            Ok(SeriesBook {
+                series: series.get("series", entry.series)?,
+                book: books.get("book", entry.book)?,
+                ..entry
+            })
+        })
+        .collect::<Result<Vec<_>, RestoreError>>()?;
+    insert_chunked!(connection, schema::series_books::table, series_books, on_conflict_do_nothing);
+
+    let mut tags = IdMap::default();
+    let existing = ids_by_name(
+        schema::tags::table
+            .filter(schema::tags::dsl::user.eq(user_id))
+            .select((schema::tags::dsl::id, schema::tags::dsl::name))
+            .load(connection)?,
+    );
+    let mut new_tags = Vec::new();
+    for tag in backup.tags {
+        match existing.get(&tag.name) {
+            Some(&id) => {
+                tags.insert(tag.id, id);
+            }
+            None => new_tags.push(Tag { id: tags.assign(tag.id), user: user_id, ..tag }),
+        }
+    }
+    insert_chunked!(connection, schema::tags::table, new_tags);
+
+    let book_tags = backup
+        .book_tags
+        .into_iter()
+        .map(|link| Ok(BookTag { book: books.get("book", link.book)?, tag: tags.get("tag", link.tag)? }))
+        .collect::<Result<Vec<_>, RestoreError>>()?;
+    insert_chunked!(connection, schema::book_tags::table, book_tags, on_conflict_do_nothing);
+
+    let mut readings = IdMap::default();
+    let new_readings = backup
+        .readings
+        .into_iter()
+        .filter(|reading| !skipped_books.contains(&reading.book))
+        .map(|reading| {
+            Ok(Reading {
+                id: readings.assign(reading.id),
+                book: books.get("book", reading.book)?,
+                user: user_id,
+                ..reading
+            })
+        })
+        .collect::<Result<Vec<_>, RestoreError>>()?;
+    summary.readings = new_readings.len();
+    insert_chunked!(connection, schema::readings::table, new_readings);
+
+    let entries = backup
+        .reading_entries
+        .into_iter()
+        .filter(|entry| !skipped_books.contains(&entry.book))
+        .map(|entry| {
+            Ok(ReadingEntry {
+                id: Uuid::new_v4(),
+                reading: readings.get("reading", entry.reading)?,
+                book: books.get("book", entry.book)?,
+                user: user_id,
+                ..entry
+            })
+        })
+        .collect::<Result<Vec<_>, RestoreError>>()?;
+    insert_chunked!(connection, schema::reading_entries::table, entries);
+
+    let reviews = backup
+        .reviews
+        .into_iter()
+        .filter(|review| !skipped_books.contains(&review.book))
+        .map(|review| {
+            Ok(Review {
+                id: Uuid::new_v4(),
+                user: user_id,
+                book: books.get("book", review.book)?,
+                reading: readings.get_optional("reading", review.reading)?,
+                ..review
+            })
+        })
+        .collect::<Result<Vec<_>, RestoreError>>()?;
+    summary.reviews = reviews.len();
+    insert_chunked!(connection, schema::reviews::table, reviews);
+
+    // Clippings are deduplicated by their hash, which the user may already have from an import
+    let notes_hashes: HashSet<String> = schema::notes::table
+        .filter(schema::notes::dsl::user.eq(user_id))
+        .filter(schema::notes::dsl::source_hash.is_not_null())
+        .select(schema::notes::dsl::source_hash.assume_not_null())
+        .load::<String>(connection)?
+        .into_iter()
+        .collect();
+    let notes = backup
+        .notes
+        .into_iter()
+        .filter(|note| !skipped_books.contains(&note.book))
+        .map(|note| {
+            Ok(Note {
+                id: Uuid::new_v4(),
+                user: user_id,
+                book: books.get("book", note.book)?,
+                reading: readings.get_optional("reading", note.reading)?,
+                source_hash: note.source_hash.filter(|hash| !notes_hashes.contains(hash)),
+                ..note
+            })
+        })
+        .collect::<Result<Vec<_>, RestoreError>>()?;
+    summary.notes = notes.len();
+    insert_chunked!(connection, schema::notes::table, notes);
+
+    let highlight_hashes: HashSet<String> = schema::highlights::table
+        .filter(schema::highlights::dsl::user.eq(user_id))
+        .filter(schema::highlights::dsl::source_hash.is_not_null())
+        .select(schema::highlights::dsl::source_hash.assume_not_null())
+        .load::<String>(connection)?
+        .into_iter()
+        .collect();
+    let mut highlights = IdMap::default();
+    let skipped_highlights: HashSet<Uuid> = backup
+        .highlights
+        .iter()
+        .filter(|highlight| skipped_books.contains(&highlight.book))
+        .map(|highlight| highlight.id)
+        .collect();
+    let new_highlights = backup
+        .highlights
+        .into_iter()
+        .filter(|highlight| !skipped_highlights.contains(&highlight.id))
+        .map(|highlight| {
+            Ok(Highlight {
+                id: highlights.assign(highlight.id),
+                user: user_id,
+                book: books.get("book", highlight.book)?,
+                reading: readings.get_optional("reading", highlight.reading)?,
+                source_hash: highlight.source_hash.filter(|hash| !highlight_hashes.contains(hash)),
+                ..highlight
+            })
+        })
+        .collect::<Result<Vec<_>, RestoreError>>()?;
+    summary.highlights = new_highlights.len();
+    insert_chunked!(connection, schema::highlights::table, new_highlights);
+
+    let highlight_tags = backup
+        .highlight_tags
+        .into_iter()
+        .filter(|link| !skipped_highlights.contains(&link.highlight))
+        .map(|link| {
+            Ok(HighlightTag {
+                highlight: highlights.get("highlight", link.highlight)?,
+                tag: tags.get("tag", link.tag)?,
+            })
+        })
+        .collect::<Result<Vec<_>, RestoreError>>()?;
+    insert_chunked!(connection, schema::highlight_tags::table, highlight_tags, on_conflict_do_nothing);
+
+    let now = chrono::Utc::now().naive_utc();
+    let templates: Vec<ImportTemplate> = backup
+        .import_templates
+        .into_iter()
+        .map(|template| ImportTemplate { id: Uuid::new_v4(), user: user_id, updated_at: now, ..template })
+        .collect();
+    for template in &templates {
+        diesel::insert_into(schema::import_templates::table)
+            .values(template)
+            .on_conflict((schema::import_templates::dsl::user, schema::import_templates::dsl::name))
+            .do_nothing()
+            .execute(connection)?;
+    }
+
+    // Covers are only restored for added books, existing ones keep theirs
+    Ok(books.ids.into_iter().filter(|(old, _)| !skipped_books.contains(old)).collect())
+}
+
+/// Identifies a book on its shelf by its ISBN-13, or by title and author without one, the same way
+/// imports check for books which already exist.
+fn book_key(book: &Book) -> Option<(Uuid, String)> {
+    match (&book.isbn13, &book.title, &book.author) {
+        (Some(isbn), _, _) if !isbn.is_empty() => Some((book.shelf, isbn.clone())),
+        (_, Some(title), Some(author)) => Some((book.shelf, format!("{}|{}", title, author))),
+        _ => None,
+    }
+}
+
+/// Maps the ids of the archive onto the ids of the restored records.
+pub type IdMapping = HashMap<Uuid, Uuid>;
+
+/// Reads an entry of a zip archive, failing once it unpacks into more than `limit` bytes, whatever
+/// size the archive claims it has.
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str, limit: u64) -> Result<Option<Vec<u8>>, String> {
+    let file = match archive.by_name(name) {
+        Ok(file) if file.is_file() => file,
+        Ok(_) | Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(format!("Failed to read the archive: {}", e)),
+    };
+    let mut content = Vec::new();
+    file.take(limit + 1)
+        .read_to_end(&mut content)
+        .map_err(|e| format!("Failed to read the archive: {}", e))?;
+    if content.len() as u64 > limit {
+        return Err(format!("{} is too large to restore.", name));
+    }
+    Ok(Some(content))
+}
+
+/// Reads a backup, either the JSON document itself or a zip archive containing it along with the
+/// covers, which are returned by their path within the archive.
+///
+/// Only the document and the covers it refers to are read from archives, each of them up to a
+/// limit, as is their total size.
+pub fn read_archive(data: &[u8]) -> Result<(Backup, HashMap<String, Vec<u8>>), String> {
+    let mut files = HashMap::new();
+    let mut archive = None;
+    let document = if data.starts_with(b"PK\x03\x04") {
+        let mut zip = ZipArchive::new(Cursor::new(data)).map_err(|e| format!("Failed to read the archive: {}", e))?;
+        let document = read_entry(&mut zip, BACKUP_FILE, MAX_DOCUMENT_BYTES)?
+            .ok_or_else(|| format!("The archive doesn't contain a {}.", BACKUP_FILE))?;
+        archive = Some(zip);
+        document
+    } else {
+        data.to_vec()
+    };
+
+    let backup: Backup = serde_json::from_slice(&document).map_err(|e| format!("Failed to read the backup: {}", e))?;
+    if backup.format != BACKUP_FORMAT {
+        return Err("The file is not a backup.".to_string());
+    }
+    if backup.version > BACKUP_VERSION {
+        return Err(format!("Unsupported backup version {}, please update the server first.", backup.version));
+    }
+
+    if let Some(mut archive) = archive {
+        let mut remaining = MAX_ARCHIVE_BYTES.saturating_sub(document.len() as u64);
+        for cover in &backup.covers {
+            if !cover.file.starts_with("covers/") || files.contains_key(&cover.file) {
+                continue;
+            }
+            let limit = (MAX_COVER_BYTES as u64).min(remaining);
+            if let Some(content) = read_entry(&mut archive, &cover.file, limit)? {
+                remaining -= content.len() as u64;
+                files.insert(cover.file.clone(), content);
+            }
+        }
+    }
+    Ok((backup, files))
+}
+
+/// Writes a zip archive of a backup along with the given cover images.
+pub fn write_archive(backup: &Backup, covers: &[(String, Vec<u8>)]) -> Result<Vec<u8>, String> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let compressed = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    let document = serde_json::to_vec_pretty(backup).map_err(|e| e.to_string())?;
+    writer.start_file(BACKUP_FILE, compressed).map_err(|e| e.to_string())?;
+    writer.write_all(&document).map_err(|e| e.to_string())?;
+
+    // Images are compressed already
+    for (path, data) in covers {
+        writer.start_file(path.as_str(), stored).map_err(|e| e.to_string())?;
+        writer.write_all(data).map_err(|e| e.to_string())?;
+    }
+    Ok(writer.finish().map_err(|e| e.to_string())?.into_inner())
+}
+
+/// Returns the file extension of a cover image.
+fn cover_extension(content_type: &str) -> &'static str {
+    match content_type {
+        "image/png" => "png",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        _ => "jpg",
+    }
+}
+
+/// Exports everything the user owns as a versioned JSON document.
+///
+/// Shelves, books, readings and their progress entries, contributors, series, tags, reviews,
+/// notes, highlights and import templates are included along with their ids. Covers are only
+/// part of `/api/backups/export-zip`.
+///
+/// Authentication is required via JWT token in the Authorization header.
+pub(crate) async fn export_backup(auth: AuthUser) -> Response {
+    let connection = &mut connect();
+
+    let backup = match load_backup(connection, auth.0) {
+        Ok(backup) => backup,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading the backup: {}", e) }))).into_response(),
+    };
+
+    match serde_json::to_vec_pretty(&backup) {
+        Ok(data) => {
+            let filename = format!("books-backup-{}.json", backup.exported_at.format("%Y-%m-%d"));
+            download("application/json", &filename, data)
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while writing the backup: {}", e) }))).into_response(),
+    }
+}
+
+/// Exports everything the user owns as a zip archive, which contains the JSON document of
+/// `/api/backups/export` as `backup.json` and the original covers of the books.
+///
+/// Authentication is required via JWT token in the Authorization header.
+pub(crate) async fn export_backup_zip(auth: AuthUser) -> Response {
+    let connection = &mut connect();
+
+    let mut backup = match load_backup(connection, auth.0) {
+        Ok(backup) => backup,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading the backup: {}", e) }))).into_response(),
+    };
+    let covers: Vec<BookCover> = match schema::book_covers::table
+        .filter(schema::book_covers::dsl::user.eq(auth.0))
+        .order(schema::book_covers::dsl::book.asc())
+        .load(connection)
+    {
+        Ok(covers) => covers,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading covers: {}", e) }))).into_response(),
+    };
+
+    let mut files = Vec::new();
+    for cover in covers {
+        let data = match cover_storage().get(&cover_key(cover.book, cover.id, CoverSize::Original)).await {
+            Ok(Some(data)) => data,
+            Ok(None) => continue,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while loading the cover: {}", e) }))).into_response(),
+        };
+        let file = format!("covers/{}.{}", cover.book, cover_extension(&cover.content_type));
+        backup.covers.push(BackupCover { book: cover.book, content_type: cover.content_type, file: file.clone() });
+        files.push((file, data));
+    }
+
+    match write_archive(&backup, &files) {
+        Ok(data) => {
+            let filename = format!("books-backup-{}.zip", backup.exported_at.format("%Y-%m-%d"));
+            download("application/zip", &filename, data)
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while writing the backup: {}", e) }))).into_response(),
+    }
+}
+
+/// Restores a backup into the account of the user, which may be empty or already have books.
+///
+/// This route accepts a multipart form data with the following structure:
+/// - `file`: The JSON document of `/api/backups/export` or the zip archive of
+///   `/api/backups/export-zip`.
+///
+/// All records are recreated with new ids, so a backup can be restored on any server. Shelves,
+/// authors, series, tags and import templates the user already has are reused by name, books
+/// already on their shelf (by ISBN-13, or title and author) are skipped. The restore either
+/// succeeds as a whole or changes nothing, covers which can't be stored are skipped.
+///
+/// Authentication is required via JWT token in the Authorization header.
+pub(crate) async fn restore_backup(
+    auth: AuthUser,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let mut file_data = None;
+    loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.name() == Some("file") => match field.bytes().await {
+                Ok(bytes) => file_data = Some(bytes),
+                Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({ "error": format!("Failed to read file field: {}", e) }))),
+            },
+            Ok(Some(_)) => continue,
+            Ok(None) => break,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({ "error": format!("Failed to read multipart data: {}", e) }))),
+        }
+    }
+    let Some(data) = file_data else {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": "Missing file." })));
+    };
+
+    let (mut backup, mut files) = match read_archive(&data) {
+        Ok(archive) => archive,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: e }))),
+    };
+    let covers = std::mem::take(&mut backup.covers);
+
+    let connection = &mut connect();
+
+    let mut summary = RestoreSummary::default();
+    let books = match connection.transaction::<_, RestoreError, _>(|conn| restore(conn, auth.0, backup, &mut summary)) {
+        Ok(books) => books,
+        Err(RestoreError::Invalid(e)) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: e }))),
+        Err(RestoreError::Database(e)) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while restoring the backup: {}", e) }))),
+    };
+
+    for cover in covers {
+        let (Some(&book), Some(data)) = (books.get(&cover.book), files.remove(&cover.file)) else {
+            continue;
+        };
+        match store_cover(connection, auth.0, book, data).await {
+            Ok(()) => summary.covers += 1,
+            Err((_, e)) => error!("Failed to restore the cover of book {}: {}", book, e),
+        }
+    }
+
+    (StatusCode::CREATED, Json(json!({ "message": "Backup restored successfully.", "restored": summary })))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_export_backup_requires_auth() {
+        let app = Router::new().route("/api/backups/export", post(export_backup));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/backups/export").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_restore_backup_requires_auth() {
+        let app = Router::new().route("/api/backups/restore", post(restore_backup));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/backups/restore").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    fn backup() -> Backup {
+        Backup {
+            format: BACKUP_FORMAT.to_string(),
+            version: BACKUP_VERSION,
+            exported_at: chrono::Utc::now().naive_utc(),
+            user: "alice".to_string(),
+            shelves: Vec::new(),
+            books: Vec::new(),
+            authors: Vec::new(),
+            book_contributors: Vec::new(),
+            series: Vec::new(),
+            series_books: Vec::new(),
+            tags: Vec::new(),
+            book_tags: Vec::new(),
+            readings: Vec::new(),
+            reading_entries: Vec::new(),
+            reviews: Vec::new(),
+            notes: Vec::new(),
+            highlights: Vec::new(),
+            highlight_tags: Vec::new(),
+            import_templates: Vec::new(),
+            covers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let mut original = backup();
+        original.covers.push(BackupCover {
+            book: Uuid::new_v4(),
+            content_type: "image/png".to_string(),
+            file: "covers/cover.png".to_string(),
+        });
+        let data = write_archive(&original, &[("covers/cover.png".to_string(), vec![1, 2, 3])]).unwrap();
+
+        let (backup, files) = read_archive(&data).unwrap();
+        assert_eq!(backup.user, "alice");
+        assert_eq!(backup.covers.len(), 1);
+        assert_eq!(files.get("covers/cover.png"), Some(&vec![1, 2, 3]));
+
+        let (backup, files) = read_archive(&serde_json::to_vec(&original).unwrap()).unwrap();
+        assert_eq!(backup.version, BACKUP_VERSION);
+        assert!(files.is_empty());
+    }
+
+    #[test]
+    fn test_read_archive_checks_version() {
+        let document = json!({ "format": BACKUP_FORMAT, "version": 1, "exported_at": "2025-05-24T10:00:00", "user": "alice" });
+        assert!(read_archive(document.to_string().as_bytes()).unwrap().0.books.is_empty());
+
+        let newer = json!({ "format": BACKUP_FORMAT, "version": BACKUP_VERSION + 1, "exported_at": "2025-05-24T10:00:00", "user": "alice" });
+        assert!(read_archive(newer.to_string().as_bytes()).err().unwrap().starts_with("Unsupported backup version"));
+        assert_eq!(read_archive(b"{\"foo\": 1}").err().as_deref().map(|e| e.starts_with("Failed to read the backup")), Some(true));
+    }
+
+    #[test]
+    fn test_read_archive_limits_entries() {
+        let mut original = backup();
+        original.covers.push(BackupCover {
+            book: Uuid::new_v4(),
+            content_type: "image/jpeg".to_string(),
+            file: "covers/cover.jpg".to_string(),
+        });
+
+        // Entries the backup doesn't refer to aren't read at all
+        let data = write_archive(&original, &[("other.bin".to_string(), vec![0; 16])]).unwrap();
+        let (_, files) = read_archive(&data).unwrap();
+        assert!(files.is_empty());
+
+        // Zeros compress well, so the entry unpacks into far more than the archive is
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        writer.start_file(BACKUP_FILE, options).unwrap();
+        writer.write_all(&serde_json::to_vec(&original).unwrap()).unwrap();
+        writer.start_file("covers/cover.jpg", options).unwrap();
+        writer.write_all(&vec![0; MAX_COVER_BYTES + 1]).unwrap();
+        let data = writer.finish().unwrap().into_inner();
+        assert!(data.len() < MAX_COVER_BYTES / 100);
+        assert_eq!(read_archive(&data).err().as_deref(), Some("covers/cover.jpg is too large to restore."));
+    }
+
+    #[test]
+    fn test_book_key() {
+        let shelf = Uuid::new_v4();
+        let book = Book {
+            id: Uuid::new_v4(),
+            user: Uuid::new_v4(),
+            shelf,
+            title: Some("Mort".to_string()),
+            author: Some("Terry Pratchett".to_string()),
+            isbn13: None,
+            isbn10: None,
+            google_books_id: None,
+            added_at: chrono::Utc::now().naive_utc(),
+            publisher: None,
+            published_year: None,
+            page_count: None,
+            cover_url: None,
+        };
+        assert_eq!(book_key(&book), Some((shelf, "Mort|Terry Pratchett".to_string())));
+
+        let with_isbn = Book { isbn13: Some("9780552131061".to_string()), ..book };
+        assert_eq!(book_key(&with_isbn), Some((shelf, "9780552131061".to_string())));
+    }
+}
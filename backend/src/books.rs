@@ -1,11 +1,14 @@
 use crate::auth::AuthUser;
+use crate::covers::cover_url;
 use crate::db::connect;
 use crate::models::{Book, Reading};
+use crate::reviews::{rating_summary, RatingSummary};
 use crate::schema::books::dsl::books;
 use crate::schema::readings::dsl::readings;
 use crate::{schema, ErrorResponse};
 use axum::routing::post;
 use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -25,6 +28,10 @@ pub struct BookInfoRequest {
 #[derive(Debug, Serialize)]
 pub struct BookInfoResponse {
     pub google_books_id: Option<String>,
+    pub has_custom_cover: bool,
+    /// Where the custom cover is served without authentication, see `covers::get_cover_by_id`.
+    pub custom_cover_url: Option<String>,
+    pub rating: Option<RatingSummary>,
     pub readings: Vec<serde_json::Value>,
 }
 
@@ -59,7 +66,7 @@ pub(crate) async fn get_book_info(
             "total_pages": reading.total_pages,
             "progress": reading.progress,
             "mode": reading.mode.to_string(),
-            "started_at": reading.started_at.to_string(),
+            "started_at": reading.started_at.map(|d| d.to_string()),
             "finished_at": reading.finished_at.map(|d| d.to_string()),
             "cancelled_at": reading.cancelled_at.map(|d| d.to_string()),
         });
@@ -71,13 +78,19 @@ pub(crate) async fn get_book_info(
         .filter(schema::books::dsl::user.eq(auth.0))
         .first::<Book>(connection)
     {
-        Ok(book) => (
-            StatusCode::OK,
-            Json(json!(BookInfoResponse {
-                google_books_id: book.google_books_id,
-                readings: json_readings,
-            })),
-        ),
+        Ok(book) => {
+            let cover_id = custom_cover_id(connection, book.id);
+            (
+                StatusCode::OK,
+                Json(json!(BookInfoResponse {
+                    google_books_id: book.google_books_id,
+                    has_custom_cover: cover_id.is_some(),
+                    custom_cover_url: cover_id.map(cover_url),
+                    rating: rating_summary(connection, book.id).unwrap_or_default(),
+                    readings: json_readings,
+                })),
+            )
+        }
         Err(_) => (
             StatusCode::NOT_FOUND,
             Json(json!(ErrorResponse {
@@ -87,6 +100,52 @@ pub(crate) async fn get_book_info(
     }
 }
 
+/// Identifies the work a book row refers to, as the same book may be placed on several shelves.
+///
+/// Books are keyed by their ISBN13 if available, otherwise by "title|author".
+pub fn work_key(book: &Book) -> String {
+    match book.isbn13.as_deref().filter(|isbn| !isbn.is_empty()) {
+        Some(isbn) => isbn.to_string(),
+        None => format!(
+            "{}|{}",
+            book.title.as_deref().unwrap_or_default(),
+            book.author.as_deref().unwrap_or_default()
+        ),
+    }
+}
+
+/// Loads the IDs of the copies of a book on the shelves of its user, the book itself included.
+pub fn work_copies(connection: &mut PgConnection, book: &Book) -> QueryResult<Vec<Uuid>> {
+    let key = work_key(book);
+    let candidates: Vec<Book> = books
+        .filter(schema::books::dsl::user.eq(book.user))
+        .filter(
+            schema::books::dsl::isbn13
+                .eq(book.isbn13.clone().unwrap_or_default())
+                .or(schema::books::dsl::title.eq(book.title.clone().unwrap_or_default())),
+        )
+        .load(connection)?;
+    Ok(candidates
+        .into_iter()
+        .filter(|candidate| work_key(candidate) == key)
+        .map(|candidate| candidate.id)
+        .collect())
+}
+
+/// Returns the path of the page of a book in the app.
+pub fn book_page(book_id: Uuid) -> String {
+    format!("/book/{}", book_id)
+}
+
+/// Returns the ID of the custom cover uploaded for the book, if there is one.
+fn custom_cover_id(connection: &mut PgConnection, book_id: Uuid) -> Option<Uuid> {
+    schema::book_covers::dsl::book_covers
+        .filter(schema::book_covers::dsl::book.eq(book_id))
+        .select(schema::book_covers::dsl::id)
+        .first(connection)
+        .ok()
+}
+
 #[cfg(test)]
 mod tests {
     use axum::{body::Body, http::Request, routing::post, Router};
@@ -0,0 +1,286 @@
+use crate::models::{Reading, ReadingEntry, ReadingMode};
+use chrono::{Days, NaiveDate, NaiveDateTime};
+use std::collections::BTreeMap;
+
+/// Longest line of an iCalendar file in bytes, longer ones are folded.
+const MAX_LINE: usize = 75;
+
+/// When deadlines are reminded of, relative to the start of their day.
+const REMINDER: &str = "-P1D";
+
+/// A reading session along with the book read, which becomes events of the calendar feed.
+pub struct CalendarReading {
+    pub reading: Reading,
+    pub title: String,
+    pub author: Option<String>,
+    /// Daily progress of the reading, only included if the feed asks for it.
+    pub entries: Vec<ReadingEntry>,
+}
+
+/// Escapes text values, as commas, semicolons and line breaks separate values.
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Folds a content line after 75 bytes, continuing it on lines starting with a space.
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 8);
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+fn format_timestamp(timestamp: NaiveDateTime) -> String {
+    timestamp.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Collects the lines of the calendar before they're folded.
+struct Calendar {
+    lines: Vec<String>,
+}
+
+impl Calendar {
+    fn line(&mut self, name: &str, value: impl Into<String>) {
+        self.lines.push(format!("{}:{}", name, value.into()));
+    }
+
+    /// Adds an all-day event lasting from `start` up to and including `end`.
+    fn event(&mut self, uid: String, stamp: NaiveDateTime, start: NaiveDate, end: NaiveDate, summary: &str, description: Option<&str>) {
+        self.line("BEGIN", "VEVENT");
+        self.line("UID", uid);
+        self.line("DTSTAMP", format_timestamp(stamp));
+        self.line("DTSTART;VALUE=DATE", format_date(start));
+        self.line("DTEND;VALUE=DATE", format_date(end.checked_add_days(Days::new(1)).unwrap_or(end)));
+        self.line("SUMMARY", escape_text(summary));
+        if let Some(description) = description {
+            self.line("DESCRIPTION", escape_text(description));
+        }
+        self.line("TRANSP", "TRANSPARENT");
+    }
+
+    fn alarm(&mut self, description: &str) {
+        self.line("BEGIN", "VALARM");
+        self.line("ACTION", "DISPLAY");
+        self.line("TRIGGER", REMINDER);
+        self.line("DESCRIPTION", escape_text(description));
+        self.line("END", "VALARM");
+    }
+
+    fn end_event(&mut self) {
+        self.line("END", "VEVENT");
+    }
+}
+
+/// Describes the progress of a reading, e.g. "Page 120 of 316" or "45%".
+fn describe_progress(mode: ReadingMode, progress: i32, total_pages: i32) -> String {
+    match mode {
+        ReadingMode::Pages if total_pages > 0 => format!("Page {} of {}", progress, total_pages),
+        ReadingMode::Pages => format!("Page {}", progress),
+        ReadingMode::Percentage => format!("{}%", progress),
+    }
+}
+
+/// Groups the progress entries of a reading by the day they were read on, with the latest entry of
+/// each day and when any entry of the day was last updated.
+fn daily_progress(entries: &[ReadingEntry]) -> BTreeMap<NaiveDate, (&ReadingEntry, NaiveDateTime)> {
+    let mut days: BTreeMap<NaiveDate, (&ReadingEntry, NaiveDateTime)> = BTreeMap::new();
+    for entry in entries {
+        let (latest, updated_at) = days.entry(entry.read_at).or_insert((entry, entry.updated_at));
+        if (entry.created_at, entry.id) > (latest.created_at, latest.id) {
+            *latest = entry;
+        }
+        *updated_at = (*updated_at).max(entry.updated_at);
+    }
+    days
+}
+
+/// Writes the readings of a user as an iCalendar feed.
+///
+/// Each reading is an event from the day it was started until it was finished or cancelled, or
+/// until `today` if it's still ongoing. Target finish dates of ongoing readings and due dates
+/// become events of their own with a reminder the day before. With `include_entries`, each day
+/// progress was tracked on becomes an all-day event as well, with the progress reached that day.
+pub fn write_calendar(readings: &[CalendarReading], include_entries: bool, today: NaiveDate) -> String {
+    let mut calendar = Calendar { lines: Vec::new() };
+    calendar.line("BEGIN", "VCALENDAR");
+    calendar.line("VERSION", "2.0");
+    calendar.line("PRODID", "-//books//Reading Calendar//EN");
+    calendar.line("CALSCALE", "GREGORIAN");
+    calendar.line("METHOD", "PUBLISH");
+    calendar.line("X-WR-CALNAME", "Reading");
+
+    for entry in readings {
+        let reading = &entry.reading;
+        let description = match &entry.author {
+            Some(author) => format!("{} by {}", entry.title, author),
+            None => entry.title.clone(),
+        };
+
+        let (summary, end) = match (reading.finished_at, reading.cancelled_at) {
+            (Some(finished_at), _) => (format!("Read {}", entry.title), finished_at),
+            (None, Some(cancelled_at)) => (format!("Stopped reading {}", entry.title), cancelled_at),
+            (None, None) => (format!("Reading {}", entry.title), reading.started_at.map_or(today, |start| today.max(start))),
+        };
+        // Readings imported without a start date only span the day they ended
+        let start = reading.started_at.unwrap_or(end);
+        let progress = describe_progress(reading.mode, reading.progress, reading.total_pages);
+        calendar.event(
+            format!("reading-{}@books", reading.id),
+            reading.updated_at,
+            start,
+            end.max(start),
+            &summary,
+            Some(&format!("{}\n{}", description, progress)),
+        );
+        calendar.end_event();
+
+        let ongoing = reading.finished_at.is_none() && reading.cancelled_at.is_none();
+        if let Some(target_finish_at) = reading.target_finish_at.filter(|_| ongoing) {
+            let summary = format!("Finish {}", entry.title);
+            calendar.event(
+                format!("target-{}@books", reading.id),
+                reading.updated_at,
+                target_finish_at,
+                target_finish_at,
+                &summary,
+                Some(&description),
+            );
+            calendar.alarm(&summary);
+            calendar.end_event();
+        }
+
+        // Borrowed copies have to be returned whether they were finished or not
+        if let Some(due_at) = reading.due_at {
+            let summary = format!("Return {}", entry.title);
+            calendar.event(
+                format!("due-{}@books", reading.id),
+                reading.updated_at,
+                due_at,
+                due_at,
+                &summary,
+                Some(&description),
+            );
+            calendar.alarm(&summary);
+            calendar.end_event();
+        }
+
+        if include_entries {
+            for (read_at, (latest, updated_at)) in daily_progress(&entry.entries) {
+                calendar.event(
+                    format!("progress-{}-{}@books", reading.id, format_date(read_at)),
+                    updated_at,
+                    read_at,
+                    read_at,
+                    &format!("{}: {}", entry.title, describe_progress(latest.mode, latest.progress, reading.total_pages)),
+                    None,
+                );
+                calendar.end_event();
+            }
+        }
+    }
+
+    calendar.line("END", "VCALENDAR");
+    calendar.lines.iter().map(|line| fold_line(line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn reading(finished_at: Option<NaiveDate>) -> Reading {
+        let updated_at = date(2025, 5, 20).and_hms_opt(18, 30, 0).unwrap();
+        Reading {
+            id: Uuid::nil(),
+            book: Uuid::new_v4(),
+            user: Uuid::new_v4(),
+            total_pages: 316,
+            progress: 120,
+            mode: ReadingMode::Pages,
+            started_at: Some(date(2025, 5, 1)),
+            finished_at,
+            cancelled_at: None,
+            created_at: updated_at,
+            updated_at,
+            target_finish_at: Some(date(2025, 6, 1)),
+            due_at: Some(date(2025, 6, 14)),
+        }
+    }
+
+    #[test]
+    fn test_write_calendar() {
+        let reading = CalendarReading {
+            reading: reading(None),
+            title: "Mort".to_string(),
+            author: Some("Terry Pratchett".to_string()),
+            entries: Vec::new(),
+        };
+        let calendar = write_calendar(&[reading], false, date(2025, 5, 24));
+        assert!(calendar.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+        assert!(calendar.contains(
+            "BEGIN:VEVENT\r\nUID:reading-00000000-0000-0000-0000-000000000000@books\r\nDTSTAMP:20250520T183000Z\r\nDTSTART;VALUE=DATE:20250501\r\nDTEND;VALUE=DATE:20250525\r\nSUMMARY:Reading Mort\r\nDESCRIPTION:Mort by Terry Pratchett\\nPage 120 of 316\r\n"
+        ));
+        assert!(calendar.contains("DTSTART;VALUE=DATE:20250601\r\nDTEND;VALUE=DATE:20250602\r\nSUMMARY:Finish Mort\r\n"));
+        assert!(calendar.contains("BEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER:-P1D\r\nDESCRIPTION:Return Mort\r\nEND:VALARM\r\n"));
+        assert_eq!(calendar.matches("BEGIN:VEVENT").count(), 3);
+    }
+
+    #[test]
+    fn test_finished_reading_with_entries() {
+        let entry = |read_at: NaiveDate, hour, progress| ReadingEntry {
+            id: Uuid::new_v4(),
+            reading: Uuid::nil(),
+            book: Uuid::new_v4(),
+            user: Uuid::new_v4(),
+            progress,
+            mode: ReadingMode::Pages,
+            read_at,
+            created_at: read_at.and_hms_opt(hour, 0, 0).unwrap(),
+            updated_at: read_at.and_hms_opt(hour, 0, 0).unwrap(),
+        };
+        let reading = CalendarReading {
+            reading: reading(Some(date(2025, 5, 10))),
+            title: "Mort, a Discworld novel".to_string(),
+            author: None,
+            entries: vec![entry(date(2025, 5, 2), 21, 40), entry(date(2025, 5, 2), 8, 12)],
+        };
+
+        let calendar = write_calendar(&[reading], true, date(2025, 5, 24));
+        assert!(calendar.contains("DTEND;VALUE=DATE:20250511\r\nSUMMARY:Read Mort\\, a Discworld novel\r\n"));
+        // Each day has a single event with the progress reached that day
+        assert!(calendar.contains("SUMMARY:Mort\\, a Discworld novel: Page 40 of 316\r\n"));
+        assert!(!calendar.contains("Page 12 of 316"));
+        // The target doesn't matter anymore once the book is finished, the due date still does
+        assert!(!calendar.contains("SUMMARY:Finish"));
+        assert_eq!(calendar.matches("BEGIN:VEVENT").count(), 3);
+    }
+
+    #[test]
+    fn test_fold_line() {
+        let line = format!("SUMMARY:{}", "ä".repeat(40));
+        let folded = fold_line(&line);
+        assert!(folded.split("\r\n").all(|part| part.len() <= MAX_LINE));
+        assert_eq!(folded.replace("\r\n ", ""), format!("{}\r\n", line));
+    }
+}
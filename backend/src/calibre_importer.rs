@@ -0,0 +1,304 @@
+use crate::contributors::ContributorInput;
+use crate::goodreads_importer::SeriesMarker;
+use crate::importer::{primary_author, ImportedBook, ImportedRows, Importer};
+use crate::models::{ContributorRole, ReadingMode};
+use chrono::{Datelike, NaiveDate};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use uuid::Uuid;
+
+/// Every SQLite database starts with this header.
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Shelf the books of a Calibre library are placed on, Calibre doesn't track what was read.
+const SHELF: &str = "calibre";
+
+/// Labels of custom columns commonly used for the page count, e.g. by the Count Pages plugin.
+const PAGE_COLUMNS: [&str; 4] = ["pages", "page_count", "pagecount", "number_of_pages"];
+
+/// Imports the `metadata.db` of a Calibre library.
+///
+/// The options of an import may contain the `library_path` the database was read from, covers
+/// are only imported then, and the `page_column` holding the page count of the books.
+pub struct CalibreImporter;
+
+impl Importer for CalibreImporter {
+    fn source(&self) -> &'static str {
+        "calibre"
+    }
+
+    fn name(&self) -> &'static str {
+        "Calibre"
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        data.starts_with(SQLITE_HEADER)
+    }
+
+    fn read(&self, data: &[u8], options: &serde_json::Value) -> Result<ImportedRows, String> {
+        if !self.detect(data) {
+            return Err("Failed to read Calibre library: not a SQLite database.".to_string());
+        }
+
+        // SQLite can only open files, so the database is written to a temporary file first
+        let file = std::env::temp_dir().join(format!("calibre-{}.db", Uuid::new_v4()));
+        std::fs::write(&file, data).map_err(|e| format!("Failed to read Calibre library: {}", e))?;
+        let result = Connection::open_with_flags(&file, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .and_then(|connection| {
+                let library = options["library_path"].as_str().map(Path::new);
+                read_books(&connection, library, options["page_column"].as_str())
+            })
+            .map_err(|e| format!("Failed to read Calibre library: {}", e));
+        let _ = std::fs::remove_file(&file);
+        result
+    }
+}
+
+/// Loads the values of a many-to-many field of the books, e.g. their tags, in link order.
+fn load_links(connection: &Connection, sql: &str) -> rusqlite::Result<HashMap<i64, Vec<String>>> {
+    let mut links: HashMap<i64, Vec<String>> = HashMap::new();
+    let mut statement = connection.prepare(sql)?;
+    let rows = statement.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+    for row in rows {
+        let (book, value) = row?;
+        links.entry(book).or_default().push(value);
+    }
+    Ok(links)
+}
+
+/// Finds the custom column holding the page count, by its label unless one is given.
+fn page_column(connection: &Connection, label: Option<&str>) -> rusqlite::Result<Option<i64>> {
+    let mut statement = connection.prepare("SELECT id, label FROM custom_columns WHERE datatype IN ('int', 'float')")?;
+    let columns = statement
+        .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let label = label.map(|label| label.trim_start_matches('#').to_lowercase());
+    Ok(columns
+        .into_iter()
+        .find(|(_, column)| match &label {
+            Some(label) => column.to_lowercase() == *label,
+            None => PAGE_COLUMNS.contains(&column.to_lowercase().as_str()),
+        })
+        .map(|(id, _)| id))
+}
+
+/// Parses a Calibre timestamp like "2021-03-04 12:34:56.123456+00:00" into its date.
+fn parse_timestamp(value: Option<String>) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value?.get(..10)?, "%Y-%m-%d").ok()
+}
+
+/// Returns the cover of a book within the library, book paths are relative to it.
+fn cover_file(library: &Path, book_path: &str) -> Option<PathBuf> {
+    let book_path = Path::new(book_path);
+    if !book_path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    Some(library.join(book_path).join("cover.jpg"))
+}
+
+fn read_books(connection: &Connection, library: Option<&Path>, page_label: Option<&str>) -> rusqlite::Result<ImportedRows> {
+    let authors = load_links(
+        connection,
+        "SELECT l.book, a.name FROM books_authors_link l JOIN authors a ON a.id = l.author ORDER BY l.id",
+    )?;
+    let tags = load_links(
+        connection,
+        "SELECT l.book, t.name FROM books_tags_link l JOIN tags t ON t.id = l.tag ORDER BY l.id",
+    )?;
+    let series = load_links(
+        connection,
+        "SELECT l.book, s.name FROM books_series_link l JOIN series s ON s.id = l.series",
+    )?;
+    let publishers = load_links(
+        connection,
+        "SELECT l.book, p.name FROM books_publishers_link l JOIN publishers p ON p.id = l.publisher",
+    )?;
+    let isbns = load_links(
+        connection,
+        "SELECT book, val FROM identifiers WHERE lower(type) = 'isbn'",
+    )?;
+
+    let mut ratings: HashMap<i64, i64> = HashMap::new();
+    let mut statement = connection.prepare(
+        "SELECT l.book, r.rating FROM books_ratings_link l JOIN ratings r ON r.id = l.rating",
+    )?;
+    for row in statement.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<i64>>(1)?)))? {
+        if let (book, Some(rating)) = row? {
+            ratings.insert(book, rating);
+        }
+    }
+
+    let mut pages: HashMap<i64, i32> = HashMap::new();
+    if let Some(column) = page_column(connection, page_label)? {
+        let mut statement = connection.prepare(&format!("SELECT book, value FROM custom_column_{}", column))?;
+        for row in statement.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<f64>>(1)?)))? {
+            if let (book, Some(value)) = row? {
+                pages.insert(book, value.round() as i32);
+            }
+        }
+    }
+
+    let mut statement = connection.prepare(
+        "SELECT id, title, series_index, timestamp, pubdate, path, has_cover FROM books ORDER BY id",
+    )?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<f64>>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, Option<String>>(4)?,
+            row.get::<_, String>(5)?,
+            row.get::<_, Option<bool>>(6)?,
+        ))
+    })?;
+
+    let mut books = Vec::new();
+    for row in rows {
+        let (id, title, series_index, added, published, path, has_cover) = match row {
+            Ok(row) => row,
+            Err(e) => {
+                books.push(Err(e.to_string()));
+                continue;
+            }
+        };
+
+        let contributors: Vec<ContributorInput> = authors
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .map(|name| ContributorInput { name: name.clone(), role: ContributorRole::Author })
+            .collect();
+
+        // Calibre stores ISBNs without hyphens, but users may have entered them by hand
+        let isbn = isbns
+            .get(&id)
+            .and_then(|values| values.first())
+            .map(|isbn| isbn.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>());
+
+        books.push(Ok(ImportedBook {
+            raw_title: title.clone(),
+            title: title.trim().to_string(),
+            series: series.get(&id).and_then(|names| names.first()).map(|name| SeriesMarker {
+                name: name.clone(),
+                position: series_index,
+            }),
+            author: primary_author(&contributors).unwrap_or_default(),
+            contributors,
+            isbn13: isbn.clone().filter(|isbn| isbn.len() == 13),
+            isbn10: isbn.filter(|isbn| isbn.len() == 10),
+            publisher: publishers.get(&id).and_then(|names| names.first()).cloned(),
+            // Calibre uses the year 101 for books without a publication date
+            published_year: parse_timestamp(published).map(|date| date.year()).filter(|year| *year > 1000),
+            page_count: pages.get(&id).copied().filter(|pages| *pages > 0),
+            added_on: parse_timestamp(added),
+            shelf: SHELF.to_string(),
+            other_shelves: Vec::new(),
+            tags: tags.get(&id).cloned().unwrap_or_default(),
+            // Calibre rates in half stars already, 0 meaning unrated
+            rating: ratings.get(&id).map(|rating| *rating as i16).filter(|rating| (1..=10).contains(rating)),
+            review: None,
+            note: None,
+            reading_mode: ReadingMode::Percentage,
+            reads: Vec::new(),
+            undated_reads: 0,
+            unfinished_read: None,
+            has_cover: has_cover.unwrap_or(false),
+            cover_file: library
+                .filter(|_| has_cover.unwrap_or(false))
+                .and_then(|library| cover_file(library, &path)),
+        }));
+    }
+    Ok(books)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Builds a database with the parts of the schema of a Calibre library which are imported.
+    fn library() -> Vec<u8> {
+        let file = std::env::temp_dir().join(format!("calibre-test-{}.db", Uuid::new_v4()));
+        let connection = Connection::open(&file).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT, series_index REAL, timestamp TIMESTAMP, pubdate TIMESTAMP, path TEXT, has_cover BOOL);
+                CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+                CREATE TABLE books_authors_link (id INTEGER PRIMARY KEY, book INTEGER, author INTEGER);
+                CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT);
+                CREATE TABLE books_tags_link (id INTEGER PRIMARY KEY, book INTEGER, tag INTEGER);
+                CREATE TABLE series (id INTEGER PRIMARY KEY, name TEXT);
+                CREATE TABLE books_series_link (id INTEGER PRIMARY KEY, book INTEGER, series INTEGER);
+                CREATE TABLE publishers (id INTEGER PRIMARY KEY, name TEXT);
+                CREATE TABLE books_publishers_link (id INTEGER PRIMARY KEY, book INTEGER, publisher INTEGER);
+                CREATE TABLE ratings (id INTEGER PRIMARY KEY, rating INTEGER);
+                CREATE TABLE books_ratings_link (id INTEGER PRIMARY KEY, book INTEGER, rating INTEGER);
+                CREATE TABLE identifiers (id INTEGER PRIMARY KEY, book INTEGER, type TEXT, val TEXT);
+                CREATE TABLE custom_columns (id INTEGER PRIMARY KEY, label TEXT, name TEXT, datatype TEXT);
+                CREATE TABLE custom_column_1 (id INTEGER PRIMARY KEY, book INTEGER, value INTEGER);
+                INSERT INTO books VALUES (1, 'Guards! Guards!', 8.0, '2021-03-04 12:34:56.123456+00:00', '1989-11-01 00:00:00+00:00', 'Terry Pratchett/Guards! Guards! (1)', 1);
+                INSERT INTO books VALUES (2, 'Good Omens', 1.0, '2022-01-02 08:00:00+00:00', '0101-01-01 00:00:00+00:00', '../Good Omens (2)', 1);
+                INSERT INTO authors VALUES (1, 'Terry Pratchett'), (2, 'Neil Gaiman');
+                INSERT INTO books_authors_link VALUES (1, 1, 1), (2, 2, 2), (3, 2, 1);
+                INSERT INTO tags VALUES (1, 'Fantasy'), (2, 'Humor');
+                INSERT INTO books_tags_link VALUES (1, 1, 1), (2, 1, 2);
+                INSERT INTO series VALUES (1, 'Discworld');
+                INSERT INTO books_series_link VALUES (1, 1, 1);
+                INSERT INTO publishers VALUES (1, 'Gollancz');
+                INSERT INTO books_publishers_link VALUES (1, 1, 1);
+                INSERT INTO ratings VALUES (1, 9);
+                INSERT INTO books_ratings_link VALUES (1, 1, 1);
+                INSERT INTO identifiers VALUES (1, 1, 'isbn', '978-0-575-04606-3'), (2, 1, 'goodreads', '64216');
+                INSERT INTO custom_columns VALUES (1, 'pages', 'Pages', 'int');
+                INSERT INTO custom_column_1 VALUES (1, 1, 288);",
+            )
+            .unwrap();
+        drop(connection);
+        let data = std::fs::read(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_read_library() {
+        let data = library();
+        assert!(CalibreImporter.detect(&data));
+        assert!(!CalibreImporter.detect(b"Title,Authors\n"));
+
+        let books: Vec<ImportedBook> = CalibreImporter
+            .read(&data, &json!({ "library_path": "/srv/calibre" }))
+            .unwrap()
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(books.len(), 2);
+
+        let guards = &books[0];
+        assert_eq!(guards.title, "Guards! Guards!");
+        assert_eq!(guards.author, "Terry Pratchett");
+        assert_eq!(guards.series, Some(SeriesMarker { name: "Discworld".to_string(), position: Some(8.0) }));
+        assert_eq!(guards.tags, vec!["Fantasy", "Humor"]);
+        assert_eq!(guards.isbn13.as_deref(), Some("9780575046063"));
+        assert_eq!(guards.publisher.as_deref(), Some("Gollancz"));
+        assert_eq!(guards.published_year, Some(1989));
+        assert_eq!(guards.page_count, Some(288));
+        assert_eq!(guards.rating, Some(9));
+        assert_eq!(guards.added_on, NaiveDate::from_ymd_opt(2021, 3, 4));
+        assert!(guards.has_cover);
+        assert_eq!(
+            guards.cover_file.as_deref(),
+            Some(Path::new("/srv/calibre/Terry Pratchett/Guards! Guards! (1)/cover.jpg"))
+        );
+
+        let omens = &books[1];
+        assert_eq!(omens.author, "Neil Gaiman");
+        assert_eq!(omens.contributors.len(), 2);
+        assert_eq!(omens.published_year, None);
+        assert_eq!(omens.page_count, None);
+        // Paths leaving the library are never read
+        assert_eq!(omens.cover_file, None);
+    }
+}
@@ -0,0 +1,585 @@
+use crate::books::work_key;
+use crate::exports::LibraryBook;
+use crate::models::ContributorRole;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Formats books can be exported to for reference managers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CitationFormat {
+    BibTex,
+    Ris,
+    CslJson,
+}
+
+impl CitationFormat {
+    /// Parses the format names of the export route: `bibtex`, `ris` and `csl-json`.
+    pub fn parse(name: &str) -> Option<CitationFormat> {
+        match name.trim().to_lowercase().as_str() {
+            "bibtex" | "bib" => Some(CitationFormat::BibTex),
+            "ris" => Some(CitationFormat::Ris),
+            "csl-json" | "csljson" | "csl" => Some(CitationFormat::CslJson),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            CitationFormat::BibTex => "application/x-bibtex; charset=utf-8",
+            CitationFormat::Ris => "application/x-research-info-systems; charset=utf-8",
+            CitationFormat::CslJson => "application/vnd.citationstyles.csl+json",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CitationFormat::BibTex => "bib",
+            CitationFormat::Ris => "ris",
+            CitationFormat::CslJson => "json",
+        }
+    }
+}
+
+/// Words skipped when picking the title word of a citation key.
+const TITLE_STOPWORDS: [&str; 14] = [
+    "a", "an", "the", "of", "on", "der", "die", "das", "ein", "eine", "le", "la", "les", "el",
+];
+
+/// Lowercase name particles, which belong to the family name, e.g. "Ludwig van Beethoven".
+const NAME_PARTICLES: [&str; 9] = ["van", "von", "de", "der", "den", "da", "du", "di", "la"];
+
+/// A name split into the parts reference managers expect.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PersonName {
+    pub family: String,
+    pub given: Option<String>,
+}
+
+/// Splits a name like "Terry Pratchett", "Pratchett, Terry" or "Ludwig van Beethoven" into the
+/// family and given names.
+pub fn split_name(name: &str) -> PersonName {
+    let name = name.trim();
+    if let Some((family, given)) = name.split_once(',') {
+        let given = given.trim();
+        return PersonName {
+            family: family.trim().to_string(),
+            given: (!given.is_empty()).then(|| given.to_string()),
+        };
+    }
+
+    let words: Vec<&str> = name.split_whitespace().collect();
+    if words.len() < 2 {
+        return PersonName { family: name.to_string(), given: None };
+    }
+    let mut start = words.len() - 1;
+    while start > 1 && NAME_PARTICLES.contains(&words[start - 1]) {
+        start -= 1;
+    }
+    PersonName {
+        family: words[start..].join(" "),
+        given: Some(words[..start].join(" ")),
+    }
+}
+
+/// Returns the names of the contributors of a book with the given role, falling back to the
+/// author text of the book for authors if it has no contributors.
+fn names(book: &LibraryBook, role: ContributorRole) -> Vec<PersonName> {
+    if book.contributors.is_empty() {
+        if role != ContributorRole::Author {
+            return Vec::new();
+        }
+        return book
+            .book
+            .author
+            .as_deref()
+            .unwrap_or_default()
+            .split([',', '&'])
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(split_name)
+            .collect();
+    }
+    book.contributors
+        .iter()
+        .filter(|contributor| contributor.role == role)
+        .map(|contributor| split_name(&contributor.name))
+        .collect()
+}
+
+/// Reduces text to lowercase ASCII letters and digits, replacing accented letters by their base
+/// letter and dropping everything else.
+fn fold_ascii(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            folded.push(c);
+            continue;
+        }
+        let replacement = match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ą' => "a",
+            'æ' => "ae",
+            'ç' | 'ć' | 'č' => "c",
+            'ď' | 'đ' => "d",
+            'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ę' | 'ě' => "e",
+            'ì' | 'í' | 'î' | 'ï' | 'ī' => "i",
+            'ł' => "l",
+            'ñ' | 'ń' | 'ň' => "n",
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => "o",
+            'œ' => "oe",
+            'ř' => "r",
+            'ś' | 'š' => "s",
+            'ß' => "ss",
+            'ť' => "t",
+            'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => "u",
+            'ý' | 'ÿ' => "y",
+            'ź' | 'ż' | 'ž' => "z",
+            _ => "",
+        };
+        folded.push_str(replacement);
+    }
+    folded
+}
+
+/// Builds the citation key of a book from the family name of its first author, the year it was
+/// published and the first significant word of its title, e.g. `pratchett1987mort`.
+fn base_key(book: &LibraryBook) -> String {
+    let name = names(book, ContributorRole::Author)
+        .into_iter()
+        .next()
+        .or_else(|| book.contributors.first().map(|contributor| split_name(&contributor.name)))
+        .map(|name| fold_ascii(&name.family))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "anon".to_string());
+    let year = book
+        .book
+        .published_year
+        .map(|year| year.to_string())
+        .unwrap_or_else(|| "nd".to_string());
+    let title = book.book.title.as_deref().unwrap_or_default();
+    let word = title
+        .split(|c: char| c.is_whitespace() || c == '-' || c == ':')
+        .map(fold_ascii)
+        .find(|word| !word.is_empty() && !TITLE_STOPWORDS.contains(&word.as_str()))
+        .unwrap_or_default();
+    format!("{}{}{}", name, year, word)
+}
+
+/// Assigns the citation keys of the books of a whole library, keyed by book ID.
+///
+/// `assigned` holds the keys assigned before by `books::work_key`, which books keep so that their
+/// keys don't change as other books are added or removed. Keys of further books are added to it,
+/// in the order the books were added. Books whose key is taken get the suffixes `a`, `b`, ... and
+/// copies of a book on several shelves share a key.
+pub fn citation_keys(library: &[LibraryBook], assigned: &mut HashMap<String, String>) -> HashMap<Uuid, String> {
+    let mut copies: Vec<&LibraryBook> = library.iter().collect();
+    copies.sort_by_key(|copy| (copy.book.added_at, copy.book.id));
+
+    let mut taken: HashSet<String> = assigned.values().cloned().collect();
+    let mut keys = HashMap::new();
+    for copy in copies {
+        let key = assigned.entry(work_key(&copy.book)).or_insert_with(|| {
+            let base = base_key(copy);
+            let mut key = base.clone();
+            let mut index = 0;
+            while taken.contains(&key) {
+                key = format!("{}{}", base, suffix(index));
+                index += 1;
+            }
+            taken.insert(key.clone());
+            key
+        });
+        keys.insert(copy.book.id, key.clone());
+    }
+    keys
+}
+
+/// Returns the suffix for a duplicate key: `a` to `z`, then `aa`, `ab`, ...
+fn suffix(mut index: usize) -> String {
+    let mut suffix = Vec::new();
+    loop {
+        suffix.push(b'a' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    suffix.reverse();
+    String::from_utf8(suffix).unwrap_or_default()
+}
+
+/// Writes the books in a citation format with the keys of `citation_keys`, sorted by their keys.
+///
+/// Copies of a book on several shelves are written once.
+pub fn write_citations(books: &[&LibraryBook], keys: &HashMap<Uuid, String>, format: CitationFormat) -> Vec<u8> {
+    let mut entries: Vec<(&String, &LibraryBook)> = books
+        .iter()
+        .filter_map(|book| Some((keys.get(&book.book.id)?, *book)))
+        .collect();
+    entries.sort_by_key(|(key, _)| *key);
+    entries.dedup_by(|(a, _), (b, _)| a == b);
+
+    match format {
+        CitationFormat::BibTex => entries
+            .iter()
+            .map(|(key, book)| bibtex_entry(key, book))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into_bytes(),
+        CitationFormat::Ris => entries
+            .iter()
+            .map(|(key, book)| ris_entry(key, book))
+            .collect::<String>()
+            .into_bytes(),
+        CitationFormat::CslJson => {
+            let items: Vec<Value> = entries.iter().map(|(key, book)| csl_item(key, book)).collect();
+            serde_json::to_vec_pretty(&items).unwrap_or_default()
+        }
+    }
+}
+
+/// Returns the ISBN of a book, preferring ISBN-13.
+fn isbn(book: &LibraryBook) -> Option<&str> {
+    book.book.isbn13.as_deref().or(book.book.isbn10.as_deref())
+}
+
+fn format_position(position: f64) -> String {
+    if position.fract() == 0.0 {
+        format!("{}", position as i64)
+    } else {
+        position.to_string()
+    }
+}
+
+/// Escapes the characters BibTeX treats as markup.
+fn escape_bibtex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '{' | '}' | '&' | '%' | '$' | '#' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Joins names the way BibTeX expects them: "Pratchett, Terry and Gaiman, Neil".
+fn bibtex_names(names: &[PersonName]) -> String {
+    names
+        .iter()
+        .map(|name| match &name.given {
+            Some(given) => format!("{}, {}", escape_bibtex(&name.family), escape_bibtex(given)),
+            None => escape_bibtex(&name.family),
+        })
+        .collect::<Vec<_>>()
+        .join(" and ")
+}
+
+fn bibtex_entry(key: &str, book: &LibraryBook) -> String {
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    for (field, role) in [
+        ("author", ContributorRole::Author),
+        ("editor", ContributorRole::Editor),
+        ("translator", ContributorRole::Translator),
+    ] {
+        let names = names(book, role);
+        if !names.is_empty() {
+            fields.push((field, bibtex_names(&names)));
+        }
+    }
+    if let Some(title) = &book.book.title {
+        fields.push(("title", escape_bibtex(title)));
+    }
+    if let Some(series) = &book.series {
+        fields.push(("series", escape_bibtex(&series.name)));
+        if let Some(position) = series.position {
+            fields.push(("number", format_position(position)));
+        }
+    }
+    if let Some(publisher) = &book.book.publisher {
+        fields.push(("publisher", escape_bibtex(publisher)));
+    }
+    if let Some(year) = book.book.published_year {
+        fields.push(("year", year.to_string()));
+    }
+    if let Some(isbn) = isbn(book) {
+        fields.push(("isbn", isbn.to_string()));
+    }
+    if let Some(pages) = book.book.page_count.filter(|pages| *pages > 0) {
+        fields.push(("pagetotal", pages.to_string()));
+    }
+
+    let mut entry = format!("@book{{{},\n", key);
+    for (field, value) in fields {
+        entry.push_str(&format!("  {} = {{{}}},\n", field, value));
+    }
+    entry.push_str("}\n");
+    entry
+}
+
+/// Formats a name the way RIS expects it: "Pratchett, Terry".
+fn ris_name(name: &PersonName) -> String {
+    match &name.given {
+        Some(given) => format!("{}, {}", name.family, given),
+        None => name.family.clone(),
+    }
+}
+
+fn ris_entry(key: &str, book: &LibraryBook) -> String {
+    let mut lines: Vec<(&str, String)> = vec![("TY", "BOOK".to_string()), ("ID", key.to_string())];
+    for (tag, role) in [
+        ("AU", ContributorRole::Author),
+        ("ED", ContributorRole::Editor),
+        ("A4", ContributorRole::Translator),
+    ] {
+        lines.extend(names(book, role).iter().map(|name| (tag, ris_name(name))));
+    }
+    if let Some(title) = &book.book.title {
+        lines.push(("TI", title.clone()));
+    }
+    if let Some(series) = &book.series {
+        lines.push(("T3", series.name.clone()));
+    }
+    if let Some(publisher) = &book.book.publisher {
+        lines.push(("PB", publisher.clone()));
+    }
+    if let Some(year) = book.book.published_year {
+        lines.push(("PY", year.to_string()));
+    }
+    if let Some(isbn) = isbn(book) {
+        lines.push(("SN", isbn.to_string()));
+    }
+    if let Some(pages) = book.book.page_count.filter(|pages| *pages > 0) {
+        lines.push(("SP", pages.to_string()));
+    }
+    lines.push(("ER", String::new()));
+
+    // RIS requires lines to end with CR LF and values on a single line
+    lines
+        .into_iter()
+        .map(|(tag, value)| format!("{}  - {}\r\n", tag, value.replace(['\r', '\n'], " ")))
+        .collect()
+}
+
+fn csl_names(names: &[PersonName]) -> Value {
+    names
+        .iter()
+        .map(|name| match &name.given {
+            Some(given) => json!({ "family": name.family, "given": given }),
+            None => json!({ "literal": name.family }),
+        })
+        .collect()
+}
+
+fn csl_item(key: &str, book: &LibraryBook) -> Value {
+    let mut item = Map::new();
+    item.insert("id".to_string(), json!(key));
+    item.insert("type".to_string(), json!("book"));
+    if let Some(title) = &book.book.title {
+        item.insert("title".to_string(), json!(title));
+    }
+    for (variable, role) in [
+        ("author", ContributorRole::Author),
+        ("editor", ContributorRole::Editor),
+        ("translator", ContributorRole::Translator),
+        ("illustrator", ContributorRole::Illustrator),
+        ("narrator", ContributorRole::Narrator),
+    ] {
+        let names = names(book, role);
+        if !names.is_empty() {
+            item.insert(variable.to_string(), csl_names(&names));
+        }
+    }
+    if let Some(series) = &book.series {
+        item.insert("collection-title".to_string(), json!(series.name));
+        if let Some(position) = series.position {
+            item.insert("collection-number".to_string(), json!(format_position(position)));
+        }
+    }
+    if let Some(publisher) = &book.book.publisher {
+        item.insert("publisher".to_string(), json!(publisher));
+    }
+    if let Some(year) = book.book.published_year {
+        item.insert("issued".to_string(), json!({ "date-parts": [[year]] }));
+    }
+    if let Some(isbn) = isbn(book) {
+        item.insert("ISBN".to_string(), json!(isbn));
+    }
+    if let Some(pages) = book.book.page_count.filter(|pages| *pages > 0) {
+        item.insert("number-of-pages".to_string(), json!(pages.to_string()));
+    }
+    Value::Object(item)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contributors::ContributorInput;
+    use crate::goodreads_importer::SeriesMarker;
+    use crate::models::Book;
+    use chrono::NaiveDate;
+
+    fn library_book(title: &str, contributors: &[(&str, ContributorRole)], year: Option<i32>, day: u32) -> LibraryBook {
+        LibraryBook {
+            book: Book {
+                id: Uuid::new_v4(),
+                user: Uuid::new_v4(),
+                shelf: Uuid::new_v4(),
+                title: Some(title.to_string()),
+                author: None,
+                isbn13: Some("9780552131061".to_string()),
+                isbn10: None,
+                google_books_id: None,
+                added_at: NaiveDate::from_ymd_opt(2020, 1, day).unwrap().and_time(chrono::NaiveTime::MIN),
+                publisher: Some("Corgi & Sons".to_string()),
+                published_year: year,
+                page_count: Some(316),
+                cover_url: None,
+            },
+            shelf: "read".to_string(),
+            contributors: contributors
+                .iter()
+                .map(|(name, role)| ContributorInput { name: name.to_string(), role: *role })
+                .collect(),
+            series: None,
+            tags: Vec::new(),
+            rating: None,
+            review: None,
+            notes: Vec::new(),
+            reads: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_split_name() {
+        assert_eq!(
+            split_name("Terry Pratchett"),
+            PersonName { family: "Pratchett".to_string(), given: Some("Terry".to_string()) }
+        );
+        assert_eq!(
+            split_name("Pratchett, Terry"),
+            PersonName { family: "Pratchett".to_string(), given: Some("Terry".to_string()) }
+        );
+        assert_eq!(split_name("Ludwig van Beethoven").family, "van Beethoven");
+        assert_eq!(split_name("Homer"), PersonName { family: "Homer".to_string(), given: None });
+    }
+
+    #[test]
+    fn test_citation_keys() {
+        let mut books = vec![
+            library_book("The Colour of Magic", &[("Terry Pratchett", ContributorRole::Author)], Some(1983), 3),
+            library_book("Die Blechtrommel", &[("Günter Grass", ContributorRole::Author)], Some(1959), 1),
+            library_book("Anthology", &[("Ann Editor", ContributorRole::Editor)], None, 2),
+            library_book("The Colour of Magic", &[("Terry Pratchett", ContributorRole::Author)], Some(1983), 1),
+            library_book("The Colour of Magic", &[("Terry Pratchett", ContributorRole::Author)], Some(1983), 4),
+        ];
+        // Two editions of the same book, the last one is a copy of the first on another shelf
+        for (book, isbn) in books.iter_mut().zip(["9780552124751", "9783423131049", "", "9780552166591", "9780552166591"]) {
+            book.book.isbn13 = Some(isbn.to_string()).filter(|isbn| !isbn.is_empty());
+        }
+        let mut assigned = HashMap::new();
+        let keys = citation_keys(&books, &mut assigned);
+        let key = |index: usize| keys[&books[index].book.id].as_str();
+        assert_eq!(
+            (0..5).map(key).collect::<Vec<_>>(),
+            vec!["pratchett1983coloura", "grass1959blechtrommel", "editorndanthology", "pratchett1983colour", "pratchett1983colour"]
+        );
+
+        // Copies are written once
+        let all: Vec<&LibraryBook> = books.iter().collect();
+        let ris = String::from_utf8(write_citations(&all, &keys, CitationFormat::Ris)).unwrap();
+        assert_eq!(ris.matches("ID  - pratchett1983colour\r\n").count(), 1);
+
+        // Keys don't depend on the order of the books
+        let reversed: Vec<LibraryBook> = books.into_iter().rev().collect();
+        let reversed_keys = citation_keys(&reversed, &mut HashMap::new());
+        assert_eq!(reversed_keys[&reversed[4].book.id], "pratchett1983coloura");
+        assert_eq!(reversed_keys[&reversed[1].book.id], "pratchett1983colour");
+
+        // Assigned keys are kept when books are removed or older books are added
+        let mut changed: Vec<LibraryBook> = reversed.into_iter().skip(2).collect();
+        let mut older = library_book("The Colour of Magic", &[("Terry Pratchett", ContributorRole::Author)], Some(1983), 1);
+        older.book.added_at -= chrono::Duration::days(30);
+        older.book.isbn13 = Some("9780062225672".to_string());
+        changed.push(older);
+        let changed_keys = citation_keys(&changed, &mut assigned);
+        assert_eq!(
+            changed.iter().map(|book| changed_keys[&book.book.id].as_str()).collect::<Vec<_>>(),
+            vec!["editorndanthology", "grass1959blechtrommel", "pratchett1983coloura", "pratchett1983colourb"]
+        );
+        assert_eq!(suffix(0), "a");
+        assert_eq!(suffix(26), "aa");
+    }
+
+    #[test]
+    fn test_write_bibtex() {
+        let mut book = library_book(
+            "Good Omens",
+            &[("Terry Pratchett", ContributorRole::Author), ("Neil Gaiman", ContributorRole::Author)],
+            Some(1990),
+            1,
+        );
+        book.series = Some(SeriesMarker { name: "Omens".to_string(), position: Some(1.0) });
+        let bibtex = String::from_utf8(write_citations(&[&book], &citation_keys(std::slice::from_ref(&book), &mut HashMap::new()), CitationFormat::BibTex)).unwrap();
+        assert_eq!(
+            bibtex,
+            "@book{pratchett1990good,
+  author = {Pratchett, Terry and Gaiman, Neil},
+  title = {Good Omens},
+  series = {Omens},
+  number = {1},
+  publisher = {Corgi \\& Sons},
+  year = {1990},
+  isbn = {9780552131061},
+  pagetotal = {316},
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_write_ris() {
+        let book = library_book(
+            "Mort",
+            &[("Terry Pratchett", ContributorRole::Author), ("Nigel Planer", ContributorRole::Narrator)],
+            Some(1987),
+            1,
+        );
+        let ris = String::from_utf8(write_citations(&[&book], &citation_keys(std::slice::from_ref(&book), &mut HashMap::new()), CitationFormat::Ris)).unwrap();
+        assert_eq!(
+            ris,
+            "TY  - BOOK\r\nID  - pratchett1987mort\r\nAU  - Pratchett, Terry\r\nTI  - Mort\r\nPB  - Corgi & Sons\r\nPY  - 1987\r\nSN  - 9780552131061\r\nSP  - 316\r\nER  - \r\n"
+        );
+    }
+
+    #[test]
+    fn test_write_csl_json() {
+        let book = library_book(
+            "Mort",
+            &[("Terry Pratchett", ContributorRole::Author), ("Nigel Planer", ContributorRole::Narrator)],
+            Some(1987),
+            1,
+        );
+        let items: Value = serde_json::from_slice(&write_citations(&[&book], &citation_keys(std::slice::from_ref(&book), &mut HashMap::new()), CitationFormat::CslJson)).unwrap();
+        assert_eq!(
+            items,
+            json!([{
+                "id": "pratchett1987mort",
+                "type": "book",
+                "title": "Mort",
+                "author": [{ "family": "Pratchett", "given": "Terry" }],
+                "narrator": [{ "family": "Planer", "given": "Nigel" }],
+                "publisher": "Corgi & Sons",
+                "issued": { "date-parts": [[1987]] },
+                "ISBN": "9780552131061",
+                "number-of-pages": "316",
+            }])
+        );
+    }
+}
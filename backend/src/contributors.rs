@@ -0,0 +1,455 @@
+use crate::auth::AuthUser;
+use crate::books::work_copies;
+use crate::db::connect;
+use crate::models::{Author, Book, BookContributor, ContributorRole};
+use crate::schema::authors::dsl::authors;
+use crate::schema::book_contributors::dsl::book_contributors;
+use crate::schema::books::dsl::books;
+use crate::{schema, ErrorResponse};
+use axum::routing::post;
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeSet, HashMap};
+use uuid::Uuid;
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/contributors", post(list_contributors))
+        .route("/api/contributors/books", post(list_contributor_books))
+        .route("/api/books/contributors/set", post(set_book_contributors))
+}
+
+/// A contributor of a book as entered by a user or found in an import.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ContributorInput {
+    pub name: String,
+    #[serde(default = "default_role")]
+    pub role: ContributorRole,
+}
+
+fn default_role() -> ContributorRole {
+    ContributorRole::Author
+}
+
+/// Splits a free-text author field like "Author A, Author B & Author C (Translator)" into its
+/// contributors. Roles given in parentheses after a name are recognized, everyone else is
+/// considered an author. Suffixes like "Jr." belong to the name before them.
+pub fn parse_contributors(text: &str) -> Vec<ContributorInput> {
+    let mut contributors: Vec<ContributorInput> = Vec::new();
+    for part in text.split([',', ';', '&']).flat_map(|p| p.split(" and ")) {
+        let part = part.trim();
+        let (name, role) = match part.strip_suffix(')').and_then(|p| p.rsplit_once('(')) {
+            Some((name, role)) => match parse_role(role) {
+                Some(role) => (name.trim(), Some(role)),
+                None => (part, None),
+            },
+            None => (part, None),
+        };
+        if is_name_suffix(name) {
+            if let Some(previous) = contributors.last_mut() {
+                previous.name = format!("{}, {}", previous.name, name);
+                previous.role = role.unwrap_or(previous.role);
+                continue;
+            }
+        }
+        let role = role.unwrap_or(ContributorRole::Author);
+        if name.is_empty() || contributors.iter().any(|c| c.name == name && c.role == role) {
+            continue;
+        }
+        contributors.push(ContributorInput {
+            name: name.to_string(),
+            role,
+        });
+    }
+    contributors
+}
+
+/// Whether a part of an author field is a suffix of the name before it, like in
+/// "Martin Luther King, Jr.".
+pub(crate) fn is_name_suffix(text: &str) -> bool {
+    matches!(
+        text.trim_end_matches('.').to_lowercase().as_str(),
+        "jr" | "sr" | "ii" | "iii" | "iv"
+    )
+}
+
+/// Recognizes the role of a contributor, e.g. "Translator" or "Narrated by".
+fn parse_role(text: &str) -> Option<ContributorRole> {
+    match text.trim().to_lowercase().as_str() {
+        "author" | "goodreads author" => Some(ContributorRole::Author),
+        "translator" | "translated by" | "translation" => Some(ContributorRole::Translator),
+        "editor" | "edited by" => Some(ContributorRole::Editor),
+        "illustrator" | "illustrated by" | "illustrations" => Some(ContributorRole::Illustrator),
+        "narrator" | "narrated by" | "reader" => Some(ContributorRole::Narrator),
+        _ => None,
+    }
+}
+
+/// Builds the display text of the authors of a book, which is kept in `books.author`.
+pub fn display_authors(contributors: &[ContributorInput]) -> Option<String> {
+    let names: Vec<&str> = contributors
+        .iter()
+        .filter(|c| c.role == ContributorRole::Author)
+        .map(|c| c.name.as_str())
+        .collect();
+    (!names.is_empty()).then(|| names.join(", "))
+}
+
+/// Links the given contributors to a book, creating the authors of the user as needed.
+pub fn attach_contributors(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    book_id: Uuid,
+    contributors: &[ContributorInput],
+) -> QueryResult<()> {
+    if contributors.is_empty() {
+        return Ok(());
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let new_authors: Vec<Author> = contributors
+        .iter()
+        .map(|c| c.name.as_str())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|name| Author {
+            id: Uuid::new_v4(),
+            user: user_id,
+            name: name.to_string(),
+            created_at: now,
+        })
+        .collect();
+
+    diesel::insert_into(authors)
+        .values(&new_authors)
+        .on_conflict((schema::authors::dsl::user, schema::authors::dsl::name))
+        .do_nothing()
+        .execute(connection)?;
+
+    let author_ids: HashMap<String, Uuid> = authors
+        .filter(schema::authors::dsl::user.eq(user_id))
+        .filter(schema::authors::dsl::name.eq_any(contributors.iter().map(|c| &c.name)))
+        .select((schema::authors::dsl::name, schema::authors::dsl::id))
+        .load::<(String, Uuid)>(connection)?
+        .into_iter()
+        .collect();
+
+    let links: Vec<BookContributor> = contributors
+        .iter()
+        .enumerate()
+        .filter_map(|(position, c)| {
+            author_ids.get(&c.name).map(|&author| BookContributor {
+                book: book_id,
+                author,
+                role: c.role,
+                position: position as i32,
+            })
+        })
+        .collect();
+
+    diesel::insert_into(book_contributors)
+        .values(&links)
+        .on_conflict_do_nothing()
+        .execute(connection)?;
+
+    Ok(())
+}
+
+/// Loads the contributors of the given books, ordered by their position.
+pub fn load_contributors(
+    connection: &mut PgConnection,
+    book_ids: &[Uuid],
+) -> QueryResult<HashMap<Uuid, Vec<serde_json::Value>>> {
+    let rows: Vec<(BookContributor, Author)> = book_contributors
+        .inner_join(authors)
+        .filter(schema::book_contributors::dsl::book.eq_any(book_ids))
+        .order(schema::book_contributors::dsl::position.asc())
+        .select((BookContributor::as_select(), Author::as_select()))
+        .load(connection)?;
+
+    let mut result: HashMap<Uuid, Vec<serde_json::Value>> = HashMap::new();
+    for (contributor, author) in rows {
+        result.entry(contributor.book).or_default().push(json!({
+            "id": author.id.to_string(),
+            "name": author.name,
+            "role": contributor.role.to_string(),
+        }));
+    }
+    Ok(result)
+}
+
+/// Response type for listing the contributors of a user.
+#[derive(Debug, Serialize)]
+pub struct ListContributorsResponse {
+    pub contributors: Vec<serde_json::Value>,
+}
+
+/// Lists the contributors of the books of a user, along with their roles and number of books.
+pub(crate) async fn list_contributors(auth: AuthUser) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let rows: Vec<(Author, Option<BookContributor>)> = match authors
+        .left_join(book_contributors)
+        .filter(schema::authors::dsl::user.eq(auth.0))
+        .order(schema::authors::dsl::name.asc())
+        .select((Author::as_select(), Option::<BookContributor>::as_select()))
+        .load(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading contributors: {}", e) }))),
+    };
+
+    let mut json_contributors: Vec<serde_json::Value> = Vec::new();
+    let mut current: Option<(Author, BTreeSet<String>, BTreeSet<Uuid>)> = None;
+    for (author, contributor) in rows {
+        if current.as_ref().is_some_and(|(a, _, _)| a.id != author.id) {
+            json_contributors.extend(current.take().map(contributor_json));
+        }
+        let entry = current.get_or_insert_with(|| (author, BTreeSet::new(), BTreeSet::new()));
+        if let Some(contributor) = contributor {
+            entry.1.insert(contributor.role.to_string());
+            entry.2.insert(contributor.book);
+        }
+    }
+    json_contributors.extend(current.map(contributor_json));
+
+    (StatusCode::OK, Json(json!(ListContributorsResponse { contributors: json_contributors })))
+}
+
+fn contributor_json((author, roles, book_ids): (Author, BTreeSet<String>, BTreeSet<Uuid>)) -> serde_json::Value {
+    json!({
+        "id": author.id.to_string(),
+        "name": author.name,
+        "roles": roles,
+        "book_count": book_ids.len(),
+    })
+}
+
+/// Request type for listing the books of a contributor.
+#[derive(Debug, Deserialize)]
+pub struct ContributorBooksRequest {
+    pub author_id: String,
+    pub role: Option<ContributorRole>,
+}
+
+/// Lists the books of a user a contributor worked on.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `author_id`: The UUID of the contributor.
+/// - `role`: Optional role to restrict the books to, e.g. `translator`.
+pub(crate) async fn list_contributor_books(
+    auth: AuthUser,
+    Json(payload): Json<ContributorBooksRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let author_id = match Uuid::parse_str(&payload.author_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid author ID.".to_string() }))),
+    };
+
+    let author: Author = match authors
+        .filter(schema::authors::dsl::id.eq(author_id))
+        .first(connection)
+    {
+        Ok(a) => a,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Contributor not found.".to_string() }))),
+    };
+
+    if author.user != auth.0 {
+        return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
+    }
+
+    let mut query = book_contributors
+        .inner_join(books)
+        .filter(schema::book_contributors::dsl::author.eq(author_id))
+        .filter(schema::books::dsl::user.eq(auth.0))
+        .into_boxed();
+    if let Some(role) = payload.role {
+        query = query.filter(schema::book_contributors::dsl::role.eq(role));
+    }
+
+    let results: Vec<(BookContributor, Book)> = match query
+        .order(schema::books::dsl::title.asc())
+        .select((BookContributor::as_select(), Book::as_select()))
+        .load(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading books: {}", e) }))),
+    };
+
+    let mut json_books = Vec::new();
+    for (contributor, book) in results {
+        json_books.push(json!({
+            "id": book.id.to_string(),
+            "shelf": book.shelf.to_string(),
+            "title": book.title,
+            "author": book.author,
+            "isbn13": book.isbn13,
+            "isbn10": book.isbn10,
+            "google_books_id": book.google_books_id,
+            "role": contributor.role.to_string(),
+            "added_at": book.added_at.to_string(),
+        }));
+    }
+
+    (StatusCode::OK, Json(json!({
+        "contributor": {
+            "id": author.id.to_string(),
+            "name": author.name,
+        },
+        "books": json_books,
+    })))
+}
+
+/// Request type for replacing the contributors of a book.
+#[derive(Debug, Deserialize)]
+pub struct SetBookContributorsRequest {
+    pub book_id: String,
+    pub contributors: Vec<ContributorInput>,
+}
+
+/// Replaces the contributors of a book, along with those of its copies on other shelves.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `book_id`: The UUID of the book.
+/// - `contributors`: The contributors in order, each with a `name` and a `role` (`author`,
+///   `translator`, `editor`, `illustrator` or `narrator`, defaults to `author`). At least one of
+///   them has to be an author, who is shown as the author of the book.
+pub(crate) async fn set_book_contributors(
+    auth: AuthUser,
+    Json(payload): Json<SetBookContributorsRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let book_id = match Uuid::parse_str(&payload.book_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+    };
+
+    let book: Book = match books
+        .filter(schema::books::dsl::id.eq(book_id))
+        .first(connection)
+    {
+        Ok(b) => b,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Book not found.".to_string() }))),
+    };
+
+    if book.user != auth.0 {
+        return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
+    }
+
+    let contributors: Vec<ContributorInput> = payload
+        .contributors
+        .into_iter()
+        .map(|c| ContributorInput { name: c.name.trim().to_string(), role: c.role })
+        .filter(|c| !c.name.is_empty())
+        .collect();
+    let Some(author) = display_authors(&contributors) else {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "At least one author is required.".to_string() })));
+    };
+
+    let result = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        let copies = work_copies(conn, &book)?;
+        diesel::delete(book_contributors.filter(schema::book_contributors::dsl::book.eq_any(&copies)))
+            .execute(conn)?;
+        for &copy in &copies {
+            attach_contributors(conn, auth.0, copy, &contributors)?;
+        }
+        diesel::update(books.filter(schema::books::dsl::id.eq_any(&copies)))
+            .set(schema::books::dsl::author.eq(author))
+            .execute(conn)?;
+        Ok(())
+    });
+
+    match result {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Contributors updated successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while updating the contributors: {}", e) }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    fn contributor(name: &str, role: ContributorRole) -> ContributorInput {
+        ContributorInput { name: name.to_string(), role }
+    }
+
+    #[test]
+    fn test_parse_contributors() {
+        assert_eq!(
+            parse_contributors("Terry Pratchett & Neil Gaiman, Stephen Briggs (Narrator)"),
+            vec![
+                contributor("Terry Pratchett", ContributorRole::Author),
+                contributor("Neil Gaiman", ContributorRole::Author),
+                contributor("Stephen Briggs", ContributorRole::Narrator),
+            ]
+        );
+        assert_eq!(
+            parse_contributors("Haruki Murakami; Jay Rubin (Translator)"),
+            vec![
+                contributor("Haruki Murakami", ContributorRole::Author),
+                contributor("Jay Rubin", ContributorRole::Translator),
+            ]
+        );
+        assert!(parse_contributors("  ").is_empty());
+    }
+
+    #[test]
+    fn test_parse_contributors_with_suffixes() {
+        assert_eq!(
+            parse_contributors("Martin Luther King, Jr., Coretta Scott King"),
+            vec![
+                contributor("Martin Luther King, Jr.", ContributorRole::Author),
+                contributor("Coretta Scott King", ContributorRole::Author),
+            ]
+        );
+        assert_eq!(
+            parse_contributors("Kurt Vonnegut; Henry Louis Gates, Jr (Editor)"),
+            vec![
+                contributor("Kurt Vonnegut", ContributorRole::Author),
+                contributor("Henry Louis Gates, Jr", ContributorRole::Editor),
+            ]
+        );
+        assert_eq!(parse_contributors("Jr."), vec![contributor("Jr.", ContributorRole::Author)]);
+    }
+
+    #[test]
+    fn test_display_authors_skips_other_roles() {
+        let contributors = parse_contributors("Haruki Murakami, Jay Rubin (Translator)");
+        assert_eq!(display_authors(&contributors).as_deref(), Some("Haruki Murakami"));
+    }
+
+    #[tokio::test]
+    async fn test_list_contributors_requires_auth() {
+        let app = Router::new().route("/api/contributors", post(list_contributors));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/contributors").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_list_contributor_books_requires_auth() {
+        let app = Router::new().route("/api/contributors/books", post(list_contributor_books));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/contributors/books").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_set_book_contributors_requires_auth() {
+        let app = Router::new().route("/api/books/contributors/set", post(set_book_contributors));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/books/contributors/set").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+}
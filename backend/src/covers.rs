@@ -0,0 +1,494 @@
+use crate::auth::AuthUser;
+use crate::db::connect;
+use crate::models::{Book, BookCover};
+use crate::schema::book_covers::dsl::book_covers;
+use crate::schema::books::dsl::books;
+use crate::storage::{cover_storage, Storage};
+use crate::{schema, ErrorResponse};
+use axum::extract::{DefaultBodyLimit, Multipart, Path, Query};
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use image::{DynamicImage, ImageFormat, ImageReader, Limits};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::Cursor;
+use uuid::Uuid;
+
+pub(crate) const MAX_COVER_BYTES: usize = 10 * 1024 * 1024; // 10 MB
+
+/// Covers may be at most this many pixels wide and high, larger images aren't decoded.
+pub(crate) const MAX_COVER_DIMENSION: u32 = 6000;
+
+/// Covers are immutable per upload, the ETag changes with every new upload.
+const CACHE_CONTROL: &str = "private, max-age=86400, must-revalidate";
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route(
+            "/api/books/cover/upload",
+            post(upload_cover).layer(DefaultBodyLimit::max(MAX_COVER_BYTES)),
+        )
+        .route("/api/books/cover/remove", post(remove_cover))
+        .route("/api/books/cover/{book_id}", get(get_cover))
+        .route("/api/covers/{cover_id}", get(get_cover_by_id))
+}
+
+/// The sizes a cover is served in.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoverSize {
+    Original,
+    Medium,
+    Small,
+}
+
+impl CoverSize {
+    /// The bounding box thumbnails of this size are fitted into.
+    fn bounds(self) -> Option<(u32, u32)> {
+        match self {
+            CoverSize::Original => None,
+            CoverSize::Medium => Some((400, 600)),
+            CoverSize::Small => Some((160, 240)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            CoverSize::Original => "original",
+            CoverSize::Medium => "medium",
+            CoverSize::Small => "small",
+        }
+    }
+}
+
+/// Builds the storage key of a cover in the given size.
+pub fn cover_key(book_id: Uuid, cover_id: Uuid, size: CoverSize) -> String {
+    format!("{}/{}/{}", book_id, cover_id, size.name())
+}
+
+/// Builds the URL a cover is served at without authentication, e.g. for `<img>` tags.
+pub fn cover_url(cover_id: Uuid) -> String {
+    format!("/api/covers/{}", cover_id)
+}
+
+/// Loads the IDs of the custom covers of books, keyed by book.
+pub fn load_cover_ids(connection: &mut PgConnection, book_ids: &[Uuid]) -> QueryResult<HashMap<Uuid, Uuid>> {
+    Ok(book_covers
+        .filter(schema::book_covers::dsl::book.eq_any(book_ids))
+        .select((schema::book_covers::dsl::book, schema::book_covers::dsl::id))
+        .load::<(Uuid, Uuid)>(connection)?
+        .into_iter()
+        .collect())
+}
+
+/// An uploaded cover along with its generated thumbnails.
+pub struct ProcessedCover {
+    pub content_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub medium: Vec<u8>,
+    pub small: Vec<u8>,
+}
+
+/// Validates an uploaded image and renders its thumbnails as JPEG.
+pub fn process_cover(data: &[u8]) -> Result<ProcessedCover, String> {
+    let format = image::guess_format(data).map_err(|_| "Unsupported image format.".to_string())?;
+    if !matches!(
+        format,
+        ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Gif
+    ) {
+        return Err("Unsupported image format. Use JPEG, PNG, WebP or GIF.".to_string());
+    }
+
+    // Small files may still decode into huge images, so check their size first
+    let (width, height) = ImageReader::with_format(Cursor::new(data), format)
+        .into_dimensions()
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+    if width > MAX_COVER_DIMENSION || height > MAX_COVER_DIMENSION {
+        return Err(format!(
+            "Image too large, covers may be at most {0}x{0} pixels.",
+            MAX_COVER_DIMENSION
+        ));
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_COVER_DIMENSION);
+    limits.max_image_height = Some(MAX_COVER_DIMENSION);
+    let mut reader = ImageReader::with_format(Cursor::new(data), format);
+    reader.limits(limits);
+    let image = reader.decode().map_err(|e| format!("Failed to decode image: {}", e))?;
+
+    let render = |size: CoverSize| -> Result<Vec<u8>, String> {
+        let (width, height) = size.bounds().unwrap_or((image.width(), image.height()));
+        let thumbnail = DynamicImage::ImageRgb8(image.thumbnail(width, height).to_rgb8());
+        let mut bytes = Cursor::new(Vec::new());
+        thumbnail
+            .write_to(&mut bytes, ImageFormat::Jpeg)
+            .map_err(|e| format!("Failed to render thumbnail: {}", e))?;
+        Ok(bytes.into_inner())
+    };
+
+    Ok(ProcessedCover {
+        content_type: format.to_mime_type(),
+        width: image.width(),
+        height: image.height(),
+        medium: render(CoverSize::Medium)?,
+        small: render(CoverSize::Small)?,
+    })
+}
+
+/// Uploads a custom cover for a book.
+///
+/// This route accepts a multipart form data with the following structure:
+/// - `book_id`: The UUID of the book to upload the cover for.
+/// - `file`: The image file (JPEG, PNG, WebP or GIF) of at most 6000x6000 pixels.
+pub(crate) async fn upload_cover(auth: AuthUser, mut multipart: Multipart) -> impl IntoResponse {
+    let mut book_id = None;
+    let mut file_data = None;
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": format!("Failed to read multipart data: {}", e) })),
+                );
+            }
+        };
+
+        match field.name() {
+            Some("book_id") => match field.text().await {
+                Ok(text) => book_id = Some(text),
+                Err(e) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({ "error": format!("Failed to read book_id field: {}", e) })),
+                    );
+                }
+            },
+            Some("file") => match field.bytes().await {
+                Ok(bytes) => file_data = Some(bytes),
+                Err(e) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({ "error": format!("Failed to read file field: {}", e) })),
+                    );
+                }
+            },
+            _ => continue,
+        }
+    }
+
+    let Some(book_id) = book_id.and_then(|id| Uuid::parse_str(id.trim()).ok()) else {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() })));
+    };
+    let Some(file_data) = file_data else {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": "Missing file." })));
+    };
+
+    let connection = &mut connect();
+
+    let book: Book = match books
+        .filter(schema::books::dsl::id.eq(book_id))
+        .first(connection)
+    {
+        Ok(b) => b,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Book not found.".to_string() }))),
+    };
+
+    if book.user != auth.0 {
+        return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
+    }
+
+    match store_cover(connection, auth.0, book_id, file_data.to_vec()).await {
+        Ok(()) => (StatusCode::CREATED, Json(json!({ "message": "Cover uploaded successfully." }))),
+        Err((status, error)) => (status, Json(json!(ErrorResponse { error }))),
+    }
+}
+
+/// Stores a cover of a book in all sizes, replacing its previous cover.
+///
+/// Fails with `BAD_REQUEST` if the image can't be processed.
+pub async fn store_cover(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    book_id: Uuid,
+    data: Vec<u8>,
+) -> Result<(), (StatusCode, String)> {
+    let processed = match tokio::task::spawn_blocking(move || process_cover(&data).map(|p| (p, data))).await {
+        Ok(Ok(processed)) => processed,
+        Ok(Err(e)) => return Err((StatusCode::BAD_REQUEST, e)),
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Error while processing the cover: {}", e))),
+    };
+    let (processed, original) = processed;
+
+    let previous: Option<BookCover> = match book_covers
+        .filter(schema::book_covers::dsl::book.eq(book_id))
+        .first(connection)
+        .optional()
+    {
+        Ok(previous) => previous,
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Error while loading the cover: {}", e))),
+    };
+
+    let cover = BookCover {
+        book: book_id,
+        id: Uuid::new_v4(),
+        user: user_id,
+        content_type: processed.content_type.to_string(),
+        width: processed.width as i32,
+        height: processed.height as i32,
+        uploaded_at: chrono::Utc::now().naive_utc(),
+    };
+
+    let storage = cover_storage();
+    let uploads = [
+        (CoverSize::Original, original, processed.content_type),
+        (CoverSize::Medium, processed.medium, "image/jpeg"),
+        (CoverSize::Small, processed.small, "image/jpeg"),
+    ];
+    for (size, data, content_type) in uploads {
+        if let Err(e) = storage.put(&cover_key(book_id, cover.id, size), data, content_type).await {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Error while storing the cover: {}", e)));
+        }
+    }
+
+    if let Err(e) = diesel::insert_into(book_covers)
+        .values(&cover)
+        .on_conflict(schema::book_covers::dsl::book)
+        .do_update()
+        .set((
+            schema::book_covers::dsl::id.eq(cover.id),
+            schema::book_covers::dsl::content_type.eq(&cover.content_type),
+            schema::book_covers::dsl::width.eq(cover.width),
+            schema::book_covers::dsl::height.eq(cover.height),
+            schema::book_covers::dsl::uploaded_at.eq(cover.uploaded_at),
+        ))
+        .execute(connection)
+    {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Error while saving the cover: {}", e)));
+    }
+
+    if let Some(previous) = previous {
+        delete_files(&previous).await;
+    }
+    Ok(())
+}
+
+/// Removes the covers of books which are about to be deleted, returning them so that their files
+/// can be deleted with `delete_files` once the deletion is committed.
+pub fn remove_covers(connection: &mut PgConnection, book_ids: &[Uuid]) -> QueryResult<Vec<BookCover>> {
+    diesel::delete(book_covers.filter(schema::book_covers::dsl::book.eq_any(book_ids))).get_results(connection)
+}
+
+/// Removes all stored sizes of a cover, logging failures as the database row is already gone.
+pub async fn delete_files(cover: &BookCover) {
+    for size in [CoverSize::Original, CoverSize::Medium, CoverSize::Small] {
+        if let Err(e) = cover_storage().delete(&cover_key(cover.book, cover.id, size)).await {
+            tracing::warn!("Failed to delete cover {} of book {}: {}", cover.id, cover.book, e);
+        }
+    }
+}
+
+/// Request type for removing the custom cover of a book.
+#[derive(Debug, Deserialize)]
+pub struct RemoveCoverRequest {
+    pub book_id: String,
+}
+
+/// Removes the custom cover of a book.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `book_id`: The UUID of the book to remove the cover from.
+pub(crate) async fn remove_cover(
+    auth: AuthUser,
+    Json(payload): Json<RemoveCoverRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let book_id = match Uuid::parse_str(&payload.book_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+    };
+
+    let cover: BookCover = match book_covers
+        .filter(schema::book_covers::dsl::book.eq(book_id))
+        .first(connection)
+    {
+        Ok(c) => c,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Cover not found.".to_string() }))),
+    };
+
+    if cover.user != auth.0 {
+        return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
+    }
+
+    match diesel::delete(book_covers.filter(schema::book_covers::dsl::book.eq(book_id))).execute(connection) {
+        Ok(_) => {
+            delete_files(&cover).await;
+            (StatusCode::OK, Json(json!({ "message": "Cover removed successfully." })))
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while removing the cover: {}", e) }))),
+    }
+}
+
+/// Query parameters for fetching a cover.
+#[derive(Debug, Deserialize)]
+pub struct CoverQuery {
+    pub size: Option<CoverSize>,
+}
+
+/// Serves the custom cover of a book.
+///
+/// The size is selected with the `size` query parameter (`original`, `medium` or `small`) and
+/// defaults to `medium`. Responses carry an ETag and caching headers, so clients can revalidate
+/// with `If-None-Match` instead of downloading the image again. Browsers can't send the JWT with
+/// `<img>` tags, they load covers from `/api/covers/{cover_id}` instead.
+pub(crate) async fn get_cover(
+    auth: AuthUser,
+    Path(book_id): Path<Uuid>,
+    Query(query): Query<CoverQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let connection = &mut connect();
+
+    let cover: BookCover = match book_covers
+        .filter(schema::book_covers::dsl::book.eq(book_id))
+        .filter(schema::book_covers::dsl::user.eq(auth.0))
+        .first(connection)
+    {
+        Ok(c) => c,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Cover not found.".to_string() }))).into_response(),
+    };
+
+    serve_cover(&cover, query.size.unwrap_or(CoverSize::Medium), &headers).await
+}
+
+/// Serves a cover by its ID, without authentication so that browsers can load it with `<img>` tags.
+///
+/// Cover IDs are random and change with every upload, so only users who were given the URL of a
+/// cover can load it, e.g. as `custom_cover_url` of `/api/books/info`. The size is selected with
+/// the `size` query parameter like for `/api/books/cover/{book_id}`.
+pub(crate) async fn get_cover_by_id(
+    Path(cover_id): Path<Uuid>,
+    Query(query): Query<CoverQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let connection = &mut connect();
+
+    let cover: BookCover = match book_covers
+        .filter(schema::book_covers::dsl::id.eq(cover_id))
+        .first(connection)
+    {
+        Ok(c) => c,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Cover not found.".to_string() }))).into_response(),
+    };
+
+    serve_cover(&cover, query.size.unwrap_or(CoverSize::Medium), &headers).await
+}
+
+/// Builds the response for a cover in the given size, honouring `If-None-Match`.
+pub async fn serve_cover(cover: &BookCover, size: CoverSize, headers: &HeaderMap) -> Response {
+    let etag = format!("\"{}-{}\"", cover.id, size.name());
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, CACHE_CONTROL.to_string()),
+    ];
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|tag| tag.trim() == etag));
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+
+    let content_type = match size {
+        CoverSize::Original => cover.content_type.clone(),
+        _ => "image/jpeg".to_string(),
+    };
+
+    match cover_storage().get(&cover_key(cover.book, cover.id, size)).await {
+        Ok(Some(data)) => {
+            let mut response = (StatusCode::OK, cache_headers, data).into_response();
+            if let Ok(value) = HeaderValue::from_str(&content_type) {
+                response.headers_mut().insert(header::CONTENT_TYPE, value);
+            }
+            response
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Cover not found.".to_string() }))).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while loading the cover: {}", e) }))).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use image::{ImageBuffer, Rgba};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[test]
+    fn test_process_cover_renders_thumbnails() {
+        let image = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(800, 1200, Rgba([200, 10, 10, 255])));
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, ImageFormat::Png).unwrap();
+
+        let processed = process_cover(png.get_ref()).unwrap();
+        assert_eq!(processed.content_type, "image/png");
+        assert_eq!((processed.width, processed.height), (800, 1200));
+
+        let small = image::load_from_memory(&processed.small).unwrap();
+        assert_eq!((small.width(), small.height()), (160, 240));
+        let medium = image::load_from_memory(&processed.medium).unwrap();
+        assert_eq!((medium.width(), medium.height()), (400, 600));
+    }
+
+    #[test]
+    fn test_process_cover_rejects_non_images() {
+        assert!(process_cover(b"not an image").is_err());
+    }
+
+    #[test]
+    fn test_process_cover_rejects_huge_images() {
+        let image = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, MAX_COVER_DIMENSION + 1, Rgba([0, 0, 0, 255])));
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, ImageFormat::Png).unwrap();
+
+        assert_eq!(
+            process_cover(png.get_ref()).err().as_deref(),
+            Some("Image too large, covers may be at most 6000x6000 pixels.")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upload_cover_requires_auth() {
+        let app = Router::new().route("/api/books/cover/upload", post(upload_cover));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/books/cover/upload").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_get_cover_requires_auth() {
+        let app = Router::new().route("/api/books/cover/{book_id}", get(get_cover));
+        let response = app
+            .oneshot(Request::builder().uri(format!("/api/books/cover/{}", Uuid::new_v4())).body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_cover_url() {
+        let cover_id = Uuid::new_v4();
+        assert_eq!(cover_url(cover_id), format!("/api/covers/{}", cover_id));
+    }
+}
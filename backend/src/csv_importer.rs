@@ -0,0 +1,236 @@
+use crate::goodreads_importer::{parse_date, split_series};
+use crate::importer::{
+    decode_text, parse_author_field, primary_author, shelf_name, split_isbn, ImportedBook, ImportedRows, Importer,
+};
+use crate::models::ReadingMode;
+use crate::readings::ReadDates;
+use chrono::NaiveDate;
+use csv::ReaderBuilder;
+use serde::{Deserialize, Serialize};
+
+/// Which column of a CSV file holds which field of a book, columns are referred to by header.
+///
+/// Mappings are stored as import templates, so that a spreadsheet kept up to date over the years
+/// can be imported again without mapping it anew.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnMapping {
+    pub title: String,
+    pub author: Option<String>,
+    pub isbn: Option<String>,
+    /// Books without a shelf are placed on `read` if they have a read date and `to-read` otherwise.
+    pub shelf: Option<String>,
+    pub date_read: Option<String>,
+    pub pages: Option<String>,
+    /// Format of the read dates, e.g. "%d.%m.%Y", dates like "2021-05-03" are read by default.
+    pub date_format: Option<String>,
+    /// The separator of the columns, the most common of `,`, `;` and tab if not given.
+    pub delimiter: Option<char>,
+}
+
+impl ColumnMapping {
+    /// Returns the separator of the columns of a file.
+    fn delimiter(&self, text: &str) -> u8 {
+        if let Some(delimiter) = self.delimiter.filter(char::is_ascii) {
+            return delimiter as u8;
+        }
+        let header = text.lines().next().unwrap_or_default();
+        [b',', b';', b'\t']
+            .into_iter()
+            .max_by_key(|delimiter| header.bytes().filter(|b| b == delimiter).count())
+            .unwrap_or(b',')
+    }
+
+    fn parse_date(&self, value: &str) -> Option<NaiveDate> {
+        match &self.date_format {
+            Some(format) => NaiveDate::parse_from_str(value.trim(), format).ok(),
+            None => parse_date(value),
+        }
+    }
+}
+
+/// Imports any CSV file, using the column mapping in the options of the import.
+///
+/// The format of such files can't be told apart from others, so this importer is never detected
+/// and has to be chosen.
+pub struct CsvImporter;
+
+impl Importer for CsvImporter {
+    fn source(&self) -> &'static str {
+        "csv"
+    }
+
+    fn name(&self) -> &'static str {
+        "CSV"
+    }
+
+    fn detect(&self, _data: &[u8]) -> bool {
+        false
+    }
+
+    fn detectable(&self) -> bool {
+        false
+    }
+
+    fn read(&self, data: &[u8], options: &serde_json::Value) -> Result<ImportedRows, String> {
+        let mapping: ColumnMapping = serde_json::from_value(options["mapping"].clone())
+            .map_err(|_| "Missing column mapping, a CSV file needs one.".to_string())?;
+        let text = decode_text(data).map_err(|e| format!("Failed to read CSV file: {}", e))?;
+        read_rows(&mapping, &text)
+    }
+}
+
+/// Reads the rows of a CSV file according to the mapping, failing if a mapped column is missing.
+fn read_rows(mapping: &ColumnMapping, text: &str) -> Result<ImportedRows, String> {
+    let mut rdr = ReaderBuilder::new()
+        .delimiter(mapping.delimiter(text))
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = rdr.headers().map_err(|e| format!("Failed to parse CSV file: {}", e))?.clone();
+
+    let column = |name: &Option<String>| -> Result<Option<usize>, String> {
+        let Some(name) = name.as_deref().map(str::trim).filter(|name| !name.is_empty()) else {
+            return Ok(None);
+        };
+        headers
+            .iter()
+            .position(|header| header.trim() == name)
+            .map(Some)
+            .ok_or_else(|| format!("Column '{}' not found in the CSV file.", name))
+    };
+    let title = column(&Some(mapping.title.clone()))?;
+    let author = column(&mapping.author)?;
+    let isbn = column(&mapping.isbn)?;
+    let shelf = column(&mapping.shelf)?;
+    let date_read = column(&mapping.date_read)?;
+    let pages = column(&mapping.pages)?;
+
+    Ok(rdr
+        .records()
+        .map(|row| {
+            let row = row.map_err(|e| e.to_string())?;
+            let field = |index: Option<usize>| {
+                index
+                    .and_then(|index| row.get(index))
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+            };
+
+            let raw_title = field(title).ok_or_else(|| "Missing title.".to_string())?;
+            let (plain_title, series) = split_series(raw_title);
+            let author_text = field(author).unwrap_or_default();
+            let contributors = parse_author_field(author_text);
+            let (isbn13, isbn10) = field(isbn).map(split_isbn).unwrap_or_default();
+
+            let finished_at = match field(date_read) {
+                Some(value) => Some(
+                    mapping
+                        .parse_date(value)
+                        .ok_or_else(|| format!("Invalid read date '{}'.", value))?,
+                ),
+                None => None,
+            };
+            let shelf = match field(shelf) {
+                Some(shelf) => shelf_name(shelf),
+                None if finished_at.is_some() => "read".to_string(),
+                None => "to-read".to_string(),
+            };
+
+            Ok(ImportedBook {
+                raw_title: raw_title.to_string(),
+                title: plain_title,
+                series,
+                author: primary_author(&contributors).unwrap_or_else(|| author_text.to_string()),
+                contributors,
+                isbn13,
+                isbn10,
+                publisher: None,
+                published_year: None,
+                page_count: field(pages).and_then(|pages| pages.parse().ok()),
+                added_on: None,
+                shelf,
+                other_shelves: Vec::new(),
+                tags: Vec::new(),
+                rating: None,
+                review: None,
+                note: None,
+                reading_mode: ReadingMode::Pages,
+                reads: finished_at
+                    .map(|finished_at| ReadDates { started_at: None, finished_at })
+                    .into_iter()
+                    .collect(),
+                undated_reads: 0,
+                unfinished_read: None,
+                has_cover: false,
+                cover_file: None,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SPREADSHEET: &str = "Buch;Autor;ISBN;Gelesen am;Seiten;Regal
+Mort (Discworld, #4);Terry Pratchett;978-0-552-13106-1;03.05.2021;316;
+Piranesi;Susanna Clarke;;;272;Wunschliste
+Dune;Frank Herbert;;31.02.2020;;
+Good Omens;\"Pratchett, Terry; Gaiman, Neil\";;;;
+A Wizard of Earthsea;Le Guin, Ursula K.;;;;
+";
+
+    fn mapping() -> ColumnMapping {
+        ColumnMapping {
+            title: "Buch".to_string(),
+            author: Some("Autor".to_string()),
+            isbn: Some("ISBN".to_string()),
+            shelf: Some("Regal".to_string()),
+            date_read: Some("Gelesen am".to_string()),
+            pages: Some("Seiten".to_string()),
+            date_format: Some("%d.%m.%Y".to_string()),
+            delimiter: None,
+        }
+    }
+
+    #[test]
+    fn test_read_mapped_rows() {
+        let rows = CsvImporter
+            .read(SPREADSHEET.as_bytes(), &json!({ "mapping": mapping() }))
+            .unwrap();
+        assert_eq!(rows.len(), 5);
+
+        let mort = rows[0].as_ref().unwrap();
+        assert_eq!(mort.title, "Mort");
+        assert_eq!(mort.series.as_ref().map(|s| s.name.as_str()), Some("Discworld"));
+        assert_eq!(mort.isbn13.as_deref(), Some("9780552131061"));
+        assert_eq!(mort.page_count, Some(316));
+        assert_eq!(mort.shelf, "read");
+        assert_eq!(mort.reads[0].finished_at, NaiveDate::from_ymd_opt(2021, 5, 3).unwrap());
+
+        let piranesi = rows[1].as_ref().unwrap();
+        assert_eq!(piranesi.shelf, "wunschliste");
+        assert!(piranesi.reads.is_empty());
+
+        assert_eq!(rows[2].as_ref().err().map(String::as_str), Some("Invalid read date '31.02.2020'."));
+
+        let good_omens = rows[3].as_ref().unwrap();
+        assert_eq!(good_omens.author, "Terry Pratchett");
+        assert_eq!(good_omens.contributors.len(), 2);
+
+        let earthsea = rows[4].as_ref().unwrap();
+        assert_eq!(earthsea.author, "Ursula K. Le Guin");
+        assert_eq!(earthsea.contributors.len(), 1);
+    }
+
+    #[test]
+    fn test_missing_mapping() {
+        assert!(CsvImporter.read(SPREADSHEET.as_bytes(), &json!({})).is_err());
+        let mapping = ColumnMapping { pages: Some("Pages".to_string()), ..mapping() };
+        assert_eq!(
+            CsvImporter.read(SPREADSHEET.as_bytes(), &json!({ "mapping": mapping })).err().as_deref(),
+            Some("Column 'Pages' not found in the CSV file.")
+        );
+        assert!(!CsvImporter.detect(SPREADSHEET.as_bytes()));
+    }
+}
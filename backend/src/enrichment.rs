@@ -0,0 +1,679 @@
+use crate::auth::AuthUser;
+use crate::db::connect;
+use crate::goodreads_importer::split_series;
+use crate::models::{Book, BookEnrichment, EnrichmentStatus};
+use crate::schema::book_enrichments::dsl::book_enrichments;
+use crate::schema::books::dsl::books;
+use crate::{schema, ErrorResponse};
+use axum::routing::post;
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Number of books looked up per run of the background worker.
+const BATCH_SIZE: i64 = 20;
+
+/// Number of failed lookups after which a book is no longer retried automatically.
+const MAX_ATTEMPTS: i32 = 3;
+
+/// Minimum confidence a title + author match needs to be applied to a book.
+const MIN_CONFIDENCE: f32 = 0.75;
+
+/// Time the worker waits before looking for new books once everything is enriched.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Time between two lookups to stay well below the rate limits of the provider.
+const LOOKUP_DELAY: Duration = Duration::from_secs(1);
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/books/enrichment/status", post(enrichment_status))
+        .route("/api/books/enrichment/retry", post(retry_enrichment))
+}
+
+/// Metadata of a book as reported by a metadata provider.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BookMetadata {
+    pub provider_id: String,
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub isbn13: Option<String>,
+    pub isbn10: Option<String>,
+    pub publisher: Option<String>,
+    pub published_year: Option<i32>,
+    pub page_count: Option<i32>,
+    pub cover_url: Option<String>,
+}
+
+/// The providers books can be enriched from.
+///
+/// The provider is configured via the `METADATA_PROVIDER` environment variable. Supported values
+/// are `google-books` (the default) and `none`, which disables the enrichment worker.
+pub enum MetadataProvider {
+    GoogleBooks {
+        client: reqwest::Client,
+        api_key: Option<String>,
+    },
+}
+
+impl MetadataProvider {
+    /// Creates the provider configured in the environment, if any.
+    pub fn from_env() -> Option<MetadataProvider> {
+        let provider = env::var("METADATA_PROVIDER").unwrap_or_else(|_| "google-books".to_string());
+        match provider.trim() {
+            "google-books" => Some(MetadataProvider::GoogleBooks {
+                client: reqwest::Client::new(),
+                api_key: env::var("GOOGLE_BOOKS_API_KEY").ok().filter(|k| !k.is_empty()),
+            }),
+            "none" | "" => None,
+            other => {
+                warn!("unknown metadata provider '{}', enrichment is disabled", other);
+                None
+            }
+        }
+    }
+
+    /// The name under which matches of this provider are recorded.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MetadataProvider::GoogleBooks { .. } => "google-books",
+        }
+    }
+
+    /// Searches the provider and returns all candidates for the given query.
+    async fn search(&self, query: &str) -> Result<Vec<BookMetadata>, reqwest::Error> {
+        match self {
+            MetadataProvider::GoogleBooks { client, api_key } => {
+                let mut request = client
+                    .get("https://www.googleapis.com/books/v1/volumes")
+                    .query(&[("q", query), ("printType", "books")]);
+                if let Some(key) = api_key {
+                    request = request.query(&[("key", key)]);
+                }
+                let response: VolumesResponse =
+                    request.send().await?.error_for_status()?.json().await?;
+                Ok(response.items.into_iter().map(BookMetadata::from).collect())
+            }
+        }
+    }
+
+    /// Looks up candidates by ISBN.
+    async fn lookup_isbn(&self, isbn: &str) -> Result<Vec<BookMetadata>, reqwest::Error> {
+        self.search(&format!("isbn:{}", isbn)).await
+    }
+
+    /// Looks up candidates by title and, if known, author.
+    async fn lookup_title(
+        &self,
+        title: &str,
+        author: Option<&str>,
+    ) -> Result<Vec<BookMetadata>, reqwest::Error> {
+        let mut query = format!("intitle:{}", split_series(title).0);
+        if let Some(author) = author.filter(|a| !a.trim().is_empty()) {
+            query.push_str(&format!(" inauthor:{}", author));
+        }
+        self.search(&query).await
+    }
+}
+
+/// Response of the Google Books volumes endpoint.
+#[derive(Debug, Deserialize)]
+struct VolumesResponse {
+    #[serde(default)]
+    items: Vec<Volume>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Volume {
+    id: String,
+    volume_info: VolumeInfo,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VolumeInfo {
+    title: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    publisher: Option<String>,
+    published_date: Option<String>,
+    page_count: Option<i32>,
+    #[serde(default)]
+    industry_identifiers: Vec<IndustryIdentifier>,
+    image_links: Option<ImageLinks>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IndustryIdentifier {
+    #[serde(rename = "type")]
+    kind: String,
+    identifier: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageLinks {
+    thumbnail: Option<String>,
+}
+
+impl From<Volume> for BookMetadata {
+    fn from(volume: Volume) -> Self {
+        let info = volume.volume_info;
+        let identifier = |kind: &str| {
+            info.industry_identifiers
+                .iter()
+                .find(|i| i.kind == kind)
+                .map(|i| i.identifier.clone())
+        };
+        BookMetadata {
+            isbn13: identifier("ISBN_13"),
+            isbn10: identifier("ISBN_10"),
+            provider_id: volume.id,
+            title: info.title,
+            authors: info.authors,
+            publisher: info.publisher,
+            published_year: info
+                .published_date
+                .as_deref()
+                .and_then(|d| d.get(..4))
+                .and_then(|y| y.parse().ok()),
+            page_count: info.page_count.filter(|&p| p > 0),
+            cover_url: info
+                .image_links
+                .and_then(|l| l.thumbnail)
+                .map(|url| url.replacen("http://", "https://", 1)),
+        }
+    }
+}
+
+/// Outcome of resolving a single book against the provider.
+#[derive(Debug)]
+enum Resolution {
+    Matched {
+        metadata: BookMetadata,
+        matched_by: &'static str,
+        confidence: f32,
+    },
+    Unmatched {
+        confidence: Option<f32>,
+    },
+}
+
+/// Resolves a book via its ISBN, falling back to a title and author search.
+async fn resolve(provider: &MetadataProvider, book: &Book) -> Result<Resolution, reqwest::Error> {
+    for isbn in [&book.isbn13, &book.isbn10].into_iter().flatten() {
+        if isbn.is_empty() {
+            continue;
+        }
+        let candidates = provider.lookup_isbn(isbn).await?;
+        if let Some(metadata) = candidates.into_iter().find(|c| {
+            c.isbn13.as_deref() == Some(isbn.as_str()) || c.isbn10.as_deref() == Some(isbn.as_str())
+        }) {
+            return Ok(Resolution::Matched {
+                metadata,
+                matched_by: "isbn",
+                confidence: 1.0,
+            });
+        }
+    }
+
+    let Some(title) = book.title.as_deref().filter(|t| !t.trim().is_empty()) else {
+        return Ok(Resolution::Unmatched { confidence: None });
+    };
+
+    let candidates = provider.lookup_title(title, book.author.as_deref()).await?;
+    let best = candidates
+        .into_iter()
+        .map(|c| (match_confidence(title, book.author.as_deref(), &c), c))
+        .max_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    Ok(match best {
+        Some((confidence, metadata)) if confidence >= MIN_CONFIDENCE => Resolution::Matched {
+            metadata,
+            matched_by: "title_author",
+            confidence,
+        },
+        Some((confidence, _)) => Resolution::Unmatched {
+            confidence: Some(confidence),
+        },
+        None => Resolution::Unmatched { confidence: None },
+    })
+}
+
+/// Splits a text into lowercase alphanumeric words.
+fn tokens(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+/// Computes the Jaccard similarity of the words of two texts.
+fn similarity(a: &str, b: &str) -> f32 {
+    let (a, b) = (tokens(a), tokens(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f32 / a.union(&b).count() as f32
+}
+
+/// Scores how likely a candidate is the book with the given title and author, from 0 to 1.
+fn match_confidence(title: &str, author: Option<&str>, candidate: &BookMetadata) -> f32 {
+    let candidate_title = candidate.title.as_deref().unwrap_or_default();
+    let title_score = similarity(title, candidate_title)
+        .max(similarity(&split_series(title).0, candidate_title));
+
+    match author.filter(|a| !a.trim().is_empty()) {
+        Some(author) => {
+            let author_score = candidate
+                .authors
+                .iter()
+                .map(|a| similarity(author, a))
+                .fold(0.0, f32::max);
+            0.6 * title_score + 0.4 * author_score
+        }
+        None => title_score,
+    }
+}
+
+/// Fields of a book that are updated after a successful match.
+#[derive(Debug, Default, PartialEq, AsChangeset)]
+#[diesel(table_name = crate::schema::books)]
+struct BookChanges {
+    google_books_id: Option<String>,
+    title: Option<String>,
+    author: Option<String>,
+    isbn13: Option<String>,
+    isbn10: Option<String>,
+    publisher: Option<String>,
+    published_year: Option<i32>,
+    page_count: Option<i32>,
+    cover_url: Option<String>,
+}
+
+/// Determines the changes to apply to a book, only filling fields which are still empty so that
+/// anything entered by the user is kept.
+fn missing_fields(book: &Book, metadata: BookMetadata) -> BookChanges {
+    fn fill<T>(current: &Option<T>, value: Option<T>) -> Option<T> {
+        if current.is_some() {
+            None
+        } else {
+            value
+        }
+    }
+    fn fill_text(current: &Option<String>, value: Option<String>) -> Option<String> {
+        match current {
+            Some(text) if !text.trim().is_empty() => None,
+            _ => value.filter(|v| !v.trim().is_empty()),
+        }
+    }
+
+    let authors = (!metadata.authors.is_empty()).then(|| metadata.authors.join(", "));
+    BookChanges {
+        google_books_id: fill_text(&book.google_books_id, Some(metadata.provider_id)),
+        title: fill_text(&book.title, metadata.title),
+        author: fill_text(&book.author, authors),
+        isbn13: fill_text(&book.isbn13, metadata.isbn13),
+        isbn10: fill_text(&book.isbn10, metadata.isbn10),
+        publisher: fill_text(&book.publisher, metadata.publisher),
+        published_year: fill(&book.published_year, metadata.published_year),
+        page_count: fill(&book.page_count, metadata.page_count),
+        cover_url: fill_text(&book.cover_url, metadata.cover_url),
+    }
+}
+
+/// Starts the background worker enriching books, if a metadata provider is configured.
+pub fn spawn_worker() {
+    let Some(provider) = MetadataProvider::from_env() else {
+        info!("no metadata provider configured, book enrichment is disabled");
+        return;
+    };
+
+    info!("starting book enrichment worker using {}...", provider.name());
+    tokio::spawn(async move {
+        loop {
+            match enrich_batch(&provider).await {
+                Ok(0) => tokio::time::sleep(POLL_INTERVAL).await,
+                Ok(count) => info!("enriched a batch of {} books", count),
+                Err(e) => {
+                    error!("Error while enriching books: {}", e);
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    });
+}
+
+/// Enriches the next batch of books without metadata and returns how many were processed.
+async fn enrich_batch(provider: &MetadataProvider) -> Result<usize, diesel::result::Error> {
+    let connection = &mut connect();
+
+    let pending: Vec<(Book, Option<BookEnrichment>)> = books
+        .left_join(book_enrichments)
+        .filter(schema::books::dsl::google_books_id.is_null())
+        .filter(
+            schema::book_enrichments::dsl::status
+                .is_null()
+                .or(schema::book_enrichments::dsl::status.eq(EnrichmentStatus::Pending)),
+        )
+        .order(schema::books::dsl::added_at.asc())
+        .limit(BATCH_SIZE)
+        .select((Book::as_select(), Option::<BookEnrichment>::as_select()))
+        .load(connection)?;
+
+    for (book, enrichment) in &pending {
+        let attempts = enrichment.as_ref().map_or(0, |e| e.attempts) + 1;
+        match resolve(provider, book).await {
+            Ok(Resolution::Matched {
+                metadata,
+                matched_by,
+                confidence,
+            }) => {
+                let changes = missing_fields(book, metadata);
+                connection.transaction::<_, diesel::result::Error, _>(|conn| {
+                    diesel::update(books.filter(schema::books::dsl::id.eq(book.id)))
+                        .set(&changes)
+                        .execute(conn)?;
+                    record(
+                        conn,
+                        book,
+                        EnrichmentStatus::Matched,
+                        provider,
+                        Some(matched_by),
+                        Some(confidence),
+                        attempts,
+                        None,
+                    )
+                })?;
+            }
+            Ok(Resolution::Unmatched { confidence }) => record(
+                connection,
+                book,
+                EnrichmentStatus::Unmatched,
+                provider,
+                None,
+                confidence,
+                attempts,
+                None,
+            )?,
+            Err(e) => {
+                warn!("Error while looking up book {}: {}", book.id, e);
+                let status = if attempts >= MAX_ATTEMPTS {
+                    EnrichmentStatus::Failed
+                } else {
+                    EnrichmentStatus::Pending
+                };
+                record(
+                    connection,
+                    book,
+                    status,
+                    provider,
+                    None,
+                    None,
+                    attempts,
+                    Some(e.to_string()),
+                )?;
+            }
+        }
+        tokio::time::sleep(LOOKUP_DELAY).await;
+    }
+
+    Ok(pending.len())
+}
+
+/// Stores the outcome of an enrichment attempt for a book.
+#[allow(clippy::too_many_arguments)]
+fn record(
+    connection: &mut PgConnection,
+    book: &Book,
+    status: EnrichmentStatus,
+    provider: &MetadataProvider,
+    matched_by: Option<&str>,
+    confidence: Option<f32>,
+    attempts: i32,
+    last_error: Option<String>,
+) -> Result<(), diesel::result::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let enrichment = BookEnrichment {
+        book: book.id,
+        user: book.user,
+        status,
+        provider: Some(provider.name().to_string()),
+        matched_by: matched_by.map(|m| m.to_string()),
+        confidence,
+        attempts,
+        last_error,
+        created_at: now,
+        updated_at: now,
+    };
+
+    diesel::insert_into(book_enrichments)
+        .values(&enrichment)
+        .on_conflict(schema::book_enrichments::dsl::book)
+        .do_update()
+        .set((
+            schema::book_enrichments::dsl::status.eq(enrichment.status),
+            schema::book_enrichments::dsl::provider.eq(&enrichment.provider),
+            schema::book_enrichments::dsl::matched_by.eq(&enrichment.matched_by),
+            schema::book_enrichments::dsl::confidence.eq(enrichment.confidence),
+            schema::book_enrichments::dsl::attempts.eq(enrichment.attempts),
+            schema::book_enrichments::dsl::last_error.eq(&enrichment.last_error),
+        ))
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Request type for querying or retrying the enrichment of books.
+#[derive(Debug, Deserialize)]
+pub struct EnrichmentRequest {
+    pub book_id: Option<String>,
+}
+
+/// Response type for the enrichment status of the books of a user.
+#[derive(Debug, Serialize)]
+pub struct EnrichmentStatusResponse {
+    pub counts: BTreeMap<String, usize>,
+    pub books: Vec<serde_json::Value>,
+}
+
+/// Reports the enrichment status of the books of a user.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `book_id`: Optional UUID of a single book to report the status for.
+pub(crate) async fn enrichment_status(
+    auth: AuthUser,
+    Json(payload): Json<EnrichmentRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let mut query = books
+        .left_join(book_enrichments)
+        .filter(schema::books::dsl::user.eq(auth.0))
+        .into_boxed();
+
+    if let Some(book_id) = &payload.book_id {
+        match Uuid::parse_str(book_id) {
+            Ok(id) => query = query.filter(schema::books::dsl::id.eq(id)),
+            Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+        }
+    }
+
+    let results: Vec<(Book, Option<BookEnrichment>)> = match query
+        .select((Book::as_select(), Option::<BookEnrichment>::as_select()))
+        .load(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading books: {}", e) }))),
+    };
+
+    let mut counts = BTreeMap::new();
+    let mut json_books = Vec::new();
+    for (book, enrichment) in results {
+        // Books which were never picked up by the worker are still waiting for their first lookup
+        let status = match &enrichment {
+            Some(e) => e.status,
+            None if book.google_books_id.is_none() => EnrichmentStatus::Pending,
+            None => continue,
+        };
+        *counts.entry(status.to_string()).or_insert(0) += 1;
+        json_books.push(json!({
+            "book_id": book.id.to_string(),
+            "title": book.title,
+            "author": book.author,
+            "status": status.to_string(),
+            "provider": enrichment.as_ref().and_then(|e| e.provider.clone()),
+            "matched_by": enrichment.as_ref().and_then(|e| e.matched_by.clone()),
+            "confidence": enrichment.as_ref().and_then(|e| e.confidence),
+            "attempts": enrichment.as_ref().map_or(0, |e| e.attempts),
+            "last_error": enrichment.as_ref().and_then(|e| e.last_error.clone()),
+            "updated_at": enrichment.as_ref().map(|e| e.updated_at.to_string()),
+        }));
+    }
+
+    (StatusCode::OK, Json(json!(EnrichmentStatusResponse { counts, books: json_books })))
+}
+
+/// Queues books which could not be matched or failed to be looked up for another attempt.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `book_id`: Optional UUID of a single book to retry, otherwise all books of the user which
+///   are unmatched or failed are retried.
+pub(crate) async fn retry_enrichment(
+    auth: AuthUser,
+    Json(payload): Json<EnrichmentRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let mut query = diesel::update(book_enrichments)
+        .filter(schema::book_enrichments::dsl::user.eq(auth.0))
+        .filter(schema::book_enrichments::dsl::status.ne(EnrichmentStatus::Matched))
+        .into_boxed();
+
+    if let Some(book_id) = &payload.book_id {
+        match Uuid::parse_str(book_id) {
+            Ok(id) => query = query.filter(schema::book_enrichments::dsl::book.eq(id)),
+            Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+        }
+    }
+
+    match query
+        .set((
+            schema::book_enrichments::dsl::status.eq(EnrichmentStatus::Pending),
+            schema::book_enrichments::dsl::attempts.eq(0),
+            schema::book_enrichments::dsl::last_error.eq(None::<String>),
+        ))
+        .execute(connection)
+    {
+        Ok(count) => (StatusCode::OK, Json(json!({ "message": format!("{} books queued for enrichment.", count) }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while queueing books: {}", e) }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    fn book() -> Book {
+        Book {
+            id: Uuid::new_v4(),
+            user: Uuid::new_v4(),
+            shelf: Uuid::new_v4(),
+            title: Some("Guards! Guards! (Discworld, #8)".to_string()),
+            author: Some("Terry Pratchett".to_string()),
+            isbn13: None,
+            isbn10: None,
+            google_books_id: None,
+            added_at: chrono::Utc::now().naive_utc(),
+            publisher: Some("Corgi".to_string()),
+            published_year: None,
+            page_count: None,
+            cover_url: None,
+        }
+    }
+
+    fn candidate() -> BookMetadata {
+        BookMetadata {
+            provider_id: "abc".to_string(),
+            title: Some("Guards! Guards!".to_string()),
+            authors: vec!["Terry Pratchett".to_string()],
+            isbn13: Some("9780552166669".to_string()),
+            isbn10: None,
+            publisher: Some("Transworld".to_string()),
+            published_year: Some(1989),
+            page_count: Some(416),
+            cover_url: Some("https://example.com/cover.jpg".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_match_confidence_ignores_series() {
+        let series_title = "Guards! Guards! (Discworld, #8)";
+        assert_eq!(match_confidence(series_title, None, &candidate()), 1.0);
+        // Parentheses which aren't a series are part of the title
+        assert!(match_confidence("Guards! Guards! (Illustrated Edition)", None, &candidate()) < 1.0);
+    }
+
+    #[test]
+    fn test_match_confidence() {
+        let book = book();
+        let exact = match_confidence(book.title.as_deref().unwrap(), book.author.as_deref(), &candidate());
+        assert!(exact >= MIN_CONFIDENCE);
+
+        let other = BookMetadata {
+            title: Some("Small Gods".to_string()),
+            ..candidate()
+        };
+        assert!(match_confidence(book.title.as_deref().unwrap(), book.author.as_deref(), &other) < MIN_CONFIDENCE);
+    }
+
+    #[test]
+    fn test_missing_fields_keeps_existing_values() {
+        let changes = missing_fields(&book(), candidate());
+        assert_eq!(changes.google_books_id.as_deref(), Some("abc"));
+        assert_eq!(changes.title, None);
+        assert_eq!(changes.author, None);
+        assert_eq!(changes.publisher, None);
+        assert_eq!(changes.isbn13.as_deref(), Some("9780552166669"));
+        assert_eq!(changes.page_count, Some(416));
+    }
+
+    #[test]
+    fn test_parse_google_books_volume() {
+        let response: VolumesResponse = serde_json::from_str(r#"{"items": [{"id": "xyz", "volumeInfo": {
+            "title": "Mort", "authors": ["Terry Pratchett"], "publishedDate": "1987-11-12",
+            "pageCount": 272, "industryIdentifiers": [{"type": "ISBN_13", "identifier": "9780552131063"}],
+            "imageLinks": {"thumbnail": "http://books.google.com/cover"}}}]}"#).unwrap();
+        let metadata = BookMetadata::from(response.items.into_iter().next().unwrap());
+        assert_eq!(metadata.provider_id, "xyz");
+        assert_eq!(metadata.published_year, Some(1987));
+        assert_eq!(metadata.isbn13.as_deref(), Some("9780552131063"));
+        assert_eq!(metadata.cover_url.as_deref(), Some("https://books.google.com/cover"));
+    }
+
+    #[tokio::test]
+    async fn test_enrichment_status_requires_auth() {
+        let app = Router::new().route("/api/books/enrichment/status", post(enrichment_status));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/books/enrichment/status").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_retry_enrichment_requires_auth() {
+        let app = Router::new().route("/api/books/enrichment/retry", post(retry_enrichment));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/books/enrichment/retry").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+}
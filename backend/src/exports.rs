@@ -0,0 +1,428 @@
+use crate::auth::AuthUser;
+use crate::contributors::ContributorInput;
+use crate::db::connect;
+use crate::citation_exporter::{citation_keys, write_citations, CitationFormat};
+use crate::goodreads_exporter::write_goodreads_csv;
+use crate::goodreads_importer::SeriesMarker;
+use crate::importer::shelf_name;
+use crate::markdown_exporter::{group_copies, write_vault, VaultBook};
+use crate::models::{Author, Book, BookContributor, CitationKey, Highlight, Note, Shelf};
+use crate::readings::ReadDates;
+use crate::schema::books::dsl::books;
+use crate::tags::{books_with_all_tags, load_tags};
+use crate::{schema, ErrorResponse};
+use axum::http::header;
+use axum::response::Response;
+use axum::routing::post;
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use chrono::NaiveDate;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/exports/goodreads", post(export_goodreads))
+        .route("/api/exports/citations", post(export_citations))
+        .route("/api/exports/markdown", post(export_markdown))
+}
+
+/// A book of a user along with everything attached to it, which each export writes in its format.
+pub struct LibraryBook {
+    pub book: Book,
+    pub shelf: String,
+    pub contributors: Vec<ContributorInput>,
+    pub series: Option<SeriesMarker>,
+    pub tags: Vec<String>,
+    /// Rating of the book in half stars, ratings of single readings aren't included.
+    pub rating: Option<i16>,
+    pub review: Option<String>,
+    /// Notes on the book which aren't attached to a reading.
+    pub notes: Vec<String>,
+    /// Finished readings of the book, latest first.
+    pub reads: Vec<ReadDates>,
+}
+
+/// Loads all books of a user in the order they were added.
+pub fn load_library(connection: &mut PgConnection, user_id: Uuid) -> QueryResult<Vec<LibraryBook>> {
+    load_books(connection, user_id, None)
+}
+
+/// Loads the given books of a user in the order they were added, or all of them if none are
+/// given. Books of other users are left out.
+pub fn load_books(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    book_ids: Option<&[Uuid]>,
+) -> QueryResult<Vec<LibraryBook>> {
+    let mut query = books
+        .inner_join(schema::shelves::table)
+        .filter(schema::books::dsl::user.eq(user_id))
+        .order((schema::books::dsl::added_at.asc(), schema::books::dsl::id.asc()))
+        .select((Book::as_select(), schema::shelves::dsl::name))
+        .into_boxed();
+    if let Some(book_ids) = book_ids {
+        query = query.filter(schema::books::dsl::id.eq_any(book_ids));
+    }
+    let rows: Vec<(Book, String)> = query.load(connection)?;
+    let book_ids: Vec<Uuid> = rows.iter().map(|(book, _)| book.id).collect();
+
+    let mut contributors: HashMap<Uuid, Vec<ContributorInput>> = HashMap::new();
+    for (contributor, author) in schema::book_contributors::table
+        .inner_join(schema::authors::table)
+        .filter(schema::book_contributors::dsl::book.eq_any(&book_ids))
+        .order(schema::book_contributors::dsl::position.asc())
+        .select((BookContributor::as_select(), Author::as_select()))
+        .load::<(BookContributor, Author)>(connection)?
+    {
+        contributors
+            .entry(contributor.book)
+            .or_default()
+            .push(ContributorInput { name: author.name, role: contributor.role });
+    }
+
+    let series: HashMap<Uuid, SeriesMarker> = schema::series_books::table
+        .inner_join(schema::series::table)
+        .filter(schema::series_books::dsl::book.eq_any(&book_ids))
+        .select((schema::series_books::dsl::book, schema::series::dsl::name, schema::series_books::dsl::position))
+        .load::<(Uuid, String, Option<f64>)>(connection)?
+        .into_iter()
+        .map(|(book, name, position)| (book, SeriesMarker { name, position }))
+        .collect();
+
+    let mut tags = load_tags(connection, &book_ids)?;
+
+    let mut reviews: HashMap<Uuid, (Option<i16>, Option<String>)> = schema::reviews::table
+        .filter(schema::reviews::dsl::book.eq_any(&book_ids))
+        .filter(schema::reviews::dsl::reading.is_null())
+        .select((schema::reviews::dsl::book, schema::reviews::dsl::rating, schema::reviews::dsl::body))
+        .load::<(Uuid, Option<i16>, Option<String>)>(connection)?
+        .into_iter()
+        .map(|(book, rating, body)| (book, (rating, body)))
+        .collect();
+
+    let mut notes: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for (book, body) in schema::notes::table
+        .filter(schema::notes::dsl::book.eq_any(&book_ids))
+        .filter(schema::notes::dsl::reading.is_null())
+        .order(schema::notes::dsl::created_at.asc())
+        .select((schema::notes::dsl::book, schema::notes::dsl::body))
+        .load::<(Uuid, String)>(connection)?
+    {
+        notes.entry(book).or_default().push(body);
+    }
+
+    let mut reads: HashMap<Uuid, Vec<ReadDates>> = HashMap::new();
+    for (book, started_at, finished_at) in schema::readings::table
+        .filter(schema::readings::dsl::book.eq_any(&book_ids))
+        .filter(schema::readings::dsl::finished_at.is_not_null())
+        .order(schema::readings::dsl::finished_at.desc())
+        .select((
+            schema::readings::dsl::book,
+            schema::readings::dsl::started_at,
+            schema::readings::dsl::finished_at,
+        ))
+        .load::<(Uuid, Option<NaiveDate>, Option<NaiveDate>)>(connection)?
+    {
+        if let Some(finished_at) = finished_at {
+            reads.entry(book).or_default().push(ReadDates { started_at, finished_at });
+        }
+    }
+
+    Ok(rows
+        .into_iter()
+        .map(|(book, shelf)| {
+            let (rating, review) = reviews.remove(&book.id).unwrap_or_default();
+            LibraryBook {
+                shelf,
+                contributors: contributors.remove(&book.id).unwrap_or_default(),
+                series: series.get(&book.id).cloned(),
+                tags: tags.remove(&book.id).unwrap_or_default(),
+                rating,
+                review,
+                notes: notes.remove(&book.id).unwrap_or_default(),
+                reads: reads.remove(&book.id).unwrap_or_default(),
+                book,
+            }
+        })
+        .collect())
+}
+
+/// Builds the response for an exported file, which is downloaded under the given name.
+pub(crate) fn download(content_type: &str, filename: &str, data: Vec<u8>) -> Response {
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        data,
+    )
+        .into_response()
+}
+
+/// Exports the library of the user as CSV in the layout of the Goodreads export, which other
+/// services accept for imports as well.
+///
+/// Each book is a row of its own, with the shelf it's on as its exclusive shelf and its tags as
+/// further shelves. Rows have no Goodreads book ID, so importing the file again turns the further
+/// shelves back into tags.
+///
+/// Authentication is required via JWT token in the Authorization header.
+pub(crate) async fn export_goodreads(auth: AuthUser) -> Response {
+    let connection = &mut connect();
+
+    let library = match load_library(connection, auth.0) {
+        Ok(library) => library,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading books: {}", e) }))).into_response(),
+    };
+
+    match write_goodreads_csv(&library) {
+        Ok(data) => download("text/csv; charset=utf-8", "goodreads_library_export.csv", data),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while writing the export: {}", e) }))).into_response(),
+    }
+}
+
+/// Exports the library as a zip archive of Markdown files, one per book, e.g. to sync it into an
+/// Obsidian vault.
+///
+/// Each file has the details of the book, its shelves, tags, status, rating and read dates as YAML
+/// front matter, followed by the review, notes and highlights. Copies of a book on several shelves
+/// share a file. File names only depend on the books, so exporting again updates the same files.
+///
+/// Authentication is required via JWT token in the Authorization header.
+pub(crate) async fn export_markdown(auth: AuthUser) -> Response {
+    let connection = &mut connect();
+
+    let library = match load_library(connection, auth.0) {
+        Ok(library) => library,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading books: {}", e) }))).into_response(),
+    };
+
+    let reading: Vec<Uuid> = match schema::readings::table
+        .filter(schema::readings::dsl::user.eq(auth.0))
+        .filter(schema::readings::dsl::finished_at.is_null())
+        .filter(schema::readings::dsl::cancelled_at.is_null())
+        .select(schema::readings::dsl::book)
+        .load(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading readings: {}", e) }))).into_response(),
+    };
+
+    let mut notes: HashMap<Uuid, Vec<Note>> = HashMap::new();
+    match schema::notes::table
+        .filter(schema::notes::dsl::user.eq(auth.0))
+        .order((schema::notes::dsl::created_at.asc(), schema::notes::dsl::id.asc()))
+        .load::<Note>(connection)
+    {
+        Ok(r) => r.into_iter().for_each(|note| notes.entry(note.book).or_default().push(note)),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading notes: {}", e) }))).into_response(),
+    }
+
+    let mut highlights: HashMap<Uuid, Vec<Highlight>> = HashMap::new();
+    match schema::highlights::table
+        .filter(schema::highlights::dsl::user.eq(auth.0))
+        .order((
+            schema::highlights::dsl::page.asc().nulls_last(),
+            schema::highlights::dsl::created_at.asc(),
+            schema::highlights::dsl::id.asc(),
+        ))
+        .load::<Highlight>(connection)
+    {
+        Ok(r) => r.into_iter().for_each(|highlight| highlights.entry(highlight.book).or_default().push(highlight)),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading highlights: {}", e) }))).into_response(),
+    }
+
+    let vault: Vec<VaultBook> = library
+        .into_iter()
+        .map(|book| VaultBook {
+            shelves: vec![book.shelf.clone()],
+            reading: reading.contains(&book.book.id),
+            notes: notes.remove(&book.book.id).unwrap_or_default(),
+            highlights: highlights.remove(&book.book.id).unwrap_or_default(),
+            book,
+        })
+        .collect();
+
+    match write_vault(&group_copies(vault)) {
+        Ok(data) => download("application/zip", "books-markdown.zip", data),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while writing the export: {}", e) }))).into_response(),
+    }
+}
+
+/// Assigns the citation keys of the library of a user, keeping the keys assigned by earlier
+/// exports and storing those of books exported for the first time.
+fn assign_citation_keys(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    library: &[LibraryBook],
+) -> QueryResult<HashMap<Uuid, String>> {
+    let mut assigned: HashMap<String, String> = schema::citation_keys::table
+        .filter(schema::citation_keys::dsl::user.eq(user_id))
+        .select((schema::citation_keys::dsl::work, schema::citation_keys::dsl::key))
+        .load::<(String, String)>(connection)?
+        .into_iter()
+        .collect();
+    let stored: HashSet<String> = assigned.keys().cloned().collect();
+
+    let keys = citation_keys(library, &mut assigned);
+
+    let now = chrono::Utc::now().naive_utc();
+    let new_keys: Vec<CitationKey> = assigned
+        .into_iter()
+        .filter(|(work, _)| !stored.contains(work))
+        .map(|(work, key)| CitationKey { user: user_id, work, key, created_at: now })
+        .collect();
+    if !new_keys.is_empty() {
+        diesel::insert_into(schema::citation_keys::table)
+            .values(&new_keys)
+            .on_conflict_do_nothing()
+            .execute(connection)?;
+    }
+
+    Ok(keys)
+}
+
+/// Request type for exporting citations.
+#[derive(Debug, Deserialize)]
+pub struct ExportCitationsRequest {
+    pub format: String,
+    pub shelf_id: Option<String>,
+    pub book_ids: Option<Vec<String>>,
+    pub tags: Option<Vec<String>>,
+}
+
+/// Exports books as citations for reference managers.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `format`: One of `bibtex`, `ris` or `csl-json`.
+/// - `shelf_id`: Optional, the shelf whose books are exported.
+/// - `book_ids`: Optional, the books to export, e.g. the results of a search.
+/// - `tags`: Optional, only books having all of these tags are exported.
+///
+/// Without a shelf or books, the whole library is exported. Each book gets a citation key built
+/// from its first author, year and title, like `pratchett1987mort`. Keys are kept once assigned, so
+/// they stay the same between exports as books are added or removed. Copies of a book on several
+/// shelves are exported once.
+///
+/// Authentication is required via JWT token in the Authorization header.
+pub(crate) async fn export_citations(
+    auth: AuthUser,
+    Json(payload): Json<ExportCitationsRequest>,
+) -> Response {
+    let Some(format) = CitationFormat::parse(&payload.format) else {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid citation format.".to_string() }))).into_response();
+    };
+
+    let connection = &mut connect();
+
+    let mut filename = "citations".to_string();
+    let mut book_ids: Option<Vec<Uuid>> = None;
+
+    if let Some(shelf_id) = &payload.shelf_id {
+        let shelf_id = match Uuid::parse_str(shelf_id) {
+            Ok(id) => id,
+            Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid shelf ID.".to_string() }))).into_response(),
+        };
+        let shelf = match schema::shelves::table
+            .filter(schema::shelves::dsl::id.eq(shelf_id))
+            .first::<Shelf>(connection)
+        {
+            Ok(s) => s,
+            Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Shelf not found.".to_string() }))).into_response(),
+        };
+        if shelf.user != auth.0 {
+            return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() }))).into_response();
+        }
+
+        filename = shelf_name(&shelf.name)
+            .chars()
+            .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+            .collect();
+        book_ids = match books
+            .filter(schema::books::dsl::shelf.eq(shelf_id))
+            .select(schema::books::dsl::id)
+            .load(connection)
+        {
+            Ok(ids) => Some(ids),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading books: {}", e) }))).into_response(),
+        };
+    }
+
+    if let Some(ids) = &payload.book_ids {
+        let ids: Vec<Uuid> = match ids.iter().map(|id| Uuid::parse_str(id)).collect() {
+            Ok(ids) => ids,
+            Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))).into_response(),
+        };
+        book_ids = Some(match book_ids {
+            Some(shelved) => ids.into_iter().filter(|id| shelved.contains(id)).collect(),
+            None => ids,
+        });
+    }
+
+    if let Some(tag_names) = payload.tags.filter(|t| !t.is_empty()) {
+        let tagged = match books_with_all_tags(connection, auth.0, &tag_names) {
+            Ok(tagged) => tagged,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading tags: {}", e) }))).into_response(),
+        };
+        book_ids = Some(match book_ids {
+            Some(ids) => ids.into_iter().filter(|id| tagged.contains(id)).collect(),
+            None => tagged,
+        });
+    }
+
+    // Keys are assigned across the whole library, so they don't change with the books exported
+    let library = match load_library(connection, auth.0) {
+        Ok(library) => library,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading books: {}", e) }))).into_response(),
+    };
+    let keys = match assign_citation_keys(connection, auth.0, &library) {
+        Ok(keys) => keys,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error assigning citation keys: {}", e) }))).into_response(),
+    };
+    let exported: Vec<&LibraryBook> = library
+        .iter()
+        .filter(|book| book_ids.as_ref().is_none_or(|ids| ids.contains(&book.book.id)))
+        .collect();
+
+    let data = write_citations(&exported, &keys, format);
+    download(format.content_type(), &format!("{}.{}", filename, format.extension()), data)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_export_goodreads_requires_auth() {
+        let app = Router::new().route("/api/exports/goodreads", post(export_goodreads));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/exports/goodreads").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_export_citations_requires_auth() {
+        let app = Router::new().route("/api/exports/citations", post(export_citations));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/exports/citations").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_export_markdown_requires_auth() {
+        let app = Router::new().route("/api/exports/markdown", post(export_markdown));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/exports/markdown").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+}
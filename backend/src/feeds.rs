@@ -0,0 +1,255 @@
+use crate::api_keys::hash_key;
+use crate::auth::AuthUser;
+use crate::calendar_exporter::{write_calendar, CalendarReading};
+use crate::db::connect;
+use crate::models::{FeedToken, Reading, ReadingEntry};
+use crate::schema::feed_tokens::dsl::feed_tokens;
+use crate::{schema, ErrorResponse};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::extract::{Path, Query};
+use axum::http::header;
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/feeds/tokens", post(list_feed_tokens))
+        .route("/api/feeds/tokens/create", post(create_feed_token))
+        .route("/api/feeds/tokens/remove", post(remove_feed_token))
+        .route("/api/feeds/{token}/calendar.ics", get(calendar_feed))
+}
+
+/// Generates a random token of 64 hex characters.
+pub(crate) fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Returns the user a feed token belongs to, if it exists.
+pub fn feed_token_user(connection: &mut PgConnection, token: &str) -> QueryResult<Option<Uuid>> {
+    feed_tokens
+        .filter(schema::feed_tokens::dsl::key_hash.eq(hash_key(token)))
+        .select(schema::feed_tokens::dsl::user)
+        .first(connection)
+        .optional()
+}
+
+/// Resolves the user of a feed token, responding with an error if it doesn't exist.
+pub(crate) fn authenticate_feed(
+    connection: &mut PgConnection,
+    token: &str,
+) -> Result<Uuid, (StatusCode, Json<serde_json::Value>)> {
+    match feed_token_user(connection, token) {
+        Ok(Some(user_id)) => Ok(user_id),
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Feed not found.".to_string() })))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading the feed: {}", e) })))),
+    }
+}
+
+/// Lists the feed tokens of the user, the tokens themselves are only shown when they're created.
+pub(crate) async fn list_feed_tokens(auth: AuthUser) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let results = match feed_tokens
+        .filter(schema::feed_tokens::dsl::user.eq(auth.0))
+        .order(schema::feed_tokens::dsl::created_at.asc())
+        .load::<FeedToken>(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading feed tokens: {}", e) }))),
+    };
+
+    let tokens: Vec<_> = results
+        .into_iter()
+        .map(|token| {
+            json!({
+                "id": token.id.to_string(),
+                "name": token.name,
+                "created_at": token.created_at.to_string(),
+            })
+        })
+        .collect();
+
+    (StatusCode::OK, Json(json!({ "tokens": tokens })))
+}
+
+/// Request type for creating a feed token.
+#[derive(Debug, Deserialize)]
+pub struct CreateFeedTokenRequest {
+    pub name: Option<String>,
+}
+
+/// Creates a token for the feeds of the user, which are read by apps that can't log in, such as
+/// calendars subscribing to `/api/feeds/{token}/calendar.ics` or e-readers browsing the OPDS
+/// catalog at `/api/feeds/{token}/opds`.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `name`: Optional, a name telling the tokens apart, e.g. "Phone calendar".
+///
+/// Anyone knowing a token can read the feeds, so each app should get a token of its own, which can
+/// be removed when it's no longer used. The token is only part of this response, as only its hash
+/// is stored.
+pub(crate) async fn create_feed_token(
+    auth: AuthUser,
+    Json(payload): Json<CreateFeedTokenRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let token = generate_token();
+    let now = chrono::Utc::now().naive_utc();
+    let feed_token = FeedToken {
+        id: Uuid::new_v4(),
+        user: auth.0,
+        key_hash: hash_key(&token),
+        name: payload.name.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()),
+        created_at: now,
+        updated_at: now,
+    };
+
+    match diesel::insert_into(feed_tokens).values(&feed_token).execute(connection) {
+        Ok(_) => (
+            StatusCode::CREATED,
+            Json(json!({ "message": "Feed token created successfully.", "id": feed_token.id.to_string(), "token": token })),
+        ),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while creating the feed token: {}", e) }))),
+    }
+}
+
+/// Request type for removing a feed token.
+#[derive(Debug, Deserialize)]
+pub struct RemoveFeedTokenRequest {
+    pub token_id: String,
+}
+
+/// Removes a feed token, apps using it can no longer read the feeds.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `token_id`: The UUID of the token to remove.
+pub(crate) async fn remove_feed_token(
+    auth: AuthUser,
+    Json(payload): Json<RemoveFeedTokenRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let token_id = match Uuid::parse_str(&payload.token_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid token ID.".to_string() }))),
+    };
+
+    match diesel::delete(
+        feed_tokens
+            .filter(schema::feed_tokens::dsl::id.eq(token_id))
+            .filter(schema::feed_tokens::dsl::user.eq(auth.0)),
+    )
+    .execute(connection)
+    {
+        Ok(0) => (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Feed token not found.".to_string() }))),
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Feed token removed successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while removing the feed token: {}", e) }))),
+    }
+}
+
+/// Query parameters of the calendar feed.
+#[derive(Debug, Deserialize)]
+pub struct CalendarQuery {
+    pub entries: Option<bool>,
+}
+
+/// Serves the readings of the user as an iCalendar feed, authenticated by a feed token in the URL.
+///
+/// Each reading is an event spanning the days it was read on, target finish dates and due dates
+/// are events with a reminder the day before. With `?entries=true`, every day progress was
+/// tracked on is an all-day event as well.
+pub(crate) async fn calendar_feed(
+    Path(token): Path<String>,
+    Query(query): Query<CalendarQuery>,
+) -> Response {
+    let connection = &mut connect();
+
+    let user_id = match authenticate_feed(connection, &token) {
+        Ok(user_id) => user_id,
+        Err(response) => return response.into_response(),
+    };
+
+    let results = match schema::readings::table
+        .inner_join(schema::books::table)
+        .filter(schema::readings::dsl::user.eq(user_id))
+        .order((schema::readings::dsl::started_at.asc(), schema::readings::dsl::id.asc()))
+        .select((Reading::as_select(), schema::books::dsl::title, schema::books::dsl::author))
+        .load::<(Reading, Option<String>, Option<String>)>(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading readings: {}", e) }))).into_response(),
+    };
+
+    let mut entries: HashMap<Uuid, Vec<ReadingEntry>> = HashMap::new();
+    if query.entries.unwrap_or(false) {
+        match schema::reading_entries::table
+            .filter(schema::reading_entries::dsl::user.eq(user_id))
+            .order((schema::reading_entries::dsl::read_at.asc(), schema::reading_entries::dsl::id.asc()))
+            .load::<ReadingEntry>(connection)
+        {
+            Ok(r) => r.into_iter().for_each(|entry| entries.entry(entry.reading).or_default().push(entry)),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading entries: {}", e) }))).into_response(),
+        }
+    }
+
+    let calendar_readings: Vec<CalendarReading> = results
+        .into_iter()
+        .map(|(reading, title, author)| CalendarReading {
+            entries: entries.remove(&reading.id).unwrap_or_default(),
+            reading,
+            title: title.unwrap_or_else(|| "Untitled".to_string()),
+            author,
+        })
+        .collect();
+
+    let calendar = write_calendar(&calendar_readings, query.entries.unwrap_or(false), chrono::Utc::now().date_naive());
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        calendar,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_create_feed_token_requires_auth() {
+        let app = Router::new().route("/api/feeds/tokens/create", post(create_feed_token));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/feeds/tokens/create").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_remove_feed_token_requires_auth() {
+        let app = Router::new().route("/api/feeds/tokens/remove", post(remove_feed_token));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/feeds/tokens/remove").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_generate_token() {
+        let token = generate_token();
+        assert_eq!(token.len(), 64);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, generate_token());
+    }
+}
@@ -0,0 +1,293 @@
+use crate::exports::LibraryBook;
+use crate::models::ContributorRole;
+use crate::reviews::stars;
+use csv::Writer;
+
+/// The columns of a Goodreads export, in their order.
+pub const HEADERS: [&str; 24] = [
+    "Book Id",
+    "Title",
+    "Author",
+    "Author l-f",
+    "Additional Authors",
+    "ISBN",
+    "ISBN13",
+    "My Rating",
+    "Average Rating",
+    "Publisher",
+    "Binding",
+    "Number of Pages",
+    "Year Published",
+    "Original Publication Year",
+    "Date Read",
+    "Date Added",
+    "Bookshelves",
+    "Bookshelves with positions",
+    "Exclusive Shelf",
+    "My Review",
+    "Spoiler",
+    "Private Notes",
+    "Read Count",
+    "Owned Copies",
+];
+
+/// Writes books as CSV in the layout of the Goodreads export.
+///
+/// Books are written without a Goodreads book ID, which tells `BookRecord` that their shelves
+/// besides the exclusive one are tags. Importing the file again restores the books with these
+/// losses: ratings are rounded to whole stars as Goodreads has no half stars, notes are joined
+/// into the private notes and of the readings only the date the book was last finished is kept,
+/// earlier reads are only counted.
+pub fn write_goodreads_csv(books: &[LibraryBook]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = Writer::from_writer(Vec::new());
+    writer.write_record(HEADERS)?;
+    for book in books {
+        writer.write_record(goodreads_row(book))?;
+    }
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+/// Builds the row of a book, mirroring how `BookRecord` reads the columns.
+fn goodreads_row(library_book: &LibraryBook) -> [String; 24] {
+    let book = &library_book.book;
+    let title = book.title.clone().unwrap_or_default();
+    let title = match &library_book.series {
+        Some(series) => format!("{} ({}, #{})", title, series.name, format_position(series.position)),
+        None => title,
+    };
+
+    // Goodreads names the first author on its own and everyone else as additional authors
+    let mut contributors = library_book.contributors.iter();
+    let author = contributors
+        .by_ref()
+        .next()
+        .map(|contributor| contributor.name.clone())
+        .or_else(|| book.author.clone())
+        .unwrap_or_default();
+    let additional_authors: Vec<String> = contributors
+        .map(|contributor| match contributor.role {
+            ContributorRole::Author => contributor.name.clone(),
+            role => format!("{} ({})", contributor.name, role),
+        })
+        .collect();
+
+    let shelves = library_book.tags.join(", ");
+    let shelves_with_positions: Vec<String> = library_book
+        .tags
+        .iter()
+        .enumerate()
+        .map(|(index, tag)| format!("{} (#{})", tag, index + 1))
+        .collect();
+
+    [
+        String::new(),
+        title,
+        author.clone(),
+        last_first(&author),
+        additional_authors.join(", "),
+        format!("=\"{}\"", book.isbn10.as_deref().unwrap_or_default()),
+        format!("=\"{}\"", book.isbn13.as_deref().unwrap_or_default()),
+        library_book.rating.map_or(0, |rating| stars(rating).round() as u8).to_string(),
+        String::new(),
+        book.publisher.clone().unwrap_or_default(),
+        String::new(),
+        book.page_count.filter(|pages| *pages > 0).map(|pages| pages.to_string()).unwrap_or_default(),
+        book.published_year
+            .and_then(|year| u16::try_from(year).ok())
+            .map(|year| year.to_string())
+            .unwrap_or_default(),
+        String::new(),
+        library_book
+            .reads
+            .first()
+            .map(|read| read.finished_at.format("%Y/%m/%d").to_string())
+            .unwrap_or_default(),
+        book.added_at.format("%Y/%m/%d").to_string(),
+        shelves,
+        shelves_with_positions.join(", "),
+        library_book.shelf.clone(),
+        library_book.review.as_deref().map(review_to_html).unwrap_or_default(),
+        String::new(),
+        library_book.notes.join("\n\n"),
+        library_book.reads.len().min(u8::MAX as usize).to_string(),
+        "0".to_string(),
+    ]
+}
+
+/// Formats the position of a book in a series, e.g. "4" or "0.5".
+fn format_position(position: Option<f64>) -> String {
+    match position {
+        Some(position) if position.fract() == 0.0 => format!("{}", position as i64),
+        Some(position) => position.to_string(),
+        None => String::new(),
+    }
+}
+
+/// Turns a name like "Terry Pratchett" into "Pratchett, Terry".
+fn last_first(name: &str) -> String {
+    match name.trim().rsplit_once(' ') {
+        Some((first, last)) => format!("{}, {}", last, first),
+        None => name.trim().to_string(),
+    }
+}
+
+/// Converts a Markdown review into the HTML Goodreads uses, the reverse of `review_to_markdown`.
+///
+/// Bold and italic text, line breaks and `>!spoiler!<` sections are kept as markup.
+pub fn review_to_html(markdown: &str) -> String {
+    let mut html = String::with_capacity(markdown.len());
+    let mut bold = false;
+    let mut italic = false;
+    let mut rest = markdown;
+    while let Some(c) = rest.chars().next() {
+        let (markup, length) = if rest.starts_with("**") {
+            bold = !bold;
+            (if bold { "<b>" } else { "</b>" }, 2)
+        } else if rest.starts_with(">!") {
+            ("<spoiler>", 2)
+        } else if rest.starts_with("!<") {
+            ("</spoiler>", 2)
+        } else {
+            match c {
+                '*' => {
+                    italic = !italic;
+                    (if italic { "<i>" } else { "</i>" }, 1)
+                }
+                '\n' => ("<br/>", 1),
+                '&' => ("&amp;", 1),
+                '<' => ("&lt;", 1),
+                '>' => ("&gt;", 1),
+                '"' => ("&quot;", 1),
+                _ => {
+                    html.push(c);
+                    rest = &rest[c.len_utf8()..];
+                    continue;
+                }
+            }
+        };
+        html.push_str(markup);
+        rest = &rest[length..];
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contributors::ContributorInput;
+    use crate::goodreads_importer::{review_to_markdown, BookRecord, SeriesMarker};
+    use crate::importer::ImportedBook;
+    use crate::models::{Book, ReadingMode};
+    use crate::readings::ReadDates;
+    use chrono::NaiveDate;
+    use uuid::Uuid;
+
+    fn library_book() -> LibraryBook {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        LibraryBook {
+            book: Book {
+                id: Uuid::new_v4(),
+                user: Uuid::new_v4(),
+                shelf: Uuid::new_v4(),
+                title: Some("Mort".to_string()),
+                author: Some("Terry Pratchett".to_string()),
+                isbn13: Some("9780552131061".to_string()),
+                isbn10: Some("0552131067".to_string()),
+                google_books_id: None,
+                added_at: date(2020, 1, 12).and_time(chrono::NaiveTime::MIN),
+                publisher: Some("Corgi".to_string()),
+                published_year: Some(1988),
+                page_count: Some(316),
+                cover_url: None,
+            },
+            shelf: "read".to_string(),
+            contributors: vec![
+                ContributorInput { name: "Terry Pratchett".to_string(), role: ContributorRole::Author },
+                ContributorInput { name: "Nigel Planer".to_string(), role: ContributorRole::Narrator },
+            ],
+            series: Some(SeriesMarker { name: "Discworld".to_string(), position: Some(4.0) }),
+            tags: vec!["fantasy".to_string(), "humor".to_string()],
+            rating: Some(8),
+            review: Some("**Death** takes an apprentice, \"Mort\" & more.\n\n>!He falls in love!<".to_string()),
+            notes: vec!["Lent to Sam".to_string()],
+            reads: vec![ReadDates { started_at: None, finished_at: date(2021, 5, 3) }],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = write_goodreads_csv(&[library_book()]).unwrap();
+        let records = BookRecord::from_reader(data.as_slice()).unwrap();
+        assert_eq!(records.len(), 1);
+
+        let original = library_book();
+        let imported = records[0].to_imported();
+        assert_eq!(
+            imported,
+            ImportedBook {
+                raw_title: "Mort (Discworld, #4)".to_string(),
+                title: "Mort".to_string(),
+                series: original.series,
+                author: "Terry Pratchett".to_string(),
+                contributors: original.contributors,
+                isbn13: original.book.isbn13,
+                isbn10: original.book.isbn10,
+                publisher: original.book.publisher,
+                published_year: original.book.published_year,
+                page_count: original.book.page_count,
+                added_on: Some(original.book.added_at.date()),
+                shelf: original.shelf,
+                other_shelves: Vec::new(),
+                tags: original.tags,
+                rating: original.rating,
+                review: original.review,
+                note: Some("Lent to Sam".to_string()),
+                reading_mode: ReadingMode::Pages,
+                reads: original.reads,
+                undated_reads: 0,
+                unfinished_read: None,
+                has_cover: false,
+                cover_file: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_round_trip_rereads_and_half_stars() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let mut original = library_book();
+        original.rating = Some(7);
+        original.notes.push("Signed copy".to_string());
+        original.reads = vec![
+            ReadDates { started_at: Some(date(2023, 2, 1)), finished_at: date(2023, 2, 14) },
+            ReadDates { started_at: Some(date(2021, 4, 20)), finished_at: date(2021, 5, 3) },
+        ];
+
+        let data = write_goodreads_csv(std::slice::from_ref(&original)).unwrap();
+        let text = String::from_utf8(data.clone()).unwrap();
+        let (header, row) = text.split_once('\n').unwrap();
+        assert_eq!(header, HEADERS.join(","));
+        assert!(row.starts_with(",\"Mort (Discworld, #4)\","));
+        assert!(row.contains(",2023/02/14,2020/01/12,"));
+        assert!(row.ends_with(",2,0\n"));
+
+        let records = BookRecord::from_reader(data.as_slice()).unwrap();
+        let imported = records[0].to_imported();
+        // Goodreads only has the date of the latest read and counts the others
+        assert_eq!(imported.reads, vec![ReadDates { started_at: None, finished_at: date(2023, 2, 14) }]);
+        assert_eq!(imported.undated_reads, 1);
+        // Goodreads has no half stars and a single private note
+        assert_eq!(imported.rating, Some(8));
+        assert_eq!(imported.note, Some("Lent to Sam\n\nSigned copy".to_string()));
+    }
+
+    #[test]
+    fn test_review_to_html() {
+        let review = "A *quiet* book <3\nwith **bold** & >!spoilers!<";
+        assert_eq!(
+            review_to_html(review),
+            "A <i>quiet</i> book &lt;3<br/>with <b>bold</b> &amp; <spoiler>spoilers</spoiler>"
+        );
+        assert_eq!(review_to_markdown(&review_to_html(review)), review);
+    }
+}
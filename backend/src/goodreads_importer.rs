@@ -1,3 +1,7 @@
+use crate::importer::{csv_headers, has_headers, merge_contributors, ImportedBook, ImportedRows, Importer};
+use crate::models::ReadingMode;
+use crate::readings::ReadDates;
+use chrono::NaiveDate;
 use csv::ReaderBuilder;
 use serde::Deserialize;
 use std::io::Read;
@@ -9,52 +13,354 @@ pub struct BookRecord {
     pub book_id: String,
     pub title: String,
     pub author: String,
+    #[serde(rename = "Additional Authors")]
+    pub additional_authors: Option<String>,
     #[serde(rename = "ISBN")]
     pub isbn: String,
     #[serde(rename = "ISBN13")]
     pub isbn13: String,
-    #[allow(dead_code)]
     #[serde(rename = "My Rating")]
     pub my_rating: Option<u8>,
-    #[allow(dead_code)]
     pub publisher: String,
     #[allow(dead_code)]
     pub binding: String,
-    #[allow(dead_code)]
     #[serde(rename = "Number of Pages")]
     pub number_of_pages: Option<u32>,
-    #[allow(dead_code)]
     #[serde(rename = "Year Published")]
     pub year_published: Option<u16>,
-    #[allow(dead_code)]
     #[serde(rename = "Date Read")]
     pub date_read: Option<String>,
-    #[allow(dead_code)]
     #[serde(rename = "Date Added")]
     pub date_added: String,
     pub bookshelves: String,
     #[serde(rename = "Exclusive Shelf")]
     pub exclusive_shelf: String,
-    #[allow(dead_code)]
     #[serde(rename = "My Review")]
     pub my_review: Option<String>,
-    #[allow(dead_code)]
+    pub spoiler: Option<String>,
     #[serde(rename = "Private Notes")]
     pub private_notes: Option<String>,
-    #[allow(dead_code)]
+    #[serde(rename = "Read Count", alias = "ReadCount")]
     pub read_count: u8,
     #[allow(dead_code)]
+    #[serde(rename = "Owned Copies", alias = "OwnedCopies")]
     pub owned_copies: u8,
 }
 
+/// A series a book belongs to, as embedded in Goodreads titles like "Title (Series, #3)".
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeriesMarker {
+    pub name: String,
+    pub position: Option<f64>,
+}
+
+/// Splits a Goodreads title into the plain title and the series it belongs to.
+///
+/// Goodreads appends the series in parentheses, e.g. "Mort (Discworld, #4)" or
+/// "The Hedge Knight (A Song of Ice and Fire, #0.5)". Omnibus ranges like "#1-3" are placed at
+/// their first position and only the first of several series is used.
+pub fn split_series(title: &str) -> (String, Option<SeriesMarker>) {
+    let trimmed = title.trim();
+    let Some((plain, marker)) = trimmed
+        .strip_suffix(')')
+        .and_then(|t| t.rsplit_once(" ("))
+    else {
+        return (trimmed.to_string(), None);
+    };
+
+    let first = marker.split(';').next().unwrap_or_default();
+    let Some((name, number)) = first.rsplit_once('#') else {
+        return (trimmed.to_string(), None);
+    };
+
+    let name = name.trim().trim_end_matches(',').trim();
+    if name.is_empty() || plain.trim().is_empty() {
+        return (trimmed.to_string(), None);
+    }
+
+    let number: String = number
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+
+    (
+        plain.trim().to_string(),
+        Some(SeriesMarker {
+            name: name.to_string(),
+            position: number.parse().ok(),
+        }),
+    )
+}
+
+/// Parses a date as exported by Goodreads, e.g. "2021/05/03".
+pub fn parse_date(value: &str) -> Option<NaiveDate> {
+    let value = value.trim();
+    NaiveDate::parse_from_str(value, "%Y/%m/%d")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d"))
+        .ok()
+}
+
+/// Converts a Goodreads review, which is HTML, into Markdown.
+///
+/// Line breaks and the basic formatting Goodreads allows are kept, `<spoiler>` sections become
+/// `>!spoiler!<` and any other markup is dropped.
+pub fn review_to_markdown(html: &str) -> String {
+    let mut markdown = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        let Some(length) = rest[start..].find('>') else {
+            break;
+        };
+        markdown.push_str(&rest[..start]);
+        let tag = rest[start + 1..start + length].trim().trim_end_matches('/').trim();
+        let name = tag.split_whitespace().next().unwrap_or_default().to_ascii_lowercase();
+        markdown.push_str(match name.as_str() {
+            "br" | "p" | "/p" => "\n",
+            "b" | "/b" | "strong" | "/strong" => "**",
+            "i" | "/i" | "em" | "/em" => "*",
+            "spoiler" => ">!",
+            "/spoiler" => "!<",
+            _ => "",
+        });
+        rest = &rest[start + length + 1..];
+    }
+    markdown.push_str(rest);
+
+    markdown
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/// Imports the CSV export of Goodreads.
+pub struct GoodreadsImporter;
+
+impl Importer for GoodreadsImporter {
+    fn source(&self) -> &'static str {
+        "goodreads"
+    }
+
+    fn name(&self) -> &'static str {
+        "Goodreads"
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        csv_headers(data).is_some_and(|headers| has_headers(&headers, &["Book Id", "Title", "Author", "Exclusive Shelf"]))
+    }
+
+    fn read(&self, data: &[u8], _options: &serde_json::Value) -> Result<ImportedRows, String> {
+        let rows = BookRecord::read_rows(data).map_err(|e| format!("Failed to parse CSV file: {}", e))?;
+        Ok(rows.into_iter().map(|row| row.map(|record| record.to_imported())).collect())
+    }
+}
+
+/// GoodReads exports ISBNs wrapped in ="..." — strip that formatting.
+fn clean_isbn(isbn: &str) -> Option<String> {
+    Some(isbn.trim_matches(|c| c == '=' || c == '"').to_string()).filter(|isbn| !isbn.is_empty())
+}
+
 impl BookRecord {
+    /// Returns the rating in half stars, Goodreads only knows full stars and uses 0 for unrated.
+    pub fn rating(&self) -> Option<i16> {
+        self.my_rating
+            .filter(|rating| (1..=5).contains(rating))
+            .map(|rating| rating as i16 * 2)
+    }
+
+    /// Returns the review as Markdown, reviews flagged as spoiler are hidden entirely.
+    pub fn review(&self) -> Option<String> {
+        let review = review_to_markdown(self.my_review.as_deref()?);
+        if review.is_empty() {
+            return None;
+        }
+        if self.spoiler.as_deref().map(str::trim) != Some("true") || review.contains(">!") {
+            return Some(review);
+        }
+
+        // Spoilers don't span paragraphs, so mark up each of them
+        Some(
+            review
+                .split("\n\n")
+                .map(|paragraph| if paragraph.trim().is_empty() { paragraph.to_string() } else { format!(">!{}!<", paragraph) })
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+        )
+    }
+
+    /// Returns the title without any series marker along with the series, if there is one.
+    pub fn title_and_series(&self) -> (String, Option<SeriesMarker>) {
+        split_series(&self.title)
+    }
+
+    /// Returns the date the book was added to Goodreads.
+    pub fn added_on(&self) -> Option<NaiveDate> {
+        parse_date(&self.date_added)
+    }
+
+    /// Returns the date the book was last finished on, for books on the `read` shelf.
+    pub fn finished_on(&self) -> Option<NaiveDate> {
+        if self.exclusive_shelf.trim() != "read" {
+            return None;
+        }
+        self.date_read.as_deref().and_then(parse_date)
+    }
+
+    /// Returns the reads of the book, Goodreads exports only have the date of the latest read.
+    pub fn reads(&self) -> Vec<ReadDates> {
+        self.finished_on()
+            .map(|finished_at| ReadDates { started_at: None, finished_at })
+            .into_iter()
+            .collect()
+    }
+
+    /// Returns how many reads Goodreads counts besides the dated one, it doesn't keep their dates.
+    ///
+    /// Books on the `read` shelf without a read date have been read at least once.
+    pub fn undated_reads(&self) -> i32 {
+        if self.exclusive_shelf.trim() != "read" {
+            return 0;
+        }
+        match self.finished_on() {
+            Some(_) => self.read_count.saturating_sub(1) as i32,
+            None => self.read_count.max(1) as i32,
+        }
+    }
+
+    /// Returns the shelves of the book besides its exclusive shelf.
+    pub fn non_exclusive_shelves(&self) -> Vec<String> {
+        let exclusive = self.exclusive_shelf.trim();
+        let mut shelves: Vec<String> = Vec::new();
+        for shelf in self.bookshelves.split(',').map(str::trim) {
+            if !shelf.is_empty() && shelf != exclusive && !shelves.iter().any(|s| s == shelf) {
+                shelves.push(shelf.to_string());
+            }
+        }
+        shelves
+    }
+
+    /// Maps the record onto the book model shared by all importers.
+    pub fn to_imported(&self) -> ImportedBook {
+        // GoodReads embeds series in titles like "Title (Series, #3)" — split them off
+        let (title, series) = self.title_and_series();
+
+        // Books exported by this app have no Goodreads ID, their further shelves are their tags
+        let (other_shelves, tags) = match self.book_id.trim() {
+            "" => (Vec::new(), self.non_exclusive_shelves()),
+            _ => (self.non_exclusive_shelves(), Vec::new()),
+        };
+
+        ImportedBook {
+            raw_title: self.title.clone(),
+            title,
+            series,
+            author: self.author.clone(),
+            contributors: merge_contributors(&self.author, self.additional_authors.as_deref()),
+            isbn13: clean_isbn(&self.isbn13),
+            isbn10: clean_isbn(&self.isbn),
+            publisher: Some(self.publisher.trim().to_string()).filter(|p| !p.is_empty()),
+            published_year: self.year_published.map(i32::from),
+            page_count: self.number_of_pages.and_then(|p| i32::try_from(p).ok()),
+            added_on: self.added_on(),
+            shelf: self.exclusive_shelf.trim().to_string(),
+            other_shelves,
+            tags,
+            rating: self.rating(),
+            review: self.review(),
+            note: self.private_notes.as_deref().map(str::trim).filter(|n| !n.is_empty()).map(str::to_string),
+            reading_mode: ReadingMode::Pages,
+            reads: self.reads(),
+            undated_reads: self.undated_reads(),
+            unfinished_read: None,
+            has_cover: false,
+            cover_file: None,
+        }
+    }
+
+    /// Reads all records of a Goodreads export, failing on the first row which doesn't parse.
+    #[allow(dead_code)]
     pub fn from_reader(data: impl Read) -> Result<Vec<BookRecord>, Box<dyn std::error::Error>> {
+        Ok(Self::read_rows(data)?.into_iter().collect::<Result<_, _>>()?)
+    }
+
+    /// Reads all rows of a Goodreads export, keeping rows which fail to parse as errors.
+    pub fn read_rows(data: impl Read) -> Result<Vec<Result<BookRecord, String>>, csv::Error> {
         let mut rdr = ReaderBuilder::new().from_reader(data);
-        let mut records = Vec::new();
-        for result in rdr.deserialize() {
-            let record: BookRecord = result?;
-            records.push(record);
-        }
-        Ok(records)
+        rdr.headers()?;
+        Ok(rdr
+            .deserialize()
+            .map(|result| result.map_err(|e| e.to_string()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_series() {
+        assert_eq!(
+            split_series("Mort (Discworld, #4)"),
+            ("Mort".to_string(), Some(SeriesMarker { name: "Discworld".to_string(), position: Some(4.0) }))
+        );
+        assert_eq!(
+            split_series("The Hedge Knight (A Song of Ice and Fire, #0.5)").1,
+            Some(SeriesMarker { name: "A Song of Ice and Fire".to_string(), position: Some(0.5) })
+        );
+        assert_eq!(
+            split_series("The Wheel of Time Box Set (The Wheel of Time #1-3)").1,
+            Some(SeriesMarker { name: "The Wheel of Time".to_string(), position: Some(1.0) })
+        );
+        assert_eq!(
+            split_series("Good Omens (Illustrated Edition)"),
+            ("Good Omens (Illustrated Edition)".to_string(), None)
+        );
+        assert_eq!(split_series("Norwegian Wood"), ("Norwegian Wood".to_string(), None));
+    }
+
+    const EXPORT: &str = "Book Id,Title,Author,Author l-f,Additional Authors,ISBN,ISBN13,My Rating,Average Rating,Publisher,Binding,Number of Pages,Year Published,Original Publication Year,Date Read,Date Added,Bookshelves,Bookshelves with positions,Exclusive Shelf,My Review,Spoiler,Private Notes,Read Count,Owned Copies
+34,\"The Fellowship of the Ring (The Lord of the Rings, #1)\",J.R.R. Tolkien,\"Tolkien, J.R.R.\",,\"=\"\"0618346252\"\"\",\"=\"\"9780618346257\"\"\",5,4.39,Houghton Mifflin Harcourt,Paperback,398,2003,1954,2021/05/03,2020/01/12,\"fantasy, classics\",\"fantasy (#1), classics (#2)\",read,,,,3,0
+12345,Norwegian Wood,Haruki Murakami,\"Murakami, Haruki\",,\"=\"\"\"\"\",\"=\"\"\"\"\",0,4.01,Vintage,Paperback,296,2000,1987,,2022/03/04,,,to-read,,,,0,1
+56,Piranesi,Susanna Clarke,\"Clarke, Susanna\",,\"=\"\"\"\"\",\"=\"\"\"\"\",4,4.09,Bloomsbury,Hardcover,272,2020,2020,,2021/09/01,,,read,,,,2,0
+";
+
+    #[test]
+    fn test_reading_history() {
+        let records = BookRecord::from_reader(EXPORT.as_bytes()).unwrap();
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+
+        assert_eq!(records[0].read_count, 3);
+        assert_eq!(records[0].added_on(), Some(date(2020, 1, 12)));
+        assert_eq!(records[0].reads(), vec![ReadDates { started_at: None, finished_at: date(2021, 5, 3) }]);
+        assert_eq!(records[0].undated_reads(), 2);
+        assert_eq!(records[0].to_imported().other_shelves, vec!["fantasy", "classics"]);
+        assert!(records[0].to_imported().tags.is_empty());
+        assert_eq!(records[1].reads(), vec![]);
+        assert_eq!(records[1].undated_reads(), 0);
+        // Read books without a read date aren't dated by when they were added
+        assert_eq!(records[2].finished_on(), None);
+        assert_eq!(records[2].reads(), vec![]);
+        assert_eq!(records[2].undated_reads(), 2);
+        assert_eq!(parse_date("2022-03-04"), Some(date(2022, 3, 4)));
+        assert_eq!(parse_date(""), None);
+    }
+
+    #[test]
+    fn test_review_to_markdown() {
+        assert_eq!(
+            review_to_markdown("Loved it.<br/><br/>Would <b>definitely</b> read again &amp; again."),
+            "Loved it.\n\nWould **definitely** read again & again."
+        );
+        assert_eq!(
+            review_to_markdown("The end: <spoiler>everybody dies</spoiler><br />"),
+            "The end: >!everybody dies!<"
+        );
+        assert_eq!(review_to_markdown("<a href=\"https://example.com\">link</a>"), "link");
     }
 }
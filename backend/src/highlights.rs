@@ -0,0 +1,431 @@
+use crate::auth::AuthUser;
+use crate::db::connect;
+use crate::models::{Book, Highlight, HighlightTag, Reading};
+use crate::schema::books::dsl::books;
+use crate::schema::highlight_tags::dsl::highlight_tags;
+use crate::schema::highlights::dsl::highlights;
+use crate::schema::readings::dsl::readings;
+use crate::schema::tags::dsl::tags;
+use crate::tags::find_or_create_tags;
+use crate::{schema, ErrorResponse};
+use axum::routing::post;
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/highlights", post(list_highlights))
+        .route("/api/highlights/create", post(create_highlight))
+        .route("/api/highlights/update", post(update_highlight))
+        .route("/api/highlights/remove", post(remove_highlight))
+        .route("/api/highlights/quote-of-the-day", post(quote_of_the_day))
+}
+
+/// Replaces the tags of a highlight with the given tags of its user, creating the missing ones.
+pub fn set_highlight_tags(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    highlight_id: Uuid,
+    names: &[String],
+) -> QueryResult<()> {
+    diesel::delete(highlight_tags.filter(schema::highlight_tags::dsl::highlight.eq(highlight_id)))
+        .execute(connection)?;
+
+    let links: Vec<HighlightTag> = find_or_create_tags(connection, user_id, names)?
+        .into_values()
+        .map(|tag| HighlightTag { highlight: highlight_id, tag })
+        .collect();
+    diesel::insert_into(highlight_tags)
+        .values(&links)
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Loads the tag names of the given highlights.
+fn load_highlight_tags(connection: &mut PgConnection, highlight_ids: &[Uuid]) -> QueryResult<HashMap<Uuid, Vec<String>>> {
+    let rows: Vec<(Uuid, String)> = highlight_tags
+        .inner_join(tags)
+        .filter(schema::highlight_tags::dsl::highlight.eq_any(highlight_ids))
+        .order(schema::tags::dsl::name.asc())
+        .select((schema::highlight_tags::dsl::highlight, schema::tags::dsl::name))
+        .load(connection)?;
+
+    let mut result: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for (highlight, name) in rows {
+        result.entry(highlight).or_default().push(name);
+    }
+    Ok(result)
+}
+
+/// Converts highlights along with their books into JSON, including their tags.
+fn highlights_json(connection: &mut PgConnection, results: Vec<(Highlight, Book)>) -> QueryResult<Vec<serde_json::Value>> {
+    let ids: Vec<Uuid> = results.iter().map(|(highlight, _)| highlight.id).collect();
+    let mut tag_map = load_highlight_tags(connection, &ids)?;
+
+    Ok(results
+        .into_iter()
+        .map(|(highlight, book)| {
+            json!({
+                "id": highlight.id.to_string(),
+                "book_id": highlight.book.to_string(),
+                "book_title": book.title,
+                "book_author": book.author,
+                "reading_id": highlight.reading.map(|r| r.to_string()),
+                "quote": highlight.quote,
+                "page": highlight.page,
+                "location": highlight.location,
+                "note": highlight.note,
+                "tags": tag_map.remove(&highlight.id).unwrap_or_default(),
+                "created_at": highlight.created_at.to_string(),
+                "updated_at": highlight.updated_at.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Picks the index of the quote of the day among `count` highlights.
+///
+/// The pick only depends on the user and the date, so it stays the same throughout the day
+/// (as long as no highlights are added or removed) and differs between users.
+fn daily_index(user_id: Uuid, date: chrono::NaiveDate, count: i64) -> i64 {
+    // FNV-1a, as the std hashers are not guaranteed to be stable across releases
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in user_id.as_bytes().iter().chain(date.to_string().as_bytes()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    (hash % count.max(1) as u64) as i64
+}
+
+/// Request type for listing highlights.
+#[derive(Debug, Deserialize)]
+pub struct ListHighlightsRequest {
+    pub book_id: Option<String>,
+    pub tag: Option<String>,
+}
+
+/// Lists the highlights of a book or of the whole library, newest first.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `book_id`: Optional, the UUID of the book to list the highlights of.
+/// - `tag`: Optional, only list highlights carrying the tag with this name.
+pub(crate) async fn list_highlights(
+    auth: AuthUser,
+    Json(payload): Json<ListHighlightsRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let book_id = match payload.book_id.as_deref().map(Uuid::parse_str).transpose() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+    };
+
+    let mut query = highlights
+        .inner_join(books)
+        .filter(schema::highlights::dsl::user.eq(auth.0))
+        .order(schema::highlights::dsl::created_at.desc())
+        .select((Highlight::as_select(), Book::as_select()))
+        .into_boxed();
+    if let Some(book_id) = book_id {
+        query = query.filter(schema::highlights::dsl::book.eq(book_id));
+    }
+    if let Some(tag) = payload.tag.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        query = query.filter(
+            schema::highlights::dsl::id.eq_any(
+                highlight_tags
+                    .inner_join(tags)
+                    .filter(schema::tags::dsl::user.eq(auth.0))
+                    .filter(schema::tags::dsl::name.eq(tag.to_string()))
+                    .select(schema::highlight_tags::dsl::highlight),
+            ),
+        );
+    }
+
+    match query
+        .load::<(Highlight, Book)>(connection)
+        .and_then(|results| highlights_json(connection, results))
+    {
+        Ok(json_highlights) => (StatusCode::OK, Json(json!({ "highlights": json_highlights }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading highlights: {}", e) }))),
+    }
+}
+
+/// Request type for creating a highlight.
+#[derive(Debug, Deserialize)]
+pub struct CreateHighlightRequest {
+    pub book_id: String,
+    pub reading_id: Option<String>,
+    pub quote: String,
+    pub page: Option<i32>,
+    pub location: Option<String>,
+    pub note: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
+/// Adds a quote or highlight of a book to the collection.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `book_id`: The UUID of the book the quote is taken from.
+/// - `reading_id`: Optional, the UUID of the reading session the quote was highlighted during.
+/// - `quote`: The quoted text.
+/// - `page`: Optional, the page of the quote.
+/// - `location`: Optional, the position of the quote for books without pages.
+/// - `note`: Optional, a comment on the quote.
+/// - `tags`: Optional, the names of the tags of the quote, missing tags are created.
+pub(crate) async fn create_highlight(
+    auth: AuthUser,
+    Json(payload): Json<CreateHighlightRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let book_id = match Uuid::parse_str(&payload.book_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+    };
+
+    let reading_id = match payload.reading_id.as_deref().map(Uuid::parse_str).transpose() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid reading ID.".to_string() }))),
+    };
+
+    if payload.quote.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Quote must not be empty.".to_string() })));
+    }
+
+    let book: Book = match books
+        .filter(schema::books::dsl::id.eq(book_id))
+        .first(connection)
+    {
+        Ok(b) => b,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Book not found.".to_string() }))),
+    };
+
+    if book.user != auth.0 {
+        return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
+    }
+
+    if let Some(reading_id) = reading_id {
+        let reading: Reading = match readings
+            .filter(schema::readings::dsl::id.eq(reading_id))
+            .first(connection)
+        {
+            Ok(r) => r,
+            Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Reading not found.".to_string() }))),
+        };
+
+        if reading.book != book_id {
+            return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Reading does not belong to the book.".to_string() })));
+        }
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let new_highlight = Highlight {
+        id: Uuid::new_v4(),
+        user: auth.0,
+        book: book_id,
+        reading: reading_id,
+        quote: payload.quote.trim().to_string(),
+        page: payload.page,
+        location: payload.location.map(|l| l.trim().to_string()).filter(|l| !l.is_empty()),
+        note: payload.note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()),
+        created_at: now,
+        updated_at: now,
+        source_hash: None,
+    };
+
+    match connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::insert_into(highlights)
+            .values(&new_highlight)
+            .execute(conn)?;
+        set_highlight_tags(conn, auth.0, new_highlight.id, payload.tags.as_deref().unwrap_or_default())
+    }) {
+        Ok(_) => (StatusCode::CREATED, Json(json!({ "message": "Highlight created successfully.", "id": new_highlight.id.to_string() }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while creating the highlight: {}", e) }))),
+    }
+}
+
+/// Request type for updating a highlight.
+#[derive(Debug, Deserialize)]
+pub struct UpdateHighlightRequest {
+    pub highlight_id: String,
+    pub quote: String,
+    pub page: Option<i32>,
+    pub location: Option<String>,
+    pub note: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
+/// Updates a highlight.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `highlight_id`: The UUID of the highlight to update.
+/// - `quote`: The quoted text.
+/// - `page`: Optional, the page of the quote.
+/// - `location`: Optional, the position of the quote for books without pages.
+/// - `note`: Optional, a comment on the quote.
+/// - `tags`: Optional, replaces the tags of the quote if given.
+pub(crate) async fn update_highlight(
+    auth: AuthUser,
+    Json(payload): Json<UpdateHighlightRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let highlight_id = match Uuid::parse_str(&payload.highlight_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid highlight ID.".to_string() }))),
+    };
+
+    if payload.quote.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Quote must not be empty.".to_string() })));
+    }
+
+    let result = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        let updated = diesel::update(
+            highlights
+                .filter(schema::highlights::dsl::id.eq(highlight_id))
+                .filter(schema::highlights::dsl::user.eq(auth.0)),
+        )
+        .set((
+            schema::highlights::dsl::quote.eq(payload.quote.trim()),
+            schema::highlights::dsl::page.eq(payload.page),
+            schema::highlights::dsl::location.eq(payload.location.as_deref().map(str::trim).filter(|l| !l.is_empty())),
+            schema::highlights::dsl::note.eq(payload.note.as_deref().map(str::trim).filter(|n| !n.is_empty())),
+        ))
+        .execute(conn)?;
+
+        if updated > 0 {
+            if let Some(names) = &payload.tags {
+                set_highlight_tags(conn, auth.0, highlight_id, names)?;
+            }
+        }
+        Ok(updated)
+    });
+
+    match result {
+        Ok(0) => (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Highlight not found.".to_string() }))),
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Highlight updated successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while updating the highlight: {}", e) }))),
+    }
+}
+
+/// Request type for removing a highlight.
+#[derive(Debug, Deserialize)]
+pub struct RemoveHighlightRequest {
+    pub highlight_id: String,
+}
+
+/// Removes a highlight.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `highlight_id`: The UUID of the highlight to remove.
+pub(crate) async fn remove_highlight(
+    auth: AuthUser,
+    Json(payload): Json<RemoveHighlightRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let highlight_id = match Uuid::parse_str(&payload.highlight_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid highlight ID.".to_string() }))),
+    };
+
+    match diesel::delete(
+        highlights
+            .filter(schema::highlights::dsl::id.eq(highlight_id))
+            .filter(schema::highlights::dsl::user.eq(auth.0)),
+    )
+    .execute(connection)
+    {
+        Ok(0) => (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Highlight not found.".to_string() }))),
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Highlight removed successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while removing the highlight: {}", e) }))),
+    }
+}
+
+/// Picks a random highlight of the user which stays the same for the whole day.
+pub(crate) async fn quote_of_the_day(auth: AuthUser) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let count: i64 = match highlights
+        .filter(schema::highlights::dsl::user.eq(auth.0))
+        .count()
+        .get_result(connection)
+    {
+        Ok(c) => c,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading highlights: {}", e) }))),
+    };
+
+    if count == 0 {
+        return (StatusCode::OK, Json(json!({ "highlight": null })));
+    }
+
+    let index = daily_index(auth.0, chrono::Utc::now().date_naive(), count);
+    match highlights
+        .inner_join(books)
+        .filter(schema::highlights::dsl::user.eq(auth.0))
+        .order((schema::highlights::dsl::created_at.asc(), schema::highlights::dsl::id.asc()))
+        .offset(index)
+        .limit(1)
+        .select((Highlight::as_select(), Book::as_select()))
+        .load::<(Highlight, Book)>(connection)
+        .and_then(|results| highlights_json(connection, results))
+    {
+        Ok(json_highlights) => (StatusCode::OK, Json(json!({ "highlight": json_highlights.into_iter().next() }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading highlights: {}", e) }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[test]
+    fn test_daily_index_is_stable_per_day() {
+        let user = Uuid::parse_str("9b2e4c1e-6f0a-4b7e-8d3c-2a1f5e6d7c8b").unwrap();
+        let day = chrono::NaiveDate::from_ymd_opt(2025, 4, 26).unwrap();
+
+        let index = daily_index(user, day, 50);
+        assert!((0..50).contains(&index));
+        assert_eq!(index, daily_index(user, day, 50));
+        assert_eq!(daily_index(user, day, 1), 0);
+
+        let picks: std::collections::HashSet<i64> = (0..30)
+            .map(|offset| daily_index(user, day + chrono::Days::new(offset), 50))
+            .collect();
+        assert!(picks.len() > 1);
+    }
+
+    #[tokio::test]
+    async fn test_list_highlights_requires_auth() {
+        let app = Router::new().route("/api/highlights", post(list_highlights));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/highlights").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_create_highlight_requires_auth() {
+        let app = Router::new().route("/api/highlights/create", post(create_highlight));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/highlights/create").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_quote_of_the_day_requires_auth() {
+        let app = Router::new().route("/api/highlights/quote-of-the-day", post(quote_of_the_day));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/highlights/quote-of-the-day").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+}
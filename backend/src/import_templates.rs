@@ -0,0 +1,173 @@
+use crate::auth::AuthUser;
+use crate::csv_importer::ColumnMapping;
+use crate::db::connect;
+use crate::models::ImportTemplate;
+use crate::schema::import_templates::dsl::import_templates;
+use crate::{schema, ErrorResponse};
+use axum::routing::post;
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/imports/templates", post(list_import_templates))
+        .route("/api/imports/templates/save", post(save_import_template))
+        .route("/api/imports/templates/remove", post(remove_import_template))
+}
+
+/// Saves the column mapping of a CSV file under a name, replacing the template of that name.
+pub fn save_template(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    name: &str,
+    mapping: &ColumnMapping,
+) -> QueryResult<Uuid> {
+    let now = chrono::Utc::now().naive_utc();
+    let template = ImportTemplate {
+        id: Uuid::new_v4(),
+        user: user_id,
+        name: name.trim().to_string(),
+        mapping: json!(mapping),
+        created_at: now,
+        updated_at: now,
+    };
+
+    diesel::insert_into(import_templates)
+        .values(&template)
+        .on_conflict((schema::import_templates::dsl::user, schema::import_templates::dsl::name))
+        .do_update()
+        .set(schema::import_templates::dsl::mapping.eq(&template.mapping))
+        .returning(schema::import_templates::dsl::id)
+        .get_result(connection)
+}
+
+/// Loads the column mapping of a template of the user.
+pub fn load_template(connection: &mut PgConnection, user_id: Uuid, template_id: Uuid) -> QueryResult<ColumnMapping> {
+    let template: ImportTemplate = import_templates
+        .filter(schema::import_templates::dsl::id.eq(template_id))
+        .filter(schema::import_templates::dsl::user.eq(user_id))
+        .first(connection)?;
+    serde_json::from_value(template.mapping).map_err(|e| diesel::result::Error::DeserializationError(Box::new(e)))
+}
+
+/// Lists the saved column mappings of a user.
+pub(crate) async fn list_import_templates(auth: AuthUser) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let results = match import_templates
+        .filter(schema::import_templates::dsl::user.eq(auth.0))
+        .order(schema::import_templates::dsl::name.asc())
+        .load::<ImportTemplate>(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading import templates: {}", e) }))),
+    };
+
+    let templates: Vec<_> = results
+        .into_iter()
+        .map(|template| {
+            json!({
+                "id": template.id.to_string(),
+                "name": template.name,
+                "mapping": template.mapping,
+                "created_at": template.created_at.to_string(),
+                "updated_at": template.updated_at.to_string(),
+            })
+        })
+        .collect();
+
+    (StatusCode::OK, Json(json!({ "templates": templates })))
+}
+
+/// Request type for saving an import template.
+#[derive(Debug, Deserialize)]
+pub struct SaveImportTemplateRequest {
+    pub name: String,
+    pub mapping: ColumnMapping,
+}
+
+/// Saves the column mapping of a CSV file for later imports.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `name`: The name of the template, an existing template of that name is replaced.
+/// - `mapping`: The headers of the columns holding the `title` and, optionally, the `author`,
+///   `isbn`, `shelf`, `date_read` and `pages` of the books. The `date_format` of the read dates
+///   and the `delimiter` of the columns may be given as well.
+///
+/// Authors may be listed by last name, e.g. "Le Guin, Ursula K.". Several authors are separated by
+/// semicolons or ampersands, e.g. "Pratchett, Terry; Gaiman, Neil".
+pub(crate) async fn save_import_template(
+    auth: AuthUser,
+    Json(payload): Json<SaveImportTemplateRequest>,
+) -> impl IntoResponse {
+    if payload.name.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Missing template name.".to_string() })));
+    }
+
+    let connection = &mut connect();
+
+    match save_template(connection, auth.0, &payload.name, &payload.mapping) {
+        Ok(id) => (StatusCode::CREATED, Json(json!({ "message": "Import template saved successfully.", "id": id.to_string() }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while saving the import template: {}", e) }))),
+    }
+}
+
+/// Request type for removing an import template.
+#[derive(Debug, Deserialize)]
+pub struct RemoveImportTemplateRequest {
+    pub template_id: String,
+}
+
+/// Removes a saved column mapping.
+pub(crate) async fn remove_import_template(
+    auth: AuthUser,
+    Json(payload): Json<RemoveImportTemplateRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let template_id = match Uuid::parse_str(&payload.template_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid template ID.".to_string() }))),
+    };
+
+    match diesel::delete(
+        import_templates
+            .filter(schema::import_templates::dsl::id.eq(template_id))
+            .filter(schema::import_templates::dsl::user.eq(auth.0)),
+    )
+    .execute(connection)
+    {
+        Ok(0) => (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Import template not found.".to_string() }))),
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Import template removed successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while removing the import template: {}", e) }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_list_import_templates_requires_auth() {
+        let app = Router::new().route("/api/imports/templates", post(list_import_templates));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/imports/templates").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_save_import_template_requires_auth() {
+        let app = Router::new().route("/api/imports/templates/save", post(save_import_template));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/imports/templates/save").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::contributors::{is_name_suffix, parse_contributors, ContributorInput};
+use crate::calibre_importer::CalibreImporter;
+use crate::csv_importer::CsvImporter;
+use crate::goodreads_importer::{GoodreadsImporter, SeriesMarker};
+use crate::librarything_importer::LibraryThingImporter;
+use crate::models::{ContributorRole, ReadingMode};
+use crate::readings::{ReadDates, UnfinishedRead};
+use crate::storygraph_importer::StoryGraphImporter;
+use chrono::NaiveDate;
+use csv::{ReaderBuilder, StringRecord};
+use std::path::PathBuf;
+
+/// A book read from the export of another service, which each importer maps its records onto.
+///
+/// The shelves, readings, rating, review and notes of the book are part of it, so that
+/// persisting them, detecting duplicates and reporting progress is shared by all importers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedBook {
+    /// The title as exported, books imported before series were split off were keyed by it.
+    pub raw_title: String,
+    pub title: String,
+    pub series: Option<SeriesMarker>,
+    pub author: String,
+    pub contributors: Vec<ContributorInput>,
+    pub isbn13: Option<String>,
+    pub isbn10: Option<String>,
+    pub publisher: Option<String>,
+    pub published_year: Option<i32>,
+    pub page_count: Option<i32>,
+    pub added_on: Option<NaiveDate>,
+    /// The shelf for the status of the book, e.g. `read` or `to-read`.
+    pub shelf: String,
+    /// Further shelves of the book, which are turned into tags if requested.
+    pub other_shelves: Vec<String>,
+    pub tags: Vec<String>,
+    pub rating: Option<i16>,
+    pub review: Option<String>,
+    pub note: Option<String>,
+    pub reading_mode: ReadingMode,
+    /// Finished reads of the book, which are only recorded with its copy on `shelf`.
+    pub reads: Vec<ReadDates>,
+    /// Further reads the service only counts without dating them, they're reported with the
+    /// import rather than recorded with made up dates.
+    pub undated_reads: i32,
+    /// The reading of a book which is being read or was given up on, recorded like `reads`.
+    pub unfinished_read: Option<UnfinishedRead>,
+    /// Whether the service has a cover for the book.
+    pub has_cover: bool,
+    /// The cover of the book, if it can be read from the server.
+    pub cover_file: Option<PathBuf>,
+}
+
+/// Rows of an imported file, rows which fail to parse are kept as errors so they can be reported.
+pub type ImportedRows = Vec<Result<ImportedBook, String>>;
+
+/// Reads the books of a file exported by another service.
+pub trait Importer: Sync {
+    /// Identifies the importer, it is stored with the import jobs.
+    fn source(&self) -> &'static str;
+
+    /// Name of the service the files come from, e.g. "Goodreads".
+    fn name(&self) -> &'static str;
+
+    /// Checks whether a file looks like an export of the service.
+    fn detect(&self, data: &[u8]) -> bool;
+
+    /// Whether files of the service can be told apart from others, the importer has to be chosen
+    /// explicitly otherwise.
+    fn detectable(&self) -> bool {
+        true
+    }
+
+    /// Reads the books of a file, `options` are those the import was started with.
+    fn read(&self, data: &[u8], options: &serde_json::Value) -> Result<ImportedRows, String>;
+}
+
+/// All importers, in the order formats are detected in.
+pub fn importers() -> &'static [&'static dyn Importer] {
+    &[&GoodreadsImporter, &StoryGraphImporter, &LibraryThingImporter, &CalibreImporter, &CsvImporter]
+}
+
+/// Looks up the importer of a source.
+pub fn find_importer(source: &str) -> Option<&'static dyn Importer> {
+    importers().iter().copied().find(|importer| importer.source() == source)
+}
+
+/// Detects the importer for a file, erroring with the formats which are supported otherwise.
+pub fn detect_importer(data: &[u8]) -> Result<&'static dyn Importer, String> {
+    importers()
+        .iter()
+        .copied()
+        .find(|importer| importer.detect(data))
+        .ok_or_else(|| {
+            let names: Vec<&str> = importers()
+                .iter()
+                .filter(|importer| importer.detectable())
+                .map(|importer| importer.name())
+                .collect();
+            let expected = match names.split_last() {
+                Some((last, [])) => last.to_string(),
+                Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+                None => String::new(),
+            };
+            format!("Unrecognized file, expected an export of {}.", expected)
+        })
+}
+
+/// Reads the header row of a CSV file.
+pub fn csv_headers(data: &[u8]) -> Option<StringRecord> {
+    ReaderBuilder::new().from_reader(data).headers().ok().cloned()
+}
+
+/// Checks whether all of the given headers are present.
+pub fn has_headers(headers: &StringRecord, expected: &[&str]) -> bool {
+    expected
+        .iter()
+        .all(|header| headers.iter().any(|h| h.trim() == *header))
+}
+
+/// Decodes a text file, spreadsheets and some services export UTF-16 rather than UTF-8.
+pub fn decode_text(data: &[u8]) -> Result<String, String> {
+    if let Some(utf16) = data.strip_prefix(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        return String::from_utf16(&units).map_err(|e| e.to_string());
+    }
+    let data = data.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(data);
+    String::from_utf8(data.to_vec()).map_err(|e| e.to_string())
+}
+
+/// Turns the name of a shelf or list into a shelf name like `to-read`.
+pub fn shelf_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join("-").to_lowercase()
+}
+
+/// Splits an ISBN into ISBN-13 and ISBN-10, ignoring dashes and anything which isn't one.
+pub fn split_isbn(value: &str) -> (Option<String>, Option<String>) {
+    let isbn: String = value
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+        .collect::<String>()
+        .to_uppercase();
+    match isbn.len() {
+        13 if isbn.chars().all(|c| c.is_ascii_digit()) => (Some(isbn), None),
+        10 if isbn[..9].chars().all(|c| c.is_ascii_digit()) => (None, Some(isbn)),
+        _ => (None, None),
+    }
+}
+
+/// Turns a name like "Pratchett, Terry" into "Terry Pratchett", keeping suffixes and roles at the
+/// end, e.g. "King, Martin Luther, Jr." or "Rubin, Jay (Translator)". Anything else with more
+/// than one comma is left as it is.
+pub fn flip_name(name: &str) -> String {
+    let name = name.trim();
+    if let Some((name, role)) = name.strip_suffix(')').and_then(|name| name.rsplit_once('(')) {
+        return format!("{} ({})", flip_name(name), role);
+    }
+    match name.rsplit_once(',') {
+        Some((rest, suffix)) if is_name_suffix(suffix.trim()) => match rest.contains(',') {
+            true => format!("{}, {}", flip_name(rest), suffix.trim()),
+            false => name.to_string(),
+        },
+        Some((last, first)) if !last.contains(',') => format!("{} {}", first.trim(), last.trim()),
+        _ => name.to_string(),
+    }
+}
+
+/// Parses the author field of a spreadsheet, which lists authors like "Terry Pratchett & Neil
+/// Gaiman" or by last name like "Le Guin, Ursula K." and "Pratchett, Terry; Gaiman, Neil".
+///
+/// A field with a single comma is a name by last name, several authors need to be separated by
+/// semicolons, ampersands or more than one comma then.
+pub fn parse_author_field(text: &str) -> Vec<ContributorInput> {
+    let names: Vec<String> = text.split(';').map(flip_name).collect();
+    parse_contributors(&names.join("; "))
+}
+
+/// Returns the first author of a book, which is kept as its author like Goodreads exports it.
+pub fn primary_author(contributors: &[ContributorInput]) -> Option<String> {
+    contributors
+        .iter()
+        .find(|contributor| contributor.role == ContributorRole::Author)
+        .map(|contributor| contributor.name.clone())
+}
+
+/// Parses the authors of a book along with further contributors, keeping each of them once.
+pub fn merge_contributors(authors: &str, additional: Option<&str>) -> Vec<ContributorInput> {
+    let mut contributors = parse_contributors(authors);
+    for contributor in parse_contributors(additional.unwrap_or_default()) {
+        if !contributors.contains(&contributor) {
+            contributors.push(contributor);
+        }
+    }
+    contributors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_importer() {
+        let goodreads = "Book Id,Title,Author,Author l-f,Additional Authors,ISBN,ISBN13,My Rating,Average Rating,Publisher,Binding,Number of Pages,Year Published,Original Publication Year,Date Read,Date Added,Bookshelves,Bookshelves with positions,Exclusive Shelf,My Review,Spoiler,Private Notes,Read Count,Owned Copies\n";
+        let storygraph = "Title,Authors,Contributors,ISBN/UID,Format,Read Status,Date Added,Last Date Read,Dates Read,Read Count,Moods,Star Rating,Review,Tags,Owned?\n";
+        assert_eq!(detect_importer(goodreads.as_bytes()).unwrap().source(), "goodreads");
+        assert_eq!(detect_importer(storygraph.as_bytes()).unwrap().source(), "storygraph");
+        assert_eq!(
+            detect_importer(b"foo,bar\n1,2\n").err().as_deref(),
+            Some("Unrecognized file, expected an export of Goodreads, StoryGraph, LibraryThing or Calibre.")
+        );
+        assert!(find_importer("storygraph").is_some());
+        assert!(find_importer("unknown").is_none());
+    }
+
+    #[test]
+    fn test_decode_text() {
+        assert_eq!(decode_text(b"\xEF\xBB\xBFTitle\tAuthor").unwrap(), "Title\tAuthor");
+        let utf16: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain("Títle".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        assert_eq!(decode_text(&utf16).unwrap(), "Títle");
+        assert!(decode_text(b"\xFF\x00").is_err());
+    }
+
+    #[test]
+    fn test_parse_author_field() {
+        let names = |text| parse_author_field(text).into_iter().map(|c| c.name).collect::<Vec<_>>();
+        assert_eq!(names("Pratchett, Terry; Gaiman, Neil"), vec!["Terry Pratchett", "Neil Gaiman"]);
+        assert_eq!(names("Le Guin, Ursula K."), vec!["Ursula K. Le Guin"]);
+        assert_eq!(names("Pratchett, Terry"), vec!["Terry Pratchett"]);
+        assert_eq!(names("King, Martin Luther, Jr."), vec!["Martin Luther King, Jr."]);
+        assert_eq!(names("Martin Luther King, Jr."), vec!["Martin Luther King, Jr."]);
+        assert_eq!(names("Terry Pratchett & Neil Gaiman"), vec!["Terry Pratchett", "Neil Gaiman"]);
+        assert_eq!(names("Le Guin, Ursula K. (Editor)"), vec!["Ursula K. Le Guin"]);
+        assert_eq!(
+            names("Terry Pratchett, Neil Gaiman, Stephen Briggs"),
+            vec!["Terry Pratchett", "Neil Gaiman", "Stephen Briggs"]
+        );
+
+        let contributors = parse_author_field("Murakami, Haruki; Jay Rubin (Translator)");
+        assert_eq!(primary_author(&contributors).as_deref(), Some("Haruki Murakami"));
+        assert_eq!(primary_author(&parse_author_field("Jay Rubin (Translator)")), None);
+    }
+
+    #[test]
+    fn test_split_isbn() {
+        assert_eq!(split_isbn("978-0-575-04606-3"), (Some("9780575046063".to_string()), None));
+        assert_eq!(split_isbn("[076532635x]"), (None, Some("076532635X".to_string())));
+        assert_eq!(split_isbn("B08FGV64B1"), (None, None));
+        assert_eq!(shelf_name("Currently  Reading"), "currently-reading");
+    }
+}
@@ -0,0 +1,1857 @@
+use crate::auth::AuthUser;
+use crate::contributors::{attach_contributors, ContributorInput};
+use crate::covers::store_cover;
+use crate::csv_importer::{ColumnMapping, CsvImporter};
+use crate::db::connect;
+use crate::import_templates::{load_template, save_template};
+use crate::importer::{detect_importer, find_importer, importers, ImportedBook, ImportedRows, Importer};
+use crate::kindle_importer::{authors_match, parse_clippings, title_key, ClippingKind};
+use crate::models::{Book, Highlight, ImportJob, ImportJobError, ImportStatus, NewImportJob, Note, Reading, ReadingMode, Shelf};
+use crate::notes::add_note;
+use crate::readings::{record_finished_reading, record_unfinished_reading, ReadDates, UnfinishedRead};
+use crate::reviews::upsert_review;
+use crate::schema::import_job_errors::dsl::import_job_errors;
+use crate::schema::import_jobs::dsl::import_jobs;
+use crate::series::{find_or_create_series, link_book};
+use crate::tags::tag_book;
+use crate::{schema, ErrorResponse};
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, Multipart};
+use axum::routing::post;
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use chrono::NaiveDate;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeSet, HashMap};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{error, info};
+use uuid::Uuid;
+
+const MAX_IMPORT_BYTES: usize = 25 * 1024 * 1024; // 25 MB
+
+/// Number of rows committed at once, an interrupted job resumes after the last committed batch.
+const BATCH_SIZE: usize = 100;
+
+/// How often the worker looks for queued jobs if it isn't notified about them.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a previewed import waits to be confirmed before its file is discarded.
+const PREVIEW_TTL: chrono::TimeDelta = chrono::TimeDelta::days(1);
+
+/// How often the worker renews its claim on the job it's running.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a claim lasts without a heartbeat, after which the worker is assumed to be gone and
+/// the job is resumed by another one.
+const CLAIM_TTL: chrono::TimeDelta = chrono::TimeDelta::minutes(2);
+
+/// Wakes up the worker when a job was queued.
+static JOB_QUEUED: Notify = Notify::const_new();
+
+/// Identifies the worker of this instance in the claims on jobs.
+static WORKER_ID: LazyLock<Uuid> = LazyLock::new(Uuid::new_v4);
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route(
+            "/api/imports/upload",
+            post(upload_import).layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)),
+        )
+        .route(
+            "/api/user/import-kindle",
+            post(import_kindle).layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)),
+        )
+        .route(
+            "/api/imports/preview",
+            post(preview_import).layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)),
+        )
+        .route("/api/imports/confirm", post(confirm_import))
+        .route("/api/imports/sources", post(list_import_sources))
+        .route("/api/imports", post(list_import_jobs))
+        .route("/api/imports/status", post(get_import_job))
+        .route("/api/imports/errors", post(list_import_job_errors))
+}
+
+/// Creates an import job storing the uploaded file along with it, queued jobs are picked up by
+/// the worker right away.
+pub fn create_job(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    source: &str,
+    status: ImportStatus,
+    options: serde_json::Value,
+    payload: Vec<u8>,
+) -> QueryResult<Uuid> {
+    let job = NewImportJob {
+        id: Uuid::new_v4(),
+        user: user_id,
+        source: source.to_string(),
+        status,
+        options,
+        payload: Some(payload),
+    };
+    diesel::insert_into(import_jobs).values(&job).execute(connection)?;
+    if job.status == ImportStatus::Queued {
+        JOB_QUEUED.notify_one();
+    }
+    Ok(job.id)
+}
+
+/// Starts the worker processing import jobs one after another.
+///
+/// Call this once at application startup. Jobs whose worker stopped, e.g. because its instance
+/// was restarted, are picked up again and continue after their last committed batch.
+pub fn spawn_worker() {
+    info!("starting import worker {}...", *WORKER_ID);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            interval.tick().await;
+            match tokio::task::spawn_blocking(renew_claims).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => error!("Error while renewing import job claims: {}", e),
+                Err(e) => error!("Error while renewing import job claims: {}", e),
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            match tokio::task::spawn_blocking(process_next_job).await {
+                Ok(Ok(true)) => {}
+                Ok(Ok(false)) => {
+                    let _ = tokio::time::timeout(POLL_INTERVAL, JOB_QUEUED.notified()).await;
+                }
+                Ok(Err(e)) => {
+                    error!("Error while processing import jobs: {}", e);
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+                Err(e) => {
+                    error!("Import worker panicked: {}", e);
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    });
+}
+
+/// Renews the claims of this worker on the jobs it's running.
+fn renew_claims() -> QueryResult<usize> {
+    let connection = &mut connect();
+    diesel::update(
+        import_jobs
+            .filter(schema::import_jobs::dsl::status.eq(ImportStatus::Running))
+            .filter(schema::import_jobs::dsl::worker.eq(*WORKER_ID)),
+    )
+    .set(schema::import_jobs::dsl::heartbeat_at.eq(chrono::Utc::now().naive_utc()))
+    .execute(connection)
+}
+
+/// Queues running jobs again whose worker stopped renewing its claim, jobs of workers which are
+/// still alive are left alone.
+fn requeue_stale_jobs(connection: &mut PgConnection, now: chrono::NaiveDateTime) -> QueryResult<usize> {
+    diesel::update(
+        import_jobs
+            .filter(schema::import_jobs::dsl::status.eq(ImportStatus::Running))
+            .filter(
+                schema::import_jobs::dsl::heartbeat_at
+                    .lt(now - CLAIM_TTL)
+                    .or(schema::import_jobs::dsl::heartbeat_at.is_null()),
+            ),
+    )
+    .set((
+        schema::import_jobs::dsl::status.eq(ImportStatus::Queued),
+        schema::import_jobs::dsl::worker.eq(None::<Uuid>),
+    ))
+    .execute(connection)
+}
+
+/// Describes why a job panicked.
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    let reason = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown error");
+    format!("The import crashed: {}", reason)
+}
+
+/// Claims the oldest queued job and runs it, returning whether there was one.
+fn process_next_job() -> QueryResult<bool> {
+    let connection = &mut connect();
+    let now = chrono::Utc::now().naive_utc();
+
+    match requeue_stale_jobs(connection, now)? {
+        0 => {}
+        count => info!("resuming {} interrupted import jobs", count),
+    }
+
+    let job = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        let job: Option<ImportJob> = import_jobs
+            .filter(schema::import_jobs::dsl::status.eq(ImportStatus::Queued))
+            .order(schema::import_jobs::dsl::created_at.asc())
+            .select(ImportJob::as_select())
+            .for_update()
+            .skip_locked()
+            .first(conn)
+            .optional()?;
+
+        if let Some(job) = &job {
+            diesel::update(import_jobs.filter(schema::import_jobs::dsl::id.eq(job.id)))
+                .set((
+                    schema::import_jobs::dsl::status.eq(ImportStatus::Running),
+                    schema::import_jobs::dsl::started_at.eq(job.started_at.unwrap_or(now)),
+                    schema::import_jobs::dsl::worker.eq(Some(*WORKER_ID)),
+                    schema::import_jobs::dsl::heartbeat_at.eq(Some(now)),
+                ))
+                .execute(conn)?;
+        }
+        Ok(job)
+    })?;
+
+    let Some(job) = job else {
+        // Nothing to do, use the time to clean up previews which were never confirmed
+        diesel::delete(
+            import_jobs
+                .filter(schema::import_jobs::dsl::status.eq(ImportStatus::Preview))
+                .filter(schema::import_jobs::dsl::created_at.lt(now - PREVIEW_TTL)),
+        )
+        .execute(connection)?;
+        return Ok(false);
+    };
+
+    info!("running {} import job {}...", job.source, job.id);
+    // A panicking job fails on its own instead of staying claimed until it's resumed, which would
+    // likely panic again
+    let result = match catch_unwind(AssertUnwindSafe(|| match find_importer(&job.source) {
+        Some(importer) => run_book_job(connection, &job, importer),
+        None => Err(format!("Unknown import source '{}'.", job.source)),
+    })) {
+        Ok(result) => result,
+        Err(panic) => {
+            // The panic may have left a transaction open, closing the connection rolls it back
+            *connection = connect();
+            Err(panic_message(panic.as_ref()))
+        }
+    };
+
+    let (status, job_error) = match result {
+        Ok(()) => (ImportStatus::Completed, None),
+        Err(e) => {
+            error!("Import job {} failed: {}", job.id, e);
+            (ImportStatus::Failed, Some(e))
+        }
+    };
+    // The job is only finished if no other worker took it over in the meantime
+    diesel::update(
+        import_jobs
+            .filter(schema::import_jobs::dsl::id.eq(job.id))
+            .filter(schema::import_jobs::dsl::worker.eq(*WORKER_ID)),
+    )
+    .set((
+        schema::import_jobs::dsl::status.eq(status),
+        schema::import_jobs::dsl::error.eq(job_error),
+        schema::import_jobs::dsl::finished_at.eq(Some(chrono::Utc::now().naive_utc())),
+        schema::import_jobs::dsl::payload.eq(None::<Vec<u8>>),
+    ))
+    .execute(connection)?;
+    Ok(true)
+}
+
+/// Outcome of a batch of rows, stored with the job when the batch is committed.
+#[derive(Default)]
+struct BatchResult {
+    added: i32,
+    merged: i32,
+    skipped: i32,
+    failed: i32,
+    undated_reads: i32,
+    errors: Vec<(usize, String)>,
+    /// Covers to store once the batch is committed, with the row and book they belong to.
+    covers: Vec<(usize, Uuid, PathBuf)>,
+}
+
+impl BatchResult {
+    fn fail(&mut self, row: usize, message: String) {
+        self.failed += 1;
+        self.errors.push((row, message));
+    }
+
+    /// Counts the books a row added or merged, along with the covers to store for them.
+    fn add_books(&mut self, row: usize, books: &[PreparedBook]) {
+        for book in books {
+            if book.merge {
+                self.merged += 1;
+            } else {
+                self.added += 1;
+            }
+            self.undated_reads += book.undated_reads;
+            if let Some(file) = &book.cover_file {
+                self.covers.push((row, book.book.id, file.clone()));
+            }
+        }
+    }
+}
+
+/// What to do with a book of a previewed import, chosen by the user when confirming it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// Leave the book out.
+    Skip,
+    /// Fill in the book already on the shelf with the imported details.
+    Merge,
+    /// Add the book to the shelf, even if it's already there.
+    Add,
+}
+
+/// A book an imported record places on one of its target shelves.
+struct PlannedBook {
+    /// Identifies the book within the import as "row:shelf", decisions refer to it.
+    item: String,
+    shelf_name: String,
+    shelf_id: Uuid,
+    key: (Uuid, String),
+    /// The book already on the shelf which the record is a duplicate of.
+    existing: Option<Uuid>,
+}
+
+impl PlannedBook {
+    fn default_decision(&self) -> Decision {
+        if self.existing.is_some() {
+            Decision::Skip
+        } else {
+            Decision::Add
+        }
+    }
+}
+
+/// A book row to insert for an imported record, along with everything attached to it.
+struct PreparedBook {
+    book: Book,
+    /// Whether `book` is an existing book which the details are merged into.
+    merge: bool,
+    contributors: Vec<ContributorInput>,
+    series: Option<(Uuid, Option<f64>)>,
+    tags: Vec<String>,
+    rating: Option<i16>,
+    review: Option<String>,
+    note: Option<String>,
+    reading_mode: ReadingMode,
+    reads: Vec<ReadDates>,
+    undated_reads: i32,
+    unfinished_read: Option<UnfinishedRead>,
+    cover_file: Option<PathBuf>,
+}
+
+impl PreparedBook {
+    /// Inserts everything attached to the book, which has to be inserted already.
+    fn insert_details(&self, connection: &mut PgConnection) -> QueryResult<()> {
+        let book = &self.book;
+        attach_contributors(connection, book.user, book.id, &self.contributors)?;
+        if let Some((series_id, position)) = self.series {
+            link_book(connection, series_id, book.id, position)?;
+        }
+        tag_book(connection, book.user, book.id, &self.tags)?;
+        if self.rating.is_some() || self.review.is_some() {
+            upsert_review(connection, book.user, book.id, None, self.rating, self.review.clone())?;
+        }
+        if let Some(note) = &self.note {
+            add_note(connection, book.user, book.id, None, None, note)?;
+        }
+        for read in &self.reads {
+            record_finished_reading(connection, book.user, book.id, self.reading_mode, book.page_count, *read)?;
+        }
+        if let Some(read) = self.unfinished_read {
+            record_unfinished_reading(connection, book.user, book.id, self.reading_mode, book.page_count, read)?;
+        }
+        Ok(())
+    }
+
+    /// Fills in the existing book with the imported details, keeping everything already set.
+    fn merge_details(&self, connection: &mut PgConnection) -> QueryResult<()> {
+        let existing: Book = schema::books::dsl::books
+            .find(self.book.id)
+            .first(connection)?;
+        let book = &self.book;
+
+        diesel::update(schema::books::dsl::books.find(book.id))
+            .set((
+                schema::books::dsl::isbn13.eq(existing.isbn13.or(book.isbn13.clone())),
+                schema::books::dsl::isbn10.eq(existing.isbn10.or(book.isbn10.clone())),
+                schema::books::dsl::publisher.eq(existing.publisher.or(book.publisher.clone())),
+                schema::books::dsl::published_year.eq(existing.published_year.or(book.published_year)),
+                schema::books::dsl::page_count.eq(existing.page_count.or(book.page_count)),
+            ))
+            .execute(connection)?;
+
+        let has_contributors: bool = diesel::select(diesel::dsl::exists(
+            schema::book_contributors::dsl::book_contributors.filter(schema::book_contributors::dsl::book.eq(book.id)),
+        ))
+        .get_result(connection)?;
+        if !has_contributors {
+            attach_contributors(connection, book.user, book.id, &self.contributors)?;
+        }
+        if let Some((series_id, position)) = self.series {
+            link_book(connection, series_id, book.id, position)?;
+        }
+        tag_book(connection, book.user, book.id, &self.tags)?;
+
+        let has_review: bool = diesel::select(diesel::dsl::exists(
+            schema::reviews::dsl::reviews
+                .filter(schema::reviews::dsl::book.eq(book.id))
+                .filter(schema::reviews::dsl::reading.is_null()),
+        ))
+        .get_result(connection)?;
+        if !has_review && (self.rating.is_some() || self.review.is_some()) {
+            upsert_review(connection, book.user, book.id, None, self.rating, self.review.clone())?;
+        }
+
+        if let Some(note) = &self.note {
+            let has_note: bool = diesel::select(diesel::dsl::exists(
+                schema::notes::dsl::notes
+                    .filter(schema::notes::dsl::book.eq(book.id))
+                    .filter(schema::notes::dsl::body.eq(note)),
+            ))
+            .get_result(connection)?;
+            if !has_note {
+                add_note(connection, book.user, book.id, None, None, note)?;
+            }
+        }
+
+        let finished: Vec<Option<NaiveDate>> = schema::readings::dsl::readings
+            .filter(schema::readings::dsl::book.eq(book.id))
+            .select(schema::readings::dsl::finished_at)
+            .load(connection)?;
+        let page_count = existing.page_count.or(book.page_count);
+        for read in &self.reads {
+            if !finished.contains(&Some(read.finished_at)) {
+                record_finished_reading(connection, book.user, book.id, self.reading_mode, page_count, *read)?;
+            }
+        }
+        // Books which were already being read or given up on keep their reading
+        if let Some(read) = self.unfinished_read.filter(|_| !finished.contains(&None)) {
+            record_unfinished_reading(connection, book.user, book.id, self.reading_mode, page_count, read)?;
+        }
+        Ok(())
+    }
+}
+
+/// State of a book import shared across its batches.
+pub struct BookImport {
+    user: Uuid,
+    shelves_as_tags: bool,
+    decisions: HashMap<String, Decision>,
+    shelf_map: HashMap<String, Uuid>,
+    /// Books keyed by (shelf_id, isbn13) if available, else (shelf_id, "title|author").
+    existing_keys: HashMap<(Uuid, String), Uuid>,
+    series_map: HashMap<String, Uuid>,
+    now: chrono::NaiveDateTime,
+}
+
+impl BookImport {
+    /// Loads the shelves and books of the user, which imported books are checked against.
+    pub fn load(
+        connection: &mut PgConnection,
+        user_id: Uuid,
+        shelves_as_tags: bool,
+        decisions: HashMap<String, Decision>,
+    ) -> QueryResult<Self> {
+        let shelf_map: HashMap<String, Uuid> = schema::shelves::dsl::shelves
+            .filter(schema::shelves::dsl::user.eq(user_id))
+            .load::<Shelf>(connection)?
+            .into_iter()
+            .map(|s| (s.name, s.id))
+            .collect();
+
+        let existing_keys: HashMap<(Uuid, String), Uuid> = schema::books::dsl::books
+            .filter(schema::books::dsl::user.eq(user_id))
+            .load::<Book>(connection)?
+            .into_iter()
+            .flat_map(|b| {
+                if let Some(isbn) = b.isbn13.filter(|s| !s.is_empty()) {
+                    vec![((b.shelf, isbn), b.id)]
+                } else if let (Some(title), Some(author)) = (b.title, b.author) {
+                    vec![((b.shelf, format!("{}|{}", title, author)), b.id)]
+                } else {
+                    vec![]
+                }
+            })
+            .collect();
+
+        Ok(BookImport {
+            user: user_id,
+            shelves_as_tags,
+            decisions,
+            shelf_map,
+            existing_keys,
+            series_map: HashMap::new(),
+            now: chrono::Utc::now().naive_utc(),
+        })
+    }
+
+    /// Returns the shelves the book of a record is placed on, the exclusive shelf first.
+    fn target_shelves(&self, book: &ImportedBook) -> Vec<String> {
+        let mut target_shelves: Vec<String> = vec![book.shelf.clone()];
+        if !self.shelves_as_tags {
+            target_shelves.extend(book.other_shelves.iter().cloned());
+        }
+        target_shelves.retain(|shelf| !shelf.is_empty());
+        target_shelves
+    }
+
+    /// Returns the shelves referenced by the books which don't exist yet, sorted by name.
+    fn missing_shelves<'a>(&self, books: impl Iterator<Item = &'a ImportedBook>) -> Vec<String> {
+        let mut shelf_names: BTreeSet<String> = BTreeSet::new();
+        for book in books {
+            shelf_names.extend(self.target_shelves(book));
+        }
+        shelf_names.retain(|name| !self.shelf_map.contains_key(name));
+        shelf_names.into_iter().collect()
+    }
+
+    /// Creates the shelves referenced by the books which don't exist yet.
+    pub fn create_shelves<'a>(
+        &mut self,
+        connection: &mut PgConnection,
+        books: impl Iterator<Item = &'a ImportedBook>,
+    ) -> QueryResult<()> {
+        for shelf_name in self.missing_shelves(books) {
+            let new_shelf = Shelf {
+                id: Uuid::new_v4(),
+                name: shelf_name.clone(),
+                description: None,
+                user: self.user,
+                created_at: self.now,
+                updated_at: self.now,
+            };
+            diesel::insert_into(schema::shelves::dsl::shelves)
+                .values(&new_shelf)
+                .execute(connection)?;
+            self.shelf_map.insert(shelf_name, new_shelf.id);
+        }
+        Ok(())
+    }
+
+    /// Determines which shelves the book of a record goes on and whether it's already there.
+    fn plan(&self, book: &ImportedBook, row: usize) -> Vec<PlannedBook> {
+        let mut planned = Vec::new();
+        for shelf_name in self.target_shelves(book) {
+            let Some(&shelf_id) = self.shelf_map.get(&shelf_name) else {
+                continue;
+            };
+
+            let key = match &book.isbn13 {
+                Some(isbn13) => (shelf_id, isbn13.clone()),
+                None => (shelf_id, format!("{}|{}", book.title, book.author)),
+            };
+
+            // Books imported before series were split off still carry the full title
+            let legacy_key = (shelf_id, format!("{}|{}", book.raw_title, book.author));
+            let existing = self
+                .existing_keys
+                .get(&key)
+                .or_else(|| self.existing_keys.get(&legacy_key))
+                .copied();
+
+            planned.push(PlannedBook {
+                item: format!("{}:{}", row, shelf_name),
+                shelf_name,
+                shelf_id,
+                key,
+                existing,
+            });
+        }
+        planned
+    }
+
+    /// Lists what importing the rows would do, without writing anything.
+    ///
+    /// Shelves which don't exist yet are only created in memory, so that duplicates within the
+    /// file are detected for them as well.
+    pub fn preview(&mut self, rows: &ImportedRows) -> serde_json::Value {
+        let new_shelves = self.missing_shelves(rows.iter().filter_map(|row| row.as_ref().ok()));
+        for shelf_name in &new_shelves {
+            self.shelf_map.insert(shelf_name.clone(), Uuid::new_v4());
+        }
+
+        // Books added by earlier rows of the file, by the row adding them
+        let mut planned_rows: HashMap<Uuid, usize> = HashMap::new();
+        let mut items = Vec::new();
+        let mut errors = Vec::new();
+        let (mut new_books, mut duplicates) = (0, 0);
+        for (index, row) in rows.iter().enumerate() {
+            let row_number = index + 1;
+            let book = match row {
+                Ok(book) => book,
+                Err(e) => {
+                    errors.push(json!({ "row": row_number, "message": format!("Failed to parse row: {}", e) }));
+                    continue;
+                }
+            };
+
+            for planned in self.plan(book, row_number) {
+                let duplicate_of_row = planned.existing.and_then(|id| planned_rows.get(&id).copied());
+                if planned.existing.is_some() {
+                    duplicates += 1;
+                } else {
+                    new_books += 1;
+                    let id = Uuid::new_v4();
+                    planned_rows.insert(id, row_number);
+                    self.existing_keys.insert(planned.key.clone(), id);
+                }
+
+                items.push(json!({
+                    "item": planned.item,
+                    "row": row_number,
+                    "title": book.title,
+                    "author": book.author,
+                    "isbn13": book.isbn13,
+                    "shelf": planned.shelf_name,
+                    "new_shelf": new_shelves.contains(&planned.shelf_name),
+                    "cover": book.has_cover,
+                    "status": if planned.existing.is_some() { "duplicate" } else { "new" },
+                    "existing_book_id": planned.existing.filter(|_| duplicate_of_row.is_none()).map(|id| id.to_string()),
+                    "duplicate_of_row": duplicate_of_row,
+                    "decision": planned.default_decision(),
+                }));
+            }
+        }
+
+        json!({
+            "total_rows": rows.len(),
+            "new_books": new_books,
+            "duplicates": duplicates,
+            "shelves": new_shelves,
+            "items": items,
+            "errors": errors,
+        })
+    }
+
+    /// Prepares the books to insert or merge for a record according to the decisions, and
+    /// returns them along with the number of books skipped.
+    fn prepare(
+        &mut self,
+        connection: &mut PgConnection,
+        book: &ImportedBook,
+        row: usize,
+    ) -> QueryResult<(Vec<PreparedBook>, usize)> {
+        let series = match &book.series {
+            Some(marker) => {
+                let series_id = match self.series_map.get(&marker.name) {
+                    Some(&id) => id,
+                    None => {
+                        let id = find_or_create_series(connection, self.user, &marker.name)?;
+                        self.series_map.insert(marker.name.clone(), id);
+                        id
+                    }
+                };
+                Some((series_id, marker.position))
+            }
+            None => None,
+        };
+
+        let added_at = book
+            .added_on
+            .map(|date| date.and_time(chrono::NaiveTime::MIN))
+            .unwrap_or(self.now);
+
+        let mut tags = book.tags.clone();
+        if self.shelves_as_tags {
+            tags.extend(book.other_shelves.iter().filter(|shelf| !tags.contains(shelf)).cloned().collect::<Vec<_>>());
+        }
+
+        let mut prepared = Vec::new();
+        let mut skipped = 0;
+        for planned in self.plan(book, row) {
+            let decision = self
+                .decisions
+                .get(&planned.item)
+                .copied()
+                .unwrap_or_else(|| planned.default_decision());
+
+            let id = match (decision, planned.existing) {
+                (Decision::Skip, _) => {
+                    skipped += 1;
+                    continue;
+                }
+                (Decision::Merge, Some(existing)) => existing,
+                _ => {
+                    let id = Uuid::new_v4();
+                    self.existing_keys.entry(planned.key).or_insert(id);
+                    id
+                }
+            };
+
+            // The reading history is only kept with the copy on the exclusive shelf, so that
+            // books on several shelves are not counted as read multiple times
+            let (reads, undated_reads, unfinished_read) = if planned.shelf_name == book.shelf {
+                (book.reads.clone(), book.undated_reads, book.unfinished_read)
+            } else {
+                (Vec::new(), 0, None)
+            };
+
+            prepared.push(PreparedBook {
+                book: Book {
+                    id,
+                    user: self.user,
+                    shelf: planned.shelf_id,
+                    title: Some(book.title.clone()),
+                    author: Some(book.author.clone()),
+                    isbn13: book.isbn13.clone(),
+                    isbn10: book.isbn10.clone(),
+                    google_books_id: None,
+                    added_at,
+                    publisher: book.publisher.clone(),
+                    published_year: book.published_year,
+                    page_count: book.page_count,
+                    cover_url: None,
+                },
+                merge: decision == Decision::Merge && planned.existing.is_some(),
+                contributors: book.contributors.clone(),
+                series,
+                tags: tags.clone(),
+                rating: book.rating,
+                review: book.review.clone(),
+                note: book.note.clone(),
+                reading_mode: book.reading_mode,
+                reads,
+                undated_reads,
+                unfinished_read,
+                cover_file: book.cover_file.clone(),
+            });
+        }
+        Ok((prepared, skipped))
+    }
+
+    /// Imports a batch of rows, `first_row` being the number of rows before the batch.
+    fn import_batch(
+        &mut self,
+        connection: &mut PgConnection,
+        rows: &[Result<ImportedBook, String>],
+        first_row: usize,
+    ) -> BatchResult {
+        let mut result = BatchResult::default();
+
+        let mut prepared: Vec<(usize, Vec<PreparedBook>)> = Vec::new();
+        for (offset, row) in rows.iter().enumerate() {
+            let row_number = first_row + offset + 1;
+            let book = match row {
+                Ok(book) => book,
+                Err(e) => {
+                    result.fail(row_number, format!("Failed to parse row: {}", e));
+                    continue;
+                }
+            };
+            // Each row gets a savepoint, so that a row failing in the database doesn't abort the
+            // transaction of the whole batch
+            match connection.transaction(|conn| self.prepare(conn, book, row_number)) {
+                Ok((books, skipped)) => {
+                    result.skipped += skipped as i32;
+                    prepared.push((row_number, books));
+                }
+                Err(e) => result.fail(row_number, format!("Failed to import '{}': {}", book.title, e)),
+            }
+        }
+
+        // Insert the books of the whole batch at once and only fall back to inserting them row
+        // by row to single out the failing rows. Merges come last, as they may refer to books
+        // added by earlier rows.
+        let new_books: Vec<&Book> = prepared
+            .iter()
+            .flat_map(|(_, books)| books.iter().filter(|p| !p.merge).map(|p| &p.book))
+            .collect();
+        let batch = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+            diesel::insert_into(schema::books::dsl::books)
+                .values(new_books)
+                .execute(conn)?;
+            for book in prepared.iter().flat_map(|(_, books)| books).filter(|p| !p.merge) {
+                book.insert_details(conn)?;
+            }
+            for book in prepared.iter().flat_map(|(_, books)| books).filter(|p| p.merge) {
+                book.merge_details(conn)?;
+            }
+            Ok(())
+        });
+
+        match batch {
+            Ok(()) => {
+                for (row_number, books) in &prepared {
+                    result.add_books(*row_number, books);
+                }
+            }
+            Err(_) => {
+                for (row_number, books) in &prepared {
+                    let row = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+                        for book in books {
+                            if book.merge {
+                                book.merge_details(conn)?;
+                            } else {
+                                diesel::insert_into(schema::books::dsl::books)
+                                    .values(&book.book)
+                                    .execute(conn)?;
+                                book.insert_details(conn)?;
+                            }
+                        }
+                        Ok(())
+                    });
+                    match row {
+                        Ok(()) => {
+                            result.add_books(*row_number, books);
+                        }
+                        Err(e) => result.fail(*row_number, format!("Failed to insert book: {}", e)),
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Runs an import of books, committing the rows in batches.
+///
+/// Failing rows are logged with the job, only errors affecting the whole import fail the job.
+fn run_book_job(connection: &mut PgConnection, job: &ImportJob, importer: &dyn Importer) -> Result<(), String> {
+    let payload: Option<Vec<u8>> = import_jobs
+        .filter(schema::import_jobs::dsl::id.eq(job.id))
+        .select(schema::import_jobs::dsl::payload)
+        .first(connection)
+        .map_err(|e| e.to_string())?;
+    let Some(payload) = payload else {
+        return Err("The uploaded file is no longer available.".to_string());
+    };
+
+    let rows = importer.read(&payload, &job.options)?;
+    if let Some(Err(e)) = rows.first().filter(|_| rows.iter().all(Result::is_err)) {
+        return Err(format!("Failed to parse CSV file: {}", e));
+    }
+
+    let shelves_as_tags = job.options["shelves_as_tags"].as_bool().unwrap_or(false);
+    let decisions: HashMap<String, Decision> =
+        serde_json::from_value(job.options["decisions"].clone()).unwrap_or_default();
+    let mut import = BookImport::load(connection, job.user, shelves_as_tags, decisions).map_err(|e| e.to_string())?;
+    import
+        .create_shelves(connection, rows.iter().filter_map(|row| row.as_ref().ok()))
+        .map_err(|e| format!("Failed to create shelves: {}", e))?;
+
+    diesel::update(import_jobs.filter(schema::import_jobs::dsl::id.eq(job.id)))
+        .set(schema::import_jobs::dsl::total_rows.eq(rows.len() as i32))
+        .execute(connection)
+        .map_err(|e| e.to_string())?;
+
+    let mut processed = job.processed_rows.max(0) as usize;
+    while processed < rows.len() {
+        let end = (processed + BATCH_SIZE).min(rows.len());
+        let covers = connection
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                let result = import.import_batch(conn, &rows[processed..end], processed);
+
+                let now = chrono::Utc::now().naive_utc();
+                let errors: Vec<ImportJobError> = result
+                    .errors
+                    .into_iter()
+                    .map(|(row, message)| ImportJobError {
+                        id: Uuid::new_v4(),
+                        job: job.id,
+                        row: row as i32,
+                        message,
+                        created_at: now,
+                    })
+                    .collect();
+                diesel::insert_into(import_job_errors).values(&errors).execute(conn)?;
+
+                // A worker whose claim went stale and was taken over rolls back instead of
+                // committing the batch a second time
+                let claimed = import_jobs
+                    .filter(schema::import_jobs::dsl::id.eq(job.id))
+                    .filter(schema::import_jobs::dsl::worker.eq(*WORKER_ID));
+                let updated = diesel::update(claimed)
+                    .set((
+                        schema::import_jobs::dsl::processed_rows.eq(end as i32),
+                        schema::import_jobs::dsl::added.eq(schema::import_jobs::dsl::added + result.added),
+                        schema::import_jobs::dsl::merged.eq(schema::import_jobs::dsl::merged + result.merged),
+                        schema::import_jobs::dsl::skipped.eq(schema::import_jobs::dsl::skipped + result.skipped),
+                        schema::import_jobs::dsl::failed.eq(schema::import_jobs::dsl::failed + result.failed),
+                        schema::import_jobs::dsl::undated_reads.eq(schema::import_jobs::dsl::undated_reads + result.undated_reads),
+                    ))
+                    .execute(conn)?;
+                if updated == 0 {
+                    return Err(diesel::result::Error::RollbackTransaction);
+                }
+                Ok(result.covers)
+            })
+            .map_err(|e| match e {
+                diesel::result::Error::RollbackTransaction => "The job was taken over by another worker.".to_string(),
+                e => e.to_string(),
+            })?;
+        store_covers(connection, job, covers);
+        processed = end;
+    }
+    Ok(())
+}
+
+/// Stores the covers of the imported books, books which already have a cover keep it.
+///
+/// Covers are stored outside of the batch as the images live in the cover storage, a cover which
+/// can't be stored is logged with the job without failing the import of its book.
+fn store_covers(connection: &mut PgConnection, job: &ImportJob, covers: Vec<(usize, Uuid, PathBuf)>) {
+    let runtime = tokio::runtime::Handle::current();
+    for (row, book_id, file) in covers {
+        let has_cover = diesel::select(diesel::dsl::exists(
+            schema::book_covers::dsl::book_covers.filter(schema::book_covers::dsl::book.eq(book_id)),
+        ))
+        .get_result::<bool>(connection);
+        if !matches!(has_cover, Ok(false)) {
+            continue;
+        }
+
+        let stored = std::fs::read(&file)
+            .map_err(|e| format!("Failed to read cover {}: {}", file.display(), e))
+            .and_then(|data| {
+                runtime
+                    .block_on(store_cover(connection, job.user, book_id, data))
+                    .map_err(|(_, e)| format!("Failed to store cover: {}", e))
+            });
+        if let Err(message) = stored {
+            let entry = ImportJobError {
+                id: Uuid::new_v4(),
+                job: job.id,
+                row: row as i32,
+                message,
+                created_at: chrono::Utc::now().naive_utc(),
+            };
+            if let Err(e) = diesel::insert_into(import_job_errors).values(&entry).execute(connection) {
+                error!("Failed to log cover error of import job {}: {}", job.id, e);
+            }
+        }
+    }
+}
+
+fn job_json(job: &ImportJob) -> serde_json::Value {
+    json!({
+        "id": job.id.to_string(),
+        "source": job.source,
+        "status": job.status.to_string(),
+        "options": job.options,
+        "total_rows": job.total_rows,
+        "processed_rows": job.processed_rows,
+        "added": job.added,
+        "merged": job.merged,
+        "skipped": job.skipped,
+        "failed": job.failed,
+        "undated_reads": job.undated_reads,
+        "error": job.error,
+        "created_at": job.created_at.to_string(),
+        "started_at": job.started_at.map(|d| d.to_string()),
+        "finished_at": job.finished_at.map(|d| d.to_string()),
+        "updated_at": job.updated_at.to_string(),
+    })
+}
+
+/// A file uploaded for importing, along with the options of the import.
+struct Upload {
+    data: Bytes,
+    source: Option<String>,
+    shelves_as_tags: bool,
+    /// The Calibre library on the server the file was read from.
+    library_path: Option<String>,
+    page_column: Option<String>,
+    /// The column mapping of a CSV file, see `csv_importer`.
+    mapping: Option<ColumnMapping>,
+}
+
+impl Upload {
+    /// Reads the fields of an uploaded export, or the Calibre library on the server at `path`.
+    async fn read(mut multipart: Multipart, user_uuid: Uuid) -> Result<Upload, (StatusCode, Json<serde_json::Value>)> {
+        let mut file_data = None;
+        let mut source = None;
+        let mut shelves_as_tags = false;
+        let mut path = None;
+        let mut page_column = None;
+        let mut mapping = None;
+        let mut template_id = None;
+        let mut template_name = None;
+
+        loop {
+            let field = match multipart.next_field().await {
+                Ok(Some(field)) => field,
+                Ok(None) => break,
+                Err(e) => {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({ "error": format!("Failed to read multipart data: {}", e) })),
+                    ));
+                }
+            };
+
+            let field_name = match field.name() {
+                Some(n) => n.to_string(),
+                None => continue,
+            };
+
+            if field_name == "file" {
+                match field.bytes().await {
+                    Ok(bytes) => file_data = Some(bytes),
+                    Err(e) => {
+                        return Err((
+                            StatusCode::BAD_REQUEST,
+                            Json(json!({ "error": format!("Failed to read file field: {}", e) })),
+                        ));
+                    }
+                }
+            } else if field_name == "source" {
+                source = field.text().await.ok().map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+            } else if field_name == "shelves_as_tags" {
+                shelves_as_tags = matches!(field.text().await.as_deref().map(str::trim), Ok("true" | "1" | "on"));
+            } else if field_name == "path" {
+                path = field.text().await.ok().map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+            } else if field_name == "page_column" {
+                page_column = field.text().await.ok().map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+            } else if field_name == "mapping" {
+                mapping = field.text().await.ok().map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+            } else if field_name == "template_id" {
+                template_id = field.text().await.ok().map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+            } else if field_name == "template_name" {
+                template_name = field.text().await.ok().map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+            }
+        }
+
+        let connection = &mut connect();
+        let mapping = Self::column_mapping(connection, user_uuid, mapping, template_id, template_name)?;
+
+        if let Some(data) = file_data {
+            return Ok(Upload { data, source, shelves_as_tags, library_path: None, page_column, mapping });
+        }
+        let Some(path) = path else {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "Missing file." })),
+            ));
+        };
+
+        // Reading files from the server is reserved to elevated users
+        let elevated: bool = schema::users::dsl::users
+            .find(user_uuid)
+            .select(schema::users::dsl::elevated)
+            .first(connection)
+            .unwrap_or(false);
+        if !elevated {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(json!(ErrorResponse { error: "Access denied.".to_string() })),
+            ));
+        }
+
+        let (library, file) = match tokio::fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_dir() => (PathBuf::from(&path), PathBuf::from(&path).join("metadata.db")),
+            Ok(_) => {
+                let file = PathBuf::from(&path);
+                (file.parent().map(PathBuf::from).unwrap_or_default(), file)
+            }
+            Err(e) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": format!("Failed to read {}: {}", path, e) })),
+                ));
+            }
+        };
+        match tokio::fs::read(&file).await {
+            Ok(data) => Ok(Upload {
+                data: Bytes::from(data),
+                source: source.or_else(|| Some("calibre".to_string())),
+                shelves_as_tags,
+                library_path: Some(library.to_string_lossy().into_owned()),
+                page_column,
+                mapping,
+            }),
+            Err(e) => Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("Failed to read {}: {}", file.display(), e) })),
+            )),
+        }
+    }
+
+    /// Returns the column mapping given with the upload or saved as the template, saving a given
+    /// mapping as a template too if it's named.
+    fn column_mapping(
+        connection: &mut PgConnection,
+        user_uuid: Uuid,
+        mapping: Option<String>,
+        template_id: Option<String>,
+        template_name: Option<String>,
+    ) -> Result<Option<ColumnMapping>, (StatusCode, Json<serde_json::Value>)> {
+        if let Some(mapping) = mapping {
+            let mapping: ColumnMapping = serde_json::from_str(&mapping).map_err(|e| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": format!("Invalid column mapping: {}", e) })),
+                )
+            })?;
+            if let Some(name) = template_name {
+                save_template(connection, user_uuid, &name, &mapping).map_err(|e| {
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!(ErrorResponse { error: format!("Error while saving the import template: {}", e) })),
+                    )
+                })?;
+            }
+            return Ok(Some(mapping));
+        }
+
+        let Some(template_id) = template_id else {
+            return Ok(None);
+        };
+        let template_id = Uuid::parse_str(&template_id).map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!(ErrorResponse { error: "Invalid template ID.".to_string() })),
+            )
+        })?;
+        match load_template(connection, user_uuid, template_id) {
+            Ok(mapping) => Ok(Some(mapping)),
+            Err(diesel::result::Error::NotFound) => Err((
+                StatusCode::NOT_FOUND,
+                Json(json!(ErrorResponse { error: "Import template not found.".to_string() })),
+            )),
+            Err(e) => Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!(ErrorResponse { error: format!("Error loading import template: {}", e) })),
+            )),
+        }
+    }
+
+    /// Returns the importer for the file, detecting it if the source wasn't given. Files with a
+    /// column mapping are read as plain CSV.
+    fn importer(&self) -> Result<&'static dyn Importer, String> {
+        match &self.source {
+            Some(source) => find_importer(source).ok_or_else(|| format!("Unknown import source '{}'.", source)),
+            None if self.mapping.is_some() => Ok(&CsvImporter),
+            None => detect_importer(&self.data),
+        }
+    }
+
+    /// Returns the options stored with the import job.
+    fn options(&self) -> serde_json::Value {
+        let mut options = json!({ "shelves_as_tags": self.shelves_as_tags });
+        if let Some(library_path) = &self.library_path {
+            options["library_path"] = json!(library_path);
+        }
+        if let Some(page_column) = &self.page_column {
+            options["page_column"] = json!(page_column);
+        }
+        if let Some(mapping) = &self.mapping {
+            options["mapping"] = json!(mapping);
+        }
+        options
+    }
+}
+
+/// Lists the services exports can be imported from.
+pub(crate) async fn list_import_sources(_auth: AuthUser) -> impl IntoResponse {
+    let sources: Vec<_> = importers()
+        .iter()
+        .map(|importer| json!({ "source": importer.source(), "name": importer.name() }))
+        .collect();
+    (StatusCode::OK, Json(json!({ "sources": sources })))
+}
+
+/// Handles importing the export of another service, see `/api/imports/sources` for the services
+/// supported.
+///
+/// This route accepts a multipart form data with the following structure:
+/// - `file`: The file to import.
+/// - `path`: Optional, instead of `file` elevated users may import the Calibre library at this
+///   path on the server, or its `metadata.db`. The covers of the library are imported as well.
+/// - `source`: Optional, the service the file was exported from, detected from the file if not
+///   given.
+/// - `shelves_as_tags`: Optional, if `true` the non-exclusive GoodReads shelves are turned into
+///   tags of the book instead of creating a shelf and a copy of the book for each of them. Rows
+///   without a Goodreads book ID, like those of the Goodreads export of this app, always have
+///   their further shelves imported as tags.
+/// - `page_column`: Optional, the label of the Calibre custom column holding the page count,
+///   columns like `#pages` are picked up if not given.
+/// - `mapping`: Optional, imports any CSV file by mapping its columns as JSON, see
+///   `/api/imports/templates/save` for its structure.
+/// - `template_name`: Optional, saves the `mapping` as an import template of this name.
+/// - `template_id`: Optional, imports a CSV file with the mapping of a saved import template.
+///
+/// Books are placed on the shelf of their status, e.g. `to-read`. Books on the `read` shelf get a
+/// finished reading session for each time they were read, based on the read dates and read count
+/// of the export. StoryGraph moods become tags prefixed with `mood:`, and books read digitally or
+/// as audiobook are tracked as a percentage. Books of a Calibre library are placed on the
+/// `calibre` shelf along with their series, tags and identifiers, LibraryThing collections become
+/// shelves. Books already on a shelf are skipped, use `/api/imports/preview` to decide about them
+/// one by one.
+///
+/// The import runs in the background, the response contains the `job_id` to follow its progress
+/// with.
+///
+/// Authentication is required via JWT token in the Authorization header.
+pub(crate) async fn upload_import(
+    auth: AuthUser,
+    multipart: Multipart,
+) -> impl IntoResponse {
+    let user_uuid = auth.0;
+    let upload = match Upload::read(multipart, user_uuid).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+
+    // Reject files which aren't a known export right away instead of failing the job later on
+    let importer = match upload.importer() {
+        Ok(importer) => importer,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({ "error": e }))),
+    };
+
+    let connection = &mut connect();
+
+    match create_job(
+        connection,
+        user_uuid,
+        importer.source(),
+        ImportStatus::Queued,
+        upload.options(),
+        upload.data.to_vec(),
+    ) {
+        Ok(id) => (
+            StatusCode::ACCEPTED,
+            Json(json!({ "message": "Import started, your books will show up shortly.", "job_id": id.to_string() })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": format!("Failed to queue import: {}", e) })),
+        ),
+    }
+}
+
+/// Previews importing the export of another service without changing anything.
+///
+/// This route accepts the same multipart form data as `/api/imports/upload`.
+///
+/// The response lists the shelves which would be created, an item for each book which would be
+/// placed on a shelf along with its default `decision`, and the rows which failed to parse. Items
+/// with the status `duplicate` are already on the shelf, either as `existing_book_id` or added by
+/// an earlier row of the file (`duplicate_of_row`).
+///
+/// The file is kept with the returned `job_id` until the import is confirmed via
+/// `/api/imports/confirm`, previews which aren't confirmed are discarded after a day.
+pub(crate) async fn preview_import(
+    auth: AuthUser,
+    multipart: Multipart,
+) -> impl IntoResponse {
+    let user_uuid = auth.0;
+    let upload = match Upload::read(multipart, user_uuid).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+
+    let options = upload.options();
+    let (importer, rows) = match upload
+        .importer()
+        .and_then(|importer| Ok((importer, importer.read(&upload.data, &options)?)))
+    {
+        Ok(rows) => rows,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({ "error": e }))),
+    };
+
+    let connection = &mut connect();
+
+    let mut import = match BookImport::load(connection, user_uuid, upload.shelves_as_tags, HashMap::new()) {
+        Ok(import) => import,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!(ErrorResponse { error: format!("Error loading books: {}", e) })),
+            );
+        }
+    };
+    let mut preview = import.preview(&rows);
+    preview["source"] = json!(importer.source());
+
+    match create_job(
+        connection,
+        user_uuid,
+        importer.source(),
+        ImportStatus::Preview,
+        options,
+        upload.data.to_vec(),
+    ) {
+        Ok(id) => {
+            preview["job_id"] = json!(id.to_string());
+            (StatusCode::OK, Json(preview))
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": format!("Failed to store import preview: {}", e) })),
+        ),
+    }
+}
+
+/// Request type for confirming a previewed import.
+#[derive(Debug, Deserialize)]
+pub struct ConfirmImportRequest {
+    pub job_id: String,
+    pub decisions: Option<HashMap<String, Decision>>,
+}
+
+/// Confirms a previewed import, which then runs in the background.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `job_id`: The UUID of the previewed import job.
+/// - `decisions`: Optional, maps items of the preview to `skip`, `merge` or `add`. Items left out
+///   keep the decision of the preview, i.e. new books are added and duplicates skipped.
+pub(crate) async fn confirm_import(
+    auth: AuthUser,
+    Json(payload): Json<ConfirmImportRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let job_id = match Uuid::parse_str(&payload.job_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid job ID.".to_string() }))),
+    };
+
+    let job = match import_jobs
+        .filter(schema::import_jobs::dsl::id.eq(job_id))
+        .filter(schema::import_jobs::dsl::user.eq(auth.0))
+        .select(ImportJob::as_select())
+        .first(connection)
+    {
+        Ok(job) => job,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Import job not found.".to_string() }))),
+    };
+
+    if job.status != ImportStatus::Preview {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Import job was already confirmed.".to_string() })));
+    }
+
+    let mut options = job.options;
+    options["decisions"] = json!(payload.decisions.unwrap_or_default());
+
+    // Only confirm the job if it is still a preview, it may have been confirmed concurrently
+    let result = diesel::update(
+        import_jobs
+            .filter(schema::import_jobs::dsl::id.eq(job_id))
+            .filter(schema::import_jobs::dsl::status.eq(ImportStatus::Preview)),
+    )
+    .set((
+        schema::import_jobs::dsl::status.eq(ImportStatus::Queued),
+        schema::import_jobs::dsl::options.eq(options),
+    ))
+    .execute(connection);
+
+    match result {
+        Ok(0) => (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Import job was already confirmed.".to_string() }))),
+        Ok(_) => {
+            JOB_QUEUED.notify_one();
+            (
+                StatusCode::ACCEPTED,
+                Json(json!({ "message": "Import started, your books will show up shortly.", "job_id": job_id.to_string() })),
+            )
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error confirming import: {}", e) }))),
+    }
+}
+
+/// Lists the most recent import jobs of a user.
+pub(crate) async fn list_import_jobs(auth: AuthUser) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    match import_jobs
+        .filter(schema::import_jobs::dsl::user.eq(auth.0))
+        .order(schema::import_jobs::dsl::created_at.desc())
+        .limit(20)
+        .select(ImportJob::as_select())
+        .load(connection)
+    {
+        Ok(jobs) => (StatusCode::OK, Json(json!({ "jobs": jobs.iter().map(job_json).collect::<Vec<_>>() }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading import jobs: {}", e) }))),
+    }
+}
+
+/// Request type for querying an import job.
+#[derive(Debug, Deserialize)]
+pub struct ImportJobRequest {
+    pub job_id: String,
+}
+
+/// Fetches the status and progress of an import job.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `job_id`: The UUID of the import job.
+///
+/// Besides the books added, merged, skipped and failed, `undated_reads` counts the reads of the
+/// imported books which the service doesn't date, e.g. Goodreads only dates the latest read of a
+/// book. They aren't recorded as readings.
+pub(crate) async fn get_import_job(
+    auth: AuthUser,
+    Json(payload): Json<ImportJobRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let job_id = match Uuid::parse_str(&payload.job_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid job ID.".to_string() }))),
+    };
+
+    match import_jobs
+        .filter(schema::import_jobs::dsl::id.eq(job_id))
+        .filter(schema::import_jobs::dsl::user.eq(auth.0))
+        .select(ImportJob::as_select())
+        .first(connection)
+    {
+        Ok(job) => (StatusCode::OK, Json(job_json(&job))),
+        Err(_) => (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Import job not found.".to_string() }))),
+    }
+}
+
+/// Lists the rows of an import job which failed, rows are numbered from 1 without the header.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `job_id`: The UUID of the import job.
+pub(crate) async fn list_import_job_errors(
+    auth: AuthUser,
+    Json(payload): Json<ImportJobRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let job_id = match Uuid::parse_str(&payload.job_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid job ID.".to_string() }))),
+    };
+
+    let owned: bool = match diesel::select(diesel::dsl::exists(
+        import_jobs
+            .filter(schema::import_jobs::dsl::id.eq(job_id))
+            .filter(schema::import_jobs::dsl::user.eq(auth.0)),
+    ))
+    .get_result(connection)
+    {
+        Ok(o) => o,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading import job: {}", e) }))),
+    };
+
+    if !owned {
+        return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Import job not found.".to_string() })));
+    }
+
+    match import_job_errors
+        .filter(schema::import_job_errors::dsl::job.eq(job_id))
+        .order(schema::import_job_errors::dsl::row.asc())
+        .load::<ImportJobError>(connection)
+    {
+        Ok(errors) => {
+            let json_errors: Vec<_> = errors
+                .iter()
+                .map(|e| json!({ "row": e.row, "message": e.message }))
+                .collect();
+            (StatusCode::OK, Json(json!({ "errors": json_errors })))
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading import errors: {}", e) }))),
+    }
+}
+
+/// Handles importing the highlights and notes of a Kindle `My Clippings.txt` file.
+///
+/// This route accepts a multipart form data with the following structure:
+/// - `file`: The clippings file to import.
+///
+/// Clippings are matched to the books of the user by title and author. The response reports the
+/// outcome of each clipping: `imported`, `attached` for notes added to their highlight, where
+/// several notes on a highlight are joined, `duplicate` if imported before, `unmatched` if no book
+/// was found or `skipped` for bookmarks.
+///
+/// Authentication is required via JWT token in the Authorization header.
+pub(crate) async fn import_kindle(
+    auth: AuthUser,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let user_uuid = auth.0;
+    let mut file_data = None;
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": format!("Failed to read multipart data: {}", e) })),
+                );
+            }
+        };
+
+        if field.name() == Some("file") {
+            match field.bytes().await {
+                Ok(bytes) => file_data = Some(bytes),
+                Err(e) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({ "error": format!("Failed to read file field: {}", e) })),
+                    );
+                }
+            }
+        }
+    }
+
+    let Some(file_data) = file_data else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Missing file." })),
+        );
+    };
+
+    let clippings = parse_clippings(&String::from_utf8_lossy(&file_data));
+
+    let connection = &mut connect();
+
+    let user_books: Vec<Book> = match crate::schema::books::dsl::books
+        .filter(crate::schema::books::dsl::user.eq(user_uuid))
+        .order(crate::schema::books::dsl::added_at.asc())
+        .load(connection)
+    {
+        Ok(b) => b,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": format!("Failed to load books: {}", e) })),
+            );
+        }
+    };
+
+    let user_readings: Vec<Reading> = match crate::schema::readings::dsl::readings
+        .filter(crate::schema::readings::dsl::user.eq(user_uuid))
+        .load(connection)
+    {
+        Ok(r) => r,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": format!("Failed to load readings: {}", e) })),
+            );
+        }
+    };
+
+    let mut books_by_title: HashMap<String, Vec<&Book>> = HashMap::new();
+    for book in &user_books {
+        if let Some(title) = &book.title {
+            books_by_title.entry(title_key(title)).or_default().push(book);
+        }
+    }
+
+    // Kindle stores a note on a highlight as separate clipping at the end of the highlight, the
+    // notes are attached to the last highlight ending there, as edited highlights are stored again
+    let mut highlight_notes: HashMap<usize, (String, Vec<usize>)> = HashMap::new();
+    for (note_index, note) in clippings.iter().enumerate() {
+        if note.kind != ClippingKind::Note {
+            continue;
+        }
+        if let Some(highlight_index) = clippings.iter().rposition(|c| {
+            c.kind == ClippingKind::Highlight
+                && c.title == note.title
+                && c.author == note.author
+                && c.location_end().is_some()
+                && c.location_end() == note.location_end()
+        }) {
+            let (text, notes) = highlight_notes.entry(highlight_index).or_default();
+            if !text.is_empty() {
+                text.push_str("\n\n");
+            }
+            text.push_str(&note.content);
+            notes.push(note_index);
+        }
+    }
+
+    // Pick the copy of the book which was being read when the clipping was taken
+    let matches: Vec<(Option<&Reading>, Option<Uuid>)> = clippings
+        .iter()
+        .map(|clipping| {
+            let author = clipping.author_name();
+            let candidates: Vec<&Book> = books_by_title
+                .get(&title_key(&clipping.title))
+                .map(|candidates| {
+                    candidates
+                        .iter()
+                        .copied()
+                        .filter(|book| match (&author, &book.author) {
+                            (Some(author), Some(book_author)) => authors_match(author, book_author),
+                            _ => true,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let reading = clipping.added_at.and_then(|added_at| {
+                let date = added_at.date();
+                user_readings.iter().find(|reading| {
+                    candidates.iter().any(|book| book.id == reading.book)
+                        && reading.started_at.is_none_or(|start| start <= date)
+                        && reading.finished_at.or(reading.cancelled_at).is_none_or(|end| date <= end)
+                })
+            });
+            (reading, reading.map(|r| r.book).or(candidates.first().map(|book| book.id)))
+        })
+        .collect();
+
+    // Highlights are stored first, their notes only count as attached once the highlight has them
+    let now = chrono::Utc::now().naive_utc();
+    let mut statuses: HashMap<usize, &str> = HashMap::new();
+    for (index, clipping) in clippings.iter().enumerate() {
+        let (reading, Some(book_id)) = matches[index] else {
+            continue;
+        };
+        if clipping.kind != ClippingKind::Highlight {
+            continue;
+        }
+
+        let (note, notes) = match highlight_notes.get(&index) {
+            Some((note, notes)) => (Some(note.clone()), notes.as_slice()),
+            None => (None, &[][..]),
+        };
+        let highlight = Highlight {
+            id: Uuid::new_v4(),
+            user: user_uuid,
+            book: book_id,
+            reading: reading.map(|r| r.id),
+            quote: clipping.content.clone(),
+            page: clipping.page,
+            location: clipping.location.clone(),
+            note: note.clone(),
+            created_at: clipping.added_at.unwrap_or(now),
+            updated_at: now,
+            source_hash: Some(clipping.source_hash()),
+        };
+        let result = diesel::insert_into(crate::schema::highlights::dsl::highlights)
+            .values(&highlight)
+            .on_conflict((
+                crate::schema::highlights::dsl::user,
+                crate::schema::highlights::dsl::source_hash,
+            ))
+            .do_nothing()
+            .execute(connection)
+            .and_then(|inserted| {
+                if inserted > 0 || note.is_none() {
+                    return Ok((inserted, Some("attached")));
+                }
+                // A note might have been added to the highlight since the last import, notes
+                // edited in the app are kept
+                let existing = crate::schema::highlights::dsl::highlights
+                    .filter(crate::schema::highlights::dsl::user.eq(user_uuid))
+                    .filter(crate::schema::highlights::dsl::source_hash.eq(&highlight.source_hash));
+                let updated = diesel::update(existing.filter(crate::schema::highlights::dsl::note.is_null()))
+                    .set(crate::schema::highlights::dsl::note.eq(&note))
+                    .execute(connection)?;
+                if updated > 0 {
+                    return Ok((0, Some("attached")));
+                }
+                let existing_note: Option<String> = existing
+                    .select(crate::schema::highlights::dsl::note)
+                    .first(connection)?;
+                Ok((0, (existing_note == note).then_some("duplicate")))
+            });
+        match result {
+            Ok((inserted, note_status)) => {
+                statuses.insert(index, if inserted == 0 { "duplicate" } else { "imported" });
+                // Notes which didn't make it onto their highlight are imported on their own
+                if let Some(note_status) = note_status {
+                    for note_index in notes {
+                        statuses.insert(*note_index, note_status);
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::error!("Error inserting highlight of '{}': {}", clipping.title, e);
+                statuses.insert(index, "failed");
+            }
+        }
+    }
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut items = Vec::new();
+    for (index, clipping) in clippings.iter().enumerate() {
+        let author = clipping.author_name();
+        let (reading, book_id) = matches[index];
+
+        let status = match (clipping.kind, book_id, statuses.get(&index)) {
+            (ClippingKind::Bookmark, _, _) => "skipped",
+            (_, _, Some(status)) => status,
+            (ClippingKind::Note, Some(book_id), None) => {
+                let note = Note {
+                    id: Uuid::new_v4(),
+                    user: user_uuid,
+                    book: book_id,
+                    reading: reading.map(|r| r.id),
+                    page: clipping.page,
+                    body: clipping.content.clone(),
+                    created_at: clipping.added_at.unwrap_or(now),
+                    updated_at: now,
+                    source_hash: Some(clipping.source_hash()),
+                };
+                match diesel::insert_into(crate::schema::notes::dsl::notes)
+                    .values(&note)
+                    .on_conflict((
+                        crate::schema::notes::dsl::user,
+                        crate::schema::notes::dsl::source_hash,
+                    ))
+                    .do_nothing()
+                    .execute(connection)
+                {
+                    Ok(0) => "duplicate",
+                    Ok(_) => "imported",
+                    Err(e) => {
+                        tracing::error!("Error inserting note of '{}': {}", clipping.title, e);
+                        "failed"
+                    }
+                }
+            }
+            _ => "unmatched",
+        };
+
+        *counts.entry(status).or_default() += 1;
+        items.push(json!({
+            "title": clipping.title,
+            "author": author,
+            "kind": clipping.kind.to_string(),
+            "page": clipping.page,
+            "location": clipping.location,
+            "added_at": clipping.added_at.map(|d| d.to_string()),
+            "status": status,
+            "book_id": book_id.map(|id| id.to_string()),
+        }));
+    }
+
+    let count = |status: &str| counts.get(status).copied().unwrap_or(0);
+    let message = format!(
+        "Import complete. {} clippings imported, {} already present, {} without matching book.",
+        count("imported"),
+        count("duplicate"),
+        count("unmatched")
+    );
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "message": message,
+            "imported": count("imported"),
+            "attached": count("attached"),
+            "duplicates": count("duplicate"),
+            "unmatched": count("unmatched"),
+            "skipped": count("skipped"),
+            "failed": count("failed"),
+            "items": items,
+        })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_upload_import_requires_auth() {
+        let app = Router::new().route(
+            "/api/imports/upload",
+            post(upload_import),
+        );
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/imports/upload")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_import_kindle_requires_auth() {
+        let app = Router::new().route("/api/user/import-kindle", post(import_kindle));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/user/import-kindle")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_confirm_import_requires_auth() {
+        let app = Router::new().route("/api/imports/confirm", post(confirm_import));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/imports/confirm").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_preview_detects_duplicates() {
+        const EXPORT: &str = "Book Id,Title,Author,Author l-f,Additional Authors,ISBN,ISBN13,My Rating,Average Rating,Publisher,Binding,Number of Pages,Year Published,Original Publication Year,Date Read,Date Added,Bookshelves,Bookshelves with positions,Exclusive Shelf,My Review,Spoiler,Private Notes,Read Count,Owned Copies
+34,\"The Fellowship of the Ring (The Lord of the Rings, #1)\",J.R.R. Tolkien,\"Tolkien, J.R.R.\",,\"=\"\"0618346252\"\"\",\"=\"\"9780618346257\"\"\",5,4.39,Houghton Mifflin Harcourt,Paperback,398,2003,1954,2021/05/03,2020/01/12,fantasy,fantasy (#1),read,,,,1,0
+12345,Norwegian Wood,Haruki Murakami,\"Murakami, Haruki\",,\"=\"\"\"\"\",\"=\"\"\"\"\",0,4.01,Vintage,Paperback,296,2000,1987,,2022/03/04,,,to-read,,,,0,1
+12345,Norwegian Wood,Haruki Murakami,\"Murakami, Haruki\",,\"=\"\"\"\"\",\"=\"\"\"\"\",0,4.01,Vintage,Paperback,296,2000,1987,,2022/03/04,,,to-read,,,,0,1
+not,enough,columns
+";
+        let read_shelf = Uuid::new_v4();
+        let existing_book = Uuid::new_v4();
+        let mut import = BookImport {
+            user: Uuid::new_v4(),
+            shelves_as_tags: false,
+            decisions: HashMap::new(),
+            shelf_map: HashMap::from([("read".to_string(), read_shelf)]),
+            existing_keys: HashMap::from([((read_shelf, "9780618346257".to_string()), existing_book)]),
+            series_map: HashMap::new(),
+            now: chrono::Utc::now().naive_utc(),
+        };
+
+        let rows = find_importer("goodreads").unwrap().read(EXPORT.as_bytes(), &json!({})).unwrap();
+        let preview = import.preview(&rows);
+        assert_eq!(preview["shelves"], json!(["fantasy", "to-read"]));
+        assert_eq!(preview["new_books"], 2);
+        assert_eq!(preview["duplicates"], 2);
+        assert_eq!(preview["errors"][0]["row"], 4);
+
+        let items = preview["items"].as_array().unwrap();
+        assert_eq!(items[0]["item"], "1:read");
+        assert_eq!(items[0]["status"], "duplicate");
+        assert_eq!(items[0]["existing_book_id"], existing_book.to_string());
+        assert_eq!(items[0]["decision"], "skip");
+        assert_eq!(items[1]["item"], "1:fantasy");
+        assert_eq!(items[1]["new_shelf"], true);
+        assert_eq!(items[1]["decision"], "add");
+        assert_eq!(items[3]["status"], "duplicate");
+        assert_eq!(items[3]["duplicate_of_row"], 2);
+        assert_eq!(items[3]["existing_book_id"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let panic = std::panic::catch_unwind(|| panic!("row {} is broken", 3)).unwrap_err();
+        assert_eq!(panic_message(panic.as_ref()), "The import crashed: row 3 is broken");
+        let panic = std::panic::catch_unwind(|| panic!("broken")).unwrap_err();
+        assert_eq!(panic_message(panic.as_ref()), "The import crashed: broken");
+    }
+
+    #[tokio::test]
+    async fn test_get_import_job_requires_auth() {
+        let app = Router::new().route("/api/imports/status", post(get_import_job));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/imports/status").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,312 @@
+use crate::goodreads_importer::split_series;
+use chrono::NaiveDateTime;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// Separator between the clippings of a `My Clippings.txt` file.
+const SEPARATOR: &str = "==========";
+
+/// Formats of the "Added on" timestamp, depending on the language setting of the Kindle.
+const DATE_FORMATS: [&str; 3] = [
+    "%A, %B %d, %Y %I:%M:%S %p",
+    "%A, %d %B %Y %H:%M:%S",
+    "%A, %B %d, %Y, %I:%M %p",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClippingKind {
+    Highlight,
+    Note,
+    Bookmark,
+}
+
+impl Display for ClippingKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ClippingKind::Highlight => write!(f, "highlight"),
+            ClippingKind::Note => write!(f, "note"),
+            ClippingKind::Bookmark => write!(f, "bookmark"),
+        }
+    }
+}
+
+/// A single entry of a Kindle `My Clippings.txt` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clipping {
+    pub title: String,
+    pub author: Option<String>,
+    pub kind: ClippingKind,
+    pub page: Option<i32>,
+    pub location: Option<String>,
+    pub added_at: Option<NaiveDateTime>,
+    pub content: String,
+}
+
+impl Clipping {
+    /// Returns the author in "First Last" order, Kindle often lists authors as "Last, First".
+    pub fn author_name(&self) -> Option<String> {
+        let author = self.author.as_deref()?;
+        match author.split_once(',') {
+            Some((last, first)) if !first.contains(',') && !first.trim().is_empty() => {
+                Some(format!("{} {}", first.trim(), last.trim()))
+            }
+            _ => Some(author.to_string()),
+        }
+    }
+
+    /// Returns the last location covered by the clipping, which is where Kindle places notes.
+    pub fn location_end(&self) -> Option<String> {
+        let location = self.location.as_deref()?;
+        let Some((start, end)) = location.split_once('-') else {
+            return Some(location.to_string());
+        };
+        // Older Kindles abbreviate ranges like "1204-06"
+        match start.len().checked_sub(end.len()) {
+            Some(prefix) if prefix > 0 => Some(format!("{}{}", &start[..prefix], end)),
+            _ => Some(end.to_string()),
+        }
+    }
+
+    /// Identifies the clipping, so that importing the same file twice doesn't duplicate it.
+    pub fn source_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for part in [
+            "kindle",
+            &self.kind.to_string(),
+            &self.title,
+            self.author.as_deref().unwrap_or_default(),
+            self.location.as_deref().unwrap_or_default(),
+            &self.page.map(|p| p.to_string()).unwrap_or_default(),
+            &self.content,
+        ] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// Parses the contents of a Kindle `My Clippings.txt` file.
+///
+/// Entries which can't be parsed, e.g. clipping limit notices, are skipped.
+pub fn parse_clippings(data: &str) -> Vec<Clipping> {
+    data.replace('\u{feff}', "")
+        .replace("\r\n", "\n")
+        .split(SEPARATOR)
+        .filter_map(parse_clipping)
+        .collect()
+}
+
+fn parse_clipping(block: &str) -> Option<Clipping> {
+    let mut lines = block.lines().skip_while(|line| line.trim().is_empty());
+    let (title, author) = split_title_line(lines.next()?.trim());
+    let metadata = lines.next()?.trim().strip_prefix('-')?.trim();
+    let content = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+
+    let mut parts = metadata.split('|').map(str::trim);
+    let description = parts.next()?.to_lowercase();
+    let kind = if description.contains("highlight") {
+        ClippingKind::Highlight
+    } else if description.contains("note") {
+        ClippingKind::Note
+    } else if description.contains("bookmark") {
+        ClippingKind::Bookmark
+    } else {
+        return None;
+    };
+
+    // Kindle replaces the text with a notice once the publisher's clipping limit is reached
+    if title.is_empty()
+        || (kind != ClippingKind::Bookmark && content.is_empty())
+        || content.starts_with("<You have reached the clipping limit")
+    {
+        return None;
+    }
+
+    let mut page = None;
+    let mut location = None;
+    let mut added_at = None;
+    for part in std::iter::once(description.as_str()).chain(parts.clone().map(|p| p.strip_prefix("Added on ").unwrap_or(p))) {
+        if let Some(date) = DATE_FORMATS
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(part, format).ok())
+        {
+            added_at = Some(date);
+            continue;
+        }
+
+        let lower = part.to_lowercase();
+        if let Some(value) = value_after(&lower, "page ") {
+            page = value.split('-').next().and_then(|p| p.parse().ok());
+        }
+        if let Some(value) = value_after(&lower, "location ").or_else(|| value_after(&lower, "loc. ")) {
+            location = Some(value.to_string());
+        }
+    }
+
+    Some(Clipping {
+        title,
+        author,
+        kind,
+        page,
+        location,
+        added_at,
+        content,
+    })
+}
+
+/// Splits a title line like "Title (Author)" into the title and the author.
+fn split_title_line(line: &str) -> (String, Option<String>) {
+    let Some(inner) = line.strip_suffix(')') else {
+        return (line.to_string(), None);
+    };
+
+    // Find the opening parenthesis matching the last one, authors may contain parentheses too
+    let mut depth = 1;
+    for (index, c) in inner.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            let title = inner[..index].trim();
+            let author = inner[index + 1..].trim();
+            if title.is_empty() || author.is_empty() {
+                break;
+            }
+            return (title.to_string(), Some(author.to_string()));
+        }
+    }
+    (line.to_string(), None)
+}
+
+/// Returns the word following the marker, e.g. "170" for "your highlight on page 170".
+fn value_after<'a>(text: &'a str, marker: &str) -> Option<&'a str> {
+    let start = text.find(marker)? + marker.len();
+    text[start..].split_whitespace().next()
+}
+
+/// Normalizes a title for matching clippings to books.
+///
+/// Series markers, trailing parentheses, subtitles, punctuation and case are ignored as the
+/// Kindle store and Goodreads rarely agree on them.
+pub fn title_key(title: &str) -> String {
+    let (mut title, _) = split_series(title);
+    while let Some((plain, _)) = title.strip_suffix(')').and_then(|t| t.rsplit_once(" (")) {
+        title = plain.to_string();
+    }
+    let title = title.split(':').next().unwrap_or_default();
+    title
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Checks whether two author names likely refer to the same person, by looking for a shared
+/// name part like the surname.
+pub fn authors_match(a: &str, b: &str) -> bool {
+    let parts = |name: &str| -> Vec<String> {
+        name.split(|c: char| !c.is_alphanumeric())
+            .filter(|part| part.chars().count() > 2)
+            .map(str::to_lowercase)
+            .collect()
+    };
+    let b_parts = parts(b);
+    parts(a).iter().any(|part| b_parts.contains(part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIPPINGS: &str = "\u{feff}The Fellowship of the Ring (The Lord of the Rings, Book 1) (Tolkien, J.R.R.)\r
+- Your Highlight on page 170 | Location 2590-2591 | Added on Saturday, April 26, 2025 10:15:32 PM\r
+\r
+Not all those who wander are lost.\r
+==========\r
+\u{feff}The Fellowship of the Ring (The Lord of the Rings, Book 1) (Tolkien, J.R.R.)\r
+- Your Note on page 170 | Location 2591 | Added on Saturday, April 26, 2025 10:16:01 PM\r
+\r
+Aragorn's poem\r
+==========\r
+Norwegian Wood (Haruki Murakami)\r
+- Your Bookmark at location 1204 | Added on Sunday, 27 April 2025 08:03:11\r
+\r
+\r
+==========\r
+Some Book (Author)\r
+- Your Highlight at location 10-12 | Added on Monday, April 28, 2025 07:00:00 AM\r
+\r
+ <You have reached the clipping limit for this item>\r
+==========\r
+";
+
+    #[test]
+    fn test_parse_clippings() {
+        let clippings = parse_clippings(CLIPPINGS);
+        assert_eq!(clippings.len(), 3);
+
+        let highlight = &clippings[0];
+        assert_eq!(highlight.title, "The Fellowship of the Ring (The Lord of the Rings, Book 1)");
+        assert_eq!(highlight.author.as_deref(), Some("Tolkien, J.R.R."));
+        assert_eq!(highlight.author_name().as_deref(), Some("J.R.R. Tolkien"));
+        assert_eq!(highlight.kind, ClippingKind::Highlight);
+        assert_eq!(highlight.page, Some(170));
+        assert_eq!(highlight.location.as_deref(), Some("2590-2591"));
+        assert_eq!(highlight.location_end().as_deref(), Some("2591"));
+        assert_eq!(highlight.content, "Not all those who wander are lost.");
+        assert_eq!(
+            highlight.added_at,
+            chrono::NaiveDate::from_ymd_opt(2025, 4, 26).unwrap().and_hms_opt(22, 15, 32)
+        );
+
+        let note = &clippings[1];
+        assert_eq!(note.kind, ClippingKind::Note);
+        assert_eq!(note.location_end().as_deref(), Some("2591"));
+        assert_eq!(note.content, "Aragorn's poem");
+
+        let bookmark = &clippings[2];
+        assert_eq!(bookmark.kind, ClippingKind::Bookmark);
+        assert_eq!(bookmark.page, None);
+        assert_eq!(bookmark.location.as_deref(), Some("1204"));
+
+        let abbreviated = Clipping { location: Some("1204-06".to_string()), ..bookmark.clone() };
+        assert_eq!(abbreviated.location_end().as_deref(), Some("1206"));
+        assert_eq!(
+            bookmark.added_at,
+            chrono::NaiveDate::from_ymd_opt(2025, 4, 27).unwrap().and_hms_opt(8, 3, 11)
+        );
+
+        assert_ne!(highlight.source_hash(), note.source_hash());
+        assert_eq!(highlight.source_hash(), parse_clippings(CLIPPINGS)[0].source_hash());
+    }
+
+    #[test]
+    fn test_split_title_line() {
+        assert_eq!(
+            split_title_line("Guards! Guards! (Discworld) (Terry Pratchett)"),
+            ("Guards! Guards! (Discworld)".to_string(), Some("Terry Pratchett".to_string()))
+        );
+        assert_eq!(
+            split_title_line("A Book (Someone (Editor))"),
+            ("A Book".to_string(), Some("Someone (Editor)".to_string()))
+        );
+        assert_eq!(split_title_line("Untitled notes"), ("Untitled notes".to_string(), None));
+    }
+
+    #[test]
+    fn test_title_key_and_authors_match() {
+        assert_eq!(
+            title_key("The Fellowship of the Ring (The Lord of the Rings, Book 1)"),
+            title_key("The Fellowship of the Ring (The Lord of the Rings, #1)")
+        );
+        assert_eq!(title_key("Thinking, Fast and Slow: A Summary"), "thinking fast and slow");
+        assert!(authors_match("Tolkien, J.R.R.", "J.R.R. Tolkien"));
+        assert!(!authors_match("Haruki Murakami", "J.R.R. Tolkien"));
+    }
+}
@@ -0,0 +1,542 @@
+use crate::auth::{hash_password, verify_password, AuthUser};
+use crate::db::connect;
+use crate::models::{Book, KoreaderAccount, KoreaderDocument, ReadingEntry, ReadingMode};
+use crate::readings::active_reading;
+use crate::schema::koreader_accounts::dsl::koreader_accounts;
+use crate::schema::koreader_documents::dsl::koreader_documents;
+use crate::{schema, ErrorResponse};
+use axum::extract::Path;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::routing::{get, post, put};
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error::DatabaseError};
+use md5::{Digest, Md5};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/koreader/account", post(get_account))
+        .route("/api/koreader/account/save", post(save_account))
+        .route("/api/koreader/account/remove", post(remove_account))
+        .route("/api/koreader/documents", post(list_documents))
+        .route("/api/koreader/documents/link", post(link_document))
+        // The KOReader sync server protocol, KOReader is pointed at `/api/koreader` as custom sync server
+        .route("/api/koreader/healthcheck", get(healthcheck))
+        .route("/api/koreader/users/create", post(register_device))
+        .route("/api/koreader/users/auth", get(authorize_device))
+        .route("/api/koreader/syncs/progress", put(update_progress))
+        .route("/api/koreader/syncs/progress/{document}", get(get_progress))
+}
+
+/// Responds with an error of the sync protocol, KOReader shows the message of the error code.
+fn sync_error(code: u16) -> (StatusCode, Json<Value>) {
+    let (status, message) = match code {
+        2001 => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+        2002 => (StatusCode::PAYMENT_REQUIRED, "Username is already registered."),
+        2003 => (StatusCode::FORBIDDEN, "Invalid request"),
+        2004 => (StatusCode::FORBIDDEN, "Field 'document' not provided."),
+        2005 => (StatusCode::PAYMENT_REQUIRED, "Registration is disabled, set up KOReader sync in the app and log in."),
+        _ => (StatusCode::BAD_GATEWAY, "Unknown server error."),
+    };
+    (status, Json(json!({ "code": code, "message": message })))
+}
+
+/// Converts the fraction KOReader reports progress as to a percentage.
+fn to_percentage(percentage: f64) -> i32 {
+    (percentage.clamp(0.0, 1.0) * 100.0).round() as i32
+}
+
+/// Converts the fraction KOReader reports progress as to the page reached in a book.
+fn to_page(percentage: f64, total_pages: i32) -> i32 {
+    (percentage.clamp(0.0, 1.0) * total_pages as f64).round() as i32
+}
+
+/// Records progress synced by KOReader on the active reading of a book, starting a reading tracked
+/// as a percentage if there's none.
+///
+/// Unlike `record_progress`, progress is tracked once per day, as KOReader syncs whenever a document
+/// is closed. Later syncs of the same day update the entry of that day.
+fn record_synced_progress(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    book_id: Uuid,
+    percentage: f64,
+) -> QueryResult<()> {
+    let now = chrono::Utc::now().naive_utc();
+    let today = now.date();
+
+    connection.transaction(|connection| {
+        let book: Book = schema::books::table.find(book_id).first(connection)?;
+        let reading = active_reading(connection, user_id, &book, ReadingMode::Percentage)?;
+
+        let progress = to_percentage(percentage);
+        let reading_progress = match reading.mode {
+            ReadingMode::Percentage => progress,
+            ReadingMode::Pages if reading.total_pages > 0 => to_page(percentage, reading.total_pages),
+            ReadingMode::Pages => reading.progress,
+        };
+        diesel::update(schema::readings::table.find(reading.id))
+            .set(schema::readings::dsl::progress.eq(reading_progress))
+            .execute(connection)?;
+
+        let updated = diesel::update(
+            schema::reading_entries::table
+                .filter(schema::reading_entries::dsl::reading.eq(reading.id))
+                .filter(schema::reading_entries::dsl::read_at.eq(today))
+                .filter(schema::reading_entries::dsl::mode.eq(ReadingMode::Percentage)),
+        )
+        .set(schema::reading_entries::dsl::progress.eq(progress))
+        .execute(connection)?;
+        if updated == 0 {
+            let entry = ReadingEntry {
+                id: Uuid::new_v4(),
+                reading: reading.id,
+                book: book_id,
+                user: user_id,
+                progress,
+                mode: ReadingMode::Percentage,
+                read_at: today,
+                created_at: now,
+                updated_at: now,
+            };
+            diesel::insert_into(schema::reading_entries::table).values(&entry).execute(connection)?;
+        }
+        Ok(())
+    })
+}
+
+fn describe_document(document: &KoreaderDocument, title: Option<String>) -> Value {
+    json!({
+        "id": document.id.to_string(),
+        "document": document.document,
+        "book_id": document.book.map(|id| id.to_string()),
+        "book_title": title,
+        "percentage": to_percentage(document.percentage),
+        "device": document.device,
+        "synced_at": document.updated_at.to_string(),
+    })
+}
+
+/// Gets the KOReader sync account of the user, `account` is `null` if there's none.
+pub(crate) async fn get_account(auth: AuthUser) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    match koreader_accounts
+        .filter(schema::koreader_accounts::dsl::user.eq(auth.0))
+        .first::<KoreaderAccount>(connection)
+        .optional()
+    {
+        Ok(account) => (
+            StatusCode::OK,
+            Json(json!({
+                "account": account.map(|account| json!({
+                    "username": account.username,
+                })),
+            })),
+        ),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading the account: {}", e) }))),
+    }
+}
+
+/// Request type for saving the KOReader sync account.
+#[derive(Debug, Deserialize)]
+pub struct SaveAccountRequest {
+    pub username: String,
+    pub password: String,
+}
+
+/// Returns the key KOReader authenticates with for a password, which is its md5 hash.
+fn koreader_key(password: &str) -> String {
+    format!("{:x}", Md5::digest(password.as_bytes()))
+}
+
+/// Sets the username and password KOReader syncs progress with.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `username`: The username to log in with in KOReader.
+/// - `password`: The password to log in with in KOReader.
+///
+/// KOReader is paired by logging in with these credentials, registering in KOReader isn't needed.
+/// Saving the account again with a new password signs out devices using the previous one.
+pub(crate) async fn save_account(
+    auth: AuthUser,
+    Json(payload): Json<SaveAccountRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let username = payload.username.trim().to_string();
+    if username.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Username must not be empty.".to_string() })));
+    }
+    if payload.password.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Password must not be empty.".to_string() })));
+    }
+
+    let taken = koreader_accounts
+        .filter(schema::koreader_accounts::dsl::username.eq(&username))
+        .filter(schema::koreader_accounts::dsl::user.ne(auth.0))
+        .count()
+        .get_result::<i64>(connection);
+    match taken {
+        Ok(0) => {}
+        Ok(_) => return (StatusCode::CONFLICT, Json(json!(ErrorResponse { error: "Username is already taken.".to_string() }))),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while saving the account: {}", e) }))),
+    }
+
+    let Some(key_hash) = hash_password(koreader_key(&payload.password)).await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: "Failed to hash the password.".to_string() })));
+    };
+
+    let now = chrono::Utc::now().naive_utc();
+    let account = KoreaderAccount {
+        id: Uuid::new_v4(),
+        user: auth.0,
+        username,
+        key_hash: Some(key_hash),
+        created_at: now,
+        updated_at: now,
+    };
+
+    match diesel::insert_into(koreader_accounts)
+        .values(&account)
+        .on_conflict(schema::koreader_accounts::dsl::user)
+        .do_update()
+        .set((
+            schema::koreader_accounts::dsl::username.eq(&account.username),
+            schema::koreader_accounts::dsl::key_hash.eq(&account.key_hash),
+        ))
+        .execute(connection)
+    {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Account saved successfully.", "username": account.username }))),
+        // Another user may have claimed the username since it was checked
+        Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => (StatusCode::CONFLICT, Json(json!(ErrorResponse { error: "Username is already taken.".to_string() }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while saving the account: {}", e) }))),
+    }
+}
+
+/// Removes the KOReader sync account, devices can no longer sync progress. Synced documents are
+/// kept, so they don't have to be linked to their books again.
+pub(crate) async fn remove_account(auth: AuthUser) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    match diesel::delete(koreader_accounts.filter(schema::koreader_accounts::dsl::user.eq(auth.0))).execute(connection) {
+        Ok(0) => (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Account not found.".to_string() }))),
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Account removed successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while removing the account: {}", e) }))),
+    }
+}
+
+/// Lists the documents KOReader synced progress of, latest first, along with the books they're
+/// linked to.
+pub(crate) async fn list_documents(auth: AuthUser) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let results = match koreader_documents
+        .left_join(schema::books::table)
+        .filter(schema::koreader_documents::dsl::user.eq(auth.0))
+        .order(schema::koreader_documents::dsl::updated_at.desc())
+        .select((KoreaderDocument::as_select(), schema::books::dsl::title.nullable()))
+        .load::<(KoreaderDocument, Option<String>)>(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading documents: {}", e) }))),
+    };
+
+    let documents: Vec<Value> = results
+        .into_iter()
+        .map(|(document, title)| describe_document(&document, title))
+        .collect();
+
+    (StatusCode::OK, Json(json!({ "documents": documents })))
+}
+
+/// Request type for linking a synced document to a book.
+#[derive(Debug, Deserialize)]
+pub struct LinkDocumentRequest {
+    pub document_id: String,
+    pub book_id: Option<String>,
+}
+
+/// Links a document synced by KOReader to the book it's an edition of.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `document_id`: The UUID of the synced document.
+/// - `book_id`: Optional, the UUID of the book, the document is unlinked if it's omitted.
+///
+/// The progress last synced is tracked on the book right away, later syncs are tracked as they come
+/// in.
+pub(crate) async fn link_document(
+    auth: AuthUser,
+    Json(payload): Json<LinkDocumentRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let document_id = match Uuid::parse_str(&payload.document_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid document ID.".to_string() }))),
+    };
+    let book_id = match payload.book_id.as_deref().map(Uuid::parse_str).transpose() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+    };
+
+    let document: KoreaderDocument = match koreader_documents
+        .filter(schema::koreader_documents::dsl::id.eq(document_id))
+        .first(connection)
+    {
+        Ok(d) => d,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Document not found.".to_string() }))),
+    };
+
+    if document.user != auth.0 {
+        return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
+    }
+
+    if let Some(book_id) = book_id {
+        let book: Book = match schema::books::table.find(book_id).first(connection) {
+            Ok(b) => b,
+            Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Book not found.".to_string() }))),
+        };
+        if book.user != auth.0 {
+            return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
+        }
+    }
+
+    let result = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+        diesel::update(koreader_documents.find(document_id))
+            .set(schema::koreader_documents::dsl::book.eq(book_id))
+            .execute(connection)?;
+        if let Some(book_id) = book_id.filter(|id| document.book != Some(*id)) {
+            record_synced_progress(connection, auth.0, book_id, document.percentage)?;
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Document linked successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while linking the document: {}", e) }))),
+    }
+}
+
+/// Tells KOReader the sync server is up.
+pub(crate) async fn healthcheck() -> impl IntoResponse {
+    (StatusCode::OK, Json(json!({ "state": "OK" })))
+}
+
+/// Answers KOReader trying to register a user, which isn't possible as accounts are set up in the
+/// app, so that nobody else can claim them.
+///
+/// Usernames which are set up already are reported as registered, so KOReader suggests logging in.
+pub(crate) async fn register_device(Json(payload): Json<Value>) -> Response {
+    let Some(username) = payload.get("username").and_then(Value::as_str) else {
+        return sync_error(2003).into_response();
+    };
+
+    let connection = &mut connect();
+
+    match koreader_accounts
+        .filter(schema::koreader_accounts::dsl::username.eq(username))
+        .count()
+        .get_result::<i64>(connection)
+    {
+        Ok(0) => sync_error(2005).into_response(),
+        Ok(_) => sync_error(2002).into_response(),
+        Err(_) => sync_error(2000).into_response(),
+    }
+}
+
+/// Resolves the user KOReader authenticates as with the `x-auth-user` and `x-auth-key` headers.
+async fn authenticate_device(headers: &HeaderMap) -> Result<Uuid, (StatusCode, Json<Value>)> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).filter(|value| !value.is_empty());
+    let (Some(username), Some(key)) = (header("x-auth-user"), header("x-auth-key")) else {
+        return Err(sync_error(2001));
+    };
+
+    let account: KoreaderAccount = match koreader_accounts
+        .filter(schema::koreader_accounts::dsl::username.eq(username))
+        .first(&mut connect())
+        .optional()
+    {
+        Ok(Some(account)) => account,
+        Ok(None) => return Err(sync_error(2001)),
+        Err(_) => return Err(sync_error(2000)),
+    };
+
+    let Some(key_hash) = account.key_hash else {
+        return Err(sync_error(2001));
+    };
+    if !verify_password(key.to_string(), key_hash).await {
+        return Err(sync_error(2001));
+    }
+    Ok(account.user)
+}
+
+/// Tells KOReader whether its credentials are valid.
+pub(crate) async fn authorize_device(headers: HeaderMap) -> Response {
+    match authenticate_device(&headers).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "authorized": "OK" }))).into_response(),
+        Err(response) => response.into_response(),
+    }
+}
+
+/// Request type of KOReader syncing its progress in a document.
+#[derive(Debug, Deserialize)]
+pub struct UpdateProgressRequest {
+    pub document: Option<String>,
+    /// Position in the document, only KOReader understands it.
+    pub progress: Option<String>,
+    /// Fraction of the document read, between 0 and 1.
+    pub percentage: Option<f64>,
+    pub device: Option<String>,
+    pub device_id: Option<String>,
+}
+
+/// Stores the progress KOReader synced for a document.
+///
+/// Documents are identified by a hash KOReader computes, once a document is linked to a book the
+/// progress is tracked on the active reading of the book as a percentage.
+pub(crate) async fn update_progress(headers: HeaderMap, Json(payload): Json<UpdateProgressRequest>) -> Response {
+    let user_id = match authenticate_device(&headers).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response.into_response(),
+    };
+
+    let Some(document) = payload.document.filter(|d| !d.is_empty()) else {
+        return sync_error(2004).into_response();
+    };
+    let (Some(progress), Some(percentage)) = (payload.progress, payload.percentage) else {
+        return sync_error(2003).into_response();
+    };
+
+    let connection = &mut connect();
+
+    let result = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+        let previous: Option<KoreaderDocument> = koreader_documents
+            .filter(schema::koreader_documents::dsl::user.eq(user_id))
+            .filter(schema::koreader_documents::dsl::document.eq(&document))
+            .first(connection)
+            .optional()?;
+
+        let now = chrono::Utc::now().naive_utc();
+        let synced = KoreaderDocument {
+            id: previous.as_ref().map_or_else(Uuid::new_v4, |previous| previous.id),
+            user: user_id,
+            document: document.clone(),
+            book: previous.as_ref().and_then(|previous| previous.book),
+            progress,
+            percentage,
+            device: payload.device,
+            device_id: payload.device_id,
+            created_at: now,
+            updated_at: now,
+        };
+        diesel::insert_into(koreader_documents)
+            .values(&synced)
+            .on_conflict((schema::koreader_documents::dsl::user, schema::koreader_documents::dsl::document))
+            .do_update()
+            .set((
+                schema::koreader_documents::dsl::progress.eq(&synced.progress),
+                schema::koreader_documents::dsl::percentage.eq(synced.percentage),
+                schema::koreader_documents::dsl::device.eq(&synced.device),
+                schema::koreader_documents::dsl::device_id.eq(&synced.device_id),
+            ))
+            .execute(connection)?;
+
+        // Opening a document syncs it too, which doesn't count as reading
+        let moved = previous.as_ref().is_none_or(|previous| to_percentage(previous.percentage) != to_percentage(percentage));
+        if let Some(book_id) = synced.book.filter(|_| moved) {
+            record_synced_progress(connection, user_id, book_id, percentage)?;
+        }
+        Ok(now)
+    });
+
+    match result {
+        Ok(timestamp) => (StatusCode::OK, Json(json!({ "document": document, "timestamp": timestamp.and_utc().timestamp() }))).into_response(),
+        Err(_) => sync_error(2000).into_response(),
+    }
+}
+
+/// Sends KOReader the progress last synced for a document, or an empty object if there's none.
+pub(crate) async fn get_progress(headers: HeaderMap, Path(document): Path<String>) -> Response {
+    let user_id = match authenticate_device(&headers).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response.into_response(),
+    };
+
+    let connection = &mut connect();
+
+    match koreader_documents
+        .filter(schema::koreader_documents::dsl::user.eq(user_id))
+        .filter(schema::koreader_documents::dsl::document.eq(&document))
+        .first::<KoreaderDocument>(connection)
+        .optional()
+    {
+        Ok(Some(synced)) => (
+            StatusCode::OK,
+            Json(json!({
+                "document": synced.document,
+                "progress": synced.progress,
+                "percentage": synced.percentage,
+                "device": synced.device,
+                "device_id": synced.device_id,
+                "timestamp": synced.updated_at.and_utc().timestamp(),
+            })),
+        )
+            .into_response(),
+        Ok(None) => (StatusCode::OK, Json(json!({}))).into_response(),
+        Err(_) => sync_error(2000).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::{get, post}, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_account_requires_auth() {
+        let app = Router::new().route("/api/koreader/account/save", post(save_account));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/koreader/account/save").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_link_document_requires_auth() {
+        let app = Router::new().route("/api/koreader/documents/link", post(link_document));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/koreader/documents/link").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_authorize_device_requires_credentials() {
+        let app = Router::new().route("/api/koreader/users/auth", get(authorize_device));
+        let response = app
+            .oneshot(Request::builder().uri("/api/koreader/users/auth").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_koreader_key() {
+        // KOReader sends the md5 hash of the password as key
+        assert_eq!(koreader_key("password"), "5f4dcc3b5aa765d61d8327deb882cf99");
+    }
+
+    #[test]
+    fn test_synced_progress() {
+        assert_eq!(to_percentage(0.4567), 46);
+        assert_eq!(to_percentage(1.2), 100);
+        assert_eq!(to_page(0.5, 317), 159);
+        assert_eq!(to_page(-0.1, 317), 0);
+    }
+}
@@ -0,0 +1,453 @@
+use crate::goodreads_importer::{parse_date, SeriesMarker};
+use crate::importer::{
+    decode_text, flip_name, merge_contributors, primary_author, shelf_name, split_isbn, ImportedBook, ImportedRows,
+    Importer,
+};
+use crate::models::ReadingMode;
+use crate::readings::ReadDates;
+use chrono::NaiveDate;
+use csv::{ReaderBuilder, StringRecord};
+use serde_json::Value;
+
+/// Collections LibraryThing creates for every user, which stand for the status of a book.
+const STATUS_COLLECTIONS: [(&str, &str); 4] = [
+    ("currently reading", "currently-reading"),
+    ("read but unowned", "read"),
+    ("to read", "to-read"),
+    ("wishlist", "wishlist"),
+];
+
+/// The collection every owned book is in, it isn't turned into a shelf of its own.
+const LIBRARY_COLLECTION: &str = "your library";
+
+/// A book of a LibraryThing export, which comes as tab-separated text or as JSON.
+#[derive(Debug, Default, PartialEq)]
+pub struct LibraryThingRecord {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub secondary_authors: Vec<String>,
+    /// Publisher along with the edition, e.g. "Tor Books (2010), Edition: 1, 1007 pages".
+    pub publication: Option<String>,
+    pub date: Option<String>,
+    pub isbns: Vec<String>,
+    pub page_count: Option<String>,
+    pub series: Vec<String>,
+    pub rating: Option<f32>,
+    pub review: Option<String>,
+    pub comment: Option<String>,
+    pub private_comment: Option<String>,
+    pub tags: Vec<String>,
+    pub collections: Vec<String>,
+    pub entry_date: Option<String>,
+    pub date_started: Option<String>,
+    pub date_read: Option<String>,
+}
+
+/// Imports the tab-separated or JSON export of LibraryThing.
+pub struct LibraryThingImporter;
+
+impl Importer for LibraryThingImporter {
+    fn source(&self) -> &'static str {
+        "librarything"
+    }
+
+    fn name(&self) -> &'static str {
+        "LibraryThing"
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        let Ok(text) = decode_text(data) else {
+            return false;
+        };
+        if text.trim_start().starts_with('{') {
+            return serde_json::from_str::<serde_json::Map<String, Value>>(&text)
+                .is_ok_and(|books| books.values().next().is_some_and(|book| book.get("books_id").is_some()));
+        }
+        let header = text.lines().next().unwrap_or_default();
+        let columns: Vec<&str> = header.split('\t').map(str::trim).collect();
+        ["Book Id", "Title", "Primary Author"].iter().all(|column| columns.contains(column))
+    }
+
+    fn read(&self, data: &[u8], _options: &serde_json::Value) -> Result<ImportedRows, String> {
+        let text = decode_text(data).map_err(|e| format!("Failed to read LibraryThing export: {}", e))?;
+        let records = if text.trim_start().starts_with('{') {
+            LibraryThingRecord::read_json(&text)?
+        } else {
+            LibraryThingRecord::read_tsv(&text).map_err(|e| format!("Failed to parse LibraryThing export: {}", e))?
+        };
+        Ok(records.into_iter().map(|row| row.map(|record| record.to_imported())).collect())
+    }
+}
+
+/// Splits a comma separated list, e.g. the tags of a book.
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Returns a non-empty text value of a JSON book.
+fn json_text(book: &Value, key: &str) -> Option<String> {
+    let text = match &book[key] {
+        Value::String(text) => text.trim().to_string(),
+        Value::Number(number) => number.to_string(),
+        _ => return None,
+    };
+    Some(text).filter(|text| !text.is_empty())
+}
+
+/// Returns the texts of a JSON list, LibraryThing exports some lists as objects keyed by index.
+fn json_list(value: &Value) -> Vec<String> {
+    let items: Vec<&Value> = match value {
+        Value::Array(items) => items.iter().collect(),
+        Value::Object(items) => items.values().collect(),
+        Value::String(_) => vec![value],
+        _ => Vec::new(),
+    };
+    items
+        .into_iter()
+        .filter_map(Value::as_str)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Appends the role of a contributor to their name, e.g. "Michael Kramer (Narrator)".
+fn with_role(name: String, role: Option<&str>) -> String {
+    match role.map(str::trim).filter(|role| !role.is_empty()) {
+        Some(role) => format!("{} ({})", name, role),
+        None => name,
+    }
+}
+
+/// Parses a LibraryThing date, which may come with a time, e.g. "2021-03-15 00:00:00".
+fn parse_day(value: Option<&str>) -> Option<NaiveDate> {
+    parse_date(value?.trim().get(..10)?)
+}
+
+/// Parses a series like "Discworld (8)" or "The Stormlight Archive ; 1".
+fn parse_series(value: &str) -> Option<SeriesMarker> {
+    let value = value.trim();
+    let (name, position) = value
+        .strip_suffix(')')
+        .and_then(|v| v.rsplit_once(" ("))
+        .or_else(|| value.rsplit_once(';'))
+        .map(|(name, position)| (name, position.trim().parse().ok()))
+        .filter(|(_, position): &(&str, Option<f64>)| position.is_some())
+        .unwrap_or((value, None));
+    let name = name.trim();
+    (!name.is_empty()).then(|| SeriesMarker { name: name.to_string(), position })
+}
+
+impl LibraryThingRecord {
+    /// Reads the rows of a tab-separated export, keeping rows which fail to parse as errors.
+    pub fn read_tsv(text: &str) -> Result<Vec<Result<LibraryThingRecord, String>>, csv::Error> {
+        let mut rdr = ReaderBuilder::new()
+            .delimiter(b'\t')
+            .quoting(false)
+            .flexible(true)
+            .from_reader(text.as_bytes());
+        let headers = rdr.headers()?.clone();
+        Ok(rdr
+            .records()
+            .map(|row| row.map(|row| Self::from_row(&headers, &row)).map_err(|e| e.to_string()))
+            .collect())
+    }
+
+    /// Reads the books of a JSON export, an object keyed by the LibraryThing ID of the books.
+    pub fn read_json(text: &str) -> Result<Vec<Result<LibraryThingRecord, String>>, String> {
+        let books: serde_json::Map<String, Value> =
+            serde_json::from_str(text).map_err(|e| format!("Failed to parse LibraryThing export: {}", e))?;
+        Ok(books.values().map(Self::from_json).collect())
+    }
+
+    fn from_row(headers: &StringRecord, row: &StringRecord) -> LibraryThingRecord {
+        let field = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim() == name)
+                .and_then(|index| row.get(index))
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        // "ISBN" is wrapped in brackets, "ISBNs" lists all ISBNs of the edition
+        let mut isbns = split_list(&field("ISBNs").unwrap_or_default());
+        if let Some(isbn) = field("ISBN") {
+            isbns.push(isbn.trim_matches(['[', ']']).to_string());
+        }
+
+        // Secondary authors and their roles are separated by "|"
+        let roles = field("Secondary Author Roles").unwrap_or_default();
+        let mut roles = roles.split('|');
+        let secondary_authors = field("Secondary Author")
+            .unwrap_or_default()
+            .split('|')
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| with_role(flip_name(name), roles.next()))
+            .collect();
+
+        LibraryThingRecord {
+            title: field("Title").unwrap_or_default(),
+            authors: field("Primary Author").map(|name| flip_name(&name)).into_iter().collect(),
+            secondary_authors,
+            publication: field("Publication"),
+            date: field("Date"),
+            isbns,
+            page_count: field("Page Count"),
+            series: field("Series").map(|series| split_list(&series)).unwrap_or_default(),
+            rating: field("Rating").and_then(|rating| rating.parse().ok()),
+            review: field("Review"),
+            comment: field("Comment"),
+            private_comment: field("Private Comment"),
+            tags: split_list(&field("Tags").unwrap_or_default()),
+            collections: split_list(&field("Collections").unwrap_or_default()),
+            entry_date: field("Entry Date"),
+            date_started: field("Date Started"),
+            date_read: field("Date Read"),
+        }
+    }
+
+    fn from_json(book: &Value) -> Result<LibraryThingRecord, String> {
+        let title = json_text(book, "title").ok_or_else(|| "Missing title.".to_string())?;
+        let mut authors = Vec::new();
+        let mut secondary_authors = Vec::new();
+        for author in book["authors"].as_array().into_iter().flatten() {
+            let Some(name) = author["fl"].as_str().or_else(|| author.as_str()) else {
+                continue;
+            };
+            match author["role"].as_str().filter(|role| !role.is_empty() && *role != "Author") {
+                Some(role) => secondary_authors.push(with_role(name.to_string(), Some(role))),
+                None => authors.push(name.to_string()),
+            }
+        }
+
+        Ok(LibraryThingRecord {
+            title,
+            authors,
+            secondary_authors,
+            publication: json_text(book, "publication"),
+            date: json_text(book, "date"),
+            isbns: json_list(&book["isbn"]),
+            page_count: json_text(book, "pages"),
+            series: json_list(&book["series"]),
+            rating: book["rating"].as_f64().map(|rating| rating as f32),
+            review: json_text(book, "review"),
+            comment: json_text(book, "comment"),
+            private_comment: json_text(book, "privatecomment"),
+            tags: json_list(&book["tags"]),
+            collections: json_list(&book["collections"]),
+            entry_date: json_text(book, "entrydate"),
+            date_started: json_text(book, "datestarted"),
+            date_read: json_text(book, "datefinished").or_else(|| json_text(book, "dateread")),
+        })
+    }
+
+    /// Maps the record onto the book model shared by all importers.
+    pub fn to_imported(&self) -> ImportedBook {
+        let contributors = merge_contributors(&self.authors.join(", "), Some(&self.secondary_authors.join(", ")));
+        let (isbn13, isbn10) = self.isbns.iter().map(|isbn| split_isbn(isbn)).fold(
+            (None, None),
+            |(isbn13, isbn10), (next13, next10)| (isbn13.or(next13), isbn10.or(next10)),
+        );
+        let shelf = self.shelf();
+
+        ImportedBook {
+            raw_title: self.title.clone(),
+            title: self.title.clone(),
+            series: self.series.first().and_then(|series| parse_series(series)),
+            author: primary_author(&contributors).unwrap_or_else(|| self.authors.first().cloned().unwrap_or_default()),
+            contributors,
+            isbn13,
+            isbn10,
+            publisher: self.publisher(),
+            published_year: self.published_year(),
+            page_count: self.page_count.as_deref().and_then(|pages| pages.trim().parse().ok()),
+            added_on: parse_day(self.entry_date.as_deref()),
+            other_shelves: self
+                .collections
+                .iter()
+                .map(|collection| collection.to_lowercase())
+                .filter(|collection| collection != LIBRARY_COLLECTION)
+                .filter(|collection| !STATUS_COLLECTIONS.iter().any(|(status, _)| status == collection))
+                .map(|collection| shelf_name(&collection))
+                .collect(),
+            tags: self.tags.clone(),
+            rating: self
+                .rating
+                .filter(|rating| *rating > 0.0)
+                .map(|rating| ((rating * 2.0).round() as i16).clamp(1, 10)),
+            review: self.review.clone(),
+            note: self.note(),
+            reading_mode: ReadingMode::Pages,
+            reads: match parse_day(self.date_read.as_deref()) {
+                Some(finished_at) if shelf == "read" => vec![ReadDates {
+                    started_at: parse_day(self.date_started.as_deref()),
+                    finished_at,
+                }],
+                _ => Vec::new(),
+            },
+            shelf,
+            undated_reads: 0,
+            unfinished_read: None,
+            has_cover: false,
+            cover_file: None,
+        }
+    }
+
+    /// Returns the shelf for the status of the book.
+    ///
+    /// LibraryThing has no status of its own, so it's taken from the collections the book is in
+    /// and books with a read date count as read. Books only in "Your library" are kept on a
+    /// `your-library` shelf.
+    pub fn shelf(&self) -> String {
+        let collections: Vec<String> = self.collections.iter().map(|c| c.to_lowercase()).collect();
+        let status = |name: &str| collections.iter().any(|c| c == name);
+        if status("currently reading") {
+            return "currently-reading".to_string();
+        }
+        if self.date_read.is_some() {
+            return "read".to_string();
+        }
+        STATUS_COLLECTIONS
+            .iter()
+            .find(|(collection, _)| status(collection))
+            .map(|(_, shelf)| shelf.to_string())
+            .unwrap_or_else(|| shelf_name(collections.first().map(String::as_str).unwrap_or(LIBRARY_COLLECTION)))
+    }
+
+    /// Returns the publisher, which leads the publication details.
+    pub fn publisher(&self) -> Option<String> {
+        let publication = self.publication.as_deref()?;
+        let publisher = publication.split([',', '(']).next().unwrap_or_default().trim();
+        (!publisher.is_empty()).then(|| publisher.to_string())
+    }
+
+    /// Returns the year the book was published in, the date may be a plain year or "c. 1989".
+    pub fn published_year(&self) -> Option<i32> {
+        let date = self.date.as_deref()?;
+        let digits: String = date.chars().skip_while(|c| !c.is_ascii_digit()).take(4).collect();
+        digits.parse().ok().filter(|_| digits.len() == 4)
+    }
+
+    /// Returns the private and public comments on the book as a note.
+    pub fn note(&self) -> Option<String> {
+        let comments: Vec<&str> = [self.private_comment.as_deref(), self.comment.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        (!comments.is_empty()).then(|| comments.join("\n\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TSV: &str = "Book Id\tTitle\tSort Character\tPrimary Author\tPrimary Author Role\tSecondary Author\tSecondary Author Roles\tPublication\tDate\tReview\tRating\tComment\tPrivate Comment\tPage Count\tDate Started\tDate Read\tTags\tCollections\tISBN\tISBNs\tEntry Date
+1001\tGuards! Guards!\t1\tPratchett, Terry\t\tKidby, Paul|Briggs, Stephen\tIllustrator|Narrator\tGollancz (1989), Hardcover, 288 pages\t1989\tThe best one.\t4.5\t\tLent to Sam\t288\t2021-02-01\t2021-03-15\tfantasy, humor\tYour library, Favorites\t[0575046066]\t9780575046063, 0575046066\t2020-12-24
+1002\tPiranesi\t1\tClarke, Susanna\t\t\t\tBloomsbury (2020)\tc. 2020\t\t\t\t\t\t\t\t\tTo read\t\t\t2024-06-30
+1003\tThe Dispossessed\t1\tLe Guin, Ursula K.\t\t\t\tHarper (1974)\t1974\t\t\t\t\t\t\t\t\tTo read\t\t\t2024-07-01
+";
+
+    const JSON: &str = r#"{
+        "1001": {
+            "books_id": "1001",
+            "title": "The Way of Kings",
+            "authors": [
+                { "lf": "Sanderson, Brandon", "fl": "Brandon Sanderson", "role": "Author" },
+                { "lf": "Kramer, Michael", "fl": "Michael Kramer", "role": "Narrator" }
+            ],
+            "date": "2010",
+            "publication": "Tor Books (2010), Edition: 1, 1007 pages",
+            "isbn": { "0": "0765326353", "2": "9780765326355" },
+            "pages": "1007 ",
+            "series": ["The Stormlight Archive (1)"],
+            "rating": 5,
+            "collections": ["Read but unowned"],
+            "tags": ["fantasy"],
+            "entrydate": "2021-01-02",
+            "datestarted": "2021-02-01",
+            "datefinished": "2021-03-15"
+        }
+    }"#;
+
+    fn read(data: &str) -> Vec<ImportedBook> {
+        LibraryThingImporter
+            .read(data.as_bytes(), &serde_json::json!({}))
+            .unwrap()
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_read_tsv() {
+        assert!(LibraryThingImporter.detect(TSV.as_bytes()));
+        let books = read(TSV);
+        assert_eq!(books.len(), 3);
+
+        let guards = &books[0];
+        assert_eq!(guards.author, "Terry Pratchett");
+        assert_eq!(guards.contributors.len(), 3);
+        assert_eq!(guards.shelf, "read");
+        assert_eq!(guards.other_shelves, vec!["favorites"]);
+        assert_eq!(guards.isbn13.as_deref(), Some("9780575046063"));
+        assert_eq!(guards.isbn10.as_deref(), Some("0575046066"));
+        assert_eq!(guards.publisher.as_deref(), Some("Gollancz"));
+        assert_eq!(guards.published_year, Some(1989));
+        assert_eq!(guards.page_count, Some(288));
+        assert_eq!(guards.rating, Some(9));
+        assert_eq!(guards.tags, vec!["fantasy", "humor"]);
+        assert_eq!(guards.note.as_deref(), Some("Lent to Sam"));
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(
+            guards.reads,
+            vec![ReadDates { started_at: Some(date(2021, 2, 1)), finished_at: date(2021, 3, 15) }]
+        );
+
+        let piranesi = &books[1];
+        assert_eq!(piranesi.shelf, "to-read");
+        assert!(piranesi.other_shelves.is_empty());
+        assert_eq!(piranesi.published_year, Some(2020));
+        assert!(piranesi.reads.is_empty());
+
+        let dispossessed = &books[2];
+        assert_eq!(dispossessed.author, "Ursula K. Le Guin");
+        assert_eq!(dispossessed.contributors.len(), 1);
+    }
+
+    #[test]
+    fn test_read_json() {
+        assert!(LibraryThingImporter.detect(JSON.as_bytes()));
+        assert!(!LibraryThingImporter.detect(b"{\"foo\": {}}"));
+        let books = read(JSON);
+        let way_of_kings = &books[0];
+        assert_eq!(way_of_kings.author, "Brandon Sanderson");
+        assert_eq!(way_of_kings.contributors.len(), 2);
+        assert_eq!(way_of_kings.shelf, "read");
+        assert_eq!(way_of_kings.isbn13.as_deref(), Some("9780765326355"));
+        assert_eq!(way_of_kings.page_count, Some(1007));
+        assert_eq!(way_of_kings.rating, Some(10));
+        assert_eq!(
+            way_of_kings.series,
+            Some(SeriesMarker { name: "The Stormlight Archive".to_string(), position: Some(1.0) })
+        );
+        assert_eq!(way_of_kings.reads.len(), 1);
+    }
+
+    #[test]
+    fn test_read_utf16() {
+        let data: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain(TSV.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        assert!(LibraryThingImporter.detect(&data));
+        assert_eq!(LibraryThingImporter.read(&data, &serde_json::json!({})).unwrap().len(), 3);
+    }
+}
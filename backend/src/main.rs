@@ -1,10 +1,40 @@
+mod api_keys;
+mod atom_exporter;
+mod backups;
 mod books;
+mod calendar_exporter;
+mod calibre_importer;
+mod citation_exporter;
+mod contributors;
+mod covers;
+mod csv_importer;
 mod db;
+mod enrichment;
+mod exports;
+mod feeds;
+mod goodreads_exporter;
 mod goodreads_importer;
+mod highlights;
+mod import_templates;
+mod importer;
+mod imports;
+mod ingest;
+mod kindle_importer;
+mod koreader;
+mod librarything_importer;
+mod markdown_exporter;
 mod models;
+mod notes;
+mod opds;
+mod public_feed;
 mod readings;
+mod reviews;
 mod schema;
+mod series;
 mod shelves;
+mod storage;
+mod storygraph_importer;
+mod tags;
 mod users;
 mod auth;
 
@@ -22,6 +52,7 @@ async fn main() {
 
     dotenv().ok();
     auth::init_jwt_secret();
+    storage::init_cover_storage();
 
     info!("initializing router...");
 
@@ -40,8 +71,29 @@ async fn main() {
     router = shelves::register_routes(router);
     router = books::register_routes(router);
     router = readings::register_routes(router);
+    router = enrichment::register_routes(router);
+    router = covers::register_routes(router);
+    router = contributors::register_routes(router);
+    router = series::register_routes(router);
+    router = tags::register_routes(router);
+    router = reviews::register_routes(router);
+    router = notes::register_routes(router);
+    router = highlights::register_routes(router);
+    router = imports::register_routes(router);
+    router = import_templates::register_routes(router);
+    router = exports::register_routes(router);
+    router = backups::register_routes(router);
+    router = feeds::register_routes(router);
+    router = opds::register_routes(router);
+    router = public_feed::register_routes(router);
+    router = koreader::register_routes(router);
+    router = api_keys::register_routes(router);
+    router = ingest::register_routes(router);
     router = router.layer(cors);
 
+    enrichment::spawn_worker();
+    imports::spawn_worker();
+
     info!("starting server...");
 
     let port = 3000_u16;
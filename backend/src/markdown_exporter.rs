@@ -0,0 +1,492 @@
+use crate::books::work_key;
+use crate::exports::LibraryBook;
+use crate::models::{ContributorRole, Highlight, Note};
+use crate::reviews::stars;
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Folder of the Markdown files within the archive.
+const VAULT_FOLDER: &str = "Books";
+
+/// Longest file name of a book, without the extension, in characters.
+const MAX_FILE_NAME: usize = 120;
+
+/// A book along with its notes and highlights, which become a Markdown file of its own.
+pub struct VaultBook {
+    pub book: LibraryBook,
+    /// Shelves the book is on, as the same book may be placed on several shelves.
+    pub shelves: Vec<String>,
+    /// Whether the book is being read at the moment.
+    pub reading: bool,
+    /// All notes of the book, including the ones on single readings.
+    pub notes: Vec<Note>,
+    pub highlights: Vec<Highlight>,
+}
+
+impl VaultBook {
+    /// Returns the reading status of the book: `reading`, `read` or `unread`.
+    fn status(&self) -> &'static str {
+        if self.reading {
+            "reading"
+        } else if !self.book.reads.is_empty() {
+            "read"
+        } else {
+            "unread"
+        }
+    }
+}
+
+/// Merges the copies of a book on several shelves, so that each book gets a single file.
+///
+/// The copy added first is kept, along with the shelves, tags, reads, notes and highlights of the
+/// others.
+pub fn group_copies(mut books: Vec<VaultBook>) -> Vec<VaultBook> {
+    books.sort_by_key(|book| (book.book.book.added_at, book.book.book.id));
+
+    let mut works: Vec<VaultBook> = Vec::new();
+    let mut indices: HashMap<String, usize> = HashMap::new();
+    for copy in books {
+        let index = match indices.get(&work_key(&copy.book.book)) {
+            Some(&index) => index,
+            None => {
+                indices.insert(work_key(&copy.book.book), works.len());
+                works.push(copy);
+                continue;
+            }
+        };
+        let work = &mut works[index];
+        work.reading |= copy.reading;
+        work.shelves.extend(copy.shelves);
+        work.book.tags.extend(copy.book.tags);
+        work.book.reads.extend(copy.book.reads);
+        work.book.rating = work.book.rating.or(copy.book.rating);
+        work.book.review = work.book.review.take().or(copy.book.review);
+        work.notes.extend(copy.notes);
+        work.highlights.extend(copy.highlights);
+    }
+
+    for work in &mut works {
+        work.shelves.sort();
+        work.shelves.dedup();
+        work.book.tags.sort();
+        work.book.tags.dedup();
+        work.book.reads.sort_by_key(|read| std::cmp::Reverse((read.finished_at, read.started_at)));
+        work.book.reads.dedup();
+        work.notes.sort_by_key(|note| (note.created_at, note.id));
+        work.highlights.sort_by_key(|highlight| (highlight.page.is_none(), highlight.page, highlight.created_at, highlight.id));
+    }
+    works
+}
+
+/// Removes the characters which aren't allowed in file names on some systems or have a meaning in
+/// Obsidian links.
+fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']' => ' ',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    let cleaned: String = cleaned.chars().take(MAX_FILE_NAME).collect();
+    cleaned.trim().trim_start_matches('.').trim().to_string()
+}
+
+/// Builds the name of the file of a book, like "Mort - Terry Pratchett".
+fn base_file_name(book: &LibraryBook) -> String {
+    let title = book.book.title.as_deref().unwrap_or_default();
+    let author = book
+        .contributors
+        .iter()
+        .find(|contributor| contributor.role == ContributorRole::Author)
+        .map(|contributor| contributor.name.as_str())
+        .or(book.book.author.as_deref())
+        .unwrap_or_default();
+    let name = match (title.trim().is_empty(), author.trim().is_empty()) {
+        (false, false) => format!("{} - {}", title, author),
+        (false, true) => title.to_string(),
+        _ => String::new(),
+    };
+    match sanitize_file_name(&name) {
+        name if name.is_empty() => "Untitled".to_string(),
+        name => name,
+    }
+}
+
+/// Assigns the file names of the books of a library, which only depend on the books themselves so
+/// that exporting again overwrites the same files.
+///
+/// Books sharing a name get the start of their id appended, except for the one added first. Copies
+/// on several shelves are expected to be merged with `group_copies` first.
+pub fn file_names(books: &[VaultBook]) -> Vec<String> {
+    let names: Vec<String> = books.iter().map(|book| base_file_name(&book.book)).collect();
+
+    let mut groups: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, name) in names.iter().enumerate() {
+        groups.entry(name.to_lowercase()).or_default().push(index);
+    }
+    let mut duplicates = vec![false; books.len()];
+    for mut indices in groups.into_values().filter(|indices| indices.len() > 1) {
+        indices.sort_by_key(|&index| (books[index].book.book.added_at, books[index].book.book.id));
+        for &index in &indices[1..] {
+            duplicates[index] = true;
+        }
+    }
+
+    names
+        .into_iter()
+        .zip(books)
+        .zip(duplicates)
+        .map(|((name, book), duplicate)| match duplicate {
+            true => format!("{} ({}).md", name, &book.book.book.id.simple().to_string()[..8]),
+            false => format!("{}.md", name),
+        })
+        .collect()
+}
+
+/// Quotes a string for YAML, JSON strings are valid YAML scalars.
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn yaml_list(front_matter: &mut String, key: &str, values: &[String]) {
+    if values.is_empty() {
+        return;
+    }
+    front_matter.push_str(&format!("{}:\n", key));
+    for value in values {
+        front_matter.push_str(&format!("  - {}\n", value));
+    }
+}
+
+/// Writes the front matter of a book.
+fn front_matter(vault_book: &VaultBook) -> String {
+    let library_book = &vault_book.book;
+    let book = &library_book.book;
+    let mut yaml = String::from("---\n");
+    yaml.push_str(&format!("id: {}\n", book.id));
+    if let Some(title) = &book.title {
+        yaml.push_str(&format!("title: {}\n", yaml_string(title)));
+    }
+
+    let mut authors: Vec<String> = library_book
+        .contributors
+        .iter()
+        .filter(|contributor| contributor.role == ContributorRole::Author)
+        .map(|contributor| yaml_string(&contributor.name))
+        .collect();
+    if authors.is_empty() {
+        authors.extend(book.author.as_deref().map(yaml_string));
+    }
+    yaml_list(&mut yaml, "authors", &authors);
+    let others: Vec<String> = library_book
+        .contributors
+        .iter()
+        .filter(|contributor| contributor.role != ContributorRole::Author)
+        .map(|contributor| yaml_string(&format!("{} ({})", contributor.name, contributor.role)))
+        .collect();
+    yaml_list(&mut yaml, "contributors", &others);
+
+    if let Some(series) = &library_book.series {
+        yaml.push_str(&format!("series: {}\n", yaml_string(&series.name)));
+        if let Some(position) = series.position {
+            yaml.push_str(&format!("series_position: {}\n", position));
+        }
+    }
+    if let Some(isbn) = &book.isbn13 {
+        yaml.push_str(&format!("isbn13: {}\n", yaml_string(isbn)));
+    }
+    if let Some(isbn) = &book.isbn10 {
+        yaml.push_str(&format!("isbn10: {}\n", yaml_string(isbn)));
+    }
+    if let Some(publisher) = &book.publisher {
+        yaml.push_str(&format!("publisher: {}\n", yaml_string(publisher)));
+    }
+    if let Some(year) = book.published_year {
+        yaml.push_str(&format!("year: {}\n", year));
+    }
+    if let Some(pages) = book.page_count.filter(|pages| *pages > 0) {
+        yaml.push_str(&format!("pages: {}\n", pages));
+    }
+
+    let shelves: Vec<String> = vault_book.shelves.iter().map(|shelf| yaml_string(shelf)).collect();
+    yaml_list(&mut yaml, "shelves", &shelves);
+    let tags: Vec<String> = library_book.tags.iter().map(|tag| yaml_string(tag)).collect();
+    yaml_list(&mut yaml, "tags", &tags);
+    yaml.push_str(&format!("status: {}\n", vault_book.status()));
+    if let Some(rating) = library_book.rating {
+        yaml.push_str(&format!("rating: {}\n", stars(rating)));
+    }
+    yaml.push_str(&format!("added: {}\n", book.added_at.format("%Y-%m-%d")));
+
+    let mut dates: Vec<String> = library_book
+        .reads
+        .iter()
+        .map(|read| read.finished_at.format("%Y-%m-%d").to_string())
+        .collect();
+    dates.sort();
+    yaml_list(&mut yaml, "dates_read", &dates);
+    yaml.push_str("---\n");
+    yaml
+}
+
+/// Writes where a note or highlight is within the book, like "Page 12, Location 120-125".
+fn position(page: Option<i32>, location: Option<&str>) -> Option<String> {
+    let parts: Vec<String> = page
+        .map(|page| format!("Page {}", page))
+        .into_iter()
+        .chain(location.map(|location| format!("Location {}", location)))
+        .collect();
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+/// Writes the Markdown file of a book, with its review, notes and highlights as sections.
+pub fn markdown_file(vault_book: &VaultBook) -> String {
+    let mut markdown = front_matter(vault_book);
+    markdown.push_str(&format!(
+        "\n# {}\n",
+        vault_book.book.book.title.as_deref().unwrap_or("Untitled")
+    ));
+
+    if let Some(review) = vault_book.book.review.as_deref().filter(|review| !review.trim().is_empty()) {
+        markdown.push_str(&format!("\n## Review\n\n{}\n", review.trim()));
+    }
+
+    if !vault_book.notes.is_empty() {
+        markdown.push_str("\n## Notes\n");
+        for note in &vault_book.notes {
+            markdown.push('\n');
+            if let Some(position) = position(note.page, None) {
+                markdown.push_str(&format!("### {}\n\n", position));
+            }
+            markdown.push_str(note.body.trim());
+            markdown.push('\n');
+        }
+    }
+
+    if !vault_book.highlights.is_empty() {
+        markdown.push_str("\n## Highlights\n");
+        for highlight in &vault_book.highlights {
+            markdown.push('\n');
+            for line in highlight.quote.trim().lines() {
+                markdown.push_str(format!("> {}", line).trim_end());
+                markdown.push('\n');
+            }
+            if let Some(position) = position(highlight.page, highlight.location.as_deref()) {
+                markdown.push_str(&format!("\n{}\n", position));
+            }
+            if let Some(note) = highlight.note.as_deref().filter(|note| !note.trim().is_empty()) {
+                markdown.push_str(&format!("\n{}\n", note.trim()));
+            }
+        }
+    }
+    markdown
+}
+
+/// Writes a zip archive with a Markdown file per book in the `Books` folder.
+///
+/// The files are written in the order of their names without timestamps, so exporting an
+/// unchanged library gives the same archive.
+pub fn write_vault(books: &[VaultBook]) -> Result<Vec<u8>, String> {
+    let mut files: Vec<(String, &VaultBook)> = file_names(books).into_iter().zip(books).collect();
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, book) in files {
+        writer
+            .start_file(format!("{}/{}", VAULT_FOLDER, name), options)
+            .map_err(|e| e.to_string())?;
+        writer.write_all(markdown_file(book).as_bytes()).map_err(|e| e.to_string())?;
+    }
+    Ok(writer.finish().map_err(|e| e.to_string())?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contributors::ContributorInput;
+    use crate::goodreads_importer::SeriesMarker;
+    use crate::models::Book;
+    use crate::readings::ReadDates;
+    use chrono::NaiveDate;
+    use uuid::Uuid;
+
+    fn vault_book(title: &str, day: u32) -> VaultBook {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let book_id = Uuid::new_v4();
+        let created_at = date(2021, 5, 1).and_time(chrono::NaiveTime::MIN);
+        VaultBook {
+            book: LibraryBook {
+                book: Book {
+                    id: book_id,
+                    user: Uuid::new_v4(),
+                    shelf: Uuid::new_v4(),
+                    title: Some(title.to_string()),
+                    author: Some("Terry Pratchett".to_string()),
+                    isbn13: Some("9780552131061".to_string()),
+                    isbn10: None,
+                    google_books_id: None,
+                    added_at: date(2020, 1, day).and_time(chrono::NaiveTime::MIN),
+                    publisher: None,
+                    published_year: Some(1987),
+                    page_count: Some(316),
+                    cover_url: None,
+                },
+                shelf: "read".to_string(),
+                contributors: vec![
+                    ContributorInput { name: "Terry Pratchett".to_string(), role: ContributorRole::Author },
+                    ContributorInput { name: "Nigel Planer".to_string(), role: ContributorRole::Narrator },
+                ],
+                series: Some(SeriesMarker { name: "Discworld".to_string(), position: Some(4.0) }),
+                tags: vec!["fantasy".to_string()],
+                rating: Some(9),
+                review: Some("Death takes an apprentice.".to_string()),
+                notes: Vec::new(),
+                reads: vec![
+                    ReadDates { started_at: None, finished_at: date(2023, 2, 1) },
+                    ReadDates { started_at: None, finished_at: date(2021, 5, 3) },
+                ],
+            },
+            shelves: vec!["read".to_string()],
+            reading: false,
+            notes: vec![Note {
+                id: Uuid::new_v4(),
+                user: Uuid::new_v4(),
+                book: book_id,
+                reading: None,
+                page: Some(12),
+                body: "Lent to Sam".to_string(),
+                created_at,
+                updated_at: created_at,
+                source_hash: None,
+            }],
+            highlights: vec![Highlight {
+                id: Uuid::new_v4(),
+                user: Uuid::new_v4(),
+                book: book_id,
+                reading: None,
+                quote: "THERE'S NO JUSTICE.\nTHERE'S JUST ME.".to_string(),
+                page: Some(240),
+                location: Some("3561-3562".to_string()),
+                note: Some("Death, on justice".to_string()),
+                created_at,
+                updated_at: created_at,
+                source_hash: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_markdown_file() {
+        let book = vault_book("Mort: A \"Discworld\" Novel", 1);
+        assert_eq!(
+            markdown_file(&book),
+            format!(
+                "---
+id: {}
+title: \"Mort: A \\\"Discworld\\\" Novel\"
+authors:
+  - \"Terry Pratchett\"
+contributors:
+  - \"Nigel Planer (narrator)\"
+series: \"Discworld\"
+series_position: 4
+isbn13: \"9780552131061\"
+year: 1987
+pages: 316
+shelves:
+  - \"read\"
+tags:
+  - \"fantasy\"
+status: read
+rating: 4.5
+added: 2020-01-01
+dates_read:
+  - 2021-05-03
+  - 2023-02-01
+---
+
+# Mort: A \"Discworld\" Novel
+
+## Review
+
+Death takes an apprentice.
+
+## Notes
+
+### Page 12
+
+Lent to Sam
+
+## Highlights
+
+> THERE'S NO JUSTICE.
+> THERE'S JUST ME.
+
+Page 240, Location 3561-3562
+
+Death, on justice
+",
+                book.book.book.id
+            )
+        );
+    }
+
+    #[test]
+    fn test_file_names() {
+        let books = vec![vault_book("Mort", 3), vault_book("Guards! Guards!", 2), vault_book("Mort", 1)];
+        let names = file_names(&books);
+        assert_eq!(
+            names[0],
+            format!("Mort - Terry Pratchett ({}).md", &books[0].book.book.id.simple().to_string()[..8])
+        );
+        assert_eq!(names[1], "Guards! Guards! - Terry Pratchett.md");
+        assert_eq!(names[2], "Mort - Terry Pratchett.md");
+        assert_eq!(sanitize_file_name("What If?: Serious / Absurd"), "What If Serious Absurd");
+    }
+
+    #[test]
+    fn test_group_copies() {
+        let mut copy = vault_book("Mort", 1);
+        copy.shelves = vec!["favorites".to_string()];
+        copy.book.tags = vec!["discworld".to_string(), "fantasy".to_string()];
+        copy.book.rating = None;
+        copy.book.reads = vec![ReadDates { started_at: None, finished_at: NaiveDate::from_ymd_opt(2023, 2, 1).unwrap() }];
+        copy.reading = true;
+        let first = vault_book("Mort", 2);
+        let first_id = first.book.book.id;
+        copy.book.book.added_at = first.book.book.added_at + chrono::Duration::days(1);
+
+        let mut other = vault_book("Eric", 3);
+        other.book.book.isbn13 = Some("9780575046368".to_string());
+
+        let works = group_copies(vec![copy, first, other]);
+        assert_eq!(works.len(), 2);
+        let mort = &works[0];
+        assert_eq!(mort.book.book.id, first_id);
+        assert_eq!(mort.shelves, vec!["favorites", "read"]);
+        assert_eq!(mort.book.tags, vec!["discworld", "fantasy"]);
+        assert_eq!(mort.book.rating, Some(9));
+        assert_eq!(mort.book.reads.len(), 2);
+        assert_eq!(mort.notes.len(), 2);
+        assert_eq!(mort.status(), "reading");
+        assert_eq!(file_names(&works[..1]), vec!["Mort - Terry Pratchett.md"]);
+    }
+
+    #[test]
+    fn test_write_vault_is_deterministic() {
+        let books = vec![vault_book("Mort", 1), vault_book("Eric", 2)];
+        let archive = write_vault(&books).unwrap();
+        assert_eq!(archive, write_vault(&books).unwrap());
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+        let names: Vec<&str> = zip.file_names().collect();
+        assert_eq!(names.len(), 2);
+        assert!(zip.by_name("Books/Eric - Terry Pratchett.md").is_ok());
+    }
+}
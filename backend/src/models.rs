@@ -14,7 +14,7 @@ pub struct User {
     pub elevated: bool,
 }
 
-#[derive(Queryable, Selectable, Insertable)]
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
 #[diesel(table_name = crate::schema::shelves)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[diesel(belongs_to(User))]
@@ -27,7 +27,7 @@ pub struct Shelf {
     pub updated_at: chrono::NaiveDateTime,
 }
 
-#[derive(Queryable, Selectable, Insertable)]
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
 #[diesel(table_name = crate::schema::books)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[diesel(belongs_to(User))]
@@ -42,10 +42,15 @@ pub struct Book {
     pub isbn10: Option<String>,
     pub google_books_id: Option<String>,
     pub added_at: chrono::NaiveDateTime,
+    pub publisher: Option<String>,
+    pub published_year: Option<i32>,
+    pub page_count: Option<i32>,
+    pub cover_url: Option<String>,
 }
 
-#[derive(Debug, diesel_derive_enum::DbEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, diesel_derive_enum::DbEnum, serde::Deserialize, serde::Serialize)]
 #[ExistingTypePath = "crate::schema::sql_types::ReadingMode"]
+#[serde(rename_all = "lowercase")]
 pub enum ReadingMode {
     Pages,
     Percentage,
@@ -60,7 +65,7 @@ impl Display for ReadingMode {
     }
 }
 
-#[derive(Queryable, Selectable, Insertable)]
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
 #[diesel(table_name = crate::schema::readings)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[diesel(belongs_to(User))]
@@ -72,14 +77,18 @@ pub struct Reading {
     pub total_pages: i32,
     pub progress: i32,
     pub mode: ReadingMode,
-    pub started_at: chrono::NaiveDate,
+    pub started_at: Option<chrono::NaiveDate>,
     pub finished_at: Option<chrono::NaiveDate>,
     pub cancelled_at: Option<chrono::NaiveDate>,
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
+    #[serde(default)]
+    pub target_finish_at: Option<chrono::NaiveDate>,
+    #[serde(default)]
+    pub due_at: Option<chrono::NaiveDate>,
 }
 
-#[derive(Queryable, Selectable, Insertable)]
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
 #[diesel(table_name = crate::schema::reading_entries)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[diesel(belongs_to(User))]
@@ -96,3 +105,382 @@ pub struct ReadingEntry {
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, diesel_derive_enum::DbEnum)]
+#[ExistingTypePath = "crate::schema::sql_types::EnrichmentStatus"]
+pub enum EnrichmentStatus {
+    Pending,
+    Matched,
+    Unmatched,
+    Failed,
+}
+
+impl Display for EnrichmentStatus {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            EnrichmentStatus::Pending => write!(f, "pending"),
+            EnrichmentStatus::Matched => write!(f, "matched"),
+            EnrichmentStatus::Unmatched => write!(f, "unmatched"),
+            EnrichmentStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::book_enrichments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Book))]
+#[diesel(belongs_to(User))]
+pub struct BookEnrichment {
+    pub book: Uuid,
+    pub user: Uuid,
+    pub status: EnrichmentStatus,
+    pub provider: Option<String>,
+    pub matched_by: Option<String>,
+    pub confidence: Option<f32>,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::book_covers)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Book))]
+#[diesel(belongs_to(User))]
+pub struct BookCover {
+    pub book: Uuid,
+    pub id: Uuid,
+    pub user: Uuid,
+    pub content_type: String,
+    pub width: i32,
+    pub height: i32,
+    pub uploaded_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, diesel_derive_enum::DbEnum, serde::Deserialize, serde::Serialize)]
+#[ExistingTypePath = "crate::schema::sql_types::ContributorRole"]
+#[serde(rename_all = "lowercase")]
+pub enum ContributorRole {
+    Author,
+    Translator,
+    Editor,
+    Illustrator,
+    Narrator,
+}
+
+impl Display for ContributorRole {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ContributorRole::Author => write!(f, "author"),
+            ContributorRole::Translator => write!(f, "translator"),
+            ContributorRole::Editor => write!(f, "editor"),
+            ContributorRole::Illustrator => write!(f, "illustrator"),
+            ContributorRole::Narrator => write!(f, "narrator"),
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
+#[diesel(table_name = crate::schema::authors)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+pub struct Author {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub name: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
+#[diesel(table_name = crate::schema::book_contributors)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Book))]
+#[diesel(belongs_to(Author))]
+pub struct BookContributor {
+    pub book: Uuid,
+    pub author: Uuid,
+    pub role: ContributorRole,
+    pub position: i32,
+}
+
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
+#[diesel(table_name = crate::schema::series)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+pub struct Series {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
+#[diesel(table_name = crate::schema::series_books)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Series))]
+#[diesel(belongs_to(Book))]
+pub struct SeriesBook {
+    pub series: Uuid,
+    pub book: Uuid,
+    pub position: Option<f64>,
+}
+
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
+#[diesel(table_name = crate::schema::tags)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+pub struct Tag {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub name: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
+#[diesel(table_name = crate::schema::book_tags)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Book))]
+#[diesel(belongs_to(Tag))]
+pub struct BookTag {
+    pub book: Uuid,
+    pub tag: Uuid,
+}
+
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
+#[diesel(table_name = crate::schema::reviews)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+#[diesel(belongs_to(Book))]
+#[diesel(belongs_to(Reading))]
+pub struct Review {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub book: Uuid,
+    pub reading: Option<Uuid>,
+    pub rating: Option<i16>,
+    pub body: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
+#[diesel(table_name = crate::schema::notes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+#[diesel(belongs_to(Book))]
+#[diesel(belongs_to(Reading))]
+pub struct Note {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub book: Uuid,
+    pub reading: Option<Uuid>,
+    pub page: Option<i32>,
+    pub body: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    pub source_hash: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
+#[diesel(table_name = crate::schema::highlights)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+#[diesel(belongs_to(Book))]
+#[diesel(belongs_to(Reading))]
+pub struct Highlight {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub book: Uuid,
+    pub reading: Option<Uuid>,
+    pub quote: String,
+    pub page: Option<i32>,
+    pub location: Option<String>,
+    pub note: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    pub source_hash: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
+#[diesel(table_name = crate::schema::highlight_tags)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Highlight))]
+#[diesel(belongs_to(Tag))]
+pub struct HighlightTag {
+    pub highlight: Uuid,
+    pub tag: Uuid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, diesel_derive_enum::DbEnum)]
+#[ExistingTypePath = "crate::schema::sql_types::ImportStatus"]
+pub enum ImportStatus {
+    Preview,
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+impl Display for ImportStatus {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ImportStatus::Preview => write!(f, "preview"),
+            ImportStatus::Queued => write!(f, "queued"),
+            ImportStatus::Running => write!(f, "running"),
+            ImportStatus::Completed => write!(f, "completed"),
+            ImportStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// An import job, without the uploaded file which is only loaded for processing.
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = crate::schema::import_jobs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+pub struct ImportJob {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub source: String,
+    pub status: ImportStatus,
+    pub options: serde_json::Value,
+    pub total_rows: i32,
+    pub processed_rows: i32,
+    pub added: i32,
+    pub merged: i32,
+    pub skipped: i32,
+    pub failed: i32,
+    pub undated_reads: i32,
+    pub error: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub started_at: Option<chrono::NaiveDateTime>,
+    pub finished_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::import_jobs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewImportJob {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub source: String,
+    pub status: ImportStatus,
+    pub options: serde_json::Value,
+    pub payload: Option<Vec<u8>>,
+}
+
+#[derive(Queryable, Selectable, Insertable, serde::Serialize, serde::Deserialize)]
+#[diesel(table_name = crate::schema::import_templates)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+pub struct ImportTemplate {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub name: String,
+    pub mapping: serde_json::Value,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::feed_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+pub struct FeedToken {
+    pub id: Uuid,
+    pub user: Uuid,
+    /// The sha256 hash of the token, the token itself isn't stored.
+    pub key_hash: String,
+    pub name: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::import_job_errors)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(ImportJob))]
+pub struct ImportJobError {
+    pub id: Uuid,
+    pub job: Uuid,
+    pub row: i32,
+    pub message: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::public_feeds)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+pub struct PublicFeed {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub enabled: bool,
+    pub title: Option<String>,
+    pub shelves: Vec<Option<Uuid>>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::koreader_accounts)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+pub struct KoreaderAccount {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub username: String,
+    pub key_hash: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::koreader_documents)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+#[diesel(belongs_to(Book))]
+pub struct KoreaderDocument {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub document: String,
+    pub book: Option<Uuid>,
+    pub progress: String,
+    pub percentage: f64,
+    pub device: Option<String>,
+    pub device_id: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::api_keys)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub name: Option<String>,
+    pub key_hash: String,
+    pub scopes: Vec<Option<String>>,
+    pub last_used_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::citation_keys)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+pub struct CitationKey {
+    pub user: Uuid,
+    /// The ISBN or title and author the key was assigned to, see `books::work_key`.
+    pub work: String,
+    pub key: String,
+    pub created_at: chrono::NaiveDateTime,
+}
@@ -0,0 +1,435 @@
+use crate::auth::AuthUser;
+use crate::db::connect;
+use crate::models::{Book, Note, Reading};
+use crate::schema::books::dsl::books;
+use crate::schema::notes::dsl::notes;
+use crate::schema::readings::dsl::readings;
+use crate::{schema, ErrorResponse};
+use axum::http::header;
+use axum::response::Response;
+use axum::routing::post;
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/notes", post(list_notes))
+        .route("/api/notes/create", post(create_note))
+        .route("/api/notes/update", post(update_note))
+        .route("/api/notes/remove", post(remove_note))
+        .route("/api/notes/search", post(search_notes))
+        .route("/api/notes/export", post(export_notes))
+}
+
+/// Adds a note to a book, optionally anchored to a reading and a page.
+pub fn add_note(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    book_id: Uuid,
+    reading_id: Option<Uuid>,
+    page: Option<i32>,
+    body: &str,
+) -> QueryResult<Uuid> {
+    let now = chrono::Utc::now().naive_utc();
+    let note = Note {
+        id: Uuid::new_v4(),
+        user: user_id,
+        book: book_id,
+        reading: reading_id,
+        page,
+        body: body.trim().to_string(),
+        created_at: now,
+        updated_at: now,
+        source_hash: None,
+    };
+    diesel::insert_into(notes).values(&note).execute(connection)?;
+    Ok(note.id)
+}
+
+/// Loads the notes of a reading session, ordered by the time they were written.
+pub fn load_reading_notes(connection: &mut PgConnection, reading_id: Uuid) -> QueryResult<Vec<serde_json::Value>> {
+    Ok(notes
+        .filter(schema::notes::dsl::reading.eq(reading_id))
+        .order(schema::notes::dsl::created_at.asc())
+        .load::<Note>(connection)?
+        .iter()
+        .map(note_json)
+        .collect())
+}
+
+/// Builds a pattern matching the query as a substring with ILIKE.
+fn like_pattern(query: &str) -> String {
+    let escaped = query
+        .trim()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+fn note_json(note: &Note) -> serde_json::Value {
+    json!({
+        "id": note.id.to_string(),
+        "book_id": note.book.to_string(),
+        "reading_id": note.reading.map(|r| r.to_string()),
+        "page": note.page,
+        "body": note.body,
+        "created_at": note.created_at.to_string(),
+        "updated_at": note.updated_at.to_string(),
+    })
+}
+
+/// Renders notes grouped by their book as a Markdown document.
+///
+/// The notes are expected to be ordered by book.
+fn render_markdown(entries: &[(Note, Book)]) -> String {
+    let mut markdown = String::from("# Notes\n");
+    let mut current_book = None;
+    for (note, book) in entries {
+        if current_book != Some(book.id) {
+            current_book = Some(book.id);
+            markdown.push_str(&format!("\n## {}", book.title.as_deref().unwrap_or("Untitled")));
+            if let Some(author) = book.author.as_deref().filter(|a| !a.is_empty()) {
+                markdown.push_str(&format!(" — {}", author));
+            }
+            markdown.push('\n');
+        }
+
+        markdown.push_str(&format!("\n### {}", note.created_at.format("%Y-%m-%d %H:%M")));
+        if let Some(page) = note.page {
+            markdown.push_str(&format!(", page {}", page));
+        }
+        markdown.push_str(&format!("\n\n{}\n", note.body.trim()));
+    }
+    markdown
+}
+
+/// Request type for listing notes.
+#[derive(Debug, Deserialize)]
+pub struct ListNotesRequest {
+    pub book_id: Option<String>,
+    pub reading_id: Option<String>,
+}
+
+/// Lists the notes of a user, newest first.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `book_id`: Optional, the UUID of the book to list the notes of.
+/// - `reading_id`: Optional, the UUID of the reading session to list the notes of.
+pub(crate) async fn list_notes(
+    auth: AuthUser,
+    Json(payload): Json<ListNotesRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let book_id = match payload.book_id.as_deref().map(Uuid::parse_str).transpose() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+    };
+
+    let reading_id = match payload.reading_id.as_deref().map(Uuid::parse_str).transpose() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid reading ID.".to_string() }))),
+    };
+
+    let mut query = notes
+        .filter(schema::notes::dsl::user.eq(auth.0))
+        .order(schema::notes::dsl::created_at.desc())
+        .into_boxed();
+    if let Some(book_id) = book_id {
+        query = query.filter(schema::notes::dsl::book.eq(book_id));
+    }
+    if let Some(reading_id) = reading_id {
+        query = query.filter(schema::notes::dsl::reading.eq(reading_id));
+    }
+
+    match query.load::<Note>(connection) {
+        Ok(results) => (StatusCode::OK, Json(json!({ "notes": results.iter().map(note_json).collect::<Vec<_>>() }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading notes: {}", e) }))),
+    }
+}
+
+/// Request type for creating a note.
+#[derive(Debug, Deserialize)]
+pub struct CreateNoteRequest {
+    pub book_id: String,
+    pub reading_id: Option<String>,
+    pub page: Option<i32>,
+    pub body: String,
+}
+
+/// Creates a private note on a book.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `book_id`: The UUID of the book the note is about.
+/// - `reading_id`: Optional, the UUID of the reading session the note was written during.
+/// - `page`: Optional, the page the note refers to.
+/// - `body`: The text of the note.
+pub(crate) async fn create_note(
+    auth: AuthUser,
+    Json(payload): Json<CreateNoteRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let book_id = match Uuid::parse_str(&payload.book_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+    };
+
+    let reading_id = match payload.reading_id.as_deref().map(Uuid::parse_str).transpose() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid reading ID.".to_string() }))),
+    };
+
+    if payload.body.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Note must not be empty.".to_string() })));
+    }
+
+    let book: Book = match books
+        .filter(schema::books::dsl::id.eq(book_id))
+        .first(connection)
+    {
+        Ok(b) => b,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Book not found.".to_string() }))),
+    };
+
+    if book.user != auth.0 {
+        return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
+    }
+
+    if let Some(reading_id) = reading_id {
+        let reading: Reading = match readings
+            .filter(schema::readings::dsl::id.eq(reading_id))
+            .first(connection)
+        {
+            Ok(r) => r,
+            Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Reading not found.".to_string() }))),
+        };
+
+        if reading.book != book_id {
+            return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Reading does not belong to the book.".to_string() })));
+        }
+    }
+
+    match add_note(connection, auth.0, book_id, reading_id, payload.page, &payload.body) {
+        Ok(id) => (StatusCode::CREATED, Json(json!({ "message": "Note created successfully.", "id": id.to_string() }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while creating the note: {}", e) }))),
+    }
+}
+
+/// Request type for updating a note.
+#[derive(Debug, Deserialize)]
+pub struct UpdateNoteRequest {
+    pub note_id: String,
+    pub page: Option<i32>,
+    pub body: String,
+}
+
+/// Updates the text and page of a note.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `note_id`: The UUID of the note to update.
+/// - `page`: Optional, the page the note refers to.
+/// - `body`: The new text of the note.
+pub(crate) async fn update_note(
+    auth: AuthUser,
+    Json(payload): Json<UpdateNoteRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let note_id = match Uuid::parse_str(&payload.note_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid note ID.".to_string() }))),
+    };
+
+    if payload.body.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Note must not be empty.".to_string() })));
+    }
+
+    match diesel::update(
+        notes
+            .filter(schema::notes::dsl::id.eq(note_id))
+            .filter(schema::notes::dsl::user.eq(auth.0)),
+    )
+    .set((
+        schema::notes::dsl::page.eq(payload.page),
+        schema::notes::dsl::body.eq(payload.body.trim()),
+    ))
+    .execute(connection)
+    {
+        Ok(0) => (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Note not found.".to_string() }))),
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Note updated successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while updating the note: {}", e) }))),
+    }
+}
+
+/// Request type for removing a note.
+#[derive(Debug, Deserialize)]
+pub struct RemoveNoteRequest {
+    pub note_id: String,
+}
+
+/// Removes a note.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `note_id`: The UUID of the note to remove.
+pub(crate) async fn remove_note(
+    auth: AuthUser,
+    Json(payload): Json<RemoveNoteRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let note_id = match Uuid::parse_str(&payload.note_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid note ID.".to_string() }))),
+    };
+
+    match diesel::delete(
+        notes
+            .filter(schema::notes::dsl::id.eq(note_id))
+            .filter(schema::notes::dsl::user.eq(auth.0)),
+    )
+    .execute(connection)
+    {
+        Ok(0) => (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Note not found.".to_string() }))),
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Note removed successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while removing the note: {}", e) }))),
+    }
+}
+
+/// Request type for searching notes.
+#[derive(Debug, Deserialize)]
+pub struct SearchNotesRequest {
+    pub query: String,
+}
+
+/// Searches the notes of a user for the given text, ignoring case.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `query`: The text to search for.
+pub(crate) async fn search_notes(
+    auth: AuthUser,
+    Json(payload): Json<SearchNotesRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    if payload.query.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Search query must not be empty.".to_string() })));
+    }
+
+    let results = match notes
+        .inner_join(books)
+        .filter(schema::notes::dsl::user.eq(auth.0))
+        .filter(schema::notes::dsl::body.ilike(like_pattern(&payload.query)))
+        .order(schema::notes::dsl::created_at.desc())
+        .select((Note::as_select(), Book::as_select()))
+        .load::<(Note, Book)>(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error searching notes: {}", e) }))),
+    };
+
+    let mut json_notes = Vec::new();
+    for (note, book) in results {
+        let mut json_note = note_json(&note);
+        json_note["book_title"] = json!(book.title);
+        json_note["book_author"] = json!(book.author);
+        json_notes.push(json_note);
+    }
+
+    (StatusCode::OK, Json(json!({ "notes": json_notes })))
+}
+
+/// Request type for exporting notes.
+#[derive(Debug, Deserialize)]
+pub struct ExportNotesRequest {
+    pub book_id: Option<String>,
+}
+
+/// Exports the notes of a user as a Markdown document, grouped by book.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `book_id`: Optional, the UUID of the book to export the notes of. All notes are exported
+///   otherwise.
+pub(crate) async fn export_notes(
+    auth: AuthUser,
+    Json(payload): Json<ExportNotesRequest>,
+) -> Response {
+    let connection = &mut connect();
+
+    let book_id = match payload.book_id.as_deref().map(Uuid::parse_str).transpose() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))).into_response(),
+    };
+
+    let mut query = notes
+        .inner_join(books)
+        .filter(schema::notes::dsl::user.eq(auth.0))
+        .order((
+            schema::books::dsl::title.asc(),
+            schema::books::dsl::id.asc(),
+            schema::notes::dsl::created_at.asc(),
+        ))
+        .select((Note::as_select(), Book::as_select()))
+        .into_boxed();
+    if let Some(book_id) = book_id {
+        query = query.filter(schema::notes::dsl::book.eq(book_id));
+    }
+
+    match query.load::<(Note, Book)>(connection) {
+        Ok(results) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "text/markdown; charset=utf-8"),
+                (header::CONTENT_DISPOSITION, "attachment; filename=\"notes.md\""),
+            ],
+            render_markdown(&results),
+        )
+            .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading notes: {}", e) }))).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[test]
+    fn test_like_pattern_escapes_wildcards() {
+        assert_eq!(like_pattern(" 100% "), "%100\\%%");
+        assert_eq!(like_pattern("snake_case"), "%snake\\_case%");
+    }
+
+    #[tokio::test]
+    async fn test_list_notes_requires_auth() {
+        let app = Router::new().route("/api/notes", post(list_notes));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/notes").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_search_notes_requires_auth() {
+        let app = Router::new().route("/api/notes/search", post(search_notes));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/notes/search").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_export_notes_requires_auth() {
+        let app = Router::new().route("/api/notes/export", post(export_notes));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/notes/export").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+}
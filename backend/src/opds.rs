@@ -0,0 +1,568 @@
+use crate::atom_exporter::{escape_xml, format_timestamp};
+use crate::books::book_page;
+use crate::covers::{serve_cover, CoverQuery, CoverSize};
+use crate::db::connect;
+use crate::exports::{load_books, LibraryBook};
+use crate::feeds::authenticate_feed;
+use crate::models::{BookCover, ContributorRole, Shelf};
+use crate::shelves::load_shelves;
+use crate::{schema, ErrorResponse};
+use axum::extract::{Path, Query};
+use axum::http::{header, HeaderMap};
+use axum::response::Response;
+use axum::routing::get;
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use chrono::NaiveDateTime;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use image::ImageFormat;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+const NAVIGATION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+const ACQUISITION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+const OPDS2_TYPE: &str = "application/opds+json";
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/feeds/{token}/opds", get(opds_shelves))
+        .route("/api/feeds/{token}/opds/shelves/{shelf_id}", get(opds_shelf_books))
+        .route("/api/feeds/{token}/opds/v2", get(opds2_shelves))
+        .route("/api/feeds/{token}/opds/v2/shelves/{shelf_id}", get(opds2_shelf_books))
+        .route("/api/feeds/{token}/covers/{book_id}", get(feed_cover))
+}
+
+/// A shelf along with the number of books on it.
+pub struct CatalogShelf {
+    pub shelf: Shelf,
+    pub books: i64,
+}
+
+/// A book of an acquisition feed.
+pub struct CatalogBook {
+    pub book: LibraryBook,
+    /// Whether a cover was uploaded for the book, which is served through the feed token.
+    pub has_cover: bool,
+}
+
+/// Links to the cover of a book and its thumbnail.
+struct CoverLinks {
+    image: String,
+    thumbnail: String,
+    /// Media type of the images, unknown for cover URLs without an image extension.
+    content_type: Option<&'static str>,
+}
+
+/// Guesses the media type of an image from the extension of its URL.
+fn image_type(url: &str) -> Option<&'static str> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let (_, extension) = path.rsplit('/').next()?.rsplit_once('.')?;
+    ImageFormat::from_extension(extension).map(|format| format.to_mime_type())
+}
+
+/// Returns the links of the cover and thumbnail of a book, uploaded covers are preferred over the
+/// cover URL of the book.
+fn cover_links(base: &str, book: &CatalogBook) -> Option<CoverLinks> {
+    if book.has_cover {
+        // Uploaded covers are served as JPEG thumbnails
+        let path = format!("{}/covers/{}", base, book.book.book.id);
+        return Some(CoverLinks {
+            image: format!("{}?size=medium", path),
+            thumbnail: format!("{}?size=small", path),
+            content_type: Some("image/jpeg"),
+        });
+    }
+    book.book.book.cover_url.clone().map(|url| CoverLinks {
+        content_type: image_type(&url),
+        image: url.clone(),
+        thumbnail: url,
+    })
+}
+
+fn authors(book: &LibraryBook) -> Vec<String> {
+    let authors: Vec<String> = book
+        .contributors
+        .iter()
+        .filter(|contributor| contributor.role == ContributorRole::Author)
+        .map(|contributor| contributor.name.clone())
+        .collect();
+    match (authors.is_empty(), &book.book.author) {
+        (true, Some(author)) => vec![author.clone()],
+        _ => authors,
+    }
+}
+
+fn latest_update(shelves: &[CatalogShelf]) -> NaiveDateTime {
+    shelves
+        .iter()
+        .map(|shelf| shelf.shelf.updated_at)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Writes the OPDS 1.2 navigation feed listing the shelves of a user.
+///
+/// `base` is the path of the feeds of the token, e.g. `/api/feeds/{token}`.
+pub fn navigation_feed(base: &str, user_id: Uuid, shelves: &[CatalogShelf]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:opds=\"http://opds-spec.org/2010/catalog\">\n");
+    xml.push_str(&format!("  <id>urn:uuid:{}</id>\n", user_id));
+    xml.push_str("  <title>Shelves</title>\n");
+    xml.push_str(&format!("  <updated>{}</updated>\n", format_timestamp(latest_update(shelves))));
+    xml.push_str(&format!("  <link rel=\"self\" href=\"{}/opds\" type=\"{}\"/>\n", escape_xml(base), NAVIGATION_TYPE));
+    xml.push_str(&format!("  <link rel=\"start\" href=\"{}/opds\" type=\"{}\"/>\n", escape_xml(base), NAVIGATION_TYPE));
+    for shelf in shelves {
+        let description = match &shelf.shelf.description {
+            Some(description) => format!("{} books. {}", shelf.books, description),
+            None => format!("{} books", shelf.books),
+        };
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <title>{}</title>\n", escape_xml(&shelf.shelf.name)));
+        xml.push_str(&format!("    <id>urn:uuid:{}</id>\n", shelf.shelf.id));
+        xml.push_str(&format!("    <updated>{}</updated>\n", format_timestamp(shelf.shelf.updated_at)));
+        xml.push_str(&format!("    <content type=\"text\">{}</content>\n", escape_xml(&description)));
+        xml.push_str(&format!(
+            "    <link rel=\"subsection\" href=\"{}/opds/shelves/{}\" type=\"{}\"/>\n",
+            escape_xml(base),
+            shelf.shelf.id,
+            ACQUISITION_TYPE
+        ));
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// Writes the OPDS 1.2 acquisition feed of the books on a shelf.
+///
+/// Books are only catalogued, there are no files to download, so entries link to the page of the
+/// book in the app instead.
+pub fn acquisition_feed(base: &str, shelf: &Shelf, books: &[CatalogBook]) -> String {
+    let updated = books
+        .iter()
+        .map(|book| book.book.book.added_at)
+        .chain([shelf.updated_at])
+        .max()
+        .unwrap_or(shelf.updated_at);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/terms/\" xmlns:opds=\"http://opds-spec.org/2010/catalog\">\n");
+    xml.push_str(&format!("  <id>urn:uuid:{}</id>\n", shelf.id));
+    xml.push_str(&format!("  <title>{}</title>\n", escape_xml(&shelf.name)));
+    xml.push_str(&format!("  <updated>{}</updated>\n", format_timestamp(updated)));
+    xml.push_str(&format!(
+        "  <link rel=\"self\" href=\"{}/opds/shelves/{}\" type=\"{}\"/>\n",
+        escape_xml(base),
+        shelf.id,
+        ACQUISITION_TYPE
+    ));
+    xml.push_str(&format!("  <link rel=\"start\" href=\"{}/opds\" type=\"{}\"/>\n", escape_xml(base), NAVIGATION_TYPE));
+    xml.push_str(&format!("  <link rel=\"up\" href=\"{}/opds\" type=\"{}\"/>\n", escape_xml(base), NAVIGATION_TYPE));
+
+    for catalog_book in books {
+        let library_book = &catalog_book.book;
+        let book = &library_book.book;
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <title>{}</title>\n", escape_xml(book.title.as_deref().unwrap_or("Untitled"))));
+        xml.push_str(&format!("    <id>urn:uuid:{}</id>\n", book.id));
+        xml.push_str(&format!("    <updated>{}</updated>\n", format_timestamp(book.added_at)));
+        for author in authors(library_book) {
+            xml.push_str(&format!("    <author><name>{}</name></author>\n", escape_xml(&author)));
+        }
+        if let Some(isbn) = book.isbn13.as_ref().or(book.isbn10.as_ref()) {
+            xml.push_str(&format!("    <dc:identifier>urn:isbn:{}</dc:identifier>\n", escape_xml(isbn)));
+        }
+        if let Some(publisher) = &book.publisher {
+            xml.push_str(&format!("    <dc:publisher>{}</dc:publisher>\n", escape_xml(publisher)));
+        }
+        if let Some(year) = book.published_year {
+            xml.push_str(&format!("    <dc:issued>{}</dc:issued>\n", year));
+        }
+        for tag in &library_book.tags {
+            xml.push_str(&format!("    <category term=\"{0}\" label=\"{0}\"/>\n", escape_xml(tag)));
+        }
+        if let Some(series) = &library_book.series {
+            let summary = match series.position {
+                Some(position) => format!("{} #{}", series.name, position),
+                None => series.name.clone(),
+            };
+            xml.push_str(&format!("    <summary type=\"text\">{}</summary>\n", escape_xml(&summary)));
+        }
+        xml.push_str(&format!("    <link rel=\"alternate\" href=\"{}\" type=\"text/html\"/>\n", escape_xml(&book_page(book.id))));
+        if let Some(links) = cover_links(base, catalog_book) {
+            let content_type = links.content_type.map(|t| format!(" type=\"{}\"", t)).unwrap_or_default();
+            xml.push_str(&format!("    <link rel=\"http://opds-spec.org/image\" href=\"{}\"{}/>\n", escape_xml(&links.image), content_type));
+            xml.push_str(&format!(
+                "    <link rel=\"http://opds-spec.org/image/thumbnail\" href=\"{}\"{}/>\n",
+                escape_xml(&links.thumbnail),
+                content_type
+            ));
+        }
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// Builds the OPDS 2.0 navigation feed listing the shelves of a user.
+pub fn navigation_feed_v2(base: &str, shelves: &[CatalogShelf]) -> Value {
+    json!({
+        "metadata": { "title": "Shelves" },
+        "links": [
+            { "rel": "self", "href": format!("{}/opds/v2", base), "type": OPDS2_TYPE },
+        ],
+        "navigation": shelves
+            .iter()
+            .map(|shelf| json!({
+                "href": format!("{}/opds/v2/shelves/{}", base, shelf.shelf.id),
+                "title": shelf.shelf.name,
+                "type": OPDS2_TYPE,
+                "rel": "subsection",
+                "properties": { "numberOfItems": shelf.books },
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Builds the OPDS 2.0 feed of the publications on a shelf.
+pub fn publications_feed_v2(base: &str, shelf: &Shelf, books: &[CatalogBook]) -> Value {
+    let publications: Vec<Value> = books
+        .iter()
+        .map(|catalog_book| {
+            let library_book = &catalog_book.book;
+            let book = &library_book.book;
+            let mut metadata = json!({
+                "@type": "http://schema.org/Book",
+                "identifier": format!("urn:uuid:{}", book.id),
+                "title": book.title.as_deref().unwrap_or("Untitled"),
+                "modified": format_timestamp(book.added_at),
+            });
+            let authors: Vec<Value> = authors(library_book).into_iter().map(|name| json!({ "name": name })).collect();
+            if !authors.is_empty() {
+                metadata["author"] = json!(authors);
+            }
+            for (key, role) in [
+                ("translator", ContributorRole::Translator),
+                ("editor", ContributorRole::Editor),
+                ("illustrator", ContributorRole::Illustrator),
+                ("narrator", ContributorRole::Narrator),
+            ] {
+                let names: Vec<Value> = library_book
+                    .contributors
+                    .iter()
+                    .filter(|contributor| contributor.role == role)
+                    .map(|contributor| json!({ "name": contributor.name }))
+                    .collect();
+                if !names.is_empty() {
+                    metadata[key] = json!(names);
+                }
+            }
+            if let Some(isbn) = book.isbn13.as_ref().or(book.isbn10.as_ref()) {
+                metadata["identifier"] = json!(format!("urn:isbn:{}", isbn));
+            }
+            if let Some(publisher) = &book.publisher {
+                metadata["publisher"] = json!(publisher);
+            }
+            if let Some(year) = book.published_year {
+                metadata["published"] = json!(year.to_string());
+            }
+            if let Some(pages) = book.page_count.filter(|pages| *pages > 0) {
+                metadata["numberOfPages"] = json!(pages);
+            }
+            if !library_book.tags.is_empty() {
+                metadata["subject"] = json!(library_book.tags);
+            }
+            if let Some(series) = &library_book.series {
+                metadata["belongsTo"] = json!({ "series": [{ "name": series.name, "position": series.position }] });
+            }
+
+            let images: Vec<Value> = cover_links(base, catalog_book)
+                .map(|links| {
+                    let mut images = vec![json!({ "href": links.image }), json!({ "href": links.thumbnail, "rel": "thumbnail" })];
+                    if let Some(content_type) = links.content_type {
+                        images.iter_mut().for_each(|image| image["type"] = json!(content_type));
+                    }
+                    images
+                })
+                .unwrap_or_default();
+            // There are no files to acquire, publications link to the page of the book instead
+            let links = json!([{ "rel": "alternate", "href": book_page(book.id), "type": "text/html" }]);
+            json!({ "metadata": metadata, "links": links, "images": images })
+        })
+        .collect();
+
+    json!({
+        "metadata": { "title": shelf.name, "numberOfItems": books.len() },
+        "links": [
+            { "rel": "self", "href": format!("{}/opds/v2/shelves/{}", base, shelf.id), "type": OPDS2_TYPE },
+            { "rel": "start", "href": format!("{}/opds/v2", base), "type": OPDS2_TYPE },
+        ],
+        "publications": publications,
+    })
+}
+
+/// Loads the shelves of a user along with the number of books on them, ordered by name.
+fn load_catalog_shelves(connection: &mut PgConnection, user_id: Uuid) -> QueryResult<Vec<CatalogShelf>> {
+    let mut shelves = load_shelves(connection, user_id)?;
+    shelves.sort_by_key(|shelf| shelf.name.to_lowercase());
+
+    let counts: HashMap<Uuid, i64> = schema::books::table
+        .filter(schema::books::dsl::user.eq(user_id))
+        .group_by(schema::books::dsl::shelf)
+        .select((schema::books::dsl::shelf, diesel::dsl::count_star()))
+        .load::<(Uuid, i64)>(connection)?
+        .into_iter()
+        .collect();
+
+    Ok(shelves
+        .into_iter()
+        .map(|shelf| CatalogShelf { books: counts.get(&shelf.id).copied().unwrap_or(0), shelf })
+        .collect())
+}
+
+/// Loads a shelf of a user and the books on it.
+fn load_catalog_books(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    shelf_id: Uuid,
+) -> QueryResult<Option<(Shelf, Vec<CatalogBook>)>> {
+    let Some(shelf) = schema::shelves::table
+        .filter(schema::shelves::dsl::id.eq(shelf_id))
+        .filter(schema::shelves::dsl::user.eq(user_id))
+        .first::<Shelf>(connection)
+        .optional()?
+    else {
+        return Ok(None);
+    };
+
+    let book_ids: Vec<Uuid> = schema::books::table
+        .filter(schema::books::dsl::shelf.eq(shelf_id))
+        .select(schema::books::dsl::id)
+        .load(connection)?;
+    let covers: HashSet<Uuid> = schema::book_covers::table
+        .filter(schema::book_covers::dsl::book.eq_any(&book_ids))
+        .select(schema::book_covers::dsl::book)
+        .load::<Uuid>(connection)?
+        .into_iter()
+        .collect();
+
+    let books = load_books(connection, user_id, Some(&book_ids))?
+        .into_iter()
+        .map(|book| CatalogBook { has_cover: covers.contains(&book.book.id), book })
+        .collect();
+    Ok(Some((shelf, books)))
+}
+
+fn xml_response(content_type: &str, xml: String) -> Response {
+    (StatusCode::OK, [(header::CONTENT_TYPE, content_type.to_string())], xml).into_response()
+}
+
+fn json_response(value: Value) -> Response {
+    (StatusCode::OK, [(header::CONTENT_TYPE, OPDS2_TYPE)], value.to_string()).into_response()
+}
+
+/// Serves the shelves of the user as an OPDS 1.2 navigation feed, authenticated by a feed token in
+/// the URL, so that e-reader apps like KOReader can browse them.
+pub(crate) async fn opds_shelves(Path(token): Path<String>) -> Response {
+    let connection = &mut connect();
+
+    let user_id = match authenticate_feed(connection, &token) {
+        Ok(user_id) => user_id,
+        Err(response) => return response.into_response(),
+    };
+
+    match load_catalog_shelves(connection, user_id) {
+        Ok(shelves) => xml_response(NAVIGATION_TYPE, navigation_feed(&format!("/api/feeds/{}", token), user_id, &shelves)),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading shelves: {}", e) }))).into_response(),
+    }
+}
+
+/// Serves the books on a shelf as an OPDS 1.2 acquisition feed with their details and covers.
+pub(crate) async fn opds_shelf_books(Path((token, shelf_id)): Path<(String, Uuid)>) -> Response {
+    let connection = &mut connect();
+
+    let user_id = match authenticate_feed(connection, &token) {
+        Ok(user_id) => user_id,
+        Err(response) => return response.into_response(),
+    };
+
+    match load_catalog_books(connection, user_id, shelf_id) {
+        Ok(Some((shelf, books))) => xml_response(ACQUISITION_TYPE, acquisition_feed(&format!("/api/feeds/{}", token), &shelf, &books)),
+        Ok(None) => (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Shelf not found.".to_string() }))).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading books: {}", e) }))).into_response(),
+    }
+}
+
+/// Serves the shelves of the user as an OPDS 2.0 navigation feed.
+pub(crate) async fn opds2_shelves(Path(token): Path<String>) -> Response {
+    let connection = &mut connect();
+
+    let user_id = match authenticate_feed(connection, &token) {
+        Ok(user_id) => user_id,
+        Err(response) => return response.into_response(),
+    };
+
+    match load_catalog_shelves(connection, user_id) {
+        Ok(shelves) => json_response(navigation_feed_v2(&format!("/api/feeds/{}", token), &shelves)),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading shelves: {}", e) }))).into_response(),
+    }
+}
+
+/// Serves the books on a shelf as an OPDS 2.0 feed of publications.
+pub(crate) async fn opds2_shelf_books(Path((token, shelf_id)): Path<(String, Uuid)>) -> Response {
+    let connection = &mut connect();
+
+    let user_id = match authenticate_feed(connection, &token) {
+        Ok(user_id) => user_id,
+        Err(response) => return response.into_response(),
+    };
+
+    match load_catalog_books(connection, user_id, shelf_id) {
+        Ok(Some((shelf, books))) => json_response(publications_feed_v2(&format!("/api/feeds/{}", token), &shelf, &books)),
+        Ok(None) => (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Shelf not found.".to_string() }))).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading books: {}", e) }))).into_response(),
+    }
+}
+
+/// Serves the cover of a book to apps authenticated by a feed token, like `get_cover` does.
+pub(crate) async fn feed_cover(
+    Path((token, book_id)): Path<(String, Uuid)>,
+    Query(query): Query<CoverQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let connection = &mut connect();
+
+    let user_id = match authenticate_feed(connection, &token) {
+        Ok(user_id) => user_id,
+        Err(response) => return response.into_response(),
+    };
+
+    let cover: BookCover = match schema::book_covers::table
+        .filter(schema::book_covers::dsl::book.eq(book_id))
+        .filter(schema::book_covers::dsl::user.eq(user_id))
+        .first(connection)
+    {
+        Ok(c) => c,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Cover not found.".to_string() }))).into_response(),
+    };
+
+    serve_cover(&cover, query.size.unwrap_or(CoverSize::Medium), &headers).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contributors::ContributorInput;
+    use crate::goodreads_importer::SeriesMarker;
+    use crate::models::Book;
+    use chrono::NaiveDate;
+
+    fn shelf() -> Shelf {
+        let timestamp = NaiveDate::from_ymd_opt(2025, 6, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        Shelf {
+            id: Uuid::nil(),
+            name: "Sci-Fi & Fantasy".to_string(),
+            description: None,
+            user: Uuid::new_v4(),
+            created_at: timestamp,
+            updated_at: timestamp,
+        }
+    }
+
+    fn catalog_book(has_cover: bool) -> CatalogBook {
+        CatalogBook {
+            book: LibraryBook {
+                book: Book {
+                    id: Uuid::max(),
+                    user: Uuid::new_v4(),
+                    shelf: Uuid::nil(),
+                    title: Some("Mort".to_string()),
+                    author: Some("Terry Pratchett".to_string()),
+                    isbn13: Some("9780552131061".to_string()),
+                    isbn10: None,
+                    google_books_id: None,
+                    added_at: NaiveDate::from_ymd_opt(2025, 6, 2).unwrap().and_hms_opt(8, 0, 0).unwrap(),
+                    publisher: Some("Corgi".to_string()),
+                    published_year: Some(1987),
+                    page_count: Some(316),
+                    cover_url: Some("https://example.com/mort.jpg".to_string()),
+                },
+                shelf: "Sci-Fi & Fantasy".to_string(),
+                contributors: vec![
+                    ContributorInput { name: "Terry Pratchett".to_string(), role: ContributorRole::Author },
+                    ContributorInput { name: "Nigel Planer".to_string(), role: ContributorRole::Narrator },
+                ],
+                series: Some(SeriesMarker { name: "Discworld".to_string(), position: Some(4.0) }),
+                tags: vec!["fantasy".to_string()],
+                rating: None,
+                review: None,
+                notes: Vec::new(),
+                reads: Vec::new(),
+            },
+            has_cover,
+        }
+    }
+
+    #[test]
+    fn test_navigation_feed() {
+        let shelves = vec![CatalogShelf { shelf: shelf(), books: 3 }];
+        let xml = navigation_feed("/api/feeds/abc", Uuid::nil(), &shelves);
+        assert!(xml.contains("<title>Sci-Fi &amp; Fantasy</title>"));
+        assert!(xml.contains("<content type=\"text\">3 books</content>"));
+        assert!(xml.contains(&format!(
+            "<link rel=\"subsection\" href=\"/api/feeds/abc/opds/shelves/{}\" type=\"{}\"/>",
+            Uuid::nil(),
+            ACQUISITION_TYPE
+        )));
+
+        let json = navigation_feed_v2("/api/feeds/abc", &shelves);
+        assert_eq!(json["navigation"][0]["href"], format!("/api/feeds/abc/opds/v2/shelves/{}", Uuid::nil()));
+        assert_eq!(json["navigation"][0]["properties"]["numberOfItems"], 3);
+    }
+
+    #[test]
+    fn test_acquisition_feed() {
+        let xml = acquisition_feed("/api/feeds/abc", &shelf(), &[catalog_book(true)]);
+        assert!(xml.contains("<updated>2025-06-02T08:00:00Z</updated>"));
+        assert!(xml.contains("<author><name>Terry Pratchett</name></author>"));
+        assert!(xml.contains("<dc:identifier>urn:isbn:9780552131061</dc:identifier>"));
+        assert!(xml.contains("<summary type=\"text\">Discworld #4</summary>"));
+        assert!(xml.contains(&format!(
+            "<link rel=\"http://opds-spec.org/image/thumbnail\" href=\"/api/feeds/abc/covers/{}?size=small\" type=\"image/jpeg\"/>",
+            Uuid::max()
+        )));
+
+        assert!(xml.contains(&format!("<link rel=\"alternate\" href=\"/book/{}\" type=\"text/html\"/>", Uuid::max())));
+
+        // Without an uploaded cover, the cover URL of the book is used
+        let xml = acquisition_feed("/api/feeds/abc", &shelf(), &[catalog_book(false)]);
+        assert!(xml.contains("href=\"https://example.com/mort.jpg\" type=\"image/jpeg\""));
+        let mut book = catalog_book(false);
+        book.book.book.cover_url = Some("https://books.google.com/books/content?id=abc&img=1".to_string());
+        let xml = acquisition_feed("/api/feeds/abc", &shelf(), &[book]);
+        assert!(xml.contains("<link rel=\"http://opds-spec.org/image\" href=\"https://books.google.com/books/content?id=abc&amp;img=1\"/>"));
+    }
+
+    #[test]
+    fn test_image_type() {
+        assert_eq!(image_type("https://example.com/covers/mort.PNG?size=large"), Some("image/png"));
+        assert_eq!(image_type("https://example.com/covers/mort.webp"), Some("image/webp"));
+        assert_eq!(image_type("https://books.google.com/books/content?id=a.b"), None);
+        assert_eq!(image_type("https://example.com/covers/mort"), None);
+    }
+
+    #[test]
+    fn test_publications_feed_v2() {
+        let json = publications_feed_v2("/api/feeds/abc", &shelf(), &[catalog_book(false)]);
+        let metadata = &json["publications"][0]["metadata"];
+        assert_eq!(metadata["identifier"], "urn:isbn:9780552131061");
+        assert_eq!(metadata["author"], json!([{ "name": "Terry Pratchett" }]));
+        assert_eq!(metadata["narrator"], json!([{ "name": "Nigel Planer" }]));
+        assert_eq!(metadata["belongsTo"]["series"][0]["position"], 4.0);
+        assert_eq!(json["publications"][0]["images"][0]["href"], "https://example.com/mort.jpg");
+        assert_eq!(json["publications"][0]["images"][1]["type"], "image/jpeg");
+        assert_eq!(json["publications"][0]["links"][0]["href"], format!("/book/{}", Uuid::max()));
+        assert_eq!(json["metadata"]["numberOfItems"], 1);
+    }
+}
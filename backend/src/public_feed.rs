@@ -0,0 +1,342 @@
+use crate::atom_exporter::{write_atom, AtomEntry};
+use crate::auth::AuthUser;
+use crate::books::{book_page, work_key};
+use crate::db::connect;
+use crate::goodreads_exporter::review_to_html;
+use crate::models::{Book, PublicFeed, Reading, Review};
+use crate::schema::public_feeds::dsl::public_feeds;
+use crate::{schema, ErrorResponse};
+use axum::extract::Path;
+use axum::http::header;
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Entries of each kind loaded for a feed, and the number of entries it has at most.
+const FEED_ENTRIES: i64 = 50;
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/feeds/public", post(get_public_feed))
+        .route("/api/feeds/public/save", post(save_public_feed))
+        .route("/api/public/{name}/feed.atom", get(atom_feed))
+}
+
+/// Returns the path of the public feed of a user.
+fn feed_path(name: &str) -> String {
+    format!("/api/public/{}/feed.atom", name)
+}
+
+/// Writes a rating in half stars as stars, e.g. "★★★★½".
+fn format_rating(half_stars: i16) -> String {
+    let half_stars = half_stars.clamp(0, 10) as usize;
+    let mut stars = "★".repeat(half_stars / 2);
+    if half_stars % 2 == 1 {
+        stars.push('½');
+    }
+    stars
+}
+
+/// Replaces the `>!spoiler!<` sections of a review, which readers of a feed can't reveal.
+fn hide_spoilers(review: &str) -> String {
+    let mut hidden = String::with_capacity(review.len());
+    let mut rest = review;
+    while let Some(start) = rest.find(">!") {
+        let Some(length) = rest[start + 2..].find("!<") else {
+            break;
+        };
+        hidden.push_str(&rest[..start]);
+        hidden.push_str("[spoiler]");
+        rest = &rest[start + 2 + length + 2..];
+    }
+    hidden.push_str(rest);
+    hidden
+}
+
+/// Loads the entries of the public feed of a user, latest first.
+///
+/// Finished readings, ratings and reviews and books added to the shelves of the feed each become
+/// an entry with the id of the record, so that they keep their id when they're updated. Finished
+/// readings are dated by when they were finished, so editing them later doesn't move them up.
+///
+/// Copies of a book on several shelves give a single entry: one per date a book was finished, its
+/// latest review and when it was first added to the shelves of the feed.
+fn load_entries(connection: &mut PgConnection, feed: &PublicFeed) -> QueryResult<Vec<AtomEntry>> {
+    let mut entries = Vec::new();
+
+    let finished: Vec<(Reading, Book)> = schema::readings::table
+        .inner_join(schema::books::table)
+        .filter(schema::readings::dsl::user.eq(feed.user))
+        .filter(schema::readings::dsl::finished_at.is_not_null())
+        .order((schema::readings::dsl::finished_at.desc(), schema::readings::dsl::id.asc()))
+        .limit(FEED_ENTRIES)
+        .select((Reading::as_select(), Book::as_select()))
+        .load(connection)?;
+    let mut seen = HashSet::new();
+    for (reading, book) in finished {
+        let Some(finished_at) = reading.finished_at else {
+            continue;
+        };
+        if !seen.insert((work_key(&book), finished_at)) {
+            continue;
+        }
+        let finished_at = finished_at.and_time(chrono::NaiveTime::MIN);
+        entries.push(AtomEntry {
+            id: reading.id,
+            title: format!("Finished {}", book.title.unwrap_or_else(|| "Untitled".to_string())),
+            updated: finished_at,
+            published: Some(finished_at),
+            content: book.author.map(|author| format!("by {}", html_escape(&author))),
+            link: Some(book_page(book.id)),
+        });
+    }
+
+    let reviews: Vec<(Review, Book)> = schema::reviews::table
+        .inner_join(schema::books::table)
+        .filter(schema::reviews::dsl::user.eq(feed.user))
+        .filter(schema::reviews::dsl::rating.is_not_null().or(schema::reviews::dsl::body.is_not_null()))
+        .order(schema::reviews::dsl::updated_at.desc())
+        .limit(FEED_ENTRIES)
+        .select((Review::as_select(), Book::as_select()))
+        .load(connection)?;
+    let mut seen = HashSet::new();
+    for (review, book) in reviews {
+        if !seen.insert(work_key(&book)) {
+            continue;
+        }
+        let title = book.title.unwrap_or_else(|| "Untitled".to_string());
+        let rating = review.rating;
+        let body = review.body.filter(|body| !body.trim().is_empty());
+        let mut content = Vec::new();
+        if let Some(rating) = rating {
+            content.push(format!("<p>{}</p>", format_rating(rating)));
+        }
+        if let Some(body) = &body {
+            content.push(format!("<p>{}</p>", review_to_html(&hide_spoilers(body))));
+        }
+        entries.push(AtomEntry {
+            id: review.id,
+            title: match (&body, rating) {
+                (Some(_), _) => format!("Reviewed {}", title),
+                (None, Some(rating)) => format!("Rated {} {}", title, format_rating(rating)),
+                (None, None) => title,
+            },
+            updated: review.updated_at,
+            published: Some(review.created_at),
+            content: Some(content.join("")),
+            link: Some(book_page(book.id)),
+        });
+    }
+
+    let shelf_ids: Vec<Uuid> = feed.shelves.iter().flatten().copied().collect();
+    if !shelf_ids.is_empty() {
+        let added: Vec<(Book, String)> = schema::books::table
+            .inner_join(schema::shelves::table)
+            .filter(schema::books::dsl::user.eq(feed.user))
+            .filter(schema::books::dsl::shelf.eq_any(&shelf_ids))
+            .order(schema::books::dsl::added_at.desc())
+            .limit(FEED_ENTRIES)
+            .select((Book::as_select(), schema::shelves::dsl::name))
+            .load(connection)?;
+        let mut seen = HashSet::new();
+        for (book, shelf) in added.into_iter().rev() {
+            if !seen.insert(work_key(&book)) {
+                continue;
+            }
+            entries.push(AtomEntry {
+                id: book.id,
+                title: format!("Added {} to {}", book.title.unwrap_or_else(|| "Untitled".to_string()), shelf),
+                updated: book.added_at,
+                published: Some(book.added_at),
+                content: book.author.map(|author| format!("by {}", html_escape(&author))),
+                link: Some(book_page(book.id)),
+            });
+        }
+    }
+
+    entries.sort_by(|a, b| b.updated.cmp(&a.updated).then(a.id.cmp(&b.id)));
+    entries.truncate(FEED_ENTRIES as usize);
+    Ok(entries)
+}
+
+/// Escapes text for the HTML content of entries.
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Fetches the settings of the public feed of the user.
+pub(crate) async fn get_public_feed(auth: AuthUser) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let name: String = match schema::users::table
+        .find(auth.0)
+        .select(schema::users::dsl::name)
+        .first(connection)
+    {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading the user: {}", e) }))),
+    };
+
+    let feed = match public_feeds
+        .filter(schema::public_feeds::dsl::user.eq(auth.0))
+        .first::<PublicFeed>(connection)
+        .optional()
+    {
+        Ok(f) => f,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading the public feed: {}", e) }))),
+    };
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "enabled": feed.as_ref().is_some_and(|feed| feed.enabled),
+            "title": feed.as_ref().and_then(|feed| feed.title.clone()),
+            "shelf_ids": feed
+                .map(|feed| feed.shelves.into_iter().flatten().map(|id| id.to_string()).collect())
+                .unwrap_or_else(Vec::new),
+            "path": feed_path(&name),
+        })),
+    )
+}
+
+/// Request type for saving the settings of the public feed.
+#[derive(Debug, Deserialize)]
+pub struct SavePublicFeedRequest {
+    pub enabled: bool,
+    pub title: Option<String>,
+    pub shelf_ids: Option<Vec<String>>,
+}
+
+/// Publishes the reading log of the user as an Atom feed at `/api/public/{name}/feed.atom`, or
+/// stops publishing it.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `enabled`: Whether the feed is published.
+/// - `title`: Optional, the title of the feed, "{name}'s reading" by default.
+/// - `shelf_ids`: Optional, the shelves whose newly added books are published.
+///
+/// The feed lists finished readings, ratings and reviews, with spoilers hidden, and the books
+/// added to the chosen shelves. Anyone can read it while it's enabled.
+pub(crate) async fn save_public_feed(
+    auth: AuthUser,
+    Json(payload): Json<SavePublicFeedRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let shelf_ids: Vec<Uuid> = match payload
+        .shelf_ids
+        .unwrap_or_default()
+        .iter()
+        .map(|id| Uuid::parse_str(id))
+        .collect()
+    {
+        Ok(ids) => ids,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid shelf ID.".to_string() }))),
+    };
+
+    let owned: i64 = match schema::shelves::table
+        .filter(schema::shelves::dsl::id.eq_any(&shelf_ids))
+        .filter(schema::shelves::dsl::user.eq(auth.0))
+        .count()
+        .get_result(connection)
+    {
+        Ok(c) => c,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading shelves: {}", e) }))),
+    };
+    if owned as usize != shelf_ids.len() {
+        return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Shelf not found.".to_string() })));
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let feed = PublicFeed {
+        id: Uuid::new_v4(),
+        user: auth.0,
+        enabled: payload.enabled,
+        title: payload.title.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()),
+        shelves: shelf_ids.into_iter().map(Some).collect(),
+        created_at: now,
+        updated_at: now,
+    };
+
+    match diesel::insert_into(public_feeds)
+        .values(&feed)
+        .on_conflict(schema::public_feeds::dsl::user)
+        .do_update()
+        .set((
+            schema::public_feeds::dsl::enabled.eq(feed.enabled),
+            schema::public_feeds::dsl::title.eq(&feed.title),
+            schema::public_feeds::dsl::shelves.eq(&feed.shelves),
+        ))
+        .execute(connection)
+    {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Public feed saved successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while saving the public feed: {}", e) }))),
+    }
+}
+
+/// Serves the public Atom feed of a user, if they published it.
+pub(crate) async fn atom_feed(Path(name): Path<String>) -> Response {
+    let connection = &mut connect();
+
+    let feed = match schema::users::table
+        .inner_join(public_feeds)
+        .filter(schema::users::dsl::name.eq(&name))
+        .filter(schema::public_feeds::dsl::enabled.eq(true))
+        .select(PublicFeed::as_select())
+        .first::<PublicFeed>(connection)
+        .optional()
+    {
+        Ok(Some(f)) => f,
+        Ok(None) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Feed not found.".to_string() }))).into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading the feed: {}", e) }))).into_response(),
+    };
+
+    let entries = match load_entries(connection, &feed) {
+        Ok(e) => e,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading the feed: {}", e) }))).into_response(),
+    };
+
+    let title = feed.title.clone().unwrap_or_else(|| format!("{}'s reading", name));
+    let xml = write_atom(feed.id, &title, &name, &feed_path(&name), feed.updated_at, &entries);
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+        xml,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_public_feed_requires_auth() {
+        let app = Router::new().route("/api/feeds/public/save", post(save_public_feed));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/feeds/public/save").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_format_rating() {
+        assert_eq!(format_rating(9), "★★★★½");
+        assert_eq!(format_rating(4), "★★");
+        assert_eq!(format_rating(0), "");
+    }
+
+    #[test]
+    fn test_hide_spoilers() {
+        assert_eq!(hide_spoilers("The end: >!everybody dies!< Great."), "The end: [spoiler] Great.");
+        assert_eq!(hide_spoilers("No >!closing"), "No >!closing");
+    }
+}
@@ -1,11 +1,13 @@
 use crate::auth::AuthUser;
 use crate::db::connect;
-use crate::models::{Reading, ReadingEntry, ReadingMode};
+use crate::models::{Book, Reading, ReadingEntry, ReadingMode};
+use crate::notes::{add_note, load_reading_notes};
 use crate::schema::reading_entries::dsl::reading_entries;
 use crate::schema::readings::dsl::readings;
 use crate::{schema, ErrorResponse};
 use axum::routing::post;
 use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use serde::Deserialize;
 use serde_json::json;
@@ -16,6 +18,204 @@ pub(crate) fn register_routes(router: Router) -> Router {
         .route("/api/books/reading", post(get_reading_info))
         .route("/api/books/start-reading", post(start_reading_session))
         .route("/api/books/track-progress", post(track_progress))
+        .route("/api/books/reading/deadlines", post(set_reading_deadlines))
+}
+
+/// A reading session which was already finished, only some services know when it started.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReadDates {
+    pub started_at: Option<chrono::NaiveDate>,
+    pub finished_at: chrono::NaiveDate,
+}
+
+/// A reading session which wasn't finished, it's ongoing unless the book was given up on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnfinishedRead {
+    pub started_at: Option<chrono::NaiveDate>,
+    pub cancelled_at: Option<chrono::NaiveDate>,
+}
+
+/// Returns the mode and page count of an imported reading session, sessions of books without a
+/// page count are tracked as a percentage rather than in pages out of 0.
+fn imported_mode(mode: ReadingMode, total_pages: Option<i32>) -> (ReadingMode, i32) {
+    match total_pages.filter(|pages| *pages > 0) {
+        Some(total_pages) => (mode, total_pages),
+        None => (ReadingMode::Percentage, 0),
+    }
+}
+
+/// Records a reading session which was already finished, e.g. when importing the reading history
+/// from another service.
+///
+/// Sessions tracked in pages end at the last page, those tracked as a percentage at 100%.
+pub fn record_finished_reading(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    book_id: Uuid,
+    mode: ReadingMode,
+    total_pages: Option<i32>,
+    dates: ReadDates,
+) -> QueryResult<Uuid> {
+    let now = chrono::Utc::now().naive_utc();
+    let (mode, total_pages) = imported_mode(mode, total_pages);
+    let progress = match mode {
+        ReadingMode::Pages => total_pages,
+        ReadingMode::Percentage => 100,
+    };
+    let reading = Reading {
+        id: Uuid::new_v4(),
+        book: book_id,
+        user: user_id,
+        total_pages,
+        progress,
+        mode,
+        started_at: dates.started_at,
+        finished_at: Some(dates.finished_at),
+        cancelled_at: None,
+        created_at: now,
+        updated_at: now,
+        target_finish_at: None,
+        due_at: None,
+    };
+    diesel::insert_into(readings).values(&reading).execute(connection)?;
+
+    if progress > 0 {
+        let entry = ReadingEntry {
+            id: Uuid::new_v4(),
+            reading: reading.id,
+            book: book_id,
+            user: user_id,
+            progress,
+            mode,
+            read_at: dates.finished_at,
+            created_at: now,
+            updated_at: now,
+        };
+        diesel::insert_into(reading_entries).values(&entry).execute(connection)?;
+    }
+    Ok(reading.id)
+}
+
+/// Records a reading session which wasn't finished when importing it from another service, the
+/// services don't export how far the book was read.
+pub fn record_unfinished_reading(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    book_id: Uuid,
+    mode: ReadingMode,
+    total_pages: Option<i32>,
+    read: UnfinishedRead,
+) -> QueryResult<Uuid> {
+    let now = chrono::Utc::now().naive_utc();
+    let (mode, total_pages) = imported_mode(mode, total_pages);
+    let reading = Reading {
+        id: Uuid::new_v4(),
+        book: book_id,
+        user: user_id,
+        total_pages,
+        progress: 0,
+        mode,
+        started_at: read.started_at,
+        finished_at: None,
+        cancelled_at: read.cancelled_at,
+        created_at: now,
+        updated_at: now,
+        target_finish_at: None,
+        due_at: None,
+    };
+    diesel::insert_into(readings).values(&reading).execute(connection)?;
+    Ok(reading.id)
+}
+
+/// Returns the ongoing reading session of a book, starting one tracked in the given mode if there's
+/// none, e.g. when progress is reported by a device rather than tracked in the app.
+pub fn active_reading(connection: &mut PgConnection, user_id: Uuid, book: &Book, mode: ReadingMode) -> QueryResult<Reading> {
+    let active = readings
+        .filter(schema::readings::dsl::book.eq(book.id))
+        .filter(schema::readings::dsl::user.eq(user_id))
+        .filter(schema::readings::dsl::finished_at.is_null())
+        .filter(schema::readings::dsl::cancelled_at.is_null())
+        .order(schema::readings::dsl::started_at.desc().nulls_last())
+        .first::<Reading>(connection)
+        .optional()?;
+    if let Some(reading) = active {
+        return Ok(reading);
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let reading = Reading {
+        id: Uuid::new_v4(),
+        book: book.id,
+        user: user_id,
+        total_pages: book.page_count.unwrap_or(0),
+        progress: 0,
+        mode,
+        started_at: Some(now.date()),
+        finished_at: None,
+        cancelled_at: None,
+        created_at: now,
+        updated_at: now,
+        target_finish_at: None,
+        due_at: None,
+    };
+    diesel::insert_into(readings).values(&reading).execute(connection)?;
+    Ok(reading)
+}
+
+/// Converts progress between pages and percentages, which needs the page count of the book.
+pub fn convert_progress(progress: i32, from: ReadingMode, to: ReadingMode, total_pages: i32) -> Option<i32> {
+    match (from, to) {
+        (from, to) if from == to => Some(progress),
+        _ if total_pages <= 0 => None,
+        (ReadingMode::Percentage, ReadingMode::Pages) => Some((progress.clamp(0, 100) as f64 / 100.0 * total_pages as f64).round() as i32),
+        (ReadingMode::Pages, ReadingMode::Percentage) => Some((progress as f64 / total_pages as f64 * 100.0).round().clamp(0.0, 100.0) as i32),
+        _ => None,
+    }
+}
+
+/// Tracks progress of a reading session read on a day, optionally with a journal note.
+///
+/// The entry keeps the mode progress was reported in, the session itself is moved to the progress in
+/// its own mode if it can be converted.
+pub fn record_progress(
+    connection: &mut PgConnection,
+    reading: &Reading,
+    progress: i32,
+    mode: ReadingMode,
+    read_at: chrono::NaiveDate,
+    note: Option<&str>,
+) -> QueryResult<()> {
+    let now = chrono::Utc::now().naive_utc();
+    let new_entry = ReadingEntry {
+        id: Uuid::new_v4(),
+        reading: reading.id,
+        book: reading.book,
+        user: reading.user,
+        progress,
+        mode,
+        read_at,
+        created_at: now,
+        updated_at: now,
+    };
+
+    connection.transaction(|connection| {
+        diesel::insert_into(reading_entries)
+            .values(&new_entry)
+            .execute(connection)?;
+
+        if let Some(reading_progress) = convert_progress(progress, mode, reading.mode, reading.total_pages) {
+            diesel::update(readings.filter(schema::readings::dsl::id.eq(reading.id)))
+                .set(schema::readings::dsl::progress.eq(reading_progress))
+                .execute(connection)?;
+        }
+
+        if let Some(note) = note.filter(|n| !n.trim().is_empty()) {
+            let page = convert_progress(progress, mode, ReadingMode::Pages, reading.total_pages);
+            add_note(connection, reading.user, reading.book, Some(reading.id), page, note)?;
+        }
+
+        Ok(())
+    })
 }
 
 /// Request type for getting information about a reading session.
@@ -59,6 +259,11 @@ pub(crate) async fn get_reading_info(
         Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading entries: {}", e) }))),
     };
 
+    let json_notes = match load_reading_notes(connection, reading_id) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading notes: {}", e) }))),
+    };
+
     let mut json_entries = Vec::new();
     for entry in db_entries {
         let json_entry = json!({
@@ -74,7 +279,10 @@ pub(crate) async fn get_reading_info(
         StatusCode::OK,
         Json(json!({
             "book_id": reading.book.to_string(),
+            "target_finish_at": reading.target_finish_at.map(|d| d.to_string()),
+            "due_at": reading.due_at.map(|d| d.to_string()),
             "entries": json_entries,
+            "notes": json_notes,
         })),
     )
 }
@@ -84,6 +292,16 @@ pub(crate) async fn get_reading_info(
 pub struct StartReadingRequest {
     pub book_id: String,
     pub total_pages: i32,
+    pub target_finish_at: Option<String>,
+    pub due_at: Option<String>,
+}
+
+/// Parses an optional date like "2025-06-30", treating empty strings as no date.
+fn parse_optional_date(value: Option<&str>) -> Result<Option<chrono::NaiveDate>, ()> {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        Some(v) => chrono::NaiveDate::parse_from_str(v, "%Y-%m-%d").map(Some).map_err(|_| ()),
+        None => Ok(None),
+    }
 }
 
 /// Starts a new reading session for a book.
@@ -91,6 +309,8 @@ pub struct StartReadingRequest {
 /// This route accepts a JSON payload with the following structure:
 /// - `book_id`: The UUID of the book to start reading.
 /// - `total_pages`: The total number of pages of the book.
+/// - `target_finish_at`: Optional, the date to finish the book by.
+/// - `due_at`: Optional, the date a borrowed copy has to be returned.
 pub(crate) async fn start_reading_session(
     auth: AuthUser,
     Json(payload): Json<StartReadingRequest>,
@@ -101,6 +321,14 @@ pub(crate) async fn start_reading_session(
         Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
     };
 
+    let (target_finish_at, due_at) = match (
+        parse_optional_date(payload.target_finish_at.as_deref()),
+        parse_optional_date(payload.due_at.as_deref()),
+    ) {
+        (Ok(target_finish_at), Ok(due_at)) => (target_finish_at, due_at),
+        _ => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid date format. Use YYYY-MM-DD.".to_string() }))),
+    };
+
     let book: crate::models::Book = match crate::schema::books::dsl::books
         .filter(crate::schema::books::dsl::id.eq(book_id))
         .first(connection)
@@ -120,11 +348,13 @@ pub(crate) async fn start_reading_session(
         total_pages: payload.total_pages,
         progress: 0,
         mode: ReadingMode::Pages,
-        started_at: chrono::Utc::now().date_naive(),
+        started_at: Some(chrono::Utc::now().date_naive()),
         finished_at: None,
         cancelled_at: None,
         updated_at: chrono::Utc::now().naive_utc(),
         created_at: chrono::Utc::now().naive_utc(),
+        target_finish_at,
+        due_at,
     };
 
     match diesel::insert_into(schema::readings::dsl::readings)
@@ -142,6 +372,7 @@ pub struct TrackProgressRequest {
     pub reading_id: String,
     pub progress: i32,
     pub read_at: String,
+    pub note: Option<String>,
 }
 
 /// Tracks progress for a reading session.
@@ -150,6 +381,7 @@ pub struct TrackProgressRequest {
 /// - `reading_id`: The UUID of the reading session.
 /// - `progress`: The page number reached.
 /// - `read_at`: The date when reading took place.
+/// - `note`: Optional, a journal note written at the page reached.
 pub(crate) async fn track_progress(
     auth: AuthUser,
     Json(payload): Json<TrackProgressRequest>,
@@ -174,36 +406,71 @@ pub(crate) async fn track_progress(
         return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
     }
 
-    let new_entry = ReadingEntry {
-        id: Uuid::new_v4(),
-        reading: reading_id,
-        book: reading.book,
-        user: auth.0,
-        progress: payload.progress,
-        mode: ReadingMode::Pages,
-        read_at: match chrono::NaiveDate::parse_from_str(&payload.read_at, "%Y-%m-%d") {
-            Ok(d) => d,
-            Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid date format. Use YYYY-MM-DD.".to_string() }))),
-        },
-        created_at: chrono::Utc::now().naive_utc(),
-        updated_at: chrono::Utc::now().naive_utc(),
+    let read_at = match chrono::NaiveDate::parse_from_str(&payload.read_at, "%Y-%m-%d") {
+        Ok(d) => d,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid date format. Use YYYY-MM-DD.".to_string() }))),
     };
 
-    let transaction_result = connection.transaction::<_, diesel::result::Error, _>(|connection| {
-        diesel::insert_into(reading_entries)
-            .values(&new_entry)
-            .execute(connection)?;
+    match record_progress(connection, &reading, payload.progress, ReadingMode::Pages, read_at, payload.note.as_deref()) {
+        Ok(_) => (StatusCode::CREATED, Json(json!({ "message": "Progress tracked successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while tracking progress: {}", e) }))),
+    }
+}
 
-        diesel::update(readings.filter(schema::readings::dsl::id.eq(reading_id)))
-            .set(schema::readings::dsl::progress.eq(payload.progress))
-            .execute(connection)?;
+/// Request type for setting the deadlines of a reading session.
+#[derive(Debug, Deserialize)]
+pub struct ReadingDeadlinesRequest {
+    pub reading_id: String,
+    pub target_finish_at: Option<String>,
+    pub due_at: Option<String>,
+}
 
-        Ok(())
-    });
+/// Sets the deadlines of a reading session, which show up in the calendar feed with reminders.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `reading_id`: The UUID of the reading session.
+/// - `target_finish_at`: Optional, the date to finish the book by, removed if not given.
+/// - `due_at`: Optional, the date a borrowed copy has to be returned, removed if not given.
+pub(crate) async fn set_reading_deadlines(
+    auth: AuthUser,
+    Json(payload): Json<ReadingDeadlinesRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
 
-    match transaction_result {
-        Ok(_) => (StatusCode::CREATED, Json(json!({ "message": "Progress tracked successfully." }))),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while tracking progress: {}", e) }))),
+    let reading_id = match Uuid::parse_str(&payload.reading_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid reading ID.".to_string() }))),
+    };
+
+    let (target_finish_at, due_at) = match (
+        parse_optional_date(payload.target_finish_at.as_deref()),
+        parse_optional_date(payload.due_at.as_deref()),
+    ) {
+        (Ok(target_finish_at), Ok(due_at)) => (target_finish_at, due_at),
+        _ => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid date format. Use YYYY-MM-DD.".to_string() }))),
+    };
+
+    let reading: Reading = match readings
+        .filter(schema::readings::dsl::id.eq(reading_id))
+        .first(connection)
+    {
+        Ok(r) => r,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Reading not found.".to_string() }))),
+    };
+
+    if reading.user != auth.0 {
+        return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
+    }
+
+    match diesel::update(readings.filter(schema::readings::dsl::id.eq(reading_id)))
+        .set((
+            schema::readings::dsl::target_finish_at.eq(target_finish_at),
+            schema::readings::dsl::due_at.eq(due_at),
+        ))
+        .execute(connection)
+    {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Reading deadlines updated successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while updating the reading deadlines: {}", e) }))),
     }
 }
 
@@ -239,4 +506,23 @@ mod tests {
             .await.unwrap();
         assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
     }
+
+    #[tokio::test]
+    async fn test_set_reading_deadlines_requires_auth() {
+        let app = Router::new().route("/api/books/reading/deadlines", post(set_reading_deadlines));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/books/reading/deadlines").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_convert_progress() {
+        assert_eq!(convert_progress(120, ReadingMode::Pages, ReadingMode::Pages, 0), Some(120));
+        assert_eq!(convert_progress(50, ReadingMode::Percentage, ReadingMode::Pages, 317), Some(159));
+        assert_eq!(convert_progress(159, ReadingMode::Pages, ReadingMode::Percentage, 317), Some(50));
+        assert_eq!(convert_progress(400, ReadingMode::Pages, ReadingMode::Percentage, 317), Some(100));
+        // Without a page count, progress can't be converted
+        assert_eq!(convert_progress(50, ReadingMode::Percentage, ReadingMode::Pages, 0), None);
+    }
 }
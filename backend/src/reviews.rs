@@ -0,0 +1,412 @@
+use crate::auth::AuthUser;
+use crate::books::work_copies;
+use crate::db::connect;
+use crate::models::{Book, Reading, Review};
+use crate::schema::books::dsl::books;
+use crate::schema::readings::dsl::readings;
+use crate::schema::reviews::dsl::reviews;
+use crate::{schema, ErrorResponse};
+use axum::routing::post;
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+/// Placeholder shown instead of the text of a spoiler when spoilers are hidden.
+const SPOILER_PLACEHOLDER: &str = "[spoiler]";
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/reviews", post(list_reviews))
+        .route("/api/reviews/save", post(save_review))
+        .route("/api/reviews/remove", post(remove_review))
+}
+
+/// Converts a rating in stars, like 3.5, into the stored number of half stars.
+///
+/// Returns `None` unless the rating is a multiple of half a star between 0.5 and 5 stars.
+pub fn half_stars(stars: f32) -> Option<i16> {
+    let doubled = stars * 2.0;
+    if doubled.fract() != 0.0 || !(1.0..=10.0).contains(&doubled) {
+        return None;
+    }
+    Some(doubled as i16)
+}
+
+/// Converts a stored number of half stars into a rating in stars.
+pub fn stars(half_stars: i16) -> f32 {
+    half_stars as f32 / 2.0
+}
+
+/// Checks whether a Markdown review contains spoilers marked up as `>!spoiler!<`.
+pub fn has_spoilers(body: &str) -> bool {
+    body.find(">!").is_some_and(|start| body[start + 2..].contains("!<"))
+}
+
+/// Replaces the spoilers of a Markdown review with a placeholder.
+pub fn hide_spoilers(body: &str) -> String {
+    let mut result = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find(">!") {
+        let Some(length) = rest[start + 2..].find("!<") else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        result.push_str(SPOILER_PLACEHOLDER);
+        rest = &rest[start + 2 + length + 2..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Creates or replaces the review of a book or of one of its readings.
+///
+/// A book has at most one review per reading and one review without a reading, which rates the
+/// book as a whole. The copies of the book on other shelves get the same review of the book as a
+/// whole, so they don't go out of date when it's changed.
+pub fn upsert_review(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    book_id: Uuid,
+    reading_id: Option<Uuid>,
+    rating: Option<i16>,
+    body: Option<String>,
+) -> QueryResult<Uuid> {
+    connection.transaction(|conn| {
+        if reading_id.is_none() {
+            let book: Book = books.find(book_id).first(conn)?;
+            for copy in work_copies(conn, &book)?.into_iter().filter(|&copy| copy != book_id) {
+                upsert_copy_review(conn, user_id, copy, None, rating, body.clone())?;
+            }
+        }
+        upsert_copy_review(conn, user_id, book_id, reading_id, rating, body)
+    })
+}
+
+/// Creates or replaces the review of a single book row or of one of its readings.
+fn upsert_copy_review(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    book_id: Uuid,
+    reading_id: Option<Uuid>,
+    rating: Option<i16>,
+    body: Option<String>,
+) -> QueryResult<Uuid> {
+    let existing: Option<Uuid> = match reading_id {
+        Some(reading_id) => reviews
+            .filter(schema::reviews::dsl::reading.eq(reading_id))
+            .select(schema::reviews::dsl::id)
+            .first(conn)
+            .optional()?,
+        None => reviews
+            .filter(schema::reviews::dsl::book.eq(book_id))
+            .filter(schema::reviews::dsl::reading.is_null())
+            .select(schema::reviews::dsl::id)
+            .first(conn)
+            .optional()?,
+    };
+
+    if let Some(id) = existing {
+        diesel::update(reviews.filter(schema::reviews::dsl::id.eq(id)))
+            .set((
+                schema::reviews::dsl::rating.eq(rating),
+                schema::reviews::dsl::body.eq(body),
+            ))
+            .execute(conn)?;
+        return Ok(id);
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let review = Review {
+        id: Uuid::new_v4(),
+        user: user_id,
+        book: book_id,
+        reading: reading_id,
+        rating,
+        body,
+        created_at: now,
+        updated_at: now,
+    };
+    diesel::insert_into(reviews).values(&review).execute(conn)?;
+    Ok(review.id)
+}
+
+/// Aggregate of the ratings given to a book across its readings.
+#[derive(Debug, Serialize)]
+pub struct RatingSummary {
+    /// Average rating in stars.
+    pub average: f32,
+    /// Number of ratings.
+    pub count: usize,
+    /// Most recently given rating in stars.
+    pub latest: f32,
+}
+
+/// Aggregates the ratings of a book, returning `None` if it was never rated.
+pub fn rating_summary(connection: &mut PgConnection, book_id: Uuid) -> QueryResult<Option<RatingSummary>> {
+    let ratings: Vec<i16> = reviews
+        .filter(schema::reviews::dsl::book.eq(book_id))
+        .filter(schema::reviews::dsl::rating.is_not_null())
+        .order(schema::reviews::dsl::updated_at.desc())
+        .select(schema::reviews::dsl::rating.assume_not_null())
+        .load(connection)?;
+
+    let Some(&latest) = ratings.first() else {
+        return Ok(None);
+    };
+    let total: i32 = ratings.iter().map(|&r| r as i32).sum();
+    Ok(Some(RatingSummary {
+        average: total as f32 / ratings.len() as f32 / 2.0,
+        count: ratings.len(),
+        latest: stars(latest),
+    }))
+}
+
+/// Request type for listing the reviews of a book.
+#[derive(Debug, Deserialize)]
+pub struct ListReviewsRequest {
+    pub book_id: String,
+    pub hide_spoilers: Option<bool>,
+}
+
+/// Response type for listing the reviews of a book.
+#[derive(Debug, Serialize)]
+pub struct ListReviewsResponse {
+    pub rating: Option<RatingSummary>,
+    pub reviews: Vec<serde_json::Value>,
+}
+
+/// Lists the reviews of a book along with its rating aggregate.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `book_id`: The UUID of the book to list the reviews of.
+/// - `hide_spoilers`: Optional, if `true` spoilers are replaced by a placeholder.
+pub(crate) async fn list_reviews(
+    auth: AuthUser,
+    Json(payload): Json<ListReviewsRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let book_id = match Uuid::parse_str(&payload.book_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+    };
+
+    let results = match reviews
+        .filter(schema::reviews::dsl::book.eq(book_id))
+        .filter(schema::reviews::dsl::user.eq(auth.0))
+        .order(schema::reviews::dsl::created_at.asc())
+        .load::<Review>(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading reviews: {}", e) }))),
+    };
+
+    let summary = match rating_summary(connection, book_id) {
+        Ok(s) => s,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading reviews: {}", e) }))),
+    };
+
+    let hide = payload.hide_spoilers.unwrap_or(false);
+    let mut json_reviews = Vec::new();
+    for review in results {
+        json_reviews.push(json!({
+            "id": review.id.to_string(),
+            "reading_id": review.reading.map(|r| r.to_string()),
+            "rating": review.rating.map(stars),
+            "body": review.body.as_deref().map(|b| if hide { hide_spoilers(b) } else { b.to_string() }),
+            "has_spoilers": review.body.as_deref().is_some_and(has_spoilers),
+            "created_at": review.created_at.to_string(),
+            "updated_at": review.updated_at.to_string(),
+        }));
+    }
+
+    (StatusCode::OK, Json(json!(ListReviewsResponse { rating: summary, reviews: json_reviews })))
+}
+
+/// Request type for rating and reviewing a book.
+#[derive(Debug, Deserialize)]
+pub struct SaveReviewRequest {
+    pub book_id: String,
+    pub reading_id: Option<String>,
+    pub rating: Option<f32>,
+    pub body: Option<String>,
+}
+
+/// Rates and reviews a book, replacing an existing review of the same reading.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `book_id`: The UUID of the book to review.
+/// - `reading_id`: Optional, the UUID of the reading session the review belongs to. Without it the
+///   review applies to the book as a whole.
+/// - `rating`: Optional, the rating in stars from 0.5 to 5 in steps of 0.5.
+/// - `body`: Optional, the review as Markdown. Spoilers are marked up as `>!spoiler!<`.
+pub(crate) async fn save_review(
+    auth: AuthUser,
+    Json(payload): Json<SaveReviewRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let book_id = match Uuid::parse_str(&payload.book_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+    };
+
+    let reading_id = match payload.reading_id.as_deref().map(Uuid::parse_str).transpose() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid reading ID.".to_string() }))),
+    };
+
+    let rating = match payload.rating.map(half_stars) {
+        None => None,
+        Some(Some(r)) => Some(r),
+        Some(None) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Rating must be between 0.5 and 5 in steps of 0.5.".to_string() }))),
+    };
+
+    let body = payload.body.map(|b| b.trim().to_string()).filter(|b| !b.is_empty());
+    if rating.is_none() && body.is_none() {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Either a rating or a review is required.".to_string() })));
+    }
+
+    let book: Book = match books
+        .filter(schema::books::dsl::id.eq(book_id))
+        .first(connection)
+    {
+        Ok(b) => b,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Book not found.".to_string() }))),
+    };
+
+    if book.user != auth.0 {
+        return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
+    }
+
+    if let Some(reading_id) = reading_id {
+        let reading: Reading = match readings
+            .filter(schema::readings::dsl::id.eq(reading_id))
+            .first(connection)
+        {
+            Ok(r) => r,
+            Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Reading not found.".to_string() }))),
+        };
+
+        if reading.book != book_id {
+            return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Reading does not belong to the book.".to_string() })));
+        }
+    }
+
+    match upsert_review(connection, auth.0, book_id, reading_id, rating, body) {
+        Ok(id) => (StatusCode::OK, Json(json!({ "message": "Review saved successfully.", "id": id.to_string() }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while saving the review: {}", e) }))),
+    }
+}
+
+/// Request type for removing a review.
+#[derive(Debug, Deserialize)]
+pub struct RemoveReviewRequest {
+    pub review_id: String,
+}
+
+/// Removes a review including its rating, the review of a book as a whole is removed from its
+/// copies on other shelves as well.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `review_id`: The UUID of the review to remove.
+pub(crate) async fn remove_review(
+    auth: AuthUser,
+    Json(payload): Json<RemoveReviewRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let review_id = match Uuid::parse_str(&payload.review_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid review ID.".to_string() }))),
+    };
+
+    let review: Review = match reviews
+        .filter(schema::reviews::dsl::id.eq(review_id))
+        .filter(schema::reviews::dsl::user.eq(auth.0))
+        .first(connection)
+    {
+        Ok(r) => r,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Review not found.".to_string() }))),
+    };
+
+    let result = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        // The review of the book as a whole is removed from its copies on other shelves as well
+        let book_ids = match review.reading {
+            Some(_) => Vec::new(),
+            None => {
+                let book: Book = books.find(review.book).first(conn)?;
+                work_copies(conn, &book)?
+            }
+        };
+        diesel::delete(
+            reviews
+                .filter(schema::reviews::dsl::id.eq(review.id))
+                .or_filter(schema::reviews::dsl::book.eq_any(&book_ids).and(schema::reviews::dsl::reading.is_null())),
+        )
+        .execute(conn)
+    });
+
+    match result {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Review removed successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while removing the review: {}", e) }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[test]
+    fn test_half_stars() {
+        assert_eq!(half_stars(0.5), Some(1));
+        assert_eq!(half_stars(3.5), Some(7));
+        assert_eq!(half_stars(5.0), Some(10));
+        assert_eq!(half_stars(0.0), None);
+        assert_eq!(half_stars(3.7), None);
+        assert_eq!(half_stars(5.5), None);
+        assert_eq!(stars(7), 3.5);
+    }
+
+    #[test]
+    fn test_hide_spoilers() {
+        let body = "Great start. >!The butler did it!< Also >!twice!<.";
+        assert!(has_spoilers(body));
+        assert_eq!(hide_spoilers(body), "Great start. [spoiler] Also [spoiler].");
+
+        assert!(!has_spoilers("Wow >! no closing marker"));
+        assert_eq!(hide_spoilers("Wow >! no closing marker"), "Wow >! no closing marker");
+    }
+
+    #[tokio::test]
+    async fn test_list_reviews_requires_auth() {
+        let app = Router::new().route("/api/reviews", post(list_reviews));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/reviews").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_save_review_requires_auth() {
+        let app = Router::new().route("/api/reviews/save", post(save_review));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/reviews/save").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_remove_review_requires_auth() {
+        let app = Router::new().route("/api/reviews/remove", post(remove_review));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/reviews/remove").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+}
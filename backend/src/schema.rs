@@ -1,11 +1,94 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "contributor_role"))]
+    pub struct ContributorRole;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "enrichment_status"))]
+    pub struct EnrichmentStatus;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "import_status"))]
+    pub struct ImportStatus;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "reading_mode"))]
     pub struct ReadingMode;
 }
 
+diesel::table! {
+    api_keys (id) {
+        id -> Uuid,
+        user -> Uuid,
+        name -> Nullable<Text>,
+        key_hash -> Text,
+        scopes -> Array<Nullable<Text>>,
+        last_used_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    authors (id) {
+        id -> Uuid,
+        user -> Uuid,
+        name -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ContributorRole;
+
+    book_contributors (book, author, role) {
+        book -> Uuid,
+        author -> Uuid,
+        role -> ContributorRole,
+        position -> Int4,
+    }
+}
+
+diesel::table! {
+    book_covers (book) {
+        book -> Uuid,
+        id -> Uuid,
+        user -> Uuid,
+        content_type -> Text,
+        width -> Int4,
+        height -> Int4,
+        uploaded_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::EnrichmentStatus;
+
+    book_enrichments (book) {
+        book -> Uuid,
+        user -> Uuid,
+        status -> EnrichmentStatus,
+        provider -> Nullable<Text>,
+        matched_by -> Nullable<Text>,
+        confidence -> Nullable<Float4>,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    book_tags (book, tag) {
+        book -> Uuid,
+        tag -> Uuid,
+    }
+}
+
 diesel::table! {
     books (id) {
         id -> Uuid,
@@ -17,6 +100,154 @@ diesel::table! {
         isbn10 -> Nullable<Text>,
         google_books_id -> Nullable<Text>,
         added_at -> Timestamptz,
+        publisher -> Nullable<Text>,
+        published_year -> Nullable<Int4>,
+        page_count -> Nullable<Int4>,
+        cover_url -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    citation_keys (user, work) {
+        user -> Uuid,
+        work -> Text,
+        key -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    feed_tokens (id) {
+        id -> Uuid,
+        user -> Uuid,
+        key_hash -> Text,
+        name -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    highlight_tags (highlight, tag) {
+        highlight -> Uuid,
+        tag -> Uuid,
+    }
+}
+
+diesel::table! {
+    highlights (id) {
+        id -> Uuid,
+        user -> Uuid,
+        book -> Uuid,
+        reading -> Nullable<Uuid>,
+        quote -> Text,
+        page -> Nullable<Int4>,
+        location -> Nullable<Text>,
+        note -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        source_hash -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    import_job_errors (id) {
+        id -> Uuid,
+        job -> Uuid,
+        row -> Int4,
+        message -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ImportStatus;
+
+    import_jobs (id) {
+        id -> Uuid,
+        user -> Uuid,
+        source -> Text,
+        status -> ImportStatus,
+        options -> Jsonb,
+        payload -> Nullable<Bytea>,
+        total_rows -> Int4,
+        processed_rows -> Int4,
+        added -> Int4,
+        skipped -> Int4,
+        failed -> Int4,
+        error -> Nullable<Text>,
+        created_at -> Timestamptz,
+        started_at -> Nullable<Timestamptz>,
+        finished_at -> Nullable<Timestamptz>,
+        updated_at -> Timestamptz,
+        merged -> Int4,
+        worker -> Nullable<Uuid>,
+        heartbeat_at -> Nullable<Timestamptz>,
+        undated_reads -> Int4,
+    }
+}
+
+diesel::table! {
+    import_templates (id) {
+        id -> Uuid,
+        user -> Uuid,
+        name -> Text,
+        mapping -> Jsonb,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    koreader_accounts (id) {
+        id -> Uuid,
+        user -> Uuid,
+        username -> Text,
+        key_hash -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    koreader_documents (id) {
+        id -> Uuid,
+        user -> Uuid,
+        document -> Text,
+        book -> Nullable<Uuid>,
+        progress -> Text,
+        percentage -> Float8,
+        device -> Nullable<Text>,
+        device_id -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    notes (id) {
+        id -> Uuid,
+        user -> Uuid,
+        book -> Uuid,
+        reading -> Nullable<Uuid>,
+        page -> Nullable<Int4>,
+        body -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        source_hash -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    public_feeds (id) {
+        id -> Uuid,
+        user -> Uuid,
+        enabled -> Bool,
+        title -> Nullable<Text>,
+        shelves -> Array<Nullable<Uuid>>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -48,11 +279,45 @@ diesel::table! {
         total_pages -> Int4,
         progress -> Int4,
         mode -> ReadingMode,
-        started_at -> Date,
+        started_at -> Nullable<Date>,
         finished_at -> Nullable<Date>,
         cancelled_at -> Nullable<Date>,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        target_finish_at -> Nullable<Date>,
+        due_at -> Nullable<Date>,
+    }
+}
+
+diesel::table! {
+    reviews (id) {
+        id -> Uuid,
+        user -> Uuid,
+        book -> Uuid,
+        reading -> Nullable<Uuid>,
+        rating -> Nullable<Int2>,
+        body -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    series (id) {
+        id -> Uuid,
+        user -> Uuid,
+        name -> Text,
+        description -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    series_books (series, book) {
+        series -> Uuid,
+        book -> Uuid,
+        position -> Nullable<Float8>,
     }
 }
 
@@ -67,6 +332,15 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    tags (id) {
+        id -> Uuid,
+        user -> Uuid,
+        name -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Uuid,
@@ -77,13 +351,74 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(api_keys -> users (user));
+diesel::joinable!(authors -> users (user));
+diesel::joinable!(book_contributors -> authors (author));
+diesel::joinable!(book_contributors -> books (book));
+diesel::joinable!(book_covers -> books (book));
+diesel::joinable!(book_covers -> users (user));
+diesel::joinable!(book_enrichments -> books (book));
+diesel::joinable!(book_enrichments -> users (user));
+diesel::joinable!(book_tags -> books (book));
+diesel::joinable!(book_tags -> tags (tag));
 diesel::joinable!(books -> shelves (shelf));
 diesel::joinable!(books -> users (user));
+diesel::joinable!(citation_keys -> users (user));
+diesel::joinable!(feed_tokens -> users (user));
+diesel::joinable!(highlight_tags -> highlights (highlight));
+diesel::joinable!(highlight_tags -> tags (tag));
+diesel::joinable!(highlights -> books (book));
+diesel::joinable!(highlights -> readings (reading));
+diesel::joinable!(highlights -> users (user));
+diesel::joinable!(import_job_errors -> import_jobs (job));
+diesel::joinable!(import_jobs -> users (user));
+diesel::joinable!(import_templates -> users (user));
+diesel::joinable!(koreader_accounts -> users (user));
+diesel::joinable!(koreader_documents -> books (book));
+diesel::joinable!(koreader_documents -> users (user));
+diesel::joinable!(notes -> books (book));
+diesel::joinable!(notes -> readings (reading));
+diesel::joinable!(notes -> users (user));
+diesel::joinable!(public_feeds -> users (user));
 diesel::joinable!(reading_entries -> books (book));
 diesel::joinable!(reading_entries -> readings (reading));
 diesel::joinable!(reading_entries -> users (user));
 diesel::joinable!(readings -> books (book));
 diesel::joinable!(readings -> users (user));
+diesel::joinable!(reviews -> books (book));
+diesel::joinable!(reviews -> readings (reading));
+diesel::joinable!(reviews -> users (user));
+diesel::joinable!(series -> users (user));
+diesel::joinable!(series_books -> books (book));
+diesel::joinable!(series_books -> series (series));
 diesel::joinable!(shelves -> users (user));
+diesel::joinable!(tags -> users (user));
 
-diesel::allow_tables_to_appear_in_same_query!(books, reading_entries, readings, shelves, users,);
+diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
+    authors,
+    book_contributors,
+    book_covers,
+    book_enrichments,
+    book_tags,
+    books,
+    citation_keys,
+    feed_tokens,
+    highlight_tags,
+    highlights,
+    import_job_errors,
+    import_jobs,
+    import_templates,
+    koreader_accounts,
+    koreader_documents,
+    notes,
+    public_feeds,
+    reading_entries,
+    readings,
+    reviews,
+    series,
+    series_books,
+    shelves,
+    tags,
+    users,
+);
@@ -0,0 +1,567 @@
+use crate::auth::AuthUser;
+use crate::books::work_key;
+use crate::db::connect;
+use crate::models::{Book, Reading, Series, SeriesBook};
+use crate::schema::books::dsl::books;
+use crate::schema::readings::dsl::readings;
+use crate::schema::series::dsl::series;
+use crate::schema::series_books::dsl::series_books;
+use crate::{schema, ErrorResponse};
+use axum::routing::post;
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error::DatabaseError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/series", post(list_series))
+        .route("/api/series/create", post(create_series))
+        .route("/api/series/remove", post(remove_series))
+        .route("/api/series/add-book", post(add_book_to_series))
+        .route("/api/series/remove-book", post(remove_book_from_series))
+        .route("/api/series/books", post(list_series_books))
+        .route("/api/series/next-unread", post(next_unread_in_series))
+}
+
+/// Reading status of a book in a series, derived from its readings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadStatus {
+    Read,
+    Reading,
+    Unread,
+}
+
+impl ReadStatus {
+    fn name(self) -> &'static str {
+        match self {
+            ReadStatus::Read => "read",
+            ReadStatus::Reading => "reading",
+            ReadStatus::Unread => "unread",
+        }
+    }
+}
+
+/// A work in a series. The same work may be placed on several shelves, so it can consist of
+/// multiple book rows.
+pub struct SeriesEntry {
+    pub position: Option<f64>,
+    pub books: Vec<Book>,
+    pub status: ReadStatus,
+}
+
+/// Loads the entries of a series ordered by position, entries without position last.
+pub fn load_entries(connection: &mut PgConnection, series_id: Uuid) -> QueryResult<Vec<SeriesEntry>> {
+    Ok(load_series_entries(connection, &[series_id])?.remove(&series_id).unwrap_or_default())
+}
+
+/// Loads the entries of several series at once, keyed by series, like `load_entries`.
+pub fn load_series_entries(
+    connection: &mut PgConnection,
+    series_ids: &[Uuid],
+) -> QueryResult<HashMap<Uuid, Vec<SeriesEntry>>> {
+    let rows: Vec<(SeriesBook, Book)> = series_books
+        .inner_join(books)
+        .filter(schema::series_books::dsl::series.eq_any(series_ids))
+        .select((SeriesBook::as_select(), Book::as_select()))
+        .load(connection)?;
+
+    let book_ids: Vec<Uuid> = rows.iter().map(|(_, b)| b.id).collect();
+    let book_readings: Vec<Reading> = readings
+        .filter(schema::readings::dsl::book.eq_any(&book_ids))
+        .load(connection)?;
+
+    let mut all_entries: HashMap<Uuid, Vec<SeriesEntry>> = HashMap::new();
+    let mut index: HashMap<(Uuid, String), usize> = HashMap::new();
+    for (link, book) in rows {
+        let entries = all_entries.entry(link.series).or_default();
+        let key = (link.series, work_key(&book));
+        match index.get(&key) {
+            Some(&i) => {
+                let entry = &mut entries[i];
+                entry.position = entry.position.or(link.position);
+                entry.books.push(book);
+            }
+            None => {
+                index.insert(key, entries.len());
+                entries.push(SeriesEntry {
+                    position: link.position,
+                    books: vec![book],
+                    status: ReadStatus::Unread,
+                });
+            }
+        }
+    }
+
+    for entries in all_entries.values_mut() {
+        for entry in entries.iter_mut() {
+            entry.status = read_status(entry, &book_readings);
+        }
+        entries.sort_by(|a, b| compare_positions(a.position, b.position));
+    }
+    Ok(all_entries)
+}
+
+/// Orders positions in a series, entries without position come last.
+fn compare_positions(a: Option<f64>, b: Option<f64>) -> std::cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    }
+}
+
+/// A work counts as read once any of its readings was finished, and as being read while a
+/// reading is neither finished nor cancelled.
+fn read_status(entry: &SeriesEntry, book_readings: &[Reading]) -> ReadStatus {
+    let entry_readings: Vec<&Reading> = book_readings
+        .iter()
+        .filter(|r| entry.books.iter().any(|b| b.id == r.book))
+        .collect();
+
+    if entry_readings.iter().any(|r| r.finished_at.is_some()) {
+        ReadStatus::Read
+    } else if entry_readings.iter().any(|r| r.cancelled_at.is_none()) {
+        ReadStatus::Reading
+    } else {
+        ReadStatus::Unread
+    }
+}
+
+fn entry_json(entry: &SeriesEntry) -> serde_json::Value {
+    let book = &entry.books[0];
+    json!({
+        "position": entry.position,
+        "status": entry.status.name(),
+        "title": book.title,
+        "author": book.author,
+        "isbn13": book.isbn13,
+        "google_books_id": book.google_books_id,
+        "book_ids": entry.books.iter().map(|b| b.id.to_string()).collect::<Vec<_>>(),
+    })
+}
+
+/// Finds the series of a user with the given name, creating it if it does not exist yet.
+pub fn find_or_create_series(connection: &mut PgConnection, user_id: Uuid, name: &str) -> QueryResult<Uuid> {
+    let now = chrono::Utc::now().naive_utc();
+    diesel::insert_into(series)
+        .values(&Series {
+            id: Uuid::new_v4(),
+            user: user_id,
+            name: name.to_string(),
+            description: None,
+            created_at: now,
+            updated_at: now,
+        })
+        .on_conflict((schema::series::dsl::user, schema::series::dsl::name))
+        .do_nothing()
+        .execute(connection)?;
+
+    series
+        .filter(schema::series::dsl::user.eq(user_id))
+        .filter(schema::series::dsl::name.eq(name))
+        .select(schema::series::dsl::id)
+        .first(connection)
+}
+
+/// Places a book in a series at the given position, replacing any previous position.
+pub fn link_book(connection: &mut PgConnection, series_id: Uuid, book_id: Uuid, position: Option<f64>) -> QueryResult<()> {
+    diesel::insert_into(series_books)
+        .values(&SeriesBook {
+            series: series_id,
+            book: book_id,
+            position,
+        })
+        .on_conflict((schema::series_books::dsl::series, schema::series_books::dsl::book))
+        .do_update()
+        .set(schema::series_books::dsl::position.eq(position))
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Loads a series and verifies that it belongs to the user.
+fn load_owned_series(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    series_id: &str,
+) -> Result<Series, (StatusCode, Json<serde_json::Value>)> {
+    let series_id = Uuid::parse_str(series_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid series ID.".to_string() }))))?;
+
+    let found: Series = series
+        .filter(schema::series::dsl::id.eq(series_id))
+        .first(connection)
+        .map_err(|_| (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Series not found.".to_string() }))))?;
+
+    if found.user != user_id {
+        return Err((StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() }))));
+    }
+    Ok(found)
+}
+
+/// Response type for listing all series of a user.
+#[derive(Debug, Serialize)]
+pub struct ListSeriesResponse {
+    pub series: Vec<serde_json::Value>,
+}
+
+/// Lists the series of a user along with how many of their books were read.
+pub(crate) async fn list_series(auth: AuthUser) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let results = match series
+        .filter(schema::series::dsl::user.eq(auth.0))
+        .order(schema::series::dsl::name.asc())
+        .load::<Series>(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading series: {}", e) }))),
+    };
+
+    let series_ids: Vec<Uuid> = results.iter().map(|found| found.id).collect();
+    let mut all_entries = match load_series_entries(connection, &series_ids) {
+        Ok(e) => e,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading series books: {}", e) }))),
+    };
+
+    let mut json_series = Vec::new();
+    for found in results {
+        let entries = all_entries.remove(&found.id).unwrap_or_default();
+        json_series.push(json!({
+            "id": found.id.to_string(),
+            "name": found.name,
+            "description": found.description,
+            "book_count": entries.len(),
+            "read_count": entries.iter().filter(|e| e.status == ReadStatus::Read).count(),
+            "created_at": found.created_at.to_string(),
+            "updated_at": found.updated_at.to_string(),
+        }));
+    }
+
+    (StatusCode::OK, Json(json!(ListSeriesResponse { series: json_series })))
+}
+
+/// Request type for creating a new series.
+#[derive(Debug, Deserialize)]
+pub struct CreateSeriesRequest {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// Creates a new series, its name has to be unique among the series of the user.
+pub(crate) async fn create_series(
+    auth: AuthUser,
+    Json(payload): Json<CreateSeriesRequest>,
+) -> impl IntoResponse {
+    if payload.name.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Missing series name.".to_string() })));
+    }
+
+    let new_series = Series {
+        id: Uuid::new_v4(),
+        user: auth.0,
+        name: payload.name.trim().to_string(),
+        description: payload.description.map(|d| d.trim().to_string()),
+        created_at: chrono::Utc::now().naive_utc(),
+        updated_at: chrono::Utc::now().naive_utc(),
+    };
+
+    let connection = &mut connect();
+
+    match diesel::insert_into(series)
+        .values(&new_series)
+        .execute(connection)
+    {
+        Ok(_) => (StatusCode::CREATED, Json(json!({ "message": "Series created successfully.", "id": new_series.id.to_string() }))),
+        Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "A series with this name already exists.".to_string() }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while creating the series: {}", e) }))),
+    }
+}
+
+/// Request type for operations on a whole series.
+#[derive(Debug, Deserialize)]
+pub struct SeriesRequest {
+    pub series_id: String,
+}
+
+/// Removes a series. The books themselves are kept.
+pub(crate) async fn remove_series(
+    auth: AuthUser,
+    Json(payload): Json<SeriesRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let found = match load_owned_series(connection, auth.0, &payload.series_id) {
+        Ok(s) => s,
+        Err(response) => return response,
+    };
+
+    match diesel::delete(series.filter(schema::series::dsl::id.eq(found.id))).execute(connection) {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Series removed successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while removing the series: {}", e) }))),
+    }
+}
+
+/// Request type for adding a book to a series.
+#[derive(Debug, Deserialize)]
+pub struct AddBookToSeriesRequest {
+    pub series_id: String,
+    pub book_id: String,
+    pub position: Option<f64>,
+}
+
+/// Adds a book to a series.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `series_id`: The UUID of the series.
+/// - `book_id`: The UUID of the book.
+/// - `position`: Optional position of the book in the reading order, e.g. `2.5` for a novella
+///   set between the second and third book.
+pub(crate) async fn add_book_to_series(
+    auth: AuthUser,
+    Json(payload): Json<AddBookToSeriesRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let found = match load_owned_series(connection, auth.0, &payload.series_id) {
+        Ok(s) => s,
+        Err(response) => return response,
+    };
+
+    let book_id = match Uuid::parse_str(&payload.book_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+    };
+
+    let book: Book = match books
+        .filter(schema::books::dsl::id.eq(book_id))
+        .first(connection)
+    {
+        Ok(b) => b,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Book not found.".to_string() }))),
+    };
+
+    if book.user != auth.0 {
+        return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
+    }
+
+    if payload.position.is_some_and(|p| !p.is_finite() || p < 0.0) {
+        return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid position.".to_string() })));
+    }
+
+    match link_book(connection, found.id, book_id, payload.position) {
+        Ok(_) => (StatusCode::CREATED, Json(json!({ "message": "Book added to series successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while adding the book to the series: {}", e) }))),
+    }
+}
+
+/// Request type for removing a book from a series.
+#[derive(Debug, Deserialize)]
+pub struct RemoveBookFromSeriesRequest {
+    pub series_id: String,
+    pub book_id: String,
+}
+
+/// Removes a book from a series.
+pub(crate) async fn remove_book_from_series(
+    auth: AuthUser,
+    Json(payload): Json<RemoveBookFromSeriesRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let found = match load_owned_series(connection, auth.0, &payload.series_id) {
+        Ok(s) => s,
+        Err(response) => return response,
+    };
+
+    let book_id = match Uuid::parse_str(&payload.book_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+    };
+
+    match diesel::delete(
+        series_books
+            .filter(schema::series_books::dsl::series.eq(found.id))
+            .filter(schema::series_books::dsl::book.eq(book_id)),
+    )
+    .execute(connection)
+    {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Book removed from series successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while removing the book from the series: {}", e) }))),
+    }
+}
+
+/// Lists the books of a series in reading order along with their read status.
+pub(crate) async fn list_series_books(
+    auth: AuthUser,
+    Json(payload): Json<SeriesRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let found = match load_owned_series(connection, auth.0, &payload.series_id) {
+        Ok(s) => s,
+        Err(response) => return response,
+    };
+
+    let entries = match load_entries(connection, found.id) {
+        Ok(e) => e,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading series books: {}", e) }))),
+    };
+
+    (StatusCode::OK, Json(json!({
+        "series": {
+            "id": found.id.to_string(),
+            "name": found.name,
+            "description": found.description,
+        },
+        "books": entries.iter().map(entry_json).collect::<Vec<_>>(),
+    })))
+}
+
+/// Returns the first book of a series in reading order which is neither read nor being read.
+pub(crate) async fn next_unread_in_series(
+    auth: AuthUser,
+    Json(payload): Json<SeriesRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let found = match load_owned_series(connection, auth.0, &payload.series_id) {
+        Ok(s) => s,
+        Err(response) => return response,
+    };
+
+    let entries = match load_entries(connection, found.id) {
+        Ok(e) => e,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading series books: {}", e) }))),
+    };
+
+    match entries.iter().find(|e| e.status == ReadStatus::Unread) {
+        Some(entry) => (StatusCode::OK, Json(json!({ "book": entry_json(entry) }))),
+        None => (StatusCode::OK, Json(json!({ "book": null }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_list_series_requires_auth() {
+        let app = Router::new().route("/api/series", post(list_series));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/series").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_create_series_requires_auth() {
+        let app = Router::new().route("/api/series/create", post(create_series));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/series/create").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_add_book_to_series_requires_auth() {
+        let app = Router::new().route("/api/series/add-book", post(add_book_to_series));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/series/add-book").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_list_series_books_requires_auth() {
+        let app = Router::new().route("/api/series/books", post(list_series_books));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/series/books").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_next_unread_in_series_requires_auth() {
+        let app = Router::new().route("/api/series/next-unread", post(next_unread_in_series));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/series/next-unread").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    fn date(day: u32) -> chrono::NaiveDate {
+        chrono::NaiveDate::from_ymd_opt(2025, 3, day).unwrap()
+    }
+
+    fn entry(position: Option<f64>, copies: usize) -> SeriesEntry {
+        let added_at = date(1).and_time(chrono::NaiveTime::MIN);
+        let book = || Book {
+            id: Uuid::new_v4(),
+            user: Uuid::nil(),
+            shelf: Uuid::new_v4(),
+            title: Some("Mort".to_string()),
+            author: Some("Terry Pratchett".to_string()),
+            isbn13: Some("9780552131061".to_string()),
+            isbn10: None,
+            google_books_id: None,
+            added_at,
+            publisher: None,
+            published_year: None,
+            page_count: Some(316),
+            cover_url: None,
+        };
+        SeriesEntry { position, books: (0..copies).map(|_| book()).collect(), status: ReadStatus::Unread }
+    }
+
+    fn reading(book: &Book, finished_at: Option<chrono::NaiveDate>, cancelled_at: Option<chrono::NaiveDate>) -> Reading {
+        let timestamp = date(1).and_time(chrono::NaiveTime::MIN);
+        Reading {
+            id: Uuid::new_v4(),
+            book: book.id,
+            user: Uuid::nil(),
+            total_pages: 316,
+            progress: 0,
+            mode: crate::models::ReadingMode::Pages,
+            started_at: Some(date(1)),
+            finished_at,
+            cancelled_at,
+            created_at: timestamp,
+            updated_at: timestamp,
+            target_finish_at: None,
+            due_at: None,
+        }
+    }
+
+    #[test]
+    fn test_read_status() {
+        let entry = entry(Some(1.0), 2);
+        let other = self::entry(Some(2.0), 1);
+        assert_eq!(read_status(&entry, &[]), ReadStatus::Unread);
+
+        // Readings of other books don't count, cancelled readings leave a book unread
+        let cancelled = || reading(&entry.books[0], None, Some(date(3)));
+        let elsewhere = || reading(&other.books[0], Some(date(4)), None);
+        assert_eq!(read_status(&entry, &[cancelled(), elsewhere()]), ReadStatus::Unread);
+
+        // Readings of any copy of the work count, a finished one wins over an ongoing one
+        let ongoing = || reading(&entry.books[1], None, None);
+        assert_eq!(read_status(&entry, &[cancelled(), ongoing()]), ReadStatus::Reading);
+        let finished = reading(&entry.books[0], Some(date(5)), None);
+        assert_eq!(read_status(&entry, &[ongoing(), finished, elsewhere()]), ReadStatus::Read);
+    }
+
+    #[test]
+    fn test_compare_positions() {
+        let mut positions = vec![None, Some(2.0), Some(0.5), None, Some(10.0), Some(1.0)];
+        positions.sort_by(|a, b| compare_positions(*a, *b));
+        assert_eq!(positions, vec![Some(0.5), Some(1.0), Some(2.0), Some(10.0), None, None]);
+    }
+}
@@ -1,10 +1,16 @@
 use crate::auth::AuthUser;
+use crate::contributors::{
+    attach_contributors, display_authors, load_contributors, parse_contributors, ContributorInput,
+};
+use crate::covers::{cover_url, delete_files, load_cover_ids, remove_covers};
 use crate::db::connect;
+use crate::tags::{books_with_all_tags, load_tags};
 use crate::models::{Book, Shelf};
 use crate::schema::books::dsl::books;
 use crate::{schema, ErrorResponse};
 use axum::routing::post;
 use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -26,15 +32,19 @@ pub struct ListShelvesResponse {
     pub shelves: Vec<serde_json::Value>,
 }
 
+/// Loads the shelves of a user.
+pub fn load_shelves(connection: &mut PgConnection, user_id: Uuid) -> QueryResult<Vec<Shelf>> {
+    crate::schema::shelves::dsl::shelves
+        .filter(crate::schema::shelves::dsl::user.eq(user_id))
+        .load::<Shelf>(connection)
+}
+
 /// Lists the shelves of a user.
 pub(crate) async fn list_shelves(auth: AuthUser) -> impl IntoResponse {
     let connection = &mut connect();
     let user_id = auth.0;
 
-    let results = match crate::schema::shelves::dsl::shelves
-        .filter(crate::schema::shelves::dsl::user.eq(user_id))
-        .load::<Shelf>(connection)
-    {
+    let results = match load_shelves(connection, user_id) {
         Ok(r) => r,
         Err(e) => return (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -123,6 +133,12 @@ pub(crate) async fn remove_shelf(
     }
 
     let result = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        let book_ids: Vec<Uuid> = crate::schema::books::dsl::books
+            .filter(crate::schema::books::dsl::shelf.eq(shelf_id))
+            .select(crate::schema::books::dsl::id)
+            .load(conn)?;
+        let covers = remove_covers(conn, &book_ids)?;
+
         diesel::delete(
             crate::schema::books::dsl::books.filter(crate::schema::books::dsl::shelf.eq(shelf_id)),
         )
@@ -133,11 +149,16 @@ pub(crate) async fn remove_shelf(
         )
         .execute(conn)?;
 
-        Ok(())
+        Ok(covers)
     });
 
     match result {
-        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Shelf and its books removed successfully." }))),
+        Ok(covers) => {
+            for cover in &covers {
+                delete_files(cover).await;
+            }
+            (StatusCode::OK, Json(json!({ "message": "Shelf and its books removed successfully." })))
+        }
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!(ErrorResponse { error: format!("Error while removing the shelf: {}", e) })),
@@ -149,9 +170,14 @@ pub(crate) async fn remove_shelf(
 #[derive(Debug, Deserialize)]
 pub struct ShelfBooksRequest {
     pub shelf_id: String,
+    pub tags: Option<Vec<String>>,
 }
 
 /// Lists the books of a shelf.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `shelf_id`: The UUID of the shelf.
+/// - `tags`: Optional tag names, only books carrying all of them are listed.
 pub(crate) async fn list_shelf_books(
     auth: AuthUser,
     Json(payload): Json<ShelfBooksRequest>,
@@ -175,10 +201,21 @@ pub(crate) async fn list_shelf_books(
         return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
     }
 
-    let results = match books
+    let mut query = books
         .filter(crate::schema::books::dsl::shelf.eq(shelf_id))
-        .load::<Book>(connection)
-    {
+        .into_boxed();
+
+    if let Some(tag_names) = payload.tags.filter(|t| !t.is_empty()) {
+        match books_with_all_tags(connection, auth.0, &tag_names) {
+            Ok(tagged) => query = query.filter(crate::schema::books::dsl::id.eq_any(tagged)),
+            Err(e) => return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!(ErrorResponse { error: format!("Error loading tags: {}", e) })),
+            ),
+        }
+    }
+
+    let results = match query.load::<Book>(connection) {
         Ok(r) => r,
         Err(e) => return (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -186,6 +223,31 @@ pub(crate) async fn list_shelf_books(
         ),
     };
 
+    let book_ids: Vec<Uuid> = results.iter().map(|b| b.id).collect();
+    let mut contributors = match load_contributors(connection, &book_ids) {
+        Ok(c) => c,
+        Err(e) => return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!(ErrorResponse { error: format!("Error loading contributors: {}", e) })),
+        ),
+    };
+
+    let cover_ids = match load_cover_ids(connection, &book_ids) {
+        Ok(c) => c,
+        Err(e) => return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!(ErrorResponse { error: format!("Error loading covers: {}", e) })),
+        ),
+    };
+
+    let mut book_tag_names = match load_tags(connection, &book_ids) {
+        Ok(t) => t,
+        Err(e) => return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!(ErrorResponse { error: format!("Error loading tags: {}", e) })),
+        ),
+    };
+
     let mut json_books = Vec::new();
     for book in results {
         let json_book = json!({
@@ -196,6 +258,13 @@ pub(crate) async fn list_shelf_books(
             "isbn10": book.isbn10,
             "google_books_id": book.google_books_id,
             "added_at": book.added_at.to_string(),
+            "publisher": book.publisher,
+            "published_year": book.published_year,
+            "page_count": book.page_count,
+            "cover_url": book.cover_url,
+            "custom_cover_url": cover_ids.get(&book.id).copied().map(cover_url),
+            "contributors": contributors.remove(&book.id).unwrap_or_default(),
+            "tags": book_tag_names.remove(&book.id).unwrap_or_default(),
         });
         json_books.push(json_book);
     }
@@ -222,9 +291,13 @@ pub struct AddBookToShelfRequest {
     pub isbn13: Option<String>,
     pub isbn10: Option<String>,
     pub google_books_id: Option<String>,
+    pub contributors: Option<Vec<ContributorInput>>,
 }
 
 /// Adds a book to a shelf.
+///
+/// The contributors of the book are taken from `contributors` if given, otherwise they are
+/// derived from the free-text `author`.
 pub(crate) async fn add_book_to_shelf(
     auth: AuthUser,
     Json(payload): Json<AddBookToShelfRequest>,
@@ -248,22 +321,35 @@ pub(crate) async fn add_book_to_shelf(
         return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
     }
 
+    let contributors = match payload.contributors {
+        Some(contributors) => contributors,
+        None => payload.author.as_deref().map(parse_contributors).unwrap_or_default(),
+    };
+
     let new_book = Book {
         id: Uuid::new_v4(),
         user: auth.0,
         shelf: shelf_id,
         title: payload.title,
-        author: payload.author,
+        author: payload.author.or_else(|| display_authors(&contributors)),
         isbn13: payload.isbn13,
         isbn10: payload.isbn10,
         google_books_id: payload.google_books_id,
         added_at: chrono::Utc::now().naive_utc(),
+        publisher: None,
+        published_year: None,
+        page_count: None,
+        cover_url: None,
     };
 
-    match diesel::insert_into(schema::books::dsl::books)
-        .values(&new_book)
-        .execute(connection)
-    {
+    let result = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::insert_into(schema::books::dsl::books)
+            .values(&new_book)
+            .execute(conn)?;
+        attach_contributors(conn, auth.0, new_book.id, &contributors)
+    });
+
+    match result {
         Ok(_) => (StatusCode::CREATED, Json(json!({ "message": "Book added to shelf successfully." }))),
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while adding the book to the shelf: {}", e) }))),
     }
@@ -299,10 +385,21 @@ pub(crate) async fn remove_book_from_shelf(
         return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
     }
 
-    match diesel::delete(
-        crate::schema::books::dsl::books.filter(crate::schema::books::dsl::id.eq(book_id)),
-    ).execute(connection) {
-        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Book removed from shelf successfully." }))),
+    let result = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        let covers = remove_covers(conn, &[book_id])?;
+        diesel::delete(
+            crate::schema::books::dsl::books.filter(crate::schema::books::dsl::id.eq(book_id)),
+        ).execute(conn)?;
+        Ok(covers)
+    });
+
+    match result {
+        Ok(covers) => {
+            for cover in &covers {
+                delete_files(cover).await;
+            }
+            (StatusCode::OK, Json(json!({ "message": "Book removed from shelf successfully." })))
+        }
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while removing the book from the shelf: {}", e) }))),
     }
 }
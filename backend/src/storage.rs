@@ -0,0 +1,244 @@
+use reqwest::Url;
+use rusty_s3::{Bucket, Credentials, S3Action, UrlStyle};
+use std::env;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Validity of the signed URLs used to talk to an S3-compatible store.
+const SIGNATURE_DURATION: Duration = Duration::from_secs(60);
+
+/// Error raised by a storage backend.
+#[derive(Debug)]
+pub struct StorageError(String);
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        StorageError(e.to_string())
+    }
+}
+
+impl From<reqwest::Error> for StorageError {
+    fn from(e: reqwest::Error) -> Self {
+        StorageError(e.to_string())
+    }
+}
+
+/// A store for binary objects like cover images, addressed by a key.
+pub trait Storage {
+    /// Stores the given data under the key, replacing any existing object.
+    fn put(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        content_type: &str,
+    ) -> impl Future<Output = Result<(), StorageError>> + Send;
+
+    /// Fetches the object stored under the key, returning `None` if there is none.
+    fn get(&self, key: &str) -> impl Future<Output = Result<Option<Vec<u8>>, StorageError>> + Send;
+
+    /// Removes the object stored under the key. Removing a missing object is not an error.
+    fn delete(&self, key: &str) -> impl Future<Output = Result<(), StorageError>> + Send;
+}
+
+/// Stores objects as files below a directory of the local filesystem.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStorage { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        // Keys are generated by the application, but never allow them to escape the root
+        self.root
+            .join(key.split('/').filter(|s| !s.is_empty() && *s != "..").collect::<PathBuf>())
+    }
+}
+
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: Vec<u8>, _content_type: &str) -> Result<(), StorageError> {
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        match tokio::fs::read(self.path(key)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match tokio::fs::remove_file(self.path(key)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Stores objects in a bucket of an S3-compatible object store.
+pub struct S3Storage {
+    client: reqwest::Client,
+    bucket: Bucket,
+    credentials: Credentials,
+}
+
+impl S3Storage {
+    /// Creates the storage from the `S3_ENDPOINT`, `S3_BUCKET`, `S3_REGION`, `S3_ACCESS_KEY_ID`
+    /// and `S3_SECRET_ACCESS_KEY` environment variables. Path style URLs, as needed by most self
+    /// hosted stores, are used unless `S3_PATH_STYLE` is set to `false`.
+    pub fn from_env() -> Result<Self, StorageError> {
+        let var = |name: &str| env::var(name).map_err(|_| StorageError(format!("{} must be set", name)));
+
+        let endpoint: Url = var("S3_ENDPOINT")?
+            .parse()
+            .map_err(|e| StorageError(format!("Invalid S3_ENDPOINT: {}", e)))?;
+        let url_style = match env::var("S3_PATH_STYLE").as_deref() {
+            Ok("false") => UrlStyle::VirtualHost,
+            _ => UrlStyle::Path,
+        };
+        let region = env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let bucket = Bucket::new(endpoint, url_style, var("S3_BUCKET")?, region)
+            .map_err(|e| StorageError(format!("Invalid S3 bucket: {}", e)))?;
+
+        Ok(S3Storage {
+            client: reqwest::Client::new(),
+            bucket,
+            credentials: Credentials::new(var("S3_ACCESS_KEY_ID")?, var("S3_SECRET_ACCESS_KEY")?),
+        })
+    }
+}
+
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<(), StorageError> {
+        let url = self
+            .bucket
+            .put_object(Some(&self.credentials), key)
+            .sign(SIGNATURE_DURATION);
+        self.client
+            .put(url)
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(data)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        let url = self
+            .bucket
+            .get_object(Some(&self.credentials), key)
+            .sign(SIGNATURE_DURATION);
+        let response = self.client.get(url).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.bytes().await?.to_vec()))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let url = self
+            .bucket
+            .delete_object(Some(&self.credentials), key)
+            .sign(SIGNATURE_DURATION);
+        self.client.delete(url).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// The storage backend configured for cover images.
+pub enum CoverStorage {
+    Local(LocalStorage),
+    S3(S3Storage),
+}
+
+impl Storage for CoverStorage {
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<(), StorageError> {
+        match self {
+            CoverStorage::Local(storage) => storage.put(key, data, content_type).await,
+            CoverStorage::S3(storage) => storage.put(key, data, content_type).await,
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        match self {
+            CoverStorage::Local(storage) => storage.get(key).await,
+            CoverStorage::S3(storage) => storage.get(key).await,
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match self {
+            CoverStorage::Local(storage) => storage.delete(key).await,
+            CoverStorage::S3(storage) => storage.delete(key).await,
+        }
+    }
+}
+
+static COVER_STORAGE: OnceLock<CoverStorage> = OnceLock::new();
+
+/// Call this once at application startup. Uses the local filesystem below `COVER_STORAGE_PATH`
+/// unless `COVER_STORAGE` is set to `s3`. Panics if the S3 configuration is incomplete.
+pub fn init_cover_storage() {
+    let storage = match env::var("COVER_STORAGE").as_deref() {
+        Ok("s3") => CoverStorage::S3(
+            S3Storage::from_env().unwrap_or_else(|e| panic!("Invalid S3 configuration: {}", e)),
+        ),
+        _ => CoverStorage::Local(LocalStorage::new(
+            env::var("COVER_STORAGE_PATH").unwrap_or_else(|_| "covers".to_string()),
+        )),
+    };
+    if COVER_STORAGE.set(storage).is_err() {
+        panic!("Cover storage already initialized");
+    }
+}
+
+pub fn cover_storage() -> &'static CoverStorage {
+    COVER_STORAGE
+        .get()
+        .expect("Cover storage not initialized — call storage::init_cover_storage() at startup")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_local_storage_roundtrip() {
+        let root = env::temp_dir().join(format!("books-storage-{}", uuid::Uuid::new_v4()));
+        let storage = LocalStorage::new(&root);
+
+        storage.put("a/original", vec![1, 2, 3], "image/png").await.unwrap();
+        assert_eq!(storage.get("a/original").await.unwrap(), Some(vec![1, 2, 3]));
+
+        storage.delete("a/original").await.unwrap();
+        assert_eq!(storage.get("a/original").await.unwrap(), None);
+        storage.delete("a/original").await.unwrap();
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_local_storage_keys_stay_below_root() {
+        let storage = LocalStorage::new("/srv/covers");
+        assert_eq!(storage.path("../../etc/passwd"), PathBuf::from("/srv/covers/etc/passwd"));
+    }
+}
@@ -0,0 +1,361 @@
+use crate::contributors::display_authors;
+use crate::goodreads_importer::{parse_date, split_series};
+use crate::importer::{csv_headers, has_headers, merge_contributors, ImportedBook, ImportedRows, Importer};
+use crate::models::ReadingMode;
+use crate::readings::{ReadDates, UnfinishedRead};
+use chrono::NaiveDate;
+use csv::ReaderBuilder;
+use serde::Deserialize;
+use std::io::Read;
+
+#[derive(Debug, Deserialize)]
+pub struct StoryGraphRecord {
+    #[serde(rename = "Title")]
+    pub title: String,
+    #[serde(rename = "Authors")]
+    pub authors: String,
+    #[serde(rename = "Contributors")]
+    pub contributors: Option<String>,
+    #[serde(rename = "ISBN/UID")]
+    pub isbn_uid: Option<String>,
+    #[serde(rename = "Format")]
+    pub format: Option<String>,
+    #[serde(rename = "Read Status")]
+    pub read_status: String,
+    #[serde(rename = "Date Added")]
+    pub date_added: Option<String>,
+    #[serde(rename = "Last Date Read")]
+    pub last_date_read: Option<String>,
+    #[serde(rename = "Dates Read")]
+    pub dates_read: Option<String>,
+    #[serde(rename = "Read Count")]
+    pub read_count: Option<u16>,
+    #[serde(rename = "Moods")]
+    pub moods: Option<String>,
+    #[serde(rename = "Star Rating")]
+    pub star_rating: Option<f32>,
+    #[serde(rename = "Review")]
+    pub review: Option<String>,
+    #[serde(rename = "Tags")]
+    pub tags: Option<String>,
+}
+
+/// Imports the CSV export of StoryGraph.
+pub struct StoryGraphImporter;
+
+impl Importer for StoryGraphImporter {
+    fn source(&self) -> &'static str {
+        "storygraph"
+    }
+
+    fn name(&self) -> &'static str {
+        "StoryGraph"
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        csv_headers(data).is_some_and(|headers| has_headers(&headers, &["Title", "Authors", "ISBN/UID", "Read Status"]))
+    }
+
+    fn read(&self, data: &[u8], _options: &serde_json::Value) -> Result<ImportedRows, String> {
+        let rows = StoryGraphRecord::read_rows(data).map_err(|e| format!("Failed to parse CSV file: {}", e))?;
+        Ok(rows.into_iter().map(|row| row.map(|record| record.to_imported())).collect())
+    }
+}
+
+/// Splits a comma separated list like the tags or moods of a book.
+fn split_list(value: Option<&str>) -> Vec<String> {
+    let mut items: Vec<String> = Vec::new();
+    for item in value.unwrap_or_default().split(',').map(str::trim) {
+        if !item.is_empty() && !items.iter().any(|i| i == item) {
+            items.push(item.to_string());
+        }
+    }
+    items
+}
+
+/// Parses an entry of "Dates Read", either a single date or a range like "2023/01/10-2023/01/20".
+///
+/// Returns `None` for reads which haven't been finished yet, e.g. "2024/05/01-".
+fn parse_read_dates(entry: &str) -> Option<ReadDates> {
+    let entry = entry.trim();
+    let first = entry.get(..10).and_then(parse_date)?;
+    let rest = entry[10..].trim_start_matches([' ', '-']);
+    if rest.is_empty() {
+        // A single date is the day the book was finished, unless it opens a range
+        return (!entry[10..].contains('-')).then_some(ReadDates { started_at: None, finished_at: first });
+    }
+    let finished_at = parse_date(rest)?;
+    Some(ReadDates { started_at: Some(first), finished_at })
+}
+
+/// Parses an entry of "Dates Read" for a read which hasn't been finished, e.g. "2024/05/01-".
+fn parse_open_read(entry: &str) -> Option<NaiveDate> {
+    parse_date(entry.trim().strip_suffix('-')?)
+}
+
+impl StoryGraphRecord {
+    /// Reads all rows of a StoryGraph export, keeping rows which fail to parse as errors.
+    pub fn read_rows(data: impl Read) -> Result<Vec<Result<StoryGraphRecord, String>>, csv::Error> {
+        let mut rdr = ReaderBuilder::new().from_reader(data);
+        rdr.headers()?;
+        Ok(rdr
+            .deserialize()
+            .map(|result| result.map_err(|e| e.to_string()))
+            .collect())
+    }
+
+    /// Maps the record onto the book model shared by all importers.
+    ///
+    /// Moods are kept as tags prefixed with `mood:` to tell them apart from the tags of the user,
+    /// books read digitally or as audiobook are tracked as a percentage.
+    pub fn to_imported(&self) -> ImportedBook {
+        let (title, series) = split_series(&self.title);
+        let contributors = merge_contributors(&self.authors, self.contributors.as_deref());
+
+        let mut tags = self.tags();
+        tags.extend(self.moods().into_iter().map(|mood| format!("mood:{}", mood)));
+
+        ImportedBook {
+            raw_title: self.title.trim().to_string(),
+            title,
+            series,
+            author: display_authors(&contributors).unwrap_or_else(|| self.authors.trim().to_string()),
+            contributors,
+            isbn13: self.isbn13(),
+            isbn10: self.isbn10(),
+            publisher: None,
+            published_year: None,
+            page_count: None,
+            added_on: self.added_on(),
+            shelf: self.shelf(),
+            other_shelves: Vec::new(),
+            tags,
+            rating: self.rating(),
+            review: self.review(),
+            note: None,
+            reading_mode: if self.tracks_percentage() { ReadingMode::Percentage } else { ReadingMode::Pages },
+            reads: self.reads(),
+            undated_reads: self.undated_reads(),
+            unfinished_read: self.unfinished_read(),
+            has_cover: false,
+            cover_file: None,
+        }
+    }
+
+    /// Returns the ISBN-13 of the book, StoryGraph uses its own IDs for books without one.
+    pub fn isbn13(&self) -> Option<String> {
+        let isbn: String = self.isbn_uid.as_deref()?.chars().filter(|c| *c != '-').collect();
+        (isbn.len() == 13 && isbn.chars().all(|c| c.is_ascii_digit())).then_some(isbn)
+    }
+
+    /// Returns the ISBN-10 of the book, if that's what StoryGraph has.
+    pub fn isbn10(&self) -> Option<String> {
+        let isbn: String = self.isbn_uid.as_deref()?.chars().filter(|c| *c != '-').collect();
+        (isbn.len() == 10 && isbn.chars().all(|c| c.is_ascii_digit() || c == 'X')).then_some(isbn)
+    }
+
+    /// Returns the status of the book, which is used as its shelf, e.g. `to-read` or `did-not-finish`.
+    pub fn shelf(&self) -> String {
+        self.read_status.trim().to_lowercase().replace(' ', "-")
+    }
+
+    /// Returns whether the book was read as an ebook or audiobook, where progress is tracked as
+    /// a percentage rather than in pages.
+    pub fn tracks_percentage(&self) -> bool {
+        matches!(
+            self.format.as_deref().map(|f| f.trim().to_lowercase()).as_deref(),
+            Some("digital" | "audio" | "ebook" | "audiobook")
+        )
+    }
+
+    /// Returns the rating in half stars, StoryGraph allows quarter stars which are rounded.
+    pub fn rating(&self) -> Option<i16> {
+        self.star_rating
+            .filter(|rating| *rating > 0.0)
+            .map(|rating| ((rating * 2.0).round() as i16).clamp(1, 10))
+    }
+
+    /// Returns the review, StoryGraph exports it as plain text.
+    pub fn review(&self) -> Option<String> {
+        Some(self.review.as_deref()?.trim().to_string()).filter(|review| !review.is_empty())
+    }
+
+    /// Returns the tags of the book.
+    pub fn tags(&self) -> Vec<String> {
+        split_list(self.tags.as_deref())
+    }
+
+    /// Returns the moods the book was tagged with, e.g. "adventurous" or "dark".
+    pub fn moods(&self) -> Vec<String> {
+        split_list(self.moods.as_deref())
+    }
+
+    /// Returns the date the book was added to StoryGraph.
+    pub fn added_on(&self) -> Option<NaiveDate> {
+        self.date_added.as_deref().and_then(parse_date)
+    }
+
+    /// Returns the date the book was read last.
+    pub fn last_read_on(&self) -> Option<NaiveDate> {
+        self.last_date_read.as_deref().and_then(parse_date)
+    }
+
+    /// Returns the entries of "Dates Read".
+    fn dates_read(&self) -> Vec<&str> {
+        self.dates_read.as_deref().unwrap_or_default().split(',').collect()
+    }
+
+    /// Returns the finished reads of a book on the `read` shelf, latest first.
+    ///
+    /// Books without "Dates Read" were finished on the last date read, reads which are only
+    /// counted in "Read Count" are left out, see `undated_reads`.
+    pub fn reads(&self) -> Vec<ReadDates> {
+        if self.shelf() != "read" {
+            return Vec::new();
+        }
+
+        let mut reads: Vec<ReadDates> = self.dates_read().into_iter().filter_map(parse_read_dates).collect();
+        reads.sort_by_key(|read| std::cmp::Reverse(read.finished_at));
+        if reads.is_empty() {
+            reads.extend(self.last_read_on().map(|finished_at| ReadDates { started_at: None, finished_at }));
+        }
+        reads
+    }
+
+    /// Returns how many reads "Read Count" has beyond the dated ones, they can't be recorded.
+    ///
+    /// Books which were given up on without a date can't be recorded either.
+    pub fn undated_reads(&self) -> i32 {
+        match self.shelf().as_str() {
+            "read" => {
+                let count = usize::from(self.read_count.unwrap_or(1).max(1));
+                count.saturating_sub(self.reads().len()) as i32
+            }
+            "did-not-finish" => i32::from(self.unfinished_read().is_none()),
+            _ => 0,
+        }
+    }
+
+    /// Returns the reading of a book which is being read or was given up on.
+    ///
+    /// Readings start with the open read of "Dates Read", e.g. "2024/05/01-". StoryGraph dates
+    /// reads which were given up on like finished ones, books without dates are given up on with
+    /// their last date read. Books given up on without any date have no reading, as it would be
+    /// taken for an ongoing one, see `undated_reads`.
+    pub fn unfinished_read(&self) -> Option<UnfinishedRead> {
+        let dates_read = self.dates_read();
+        let started_at = dates_read.iter().copied().filter_map(parse_open_read).max();
+        match self.shelf().as_str() {
+            "currently-reading" => Some(UnfinishedRead { started_at, cancelled_at: None }),
+            "did-not-finish" => {
+                let last = dates_read.into_iter().filter_map(parse_read_dates).max_by_key(|read| read.finished_at);
+                let cancelled_at = last.map(|read| read.finished_at).or_else(|| self.last_read_on())?;
+                Some(UnfinishedRead {
+                    started_at: last.and_then(|read| read.started_at).or(started_at),
+                    cancelled_at: Some(cancelled_at),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = "Title,Authors,Contributors,ISBN/UID,Format,Read Status,Date Added,Last Date Read,Dates Read,Read Count,Moods,Pace,Character- or Plot-Driven?,Strong Character Development?,Loveable Characters?,Diverse Characters?,Flawed Characters?,Star Rating,Review,Content Warnings,Content Warning Description,Tags,Owned?
+The Way of Kings,Brandon Sanderson,Michael Kramer (Narrator),9780765326355,audio,read,2021/01/02,2023/05/12,\"2021/02/01-2021/03/15, 2023/04/20-2023/05/12\",3,\"adventurous, emotional\",medium,,,,,,4.75,A slow start but worth it.,,,\"fantasy, epic\",No
+Piranesi,Susanna Clarke,,B08FGV64B1,digital,to-read,2024/06/30,,,0,,,,,,,,,,,,,No
+Circe,Madeline Miller,,9780316556347,paperback,currently-reading,2024/04/02,,2024/05/01-,0,,,,,,,,,,,,,No
+Ulysses,James Joyce,,9780679722762,paperback,did-not-finish,2022/08/15,2023/01/20,2023/01/02-2023/01/20,0,,,,,,,,,,,,,No
+Moby-Dick,Herman Melville,,9780142437247,paperback,did-not-finish,2022/03/01,,2022/03/05-,0,,,,,,,,,,,,,No
+";
+
+    fn records() -> Vec<StoryGraphRecord> {
+        StoryGraphRecord::read_rows(EXPORT.as_bytes())
+            .unwrap()
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_read_export() {
+        let records = records();
+        let way_of_kings = &records[0];
+        assert_eq!(way_of_kings.shelf(), "read");
+        assert_eq!(way_of_kings.isbn13().as_deref(), Some("9780765326355"));
+        assert!(way_of_kings.tracks_percentage());
+        assert_eq!(way_of_kings.rating(), Some(10));
+        assert_eq!(way_of_kings.tags(), vec!["fantasy", "epic"]);
+        assert_eq!(way_of_kings.moods(), vec!["adventurous", "emotional"]);
+        assert_eq!(way_of_kings.review().as_deref(), Some("A slow start but worth it."));
+
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(
+            way_of_kings.reads(),
+            vec![
+                ReadDates { started_at: Some(date(2023, 4, 20)), finished_at: date(2023, 5, 12) },
+                ReadDates { started_at: Some(date(2021, 2, 1)), finished_at: date(2021, 3, 15) },
+            ]
+        );
+        assert_eq!(way_of_kings.undated_reads(), 1);
+        assert_eq!(way_of_kings.unfinished_read(), None);
+
+        let piranesi = &records[1];
+        assert_eq!(piranesi.shelf(), "to-read");
+        assert_eq!(piranesi.isbn13(), None);
+        assert_eq!(piranesi.isbn10(), None);
+        assert_eq!(piranesi.rating(), None);
+        assert!(piranesi.reads().is_empty());
+        assert_eq!(piranesi.undated_reads(), 0);
+
+        let circe = &records[2];
+        assert!(circe.reads().is_empty());
+        assert_eq!(circe.unfinished_read(), Some(UnfinishedRead { started_at: Some(date(2024, 5, 1)), cancelled_at: None }));
+
+        let ulysses = &records[3];
+        assert!(ulysses.reads().is_empty());
+        assert_eq!(
+            ulysses.unfinished_read(),
+            Some(UnfinishedRead { started_at: Some(date(2023, 1, 2)), cancelled_at: Some(date(2023, 1, 20)) })
+        );
+        assert_eq!(ulysses.undated_reads(), 0);
+
+        // Books given up on without a date aren't dated by when they were added
+        let moby_dick = &records[4];
+        assert_eq!(moby_dick.unfinished_read(), None);
+        assert_eq!(moby_dick.undated_reads(), 1);
+    }
+
+    #[test]
+    fn test_parse_read_dates() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(
+            parse_read_dates("2023-04-20 - 2023-05-12"),
+            Some(ReadDates { started_at: Some(date(2023, 4, 20)), finished_at: date(2023, 5, 12) })
+        );
+        assert_eq!(
+            parse_read_dates(" 2023/05/12"),
+            Some(ReadDates { started_at: None, finished_at: date(2023, 5, 12) })
+        );
+        assert_eq!(parse_read_dates("2024/05/01-"), None);
+        assert_eq!(parse_read_dates(""), None);
+        assert_eq!(parse_open_read(" 2024/05/01-"), Some(date(2024, 5, 1)));
+        assert_eq!(parse_open_read("2023/01/02-2023/01/20"), None);
+    }
+
+    #[test]
+    fn test_to_imported() {
+        let book = records()[0].to_imported();
+        assert_eq!(book.author, "Brandon Sanderson");
+        assert_eq!(book.contributors.len(), 2);
+        assert_eq!(book.shelf, "read");
+        assert_eq!(book.tags, vec!["fantasy", "epic", "mood:adventurous", "mood:emotional"]);
+        assert_eq!(book.reading_mode, ReadingMode::Percentage);
+        assert_eq!(book.reads.len(), 2);
+        assert_eq!(book.undated_reads, 1);
+        assert!(StoryGraphImporter.detect(EXPORT.as_bytes()));
+    }
+}
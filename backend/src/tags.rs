@@ -0,0 +1,407 @@
+use crate::auth::AuthUser;
+use crate::db::connect;
+use crate::models::{Book, BookTag, Tag};
+use crate::schema::book_tags::dsl::book_tags;
+use crate::schema::books::dsl::books;
+use crate::schema::highlight_tags::dsl::highlight_tags;
+use crate::schema::tags::dsl::tags;
+use crate::{schema, ErrorResponse};
+use axum::routing::post;
+use axum::{extract::Json, http::StatusCode, response::IntoResponse, Router};
+use diesel::dsl::count_star;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use uuid::Uuid;
+
+pub(crate) fn register_routes(router: Router) -> Router {
+    router
+        .route("/api/tags", post(list_tags))
+        .route("/api/tags/create", post(create_tag))
+        .route("/api/tags/remove", post(remove_tag))
+        .route("/api/tags/add-book", post(add_tag_to_book))
+        .route("/api/tags/remove-book", post(remove_tag_from_book))
+        .route("/api/tags/books", post(list_tagged_books))
+}
+
+/// Finds the tags of a user with the given names, creating the missing ones.
+pub fn find_or_create_tags(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    names: &[String],
+) -> QueryResult<HashMap<String, Uuid>> {
+    let names: BTreeSet<&str> = names.iter().map(|n| n.trim()).filter(|n| !n.is_empty()).collect();
+    if names.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let new_tags: Vec<Tag> = names
+        .iter()
+        .map(|name| Tag {
+            id: Uuid::new_v4(),
+            user: user_id,
+            name: name.to_string(),
+            created_at: now,
+        })
+        .collect();
+
+    diesel::insert_into(tags)
+        .values(&new_tags)
+        .on_conflict((schema::tags::dsl::user, schema::tags::dsl::name))
+        .do_nothing()
+        .execute(connection)?;
+
+    Ok(tags
+        .filter(schema::tags::dsl::user.eq(user_id))
+        .filter(schema::tags::dsl::name.eq_any(names))
+        .select((schema::tags::dsl::name, schema::tags::dsl::id))
+        .load::<(String, Uuid)>(connection)?
+        .into_iter()
+        .collect())
+}
+
+/// Tags a book with the given tags of its user, creating the missing ones.
+pub fn tag_book(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    book_id: Uuid,
+    names: &[String],
+) -> QueryResult<()> {
+    let tag_ids = find_or_create_tags(connection, user_id, names)?;
+    let links: Vec<BookTag> = tag_ids
+        .into_values()
+        .map(|tag| BookTag { book: book_id, tag })
+        .collect();
+
+    diesel::insert_into(book_tags)
+        .values(&links)
+        .on_conflict_do_nothing()
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Loads the tag names of the given books.
+pub fn load_tags(connection: &mut PgConnection, book_ids: &[Uuid]) -> QueryResult<HashMap<Uuid, Vec<String>>> {
+    let rows: Vec<(Uuid, String)> = book_tags
+        .inner_join(tags)
+        .filter(schema::book_tags::dsl::book.eq_any(book_ids))
+        .order(schema::tags::dsl::name.asc())
+        .select((schema::book_tags::dsl::book, schema::tags::dsl::name))
+        .load(connection)?;
+
+    let mut result: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for (book, name) in rows {
+        result.entry(book).or_default().push(name);
+    }
+    Ok(result)
+}
+
+/// Finds the books of a user carrying all of the given tags.
+pub fn books_with_all_tags(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    names: &[String],
+) -> QueryResult<Vec<Uuid>> {
+    let names: BTreeSet<&str> = names.iter().map(|n| n.trim()).filter(|n| !n.is_empty()).collect();
+    book_tags
+        .inner_join(tags)
+        .filter(schema::tags::dsl::user.eq(user_id))
+        .filter(schema::tags::dsl::name.eq_any(&names))
+        .group_by(schema::book_tags::dsl::book)
+        .having(count_star().eq(names.len() as i64))
+        .select(schema::book_tags::dsl::book)
+        .load(connection)
+}
+
+/// Response type for listing all tags of a user.
+#[derive(Debug, Serialize)]
+pub struct ListTagsResponse {
+    pub tags: Vec<serde_json::Value>,
+}
+
+/// Lists the book tags of a user along with the number of tagged books.
+///
+/// Highlights share the tags of books, tags which are only used on highlights aren't listed.
+pub(crate) async fn list_tags(auth: AuthUser) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let results = match tags
+        .filter(schema::tags::dsl::user.eq(auth.0))
+        .order(schema::tags::dsl::name.asc())
+        .load::<Tag>(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading tags: {}", e) }))),
+    };
+
+    let counts: HashMap<Uuid, i64> = match book_tags
+        .inner_join(tags)
+        .filter(schema::tags::dsl::user.eq(auth.0))
+        .group_by(schema::book_tags::dsl::tag)
+        .select((schema::book_tags::dsl::tag, count_star()))
+        .load::<(Uuid, i64)>(connection)
+    {
+        Ok(c) => c.into_iter().collect(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading tags: {}", e) }))),
+    };
+
+    let highlight_only: HashSet<Uuid> = match highlight_tags
+        .inner_join(tags)
+        .filter(schema::tags::dsl::user.eq(auth.0))
+        .select(schema::highlight_tags::dsl::tag)
+        .distinct()
+        .load::<Uuid>(connection)
+    {
+        Ok(t) => t.into_iter().filter(|tag| !counts.contains_key(tag)).collect(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading tags: {}", e) }))),
+    };
+
+    let mut json_tags = Vec::new();
+    for tag in results.into_iter().filter(|tag| !highlight_only.contains(&tag.id)) {
+        json_tags.push(json!({
+            "id": tag.id.to_string(),
+            "name": tag.name,
+            "book_count": counts.get(&tag.id).copied().unwrap_or(0),
+            "created_at": tag.created_at.to_string(),
+        }));
+    }
+
+    (StatusCode::OK, Json(json!(ListTagsResponse { tags: json_tags })))
+}
+
+/// Request type for creating a new tag.
+#[derive(Debug, Deserialize)]
+pub struct CreateTagRequest {
+    pub name: String,
+}
+
+/// Creates a new tag.
+pub(crate) async fn create_tag(
+    auth: AuthUser,
+    Json(payload): Json<CreateTagRequest>,
+) -> impl IntoResponse {
+    let new_tag = Tag {
+        id: Uuid::new_v4(),
+        user: auth.0,
+        name: payload.name.trim().to_string(),
+        created_at: chrono::Utc::now().naive_utc(),
+    };
+
+    let connection = &mut connect();
+
+    match diesel::insert_into(tags)
+        .values(&new_tag)
+        .execute(connection)
+    {
+        Ok(_) => (StatusCode::CREATED, Json(json!({ "message": "Tag created successfully.", "id": new_tag.id.to_string() }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while creating the tag: {}", e) }))),
+    }
+}
+
+/// Request type for removing a tag.
+#[derive(Debug, Deserialize)]
+pub struct RemoveTagRequest {
+    pub tag_id: String,
+}
+
+/// Removes a tag from all books and deletes it.
+pub(crate) async fn remove_tag(
+    auth: AuthUser,
+    Json(payload): Json<RemoveTagRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let tag_id = match Uuid::parse_str(&payload.tag_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid tag ID.".to_string() }))),
+    };
+
+    match diesel::delete(
+        tags.filter(schema::tags::dsl::id.eq(tag_id))
+            .filter(schema::tags::dsl::user.eq(auth.0)),
+    )
+    .execute(connection)
+    {
+        Ok(0) => (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Tag not found.".to_string() }))),
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Tag removed successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while removing the tag: {}", e) }))),
+    }
+}
+
+/// Request type for tagging a book.
+#[derive(Debug, Deserialize)]
+pub struct AddTagToBookRequest {
+    pub book_id: String,
+    pub tags: Vec<String>,
+}
+
+/// Tags a book.
+///
+/// This route accepts a JSON payload with the following structure:
+/// - `book_id`: The UUID of the book to tag.
+/// - `tags`: The names of the tags, missing tags are created.
+pub(crate) async fn add_tag_to_book(
+    auth: AuthUser,
+    Json(payload): Json<AddTagToBookRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let book_id = match Uuid::parse_str(&payload.book_id) {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book ID.".to_string() }))),
+    };
+
+    let book: Book = match books
+        .filter(schema::books::dsl::id.eq(book_id))
+        .first(connection)
+    {
+        Ok(b) => b,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Book not found.".to_string() }))),
+    };
+
+    if book.user != auth.0 {
+        return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
+    }
+
+    match tag_book(connection, auth.0, book_id, &payload.tags) {
+        Ok(_) => (StatusCode::CREATED, Json(json!({ "message": "Book tagged successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while tagging the book: {}", e) }))),
+    }
+}
+
+/// Request type for removing a tag from a book.
+#[derive(Debug, Deserialize)]
+pub struct RemoveTagFromBookRequest {
+    pub book_id: String,
+    pub tag_id: String,
+}
+
+/// Removes a tag from a book.
+pub(crate) async fn remove_tag_from_book(
+    auth: AuthUser,
+    Json(payload): Json<RemoveTagFromBookRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let (book_id, tag_id) = match (Uuid::parse_str(&payload.book_id), Uuid::parse_str(&payload.tag_id)) {
+        (Ok(book_id), Ok(tag_id)) => (book_id, tag_id),
+        _ => return (StatusCode::BAD_REQUEST, Json(json!(ErrorResponse { error: "Invalid book or tag ID.".to_string() }))),
+    };
+
+    let tag: Tag = match tags.filter(schema::tags::dsl::id.eq(tag_id)).first(connection) {
+        Ok(t) => t,
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!(ErrorResponse { error: "Tag not found.".to_string() }))),
+    };
+
+    if tag.user != auth.0 {
+        return (StatusCode::FORBIDDEN, Json(json!(ErrorResponse { error: "Access denied.".to_string() })));
+    }
+
+    match diesel::delete(
+        book_tags
+            .filter(schema::book_tags::dsl::book.eq(book_id))
+            .filter(schema::book_tags::dsl::tag.eq(tag_id)),
+    )
+    .execute(connection)
+    {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Tag removed from book successfully." }))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error while removing the tag from the book: {}", e) }))),
+    }
+}
+
+/// Request type for listing the books carrying tags.
+#[derive(Debug, Deserialize)]
+pub struct TaggedBooksRequest {
+    pub tags: Vec<String>,
+}
+
+/// Lists the books of a user across all shelves which carry all of the given tags.
+pub(crate) async fn list_tagged_books(
+    auth: AuthUser,
+    Json(payload): Json<TaggedBooksRequest>,
+) -> impl IntoResponse {
+    let connection = &mut connect();
+
+    let book_ids = match books_with_all_tags(connection, auth.0, &payload.tags) {
+        Ok(ids) => ids,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading tags: {}", e) }))),
+    };
+
+    let results = match books
+        .filter(schema::books::dsl::id.eq_any(&book_ids))
+        .filter(schema::books::dsl::user.eq(auth.0))
+        .order(schema::books::dsl::title.asc())
+        .load::<Book>(connection)
+    {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading books: {}", e) }))),
+    };
+
+    let mut book_tag_names = match load_tags(connection, &book_ids) {
+        Ok(t) => t,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(ErrorResponse { error: format!("Error loading tags: {}", e) }))),
+    };
+
+    let mut json_books = Vec::new();
+    for book in results {
+        json_books.push(json!({
+            "id": book.id.to_string(),
+            "shelf": book.shelf.to_string(),
+            "title": book.title,
+            "author": book.author,
+            "isbn13": book.isbn13,
+            "isbn10": book.isbn10,
+            "google_books_id": book.google_books_id,
+            "added_at": book.added_at.to_string(),
+            "tags": book_tag_names.remove(&book.id).unwrap_or_default(),
+        }));
+    }
+
+    (StatusCode::OK, Json(json!({ "books": json_books })))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_list_tags_requires_auth() {
+        let app = Router::new().route("/api/tags", post(list_tags));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/tags").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_create_tag_requires_auth() {
+        let app = Router::new().route("/api/tags/create", post(create_tag));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/tags/create").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_add_tag_to_book_requires_auth() {
+        let app = Router::new().route("/api/tags/add-book", post(add_tag_to_book));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/tags/add-book").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_list_tagged_books_requires_auth() {
+        let app = Router::new().route("/api/tags/books", post(list_tagged_books));
+        let response = app
+            .oneshot(Request::builder().method("POST").uri("/api/tags/books").body(Body::empty()).unwrap())
+            .await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+}